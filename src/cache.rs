@@ -0,0 +1,128 @@
+use crate::{XArrayBoxed, XaMark};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+/// Mark flagging an entry of an [`XArrayCache`] as stale.
+pub const STALE: XaMark = XaMark::Mark0;
+
+/// Read-mostly cache laid over an [`XArrayBoxed`], invalidating entries
+/// with a mark.
+///
+/// Writers flag entries as stale with [`XArrayCache::invalidate`] without
+/// dropping them. Readers only see fresh entries through
+/// [`XArrayCache::get`] and recompute stale ones with
+/// [`XArrayCache::get_or_refresh`], or refresh every stale entry at once
+/// with [`XArrayCache::revalidate`].
+pub struct XArrayCache<'a, T: 'a> {
+    xa: XArrayBoxed<'a, T>,
+}
+
+impl<'a, T: 'a> XArrayCache<'a, T> {
+    /// Create an empty cache.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            xa: XArrayBoxed::new(),
+        }
+    }
+
+    /// Returns `true` if the cache holds no entry.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.xa.is_empty()
+    }
+
+    /// Returns `true` if the entry at `index` is present and stale.
+    #[inline]
+    pub fn is_stale(&self, index: u64) -> bool {
+        self.xa.cursor(index).is_marked(STALE)
+    }
+
+    /// Get the entry at `index` if it is present and fresh.
+    #[inline]
+    pub fn get(&self, index: u64) -> Option<&T> {
+        let mut cursor = self.xa.cursor(index);
+        if cursor.is_marked(STALE) {
+            None
+        } else {
+            cursor.current()
+        }
+    }
+
+    /// Get the entry at `index` whether it is fresh or stale.
+    #[inline]
+    pub fn peek(&self, index: u64) -> Option<&T> {
+        self.xa.get(index)
+    }
+
+    /// Store a fresh `value` at `index`, returning the entry it replaces.
+    pub fn insert(&mut self, index: u64, value: T) -> Option<T> {
+        match self
+            .xa
+            .cursor_mut(index)
+            .replace_clear_marks(Box::new(value))
+        {
+            Ok(old) => old.map(|v| *v),
+            Err(err) => panic!("xarray: {}", err),
+        }
+    }
+
+    /// Remove the entry at `index`, returning it.
+    #[inline]
+    pub fn remove(&mut self, index: u64) -> Option<T> {
+        self.xa.remove(index).map(|v| *v)
+    }
+
+    /// Get the entry at `index`, computing it with `f` if it is absent or
+    /// stale. `f` is passed the stale entry, if any.
+    pub fn get_or_refresh<F>(&mut self, index: u64, f: F) -> &T
+    where
+        F: FnOnce(u64, Option<&T>) -> T,
+    {
+        if self.get(index).is_none() {
+            let value = f(index, self.xa.get(index));
+            self.insert(index, value);
+        }
+        self.xa.get(index).unwrap()
+    }
+
+    /// Flag the entry at `index` as stale.
+    #[inline]
+    pub fn invalidate(&mut self, index: u64) {
+        self.xa.cursor_mut(index).mark(STALE);
+    }
+
+    /// Flag every entry within `range` as stale.
+    pub fn invalidate_range(&mut self, range: RangeInclusive<u64>) {
+        let mut entries = self.xa.extract_mut(*range.start(), *range.end());
+        while entries.next().is_some() {
+            entries.as_cursor_mut().mark(STALE);
+        }
+    }
+
+    /// Recompute every stale entry with `f`, passing it the stale entry,
+    /// and return the number of refreshed entries.
+    pub fn revalidate<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(u64, &T) -> T,
+    {
+        let stale = self
+            .xa
+            .iter()
+            .filter_mark(STALE)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        for &index in &stale {
+            let value = f(index, self.xa.get(index).unwrap());
+            self.insert(index, value);
+        }
+        stale.len()
+    }
+}
+
+impl<'a, T: 'a> Default for XArrayCache<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
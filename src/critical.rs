@@ -0,0 +1,102 @@
+use crate::{RawXArray, XaError, XaMark};
+use core::cell::RefCell;
+use critical_section::Mutex;
+
+/// Array shared between thread and interrupt context.
+///
+/// Every operation runs inside `critical_section::with`, so on single-core
+/// targets an interrupt handler never observes the tree halfway through an
+/// update. Keep the sections short, as interrupts stay masked while the
+/// tree is walked.
+///
+/// The array is only reachable through the operations below, so no
+/// shrinker, observer or removal hook can be attached to it and run in the
+/// wrong context.
+pub struct CsXArray<'a, T> {
+    xa: Mutex<RefCell<RawXArray<'a, T>>>,
+}
+
+// SAFETY: the array holds no hooks, and the nodes are only touched inside a
+// critical section. Values are shared between contexts, hence `T: Sync`.
+unsafe impl<'a, T: Sync> Send for CsXArray<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for CsXArray<'a, T> {}
+
+impl<'a, T> CsXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Create an empty array, usable to initialize a `static`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            xa: Mutex::new(RefCell::new(RawXArray::new())),
+        }
+    }
+
+    #[inline]
+    fn with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut RawXArray<'a, T>) -> R,
+    {
+        critical_section::with(|cs| f(&mut self.xa.borrow_ref_mut(cs)))
+    }
+
+    /// Run `f` on the array inside a critical section, for lookups beyond
+    /// the single-entry ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` calls back into this array.
+    #[inline]
+    pub fn read<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&RawXArray<'a, T>) -> R,
+    {
+        self.with(|xa| f(xa))
+    }
+
+    /// Get value at the index.
+    #[inline]
+    pub fn get(&self, index: u64) -> Option<&'a T> {
+        self.with(|xa| xa.get(index))
+    }
+
+    /// Insert value into the index. See [`RawXArray::insert`].
+    #[inline]
+    pub fn insert(&self, index: u64, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        self.with(|xa| xa.insert(index, value))
+    }
+
+    /// Remove value at the index, returning the value at the index.
+    #[inline]
+    pub fn remove(&self, index: u64) -> Option<&'a T> {
+        self.with(|xa| xa.remove(index))
+    }
+
+    /// Set `mark` on the entry at the index, if present.
+    #[inline]
+    pub fn set_mark(&self, index: u64, mark: XaMark) {
+        self.with(|xa| xa.cursor_mut(index).mark(mark))
+    }
+
+    /// Clear `mark` on the entry at the index.
+    #[inline]
+    pub fn clear_mark(&self, index: u64, mark: XaMark) {
+        self.with(|xa| xa.cursor_mut(index).unmark(mark))
+    }
+
+    /// Take the array out of the critical section.
+    #[inline]
+    pub fn into_inner(self) -> RawXArray<'a, T> {
+        self.xa.into_inner().into_inner()
+    }
+}
+
+impl<'a, T> Default for CsXArray<'a, T>
+where
+    T: 'a,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,119 @@
+use crate::RawXArray;
+
+/// Double-ended queue laid over a [`RawXArray`].
+///
+/// Elements occupy the indices `head..tail` of the underlying array, so a
+/// queue that is only pushed at the back and popped at the front stays a
+/// dense prefix walking up the index space. Pushing at the front wraps
+/// around below index 0. Random access costs a single lookup.
+pub struct XArrayDeque<'a, T> {
+    xa: RawXArray<'a, T>,
+    head: u64,
+    tail: u64,
+}
+
+impl<'a, T> XArrayDeque<'a, T>
+where
+    T: 'a,
+{
+    /// Create an empty deque.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            xa: RawXArray::new(),
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    /// Number of elements in the deque.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.tail.wrapping_sub(self.head)
+    }
+
+    /// Returns `true` if the deque holds no element.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    /// Index of the underlying array the `i`-th element is stored at.
+    #[inline]
+    pub fn index_of(&self, i: u64) -> Option<u64> {
+        (i < self.len()).then(|| self.head.wrapping_add(i))
+    }
+
+    /// Get the `i`-th element counting from the front.
+    #[inline]
+    pub fn get(&self, i: u64) -> Option<&'a T> {
+        self.xa.get(self.index_of(i)?)
+    }
+
+    /// Get the element at the front.
+    #[inline]
+    pub fn front(&self) -> Option<&'a T> {
+        self.get(0)
+    }
+
+    /// Get the element at the back.
+    #[inline]
+    pub fn back(&self) -> Option<&'a T> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    /// Append an element at the back.
+    pub fn push_back(&mut self, value: &'a T) {
+        if let Err(err) = self.xa.insert(self.tail, value) {
+            panic!("xarray: {}", err);
+        }
+        self.tail = self.tail.wrapping_add(1);
+    }
+
+    /// Prepend an element at the front.
+    pub fn push_front(&mut self, value: &'a T) {
+        self.head = self.head.wrapping_sub(1);
+        if let Err(err) = self.xa.insert(self.head, value) {
+            panic!("xarray: {}", err);
+        }
+    }
+
+    /// Remove the element at the front, returning it.
+    pub fn pop_front(&mut self) -> Option<&'a T> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = self.xa.remove(self.head);
+        self.head = self.head.wrapping_add(1);
+        value
+    }
+
+    /// Remove the element at the back, returning it.
+    pub fn pop_back(&mut self) -> Option<&'a T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.tail = self.tail.wrapping_sub(1);
+        self.xa.remove(self.tail)
+    }
+
+    /// Iterate over the elements from front to back.
+    pub fn iter(&self) -> impl Iterator<Item = &'a T> + '_ {
+        (0..self.len()).filter_map(|i| self.get(i))
+    }
+
+    /// Get the underlying array.
+    #[inline]
+    pub fn as_raw(&self) -> &RawXArray<'a, T> {
+        &self.xa
+    }
+}
+
+impl<'a, T> Default for XArrayDeque<'a, T>
+where
+    T: 'a,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,140 @@
+use crate::node::{Node, NodeOrValue, CHUNK_SIZE};
+use crate::RawXArray;
+use core::fmt::{self, Debug, Write};
+use core::ops::RangeInclusive;
+
+/// Limits of a dump written by [`RawXArray::dump_to`].
+///
+/// The default options dump the whole tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpOptions {
+    /// Deepest level whose nodes have their slots listed, the head node
+    /// being at depth 0.
+    pub max_depth: usize,
+    /// Number of slots listed per node before the rest are summarized.
+    pub max_entries: usize,
+    /// Number of bytes written at most. The dump is cut short, without
+    /// failing, when this runs out.
+    pub max_bytes: usize,
+    /// Only slots covering an index within this range are listed.
+    pub range: RangeInclusive<u64>,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: usize::MAX,
+            max_entries: usize::MAX,
+            max_bytes: usize::MAX,
+            range: 0..=u64::MAX,
+        }
+    }
+}
+
+/// Writer giving up once its byte budget is spent.
+struct Bounded<'w, W> {
+    inner: &'w mut W,
+    left: usize,
+    truncated: bool,
+}
+
+impl<W: Write> Write for Bounded<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if s.len() <= self.left {
+            self.left -= s.len();
+            return self.inner.write_str(s);
+        }
+        let mut end = self.left;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.left = 0;
+        self.truncated = true;
+        self.inner.write_str(&s[..end])?;
+        Err(fmt::Error)
+    }
+}
+
+fn dump_node<T: Debug, W: Write>(
+    w: &mut Bounded<'_, W>,
+    node: &mut Node<T>,
+    depth: usize,
+    opts: &DumpOptions,
+) -> fmt::Result {
+    let (start, end) = (*opts.range.start(), *opts.range.end());
+    let mut listed = 0;
+    let mut skipped = 0;
+    for offset in 0..CHUNK_SIZE as u8 {
+        let first = node.slot_index(offset);
+        let last = first | ((1u64 << node.shift) - 1);
+        let entry = node.entry(offset).as_node_or_value();
+        if entry.is_none() || last < start || first > end {
+            continue;
+        }
+        if listed == opts.max_entries {
+            skipped += 1;
+            continue;
+        }
+        listed += 1;
+        for _ in 0..=depth {
+            w.write_str("  ")?;
+        }
+        match entry {
+            Some(NodeOrValue::Node(child)) => {
+                writeln!(
+                    w,
+                    "#{}: Node [{:#x}..={:#x}] count={},",
+                    offset,
+                    child.index(),
+                    child.index() | child.max_index(),
+                    child.count
+                )?;
+                if depth < opts.max_depth {
+                    dump_node(w, child, depth + 1, opts)?;
+                }
+            }
+            Some(NodeOrValue::Value(v)) => writeln!(w, "#{}: {:?},", offset, v)?,
+            None => (),
+        }
+    }
+    if skipped != 0 {
+        for _ in 0..=depth {
+            w.write_str("  ")?;
+        }
+        writeln!(w, "... {} more,", skipped)?;
+    }
+    Ok(())
+}
+
+impl<'a, T> RawXArray<'a, T>
+where
+    T: Debug + 'a,
+{
+    /// Write a dump of the tree into `w`, bounded by `opts`, for logging
+    /// into fixed-size buffers where the [`Debug`] output would not fit.
+    ///
+    /// Running out of `opts.max_bytes` ends the dump early but is not an
+    /// error; only errors of `w` itself are returned.
+    pub fn dump_to<W: Write>(&self, w: &mut W, opts: &DumpOptions) -> fmt::Result {
+        let mut w = Bounded {
+            inner: w,
+            left: opts.max_bytes,
+            truncated: false,
+        };
+        let result = (|| {
+            writeln!(w, "XArray {{")?;
+            match self.head.as_node_or_value() {
+                Some(NodeOrValue::Node(head)) => dump_node(&mut w, head, 0, opts)?,
+                Some(NodeOrValue::Value(v)) if opts.range.contains(&0) => {
+                    writeln!(w, "  #0: {:?},", v)?
+                }
+                _ => (),
+            }
+            writeln!(w, "}}")
+        })();
+        match result {
+            Err(_) if w.truncated => Ok(()),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,24 @@
+/// Errors reported by the fallible operations of an array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XaError {
+    /// The index lies beyond the maximum index of the array.
+    OutOfBounds,
+    /// Allocating a node failed.
+    NoMem,
+    /// The index is reserved, the range already holds an entry, or no free
+    /// index is left within the requested range.
+    Busy,
+}
+
+/// Error returned by [`crate::RawXArray::alloc`].
+pub type AllocError = XaError;
+
+impl core::fmt::Display for XaError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::OutOfBounds => write!(f, "index out of bounds"),
+            Self::NoMem => write!(f, "out of memory"),
+            Self::Busy => write!(f, "index busy"),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{GlobalBox, NodeAlloc, RawXArray};
+
+/// Read-only handle to an array returned by [`RawXArray::freeze`].
+///
+/// The handle only gives out shared access to the array, so it can be
+/// shared across threads (e.g. behind an `Arc`) without any locking.
+pub struct FrozenXArray<'a, T, A: NodeAlloc = GlobalBox> {
+    xa: RawXArray<'a, T, 3, A>,
+}
+
+// SAFETY: a frozen array is never modified and carries no hooks, so sharing
+// it only shares `&T` between threads. Dropping it on another thread frees
+// its nodes there, which `NodeAlloc: Sync` allows once the allocator itself
+// may be sent.
+unsafe impl<'a, T: Sync, A: NodeAlloc> Sync for FrozenXArray<'a, T, A> {}
+unsafe impl<'a, T: Sync, A: NodeAlloc + Send> Send for FrozenXArray<'a, T, A> {}
+
+impl<'a, T, A: NodeAlloc> FrozenXArray<'a, T, A>
+where
+    T: 'a,
+{
+    /// Turn the handle back into a mutable array.
+    ///
+    /// The hooks detached by [`RawXArray::freeze`] are not restored.
+    #[inline]
+    pub fn thaw(self) -> RawXArray<'a, T, 3, A> {
+        self.xa
+    }
+}
+
+impl<'a, T, A: NodeAlloc> core::ops::Deref for FrozenXArray<'a, T, A> {
+    type Target = RawXArray<'a, T, 3, A>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.xa
+    }
+}
+
+impl<'a, T, A: NodeAlloc> RawXArray<'a, T, 3, A>
+where
+    T: 'a,
+{
+    /// Freeze the array into a read-only handle that is [`Sync`].
+    ///
+    /// The shrinker, removal hook, observer and instrument are detached and
+    /// dropped, as they can neither run on a read-only array nor be shared.
+    pub fn freeze(mut self) -> FrozenXArray<'a, T, A> {
+        self.shrinker = None;
+        self.on_remove = None;
+        self.observer = None;
+        #[cfg(feature = "instrument")]
+        {
+            self.instrument = None;
+        }
+        FrozenXArray { xa: self }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::XArrayBoxed;
+use alloc::boxed::Box;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+
+/// Hash map laid over an [`XArrayBoxed`].
+///
+/// Keys are hashed to a `u64` index and collisions are resolved by linear
+/// probing over the following indices. Each entry lives in its own box, so
+/// references to values stay valid while the map grows. The map never
+/// rehashes: the index space is the table.
+pub struct XArrayHashMap<K: 'static, V: 'static, S> {
+    xa: XArrayBoxed<'static, (K, V)>,
+    len: u64,
+    hasher: S,
+}
+
+impl<K, V, S> XArrayHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Create an empty map hashing its keys with `hasher`.
+    #[inline]
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            xa: XArrayBoxed::new(),
+            len: 0,
+            hasher,
+        }
+    }
+
+    /// Number of entries in the map.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the map holds no entry.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Find the index holding `key`, or the first empty index of its probe
+    /// sequence.
+    fn probe<Q>(&self, key: &Q) -> (u64, bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut index = self.hasher.hash_one(key);
+        while let Some((k, _)) = self.xa.get(index) {
+            if k.borrow() == key {
+                return (index, true);
+            }
+            index = index.wrapping_add(1);
+        }
+        (index, false)
+    }
+
+    /// Get the value of `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.probe(key) {
+            (index, true) => self.xa.get(index).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the map holds `key`.
+    #[inline]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.probe(key).1
+    }
+
+    /// Insert `value` under `key`, returning the value it replaces.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (index, found) = self.probe(&key);
+        let old = if found {
+            self.xa.remove(index)
+        } else {
+            self.len += 1;
+            None
+        };
+        if let Err(err) = self.xa.insert(index, Box::new((key, value))) {
+            panic!("xarray: {}", err);
+        }
+        old.map(|entry| entry.1)
+    }
+
+    /// Remove `key` from the map, returning its value.
+    ///
+    /// Entries further along the probe sequence are shifted back into the
+    /// hole, so lookups never have to skip over removed entries.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (mut hole, true) = self.probe(key) else {
+            return None;
+        };
+        let (_, value) = *self.xa.remove(hole)?;
+        self.len -= 1;
+        let mut index = hole.wrapping_add(1);
+        while let Some((k, _)) = self.xa.get(index) {
+            let home = self.hasher.hash_one(k);
+            if index.wrapping_sub(home) >= index.wrapping_sub(hole) {
+                let entry = self.xa.remove(index).unwrap();
+                if let Err(err) = self.xa.insert(hole, entry) {
+                    panic!("xarray: {}", err);
+                }
+                hole = index;
+            }
+            index = index.wrapping_add(1);
+        }
+        Some(value)
+    }
+
+    /// Iterate over the entries in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.xa.iter().map(|(_, (k, v))| (k, v))
+    }
+}
+
+impl<K, V, S> Default for XArrayHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
@@ -0,0 +1,17 @@
+/// Hooks invoked at internal events of an array with an attached
+/// instrument, for profilers and tracing tools.
+///
+/// The hooks are only compiled in with the `instrument` feature. Every
+/// method has an empty default body and takes `&self`, as they run in the
+/// middle of lookups that only borrow the array; use interior mutability to
+/// record events.
+pub trait Instrument {
+    /// A node at level `shift` was allocated.
+    fn on_alloc(&self, _shift: u8) {}
+
+    /// A node at level `shift` was released.
+    fn on_free(&self, _shift: u8) {}
+
+    /// A walk from the head stepped into a node at level `shift`.
+    fn on_descend(&self, _shift: u8) {}
+}
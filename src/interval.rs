@@ -0,0 +1,122 @@
+use crate::RawXArray;
+use core::ops::Range;
+
+/// Map of disjoint `[start, end)` intervals laid over two [`RawXArray`]s.
+///
+/// Each interval stores its value at two boundary entries: at `start` in
+/// one array and at `end - 1` in the other. The interval covering an index
+/// is found from the nearest start at or below it and the nearest end at or
+/// above that start, so a stabbing query costs a couple of lookups no matter
+/// how wide the intervals are.
+pub struct XArrayIntervalMap<'a, T> {
+    starts: RawXArray<'a, T>,
+    ends: RawXArray<'a, T>,
+    len: u64,
+}
+
+impl<'a, T> XArrayIntervalMap<'a, T>
+where
+    T: 'a,
+{
+    /// Create an empty map.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            starts: RawXArray::new(),
+            ends: RawXArray::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of intervals in the map.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the map holds no interval.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the interval starting at `start`.
+    fn at(&self, start: u64) -> Option<(Range<u64>, &'a T)> {
+        let value = self.starts.get(start)?;
+        let last = match self.ends.get(start) {
+            Some(_) => start,
+            None => self.ends.next_present(start)?,
+        };
+        Some((start..last + 1, value))
+    }
+
+    /// Get the interval covering `index` along with its value.
+    pub fn get(&self, index: u64) -> Option<(Range<u64>, &'a T)> {
+        let start = match self.starts.get(index) {
+            Some(_) => index,
+            None => self.starts.prev_present(index)?,
+        };
+        self.at(start).filter(|(range, _)| range.contains(&index))
+    }
+
+    /// Iterate over the intervals overlapping `range`, in ascending order.
+    pub fn overlapping(&self, range: Range<u64>) -> impl Iterator<Item = (Range<u64>, &'a T)> + '_ {
+        let head = self
+            .get(range.start)
+            .filter(|(found, _)| found.start < range.start && !range.is_empty());
+        let rest = (!range.is_empty())
+            .then(|| self.starts.extract(range.start, range.end - 1))
+            .into_iter()
+            .flatten()
+            .filter_map(move |(start, _)| self.at(start));
+        head.into_iter().chain(rest)
+    }
+
+    /// Insert `value` over `range`.
+    ///
+    /// If `range` overlaps an interval already in the map, nothing is stored
+    /// and the first overlapping interval is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty.
+    pub fn insert(&mut self, range: Range<u64>, value: &'a T) -> Option<(Range<u64>, &'a T)> {
+        assert!(!range.is_empty(), "xarray: empty interval");
+        if let Some(found) = self.overlapping(range.clone()).next() {
+            return Some(found);
+        }
+        if let Err(err) = self.starts.insert(range.start, value) {
+            panic!("xarray: {}", err);
+        }
+        if let Err(err) = self.ends.insert(range.end - 1, value) {
+            panic!("xarray: {}", err);
+        }
+        self.len += 1;
+        None
+    }
+
+    /// Remove the interval covering `index`, returning it.
+    pub fn remove(&mut self, index: u64) -> Option<(Range<u64>, &'a T)> {
+        let (range, value) = self.get(index)?;
+        self.starts.remove(range.start);
+        self.ends.remove(range.end - 1);
+        self.len -= 1;
+        Some((range, value))
+    }
+
+    /// Iterate over the intervals in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (Range<u64>, &'a T)> + '_ {
+        self.starts
+            .iter()
+            .filter_map(move |(start, _)| self.at(start))
+    }
+}
+
+impl<'a, T> Default for XArrayIntervalMap<'a, T>
+where
+    T: 'a,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
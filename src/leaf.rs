@@ -0,0 +1,160 @@
+use crate::node::{Node, RawEntry, CHUNK_MASK, CHUNK_SIZE};
+use crate::state::State;
+use crate::RawXArray;
+use core::marker::PhantomData;
+use core::ops::RangeInclusive;
+
+/// Slot of a leaf node yielded by [`Leaves`].
+///
+/// Slots are borrowed in place from the leaf, so a whole leaf can be
+/// scanned as a plain array without going through the tree per entry.
+#[repr(transparent)]
+pub struct LeafEntry<'a, T> {
+    entry: RawEntry<T>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> LeafEntry<'a, T> {
+    /// Get the value held by the slot.
+    #[inline]
+    pub fn value(&self) -> Option<&'a T> {
+        self.entry.as_value()
+    }
+
+    /// Returns `true` if the slot holds a value.
+    #[inline]
+    pub fn is_value(&self) -> bool {
+        self.entry.is_value()
+    }
+
+    /// Get the user tag bits of the value held by the slot, or [`None`] if
+    /// there is no value.
+    #[inline]
+    pub fn tags(&self) -> Option<u8> {
+        self.entry.is_value().then(|| self.entry.value_tags())
+    }
+
+    /// The raw encoding of the slot.
+    #[inline]
+    pub fn to_bits(&self) -> usize {
+        self.entry.inner
+    }
+}
+
+/// Iterator over the leaf nodes of an array in index order, yielding the
+/// first index of each leaf with its slots.
+///
+/// Created by [`RawXArray::leaves`].
+pub struct Leaves<'a, 'b, T> {
+    xa: &'b RawXArray<'a, T>,
+    xas: State<'b, T>,
+    done: bool,
+}
+
+impl<'a, 'b, T> Iterator for Leaves<'a, 'b, T> {
+    type Item = (u64, &'b [LeafEntry<'a, T>; CHUNK_SIZE]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Self { xa, xas, done } = self;
+        while !*done {
+            xas.get_next(xa, u64::MAX)?;
+            // Multi-order values above the leaves are stepped over.
+            let Some(node) = xas.node.get().filter(|node| node.shift == 0) else {
+                continue;
+            };
+            // Step over the rest of the leaf, so the next walk starts from
+            // the following one.
+            *done = xas.index | CHUNK_MASK as u64 == u64::MAX;
+            xas.index |= CHUNK_MASK as u64;
+            xas.offset = CHUNK_MASK as u8;
+            let slots = &node.slots as *const [RawEntry<T>; CHUNK_SIZE];
+            // SAFETY: `LeafEntry` is a transparent wrapper of `RawEntry`, and
+            // the node lives as long as the array is borrowed.
+            return Some((node.index(), unsafe { &*slots.cast() }));
+        }
+        None
+    }
+}
+
+/// Set the bits of `bitmap` for the indices from `first` to `last`, as
+/// offsets from the start of the bitmap.
+fn set_bits(bitmap: &mut [u64], mut first: u64, last: u64) {
+    while first <= last {
+        let (i, bit) = ((first / 64) as usize, first % 64);
+        let n = core::cmp::min(64 - bit, last - first + 1);
+        bitmap[i] |= u64::MAX >> (64 - n) << bit;
+        first += n;
+    }
+}
+
+fn fill_present<T>(node: &mut Node<T>, start: u64, end: u64, bitmap: &mut [u64]) {
+    for offset in 0..=node.get_offset(node.last_index()) {
+        let first = node.slot_index(offset);
+        let last = first | ((1 << node.shift) - 1);
+        if last < start || first > end {
+            continue;
+        }
+        let mut entry = *node.entry(offset);
+        if let Some(sibling) = entry.as_sibling() {
+            entry = *node.entry(sibling);
+        }
+        if let Some(child) = entry.as_node() {
+            fill_present(child, start, end, bitmap);
+        } else if entry.is_value() {
+            set_bits(
+                bitmap,
+                core::cmp::max(first, start) - start,
+                core::cmp::min(last, end) - start,
+            );
+        }
+    }
+}
+
+impl<'a, T> RawXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Iterate over the leaf nodes of the array, for bulk scans over whole
+    /// leaves without per-entry iterator overhead.
+    ///
+    /// An array holding only index 0 keeps it in the head rather than in a
+    /// leaf, so nothing is yielded for it. Neither are multi-order entries
+    /// of order 6 or more, which live above the leaves; smaller ones show
+    /// up as a value followed by sibling slots holding none.
+    #[inline]
+    pub fn leaves(&self) -> Leaves<'a, '_, T> {
+        self.leaves_from(0)
+    }
+
+    fn leaves_from(&self, index: u64) -> Leaves<'a, '_, T> {
+        Leaves {
+            xa: self,
+            xas: State::new(index),
+            done: false,
+        }
+    }
+
+    /// Fill `bitmap` with one bit per index within `range`, set if the index
+    /// holds a value, including every index covered by a multi-order entry.
+    /// Bit `i` of the bitmap stands for index `range.start() + i`, counting
+    /// from the least significant bit of `bitmap[0]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty or `bitmap` has fewer bits than `range`
+    /// has indices.
+    pub fn present_bitmap(&self, range: RangeInclusive<u64>, bitmap: &mut [u64]) {
+        let (start, end) = range.into_inner();
+        assert!(start <= end, "xarray: empty range");
+        assert!(
+            (end - start) / (u64::BITS as u64) < bitmap.len() as u64,
+            "xarray: bitmap too small"
+        );
+        bitmap.fill(0);
+        match self.head.as_node() {
+            Some(head) => fill_present(head, start, end, bitmap),
+            None if start == 0 && self.head.is_value() => bitmap[0] = 1,
+            None => (),
+        }
+    }
+}
@@ -1,5 +1,8 @@
 #![no_std]
 
+#[cfg(all(feature = "linux", feature = "aux"))]
+compile_error!("the `aux` feature changes the node layout required by `linux`");
+
 #[cfg(test)]
 mod tests;
 
@@ -8,23 +11,110 @@ mod tests;
 extern crate std;
 extern crate alloc;
 
+pub mod cache;
+#[cfg(feature = "critical-section")]
+pub mod critical;
+pub mod deque;
+pub mod dump;
+mod error;
+pub mod frozen;
+pub mod hashmap;
+#[cfg(feature = "instrument")]
+pub mod instrument;
+pub mod interval;
+pub mod leaf;
+pub mod lpm;
 mod node;
+pub mod node_alloc;
+pub mod observer;
+pub mod ordered;
+#[cfg(feature = "paranoid")]
+mod paranoid;
+#[cfg(feature = "poison")]
+mod poison;
+pub mod registry;
+mod reindex;
+pub mod report;
+pub mod shared;
+pub mod shrinker;
+pub mod slab;
+pub mod slot;
 mod state;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod transaction;
+pub mod view;
+pub mod watch;
 pub mod xarray;
 pub mod xarray_raw;
+pub mod zip;
 
-pub use crate::xarray::{OwnedPointer, XArray};
-pub use crate::xarray_raw::{RawXArray, XaMark};
+pub use crate::cache::XArrayCache;
+#[cfg(feature = "critical-section")]
+pub use crate::critical::CsXArray;
+pub use crate::deque::XArrayDeque;
+pub use crate::dump::DumpOptions;
+pub use crate::error::{AllocError, XaError};
+pub use crate::frozen::FrozenXArray;
+pub use crate::hashmap::XArrayHashMap;
+#[cfg(feature = "instrument")]
+pub use crate::instrument::Instrument;
+pub use crate::interval::XArrayIntervalMap;
+pub use crate::leaf::{LeafEntry, Leaves};
+pub use crate::lpm::XArrayLpm;
+pub use crate::node_alloc::{node_layout, GlobalBox, NodeAlloc, NodePool, NodeSlot, NODE_LAYOUT};
+pub use crate::observer::Observer;
+pub use crate::ordered::XArrayOrdered;
+pub use crate::registry::{Handle, XArrayRegistry};
+pub use crate::reindex::Collision;
+pub use crate::report::LevelReport;
+pub use crate::shared::{AsyncMutex, AsyncXArray};
+pub use crate::shrinker::Shrinker;
+pub use crate::slab::XArraySlab;
+pub use crate::slot::Slot;
+#[cfg(feature = "stats")]
+pub use crate::stats::Stats;
+pub use crate::transaction::Transaction;
+pub use crate::view::XArrayView;
+pub use crate::watch::Watches;
+pub use crate::xarray::{Intrusive, OwnedPointer, XArray, VALUE_ALIGN};
+pub use crate::xarray_raw::{
+    Bookmark, MarkIndex, MarkSet, RawXArray, ResumeToken, ShrinkPolicy, StorePolicy, XaLimit,
+    XaMark,
+};
+pub use crate::zip::zip_iter;
 
 use alloc::boxed::Box;
+use core::pin::Pin;
 
 impl<T> OwnedPointer<T> for Box<T> {
     fn from_raw(t: *mut T) -> Self {
         unsafe { Box::from_raw(t) }
     }
-    fn into_raw(self) -> &'static T {
+    fn into_raw<'a>(self) -> &'a T
+    where
+        T: 'a,
+    {
         Box::leak(self)
     }
 }
 
-pub type XArrayBoxed<T> = XArray<T, Box<T>>;
+/// Values are never moved while in the array, as they are only reached
+/// through the leaked pointer, so pinning holds.
+impl<T> OwnedPointer<T> for Pin<Box<T>> {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn from_raw(t: *mut T) -> Self {
+        unsafe { Pin::new_unchecked(Box::from_raw(t)) }
+    }
+    fn into_raw<'a>(self) -> &'a T
+    where
+        T: 'a,
+    {
+        Box::leak(unsafe { Pin::into_inner_unchecked(self) })
+    }
+}
+
+pub type XArrayBoxed<'a, T> = XArray<'a, T, Box<T>>;
+
+/// XArray holding pinned values, for self-referential types.
+pub type XArrayPinned<'a, T> = XArray<'a, T, Pin<Box<T>>>;
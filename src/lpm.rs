@@ -0,0 +1,93 @@
+use crate::{RawXArray, XaError};
+
+/// Longest-prefix-match table laid over one [`RawXArray`] per prefix
+/// length.
+///
+/// A prefix of length `len` is the top `len` bits of a 64-bit key, and is
+/// stored at the index holding those bits in the array for `len`. Lookups
+/// walk the lengths in use from the most specific one down, so a routing
+/// table with a handful of distinct lengths costs a handful of lookups.
+pub struct XArrayLpm<'a, T> {
+    tables: [RawXArray<'a, T>; 65],
+    lens: u128,
+}
+
+/// Index of the prefix of length `len` of `key`.
+#[inline]
+fn prefix_index(key: u64, len: u8) -> u64 {
+    key.checked_shr(64 - len as u32).unwrap_or(0)
+}
+
+impl<'a, T> XArrayLpm<'a, T>
+where
+    T: 'a,
+{
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self {
+            tables: core::array::from_fn(|_| RawXArray::new()),
+            lens: 0,
+        }
+    }
+
+    /// Returns `true` if the table holds no prefix.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lens == 0
+    }
+
+    /// Insert `value` for the prefix made of the top `len` bits of `key`.
+    ///
+    /// If the table already contains the prefix, the stored value is
+    /// returned and nothing is inserted. Fails with [`XaError::NoMem`] if a
+    /// node cannot be allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is larger than 64.
+    pub fn insert(&mut self, key: u64, len: u8, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        assert!(len <= 64, "xarray: prefix length out of bounds");
+        let old = self.tables[len as usize].insert(prefix_index(key, len), value)?;
+        self.lens |= 1 << len;
+        Ok(old)
+    }
+
+    /// Get the value of the prefix made of the top `len` bits of `key`.
+    pub fn get(&self, key: u64, len: u8) -> Option<&'a T> {
+        self.tables.get(len as usize)?.get(prefix_index(key, len))
+    }
+
+    /// Remove the prefix made of the top `len` bits of `key`, returning its
+    /// value.
+    pub fn remove(&mut self, key: u64, len: u8) -> Option<&'a T> {
+        let table = self.tables.get_mut(len as usize)?;
+        let value = table.remove(prefix_index(key, len));
+        if table.is_empty() {
+            self.lens &= !(1 << len);
+        }
+        value
+    }
+
+    /// Find the longest stored prefix of `key` that is at most
+    /// `max_prefix_len` bits long, returning its length and value.
+    pub fn lookup_lpm(&self, key: u64, max_prefix_len: u8) -> Option<(u8, &'a T)> {
+        let mut lens = self.lens & (u128::MAX >> (127 - max_prefix_len.min(64) as u32));
+        while lens != 0 {
+            let len = (127 - lens.leading_zeros()) as u8;
+            if let Some(value) = self.get(key, len) {
+                return Some((len, value));
+            }
+            lens &= !(1 << len);
+        }
+        None
+    }
+}
+
+impl<'a, T> Default for XArrayLpm<'a, T>
+where
+    T: 'a,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
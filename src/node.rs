@@ -1,10 +1,36 @@
 use crate::state::NodeOrState;
-use crate::XaMark;
+use crate::xarray_raw::MarkFilter;
+use crate::MarkIndex;
 
 pub const CHUNK_SHIFT: usize = 6;
 pub const CHUNK_SIZE: usize = 1 << CHUNK_SHIFT;
 pub const CHUNK_MASK: usize = CHUNK_SIZE - 1;
 
+/// Low bits of an entry that tell its type.
+///
+/// Values are tagged `0b?01` and internal entries `0b?10`. Among internal
+/// entries, nodes are tagged `0b010` and carry an 8-byte aligned pointer,
+/// while siblings and the other special entries are tagged `0b110` and carry
+/// a small number above the tag.
+#[cfg(not(feature = "linux"))]
+const TAG_MASK: usize = 0b111;
+const NODE_TAG: usize = 0b010;
+#[cfg(not(feature = "linux"))]
+const SPECIAL_TAG: usize = 0b110;
+#[cfg(not(feature = "linux"))]
+const SPECIAL_SHIFT: usize = 3;
+
+/// Kernel encoding of entries: values are bare pointers, and internal
+/// entries are tagged `0b10` with a number above the tag. Internal entries
+/// above 4096 are node pointers, smaller ones are siblings and special
+/// entries (`xa_is_node`, `xa_mk_internal`).
+#[cfg(feature = "linux")]
+const SPECIAL_TAG: usize = 0b10;
+#[cfg(feature = "linux")]
+const SPECIAL_SHIFT: usize = 2;
+#[cfg(feature = "linux")]
+const NODE_MIN: usize = 4096;
+
 #[repr(transparent)]
 #[derive(Clone, Copy, Default)]
 pub struct Mark {
@@ -24,37 +50,87 @@ impl Mark {
         self.inner[p] &= !(1 << ofs);
     }
 
+    #[inline]
+    pub fn get(&self, idx: usize) -> bool {
+        let (p, ofs) = (idx / usize::BITS as usize, idx % usize::BITS as usize);
+        self.inner[p] & (1 << ofs) != 0
+    }
+
     pub fn any(&mut self) -> bool {
         self.inner.iter().any(|n| *n != 0)
     }
 }
 
-pub struct Node<T> {
+/// Node of the tree.
+///
+/// With the `linux` feature, fields are laid out like the kernel's
+/// `struct xa_node` and every node sits exactly one level below its parent,
+/// so trees built by C code are walked in place. Such nodes carry no first
+/// index or subtree total: [`Node::index`] follows the offsets up to the
+/// head, and [`Node::total`] counts the subtree.
+#[repr(align(8))]
+#[cfg_attr(feature = "linux", repr(C))]
+pub struct Node<T, const MARKS: usize = 3> {
     pub shift: u8,
     pub offset: u8,
     pub count: u8,
     pub nr_value: u8,
-    pub parent: RawEntry<T>,
-    pub slots: [RawEntry<T>; CHUNK_SIZE],
-    pub marks: [Mark; 3],
+    pub parent: Parent<T, MARKS>,
+    /// The owning `struct xarray`, always null as nodes do not point back to
+    /// the array.
+    #[cfg(feature = "linux")]
+    pub array: usize,
+    /// First index covered by this node.
+    ///
+    /// A node may sit several levels below its parent, covering only part
+    /// of the parent's slot, so that chains of single-child nodes are not
+    /// needed to reach a sparse index.
+    #[cfg(not(feature = "linux"))]
+    pub index: u64,
+    /// Number of present entries in the subtree rooted at this node.
+    #[cfg(not(feature = "linux"))]
+    pub total: u64,
+    /// The kernel's `private_list`, left for the C side.
+    #[cfg(feature = "linux")]
+    pub private_list: [usize; 2],
+    pub slots: [RawEntry<T, MARKS>; CHUNK_SIZE],
+    pub marks: [Mark; MARKS],
+    /// User byte of every slot holding a value.
+    #[cfg(feature = "aux")]
+    pub aux: [u8; CHUNK_SIZE],
 }
 
-impl<T> Node<T> {
+#[cfg(all(feature = "linux", target_pointer_width = "64"))]
+const _: () = assert!(core::mem::size_of::<Node<()>>() == 576);
+
+impl<T, const MARKS: usize> Node<T, MARKS> {
+    /// Kernel nodes take their `index` from where they are linked.
     #[inline]
-    pub fn new(shift: u8, parent: &mut NodeOrState<T>) -> Option<Self> {
+    #[cfg_attr(feature = "linux", allow(unused_variables))]
+    pub fn new(shift: u8, index: u64, parent: &mut NodeOrState<T, MARKS>) -> Option<Self> {
         if parent.is_empty() {
-            Some(RawEntry::EMPTY)
+            Some(Parent::EMPTY)
         } else {
-            parent.get().map(|n| RawEntry::node(n))
+            parent.get().map(|n| Parent::node(n))
         }
         .map(|parent| Self {
+            #[cfg(not(feature = "linux"))]
+            index,
             shift,
             offset: 0,
             count: 0,
             nr_value: 0,
+            #[cfg(not(feature = "linux"))]
+            total: 0,
             parent,
+            #[cfg(feature = "linux")]
+            array: 0,
+            #[cfg(feature = "linux")]
+            private_list: [0; 2],
             slots: [RawEntry::EMPTY; CHUNK_SIZE],
-            marks: [Mark::default(); 3],
+            marks: [Mark::default(); MARKS],
+            #[cfg(feature = "aux")]
+            aux: [0; CHUNK_SIZE],
         })
     }
 
@@ -64,34 +140,32 @@ impl<T> Node<T> {
     }
 
     #[inline]
-    pub fn entry(&mut self, index: u8) -> &mut RawEntry<T> {
+    pub fn entry(&mut self, index: u8) -> &mut RawEntry<T, MARKS> {
+        #[cfg(feature = "poison")]
+        crate::poison::check(self);
         &mut self.slots[index as usize]
     }
 
     #[inline]
-    pub fn as_raw(&self) -> RawEntry<T> {
-        RawEntry::new(self as *const _ as usize | 2)
+    pub fn as_raw(&self) -> RawEntry<T, MARKS> {
+        RawEntry::node(self)
     }
 
     #[inline]
-    pub fn mark_mut(&mut self, mark: XaMark) -> &mut Mark {
-        match mark {
-            XaMark::Mark0 => &mut self.marks[0],
-            XaMark::Mark1 => &mut self.marks[1],
-            XaMark::Mark2 => &mut self.marks[2],
-        }
+    pub fn mark_mut(&mut self, mark: impl MarkIndex) -> &mut Mark {
+        #[cfg(feature = "poison")]
+        crate::poison::check(self);
+        &mut self.marks[mark.index()]
     }
 
     #[inline]
-    pub fn mark(&self, mark: XaMark) -> &Mark {
-        match mark {
-            XaMark::Mark0 => &self.marks[0],
-            XaMark::Mark1 => &self.marks[1],
-            XaMark::Mark2 => &self.marks[2],
-        }
+    pub fn mark(&self, mark: impl MarkIndex) -> &Mark {
+        #[cfg(feature = "poison")]
+        crate::poison::check(self);
+        &self.marks[mark.index()]
     }
 
-    pub fn find_mark(&self, start: u8, mark: XaMark) -> u8 {
+    pub fn find_mark(&self, start: u8, mark: impl MarkIndex) -> u8 {
         const USIZE_BITS: u8 = usize::BITS as u8;
         for (i, m) in self
             .mark(mark)
@@ -138,18 +212,459 @@ impl<T> Node<T> {
 
     #[inline]
     pub fn max_index(&self) -> u64 {
-        ((CHUNK_SIZE as u64) << (self.shift as u64)) - 1
+        if self.shift as usize + CHUNK_SHIFT >= u64::BITS as usize {
+            u64::MAX
+        } else {
+            ((CHUNK_SIZE as u64) << (self.shift as u64)) - 1
+        }
+    }
+
+    /// First index covered by this node.
+    #[cfg(not(feature = "linux"))]
+    #[inline]
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// First index covered by this node, following the offsets up to the
+    /// head, which starts at index 0.
+    #[cfg(feature = "linux")]
+    pub fn index(&self) -> u64 {
+        match self.parent.as_node() {
+            Some(parent) => parent.slot_index(self.offset),
+            None => 0,
+        }
+    }
+
+    /// Number of present entries in the subtree rooted at this node.
+    #[cfg(not(feature = "linux"))]
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of present entries in the subtree rooted at this node,
+    /// counted by walking it.
+    #[cfg(feature = "linux")]
+    pub fn total(&self) -> u64 {
+        self.slots.iter().map(RawEntry::total).sum()
+    }
+
+    /// Returns `true` if the subtree holds at least one present entry.
+    #[cfg(not(feature = "linux"))]
+    #[inline]
+    pub fn is_populated(&self) -> bool {
+        self.total != 0
+    }
+
+    /// Returns `true` if the subtree holds at least one present entry.
+    #[cfg(feature = "linux")]
+    pub fn is_populated(&self) -> bool {
+        self.slots.iter().any(RawEntry::is_populated)
+    }
+
+    /// Returns `true` if every index of the subtree is known to be present.
+    #[inline]
+    fn is_full(&self) -> bool {
+        #[cfg(not(feature = "linux"))]
+        return self.total == self.max_index().wrapping_add(1);
+        #[cfg(feature = "linux")]
+        return false;
+    }
+
+    /// Last index covered by this node.
+    #[inline]
+    pub fn last_index(&self) -> u64 {
+        self.index() | self.max_index()
+    }
+
+    /// Whether `index` falls within the range covered by this node.
+    #[inline]
+    pub fn covers(&self, index: u64) -> bool {
+        index & !self.max_index() == self.index()
+    }
+
+    /// First index covered by the slot at `offset`.
+    #[inline]
+    pub fn slot_index(&self, offset: u8) -> u64 {
+        self.index().wrapping_add((offset as u64) << self.shift)
+    }
+
+    /// Move every node of this subtree by `delta` indices.
+    ///
+    /// Kernel nodes take their index from where they are linked, so there
+    /// is nothing to move.
+    #[cfg(feature = "linux")]
+    pub fn rebase(&mut self, _delta: u64) {}
+
+    /// Move every node of this subtree by `delta` indices.
+    #[cfg(not(feature = "linux"))]
+    pub fn rebase(&mut self, delta: u64) {
+        self.index = self.index.wrapping_add(delta);
+        for offset in 0..CHUNK_SIZE as u8 {
+            if let Some(node) = self.entry(offset).as_node() {
+                node.rebase(delta);
+            }
+        }
+    }
+
+    /// Count the nodes of this subtree, including this one.
+    pub fn node_count(&mut self) -> u64 {
+        1 + (0..CHUNK_SIZE as u8)
+            .filter_map(|offset| self.entry(offset).as_node())
+            .map(|node| node.node_count())
+            .sum::<u64>()
+    }
+
+    /// Call `f` with the index and value of every entry in this node's
+    /// subtree.
+    pub fn for_each_value<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(u64, &T) + ?Sized,
+    {
+        for offset in 0..CHUNK_SIZE as u8 {
+            let index = self.slot_index(offset);
+            match self.entry(offset).as_node_or_value() {
+                Some(NodeOrValue::Node(node)) => node.for_each_value(f),
+                Some(NodeOrValue::Value(v)) => f(index, v),
+                None => (),
+            }
+        }
+    }
+
+    /// Find the lowest present index at or after `index` in this node's
+    /// subtree.
+    pub fn find_at_or_after(&mut self, index: u64) -> Option<u64> {
+        if index > self.last_index() {
+            return None;
+        }
+        let index = core::cmp::max(index, self.index());
+        for offset in self.get_offset(index)..CHUNK_SIZE as u8 {
+            let entry = *self.entry(offset);
+            if !entry.has_value() {
+                continue;
+            }
+            let index = core::cmp::max(self.slot_index(offset), index);
+            match entry.as_node() {
+                Some(node) => {
+                    if let Some(found) = node.find_at_or_after(index) {
+                        return Some(found);
+                    }
+                }
+                None => return Some(index),
+            }
+        }
+        None
+    }
+
+    /// Find the highest present index at or before `index` in this node's
+    /// subtree.
+    pub fn find_at_or_before(&mut self, index: u64) -> Option<u64> {
+        if index < self.index() {
+            return None;
+        }
+        let index = core::cmp::min(index, self.last_index());
+        for offset in (0..=self.get_offset(index)).rev() {
+            let entry = *self.entry(offset);
+            if !entry.has_value() {
+                continue;
+            }
+            let first = self.slot_index(offset);
+            let last = core::cmp::min(first + ((1 << self.shift) - 1), index);
+            match entry.as_node() {
+                Some(node) => {
+                    if let Some(found) = node.find_at_or_before(last) {
+                        return Some(found);
+                    }
+                }
+                None => return Some(last),
+            }
+        }
+        None
+    }
+
+    /// Find the highest index at or before `index` in this node's subtree
+    /// whose entry carries `mark`, descending only into marked slots.
+    pub fn find_marked_at_or_before(&mut self, index: u64, mark: impl MarkIndex) -> Option<u64> {
+        if index < self.index() {
+            return None;
+        }
+        let index = core::cmp::min(index, self.last_index());
+        for offset in (0..=self.get_offset(index)).rev() {
+            if !self.mark(mark).get(offset as usize) {
+                continue;
+            }
+            let first = self.slot_index(offset);
+            let last = core::cmp::min(first + ((1 << self.shift) - 1), index);
+            match self.entry(offset).as_node() {
+                Some(node) => {
+                    if let Some(found) = node.find_marked_at_or_before(last, mark) {
+                        return Some(found);
+                    }
+                }
+                None => return Some(last),
+            }
+        }
+        None
+    }
+
+    /// Marks set on the slot at `offset`, as a bitmap indexed by
+    /// [`MarkIndex::index`].
+    pub fn slot_marks(&self, offset: u8) -> usize {
+        (0..MARKS)
+            .filter(|mark| self.mark(*mark).get(offset as usize))
+            .fold(0, |marks, mark| marks | 1 << mark)
+    }
+
+    /// Find the lowest index at or after `index` in this node's subtree
+    /// whose value entry passes `filter`, descending only into the slots
+    /// whose marks may lead to one.
+    pub fn find_filtered_at_or_after(&mut self, index: u64, filter: &MarkFilter) -> Option<u64> {
+        if index > self.last_index() {
+            return None;
+        }
+        let index = core::cmp::max(index, self.index());
+        for offset in self.get_offset(index)..CHUNK_SIZE as u8 {
+            let marks = self.slot_marks(offset);
+            let entry = *self.entry(offset);
+            match entry.as_node() {
+                Some(node) if filter.may_pass(marks) => {
+                    if let Some(found) = node.find_filtered_at_or_after(index, filter) {
+                        return Some(found);
+                    }
+                }
+                // An entry spanning the index was yielded already.
+                None if entry.is_value()
+                    && filter.passes(marks)
+                    && self.slot_index(offset) >= index =>
+                {
+                    return Some(self.slot_index(offset));
+                }
+                _ => (),
+            }
+        }
+        None
+    }
+
+    /// Find the highest index at or before `index` in this node's subtree
+    /// whose value entry passes `filter`. See
+    /// [`Node::find_filtered_at_or_after`].
+    pub fn find_filtered_at_or_before(&mut self, index: u64, filter: &MarkFilter) -> Option<u64> {
+        if index < self.index() {
+            return None;
+        }
+        let index = core::cmp::min(index, self.last_index());
+        for offset in (0..=self.get_offset(index)).rev() {
+            let marks = self.slot_marks(offset);
+            let entry = *self.entry(offset);
+            let first = self.slot_index(offset);
+            let last = core::cmp::min(first + ((1 << self.shift) - 1), index);
+            match entry.as_node() {
+                Some(node) if filter.may_pass(marks) => {
+                    if let Some(found) = node.find_filtered_at_or_before(last, filter) {
+                        return Some(found);
+                    }
+                }
+                None if entry.is_value() && filter.passes(marks) => return Some(last),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    /// Set `mark` on every value entry of this subtree within `start..=end`,
+    /// or clear it unless `set`, fixing the bits of the branches on the way
+    /// back up. `changed` is called with the index of every entry whose mark
+    /// flips.
+    ///
+    /// Returns whether any entry of the subtree carries `mark` afterwards.
+    pub fn mark_range(
+        &mut self,
+        start: u64,
+        end: u64,
+        mark: impl MarkIndex,
+        set: bool,
+        changed: &mut dyn FnMut(u64),
+    ) -> bool {
+        if end >= self.index() && start <= self.last_index() {
+            let mut lo = self.get_offset(core::cmp::max(start, self.index()));
+            let hi = self.get_offset(core::cmp::min(end, self.last_index()));
+            // An entry spanning several slots keeps its marks on the first.
+            if let Some(sib) = self.entry(lo).as_sibling() {
+                lo = sib;
+            }
+            for offset in lo..=hi {
+                let entry = *self.entry(offset);
+                let marked = self.mark(mark).get(offset as usize);
+                if let Some(child) = entry.as_node() {
+                    if !set && !marked {
+                        continue;
+                    }
+                    if child.mark_range(start, end, mark, set, changed) {
+                        self.mark_mut(mark).set(offset as usize);
+                    } else {
+                        self.mark_mut(mark).unset(offset as usize);
+                    }
+                } else if entry.is_value() && marked != set {
+                    if set {
+                        self.mark_mut(mark).set(offset as usize);
+                    } else {
+                        self.mark_mut(mark).unset(offset as usize);
+                    }
+                    changed(self.slot_index(offset));
+                }
+            }
+        }
+        self.mark_mut(mark).any()
+    }
+
+    /// Clear `mark` throughout this subtree, visiting only the slots that
+    /// have it set. `changed` is called with the index of every entry the
+    /// mark is cleared from.
+    pub fn clear_mark_all(&mut self, mark: impl MarkIndex, changed: &mut dyn FnMut(u64)) {
+        let mut offset = self.find_mark(0, mark);
+        while offset < CHUNK_SIZE as u8 {
+            match self.entry(offset).as_node() {
+                Some(node) => node.clear_mark_all(mark, changed),
+                None => changed(self.slot_index(offset)),
+            }
+            offset = self.find_mark(offset + 1, mark);
+        }
+        *self.mark_mut(mark) = Mark::default();
+    }
+
+    /// Find the lowest absent index at or after `index` in this node's
+    /// subtree, skipping the subtrees that are full.
+    pub fn find_free_at_or_after(&mut self, index: u64) -> Option<u64> {
+        if index > self.last_index() || self.is_full() {
+            return None;
+        }
+        if index < self.index() {
+            return Some(index);
+        }
+        // The top level of the index space only has 16 slots.
+        for offset in self.get_offset(index)..=self.get_offset(self.last_index()) {
+            let entry = *self.entry(offset);
+            let index = core::cmp::max(self.slot_index(offset), index);
+            match entry.as_node() {
+                Some(node) if !node.covers(index) => return Some(index),
+                Some(node) => {
+                    if let Some(found) = node.find_free_at_or_after(index) {
+                        return Some(found);
+                    }
+                    let last = self.slot_index(offset) + ((1 << self.shift) - 1);
+                    if node.last_index() < last {
+                        return Some(node.last_index() + 1);
+                    }
+                }
+                None if !entry.has_value() => return Some(index),
+                None => (),
+            }
+        }
+        None
+    }
+
+    /// Count present entries of this node's subtree within `start..=end`.
+    pub fn count_range(&mut self, start: u64, end: u64) -> u64 {
+        if end < self.index() || start > self.last_index() {
+            return 0;
+        }
+        if start <= self.index() && self.last_index() <= end {
+            return self.total();
+        }
+        let lo = self.get_offset(core::cmp::max(start, self.index()));
+        let hi = self.get_offset(core::cmp::min(end, self.last_index()));
+        let mut count = 0;
+        for offset in lo..=hi {
+            match self.entry(offset).as_node_or_value() {
+                Some(NodeOrValue::Node(node)) => count += node.count_range(start, end),
+                _ => count += self.entry(offset).total(),
+            }
+        }
+        count
+    }
+
+    /// Count entries of this node's subtree within `start..=end` that have
+    /// `mark` set, descending only through slots whose bit is set.
+    pub fn count_marked(&mut self, start: u64, end: u64, mark: impl MarkIndex) -> u64 {
+        if end < self.index() || start > self.last_index() {
+            return 0;
+        }
+        let mut lo = self.get_offset(core::cmp::max(start, self.index()));
+        let hi = self.get_offset(core::cmp::min(end, self.last_index()));
+        // An entry spanning several slots keeps its marks on the first.
+        if let Some(sib) = self.entry(lo).as_sibling() {
+            lo = sib;
+        }
+        let mut count = 0;
+        let mut offset = self.find_mark(lo, mark);
+        while offset <= hi {
+            match self.entry(offset).as_node() {
+                Some(node) => count += node.count_marked(start, end, mark),
+                None => count += 1,
+            }
+            offset = self.find_mark(offset + 1, mark);
+        }
+        count
+    }
+}
+
+/// Link from a node up to its parent, kept as a bare pointer that is null
+/// for the head node.
+#[derive(Eq)]
+#[repr(transparent)]
+pub struct Parent<T, const MARKS: usize = 3> {
+    inner: usize,
+    _t: core::marker::PhantomData<T>,
+}
+
+impl<T, const MARKS: usize> PartialEq for Parent<T, MARKS> {
+    fn eq(&self, o: &Self) -> bool {
+        self.inner == o.inner
+    }
+}
+
+impl<T, const MARKS: usize> Clone for Parent<T, MARKS> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, const MARKS: usize> Copy for Parent<T, MARKS> {}
+
+impl<T, const MARKS: usize> Parent<T, MARKS> {
+    pub const EMPTY: Self = Self::new(0);
+
+    pub(crate) const fn new(inner: usize) -> Self {
+        Self {
+            inner,
+            _t: core::marker::PhantomData,
+        }
+    }
+
+    pub fn node(v: &Node<T, MARKS>) -> Self {
+        Self::new(v as *const _ as usize)
+    }
+
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.inner == 0
+    }
+
+    #[inline]
+    pub fn as_node<'a>(&self) -> Option<&'a mut Node<T, MARKS>> {
+        unsafe { (self.inner as *mut Node<T, MARKS>).as_mut() }
     }
 }
 
 #[derive(Eq)]
 #[repr(transparent)]
-pub struct RawEntry<T> {
+pub struct RawEntry<T, const MARKS: usize = 3> {
     pub inner: usize,
     _t: core::marker::PhantomData<T>,
 }
 
-impl<T> core::fmt::Debug for RawEntry<T> {
+impl<T, const MARKS: usize> core::fmt::Debug for RawEntry<T, MARKS> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
         match self.as_node_or_value() {
             Some(NodeOrValue::Node(n)) => write!(f, "Node<{:x}>", n as *const _ as usize),
@@ -159,13 +674,13 @@ impl<T> core::fmt::Debug for RawEntry<T> {
     }
 }
 
-impl<T> PartialEq for RawEntry<T> {
+impl<T, const MARKS: usize> PartialEq for RawEntry<T, MARKS> {
     fn eq(&self, o: &Self) -> bool {
         self.inner == o.inner
     }
 }
 
-impl<T> Clone for RawEntry<T> {
+impl<T, const MARKS: usize> Clone for RawEntry<T, MARKS> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner,
@@ -174,28 +689,58 @@ impl<T> Clone for RawEntry<T> {
     }
 }
 
-impl<T> Copy for RawEntry<T> {}
+impl<T, const MARKS: usize> Copy for RawEntry<T, MARKS> {}
 
-impl<T> RawEntry<T> {
+impl<T, const MARKS: usize> RawEntry<T, MARKS> {
     pub const EMPTY: Self = Self::new(0);
-
-    const fn new(inner: usize) -> Self {
+    /// Entry of an index that is present but holds no value.
+    pub const ZERO: Self = Self::special(257);
+    /// Number of low bits of a value entry left free by the alignment of
+    /// `T`, above the bit tagging the entry as a value.
+    #[cfg(not(feature = "linux"))]
+    pub const VALUE_TAG_BITS: u8 = match core::mem::align_of::<T>() {
+        1 | 2 => 0,
+        4 => 1,
+        _ => 2,
+    };
+    /// Values are bare pointers in the kernel encoding, leaving no bit free.
+    #[cfg(feature = "linux")]
+    pub const VALUE_TAG_BITS: u8 = 0;
+    const VALUE_TAG_MASK: usize = ((1 << Self::VALUE_TAG_BITS) - 1) << 1;
+    /// Bits tagging a value entry.
+    const VALUE_TAG: usize = if cfg!(feature = "linux") { 0 } else { 1 };
+
+    pub(crate) const fn new(inner: usize) -> Self {
         Self {
             inner,
             _t: core::marker::PhantomData,
         }
     }
 
+    const fn special(v: usize) -> Self {
+        Self::new((v << SPECIAL_SHIFT) | SPECIAL_TAG)
+    }
+
     pub fn value(v: &T) -> Self {
-        Self::new(v as *const _ as usize | 1)
+        const {
+            assert!(
+                Self::VALUE_TAG != 0 || core::mem::align_of::<T>() >= 4,
+                "xarray: the kernel encoding needs values aligned to 4 bytes"
+            )
+        };
+        Self::new(v as *const _ as usize | Self::VALUE_TAG)
     }
 
-    pub fn node(v: &Node<T>) -> Self {
-        Self::new(v as *const _ as usize | 2)
+    pub fn node(v: &Node<T, MARKS>) -> Self {
+        Self::new(v as *const _ as usize | NODE_TAG)
+    }
+
+    pub fn from_option(v: Option<&T>) -> Self {
+        v.map_or(Self::ZERO, Self::value)
     }
 
     pub fn sibling(v: u8) -> Self {
-        Self::new((v as usize) << 2)
+        Self::special(v as usize)
     }
 
     #[inline]
@@ -208,14 +753,28 @@ impl<T> RawEntry<T> {
         self.inner & 3 == 2
     }
 
+    #[cfg(not(feature = "linux"))]
     #[inline]
     pub fn is_value(&self) -> bool {
         self.inner & 1 == 1
     }
 
+    #[cfg(feature = "linux")]
+    #[inline]
+    pub fn is_value(&self) -> bool {
+        self.inner & 3 == 0 && self.inner != 0
+    }
+
+    #[cfg(not(feature = "linux"))]
+    #[inline]
+    pub fn is_node(&self) -> bool {
+        self.inner & TAG_MASK == NODE_TAG
+    }
+
+    #[cfg(feature = "linux")]
     #[inline]
     pub fn is_node(&self) -> bool {
-        self.is_internal() && self.inner > 4096
+        self.is_internal() && self.inner > NODE_MIN
     }
 
     #[inline]
@@ -223,26 +782,37 @@ impl<T> RawEntry<T> {
         *self != Self::EMPTY
     }
 
+    #[inline]
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    #[cfg(not(feature = "linux"))]
     #[inline]
     pub fn is_sibling(&self) -> bool {
-        self.is_internal() && self.inner < (((CHUNK_SIZE - 1) << 2) | 2)
+        self.inner & TAG_MASK == SPECIAL_TAG && self.inner >> SPECIAL_SHIFT < CHUNK_SIZE
     }
 
+    #[cfg(feature = "linux")]
     #[inline]
-    pub fn max_index(&mut self) -> u64 {
-        if self.is_node() {
-            let sz = CHUNK_SIZE << self.as_node().unwrap().shift as u64;
-            let (r, _) = sz.overflowing_sub(1);
-            r as u64
-        } else {
-            0
+    pub fn is_sibling(&self) -> bool {
+        self.is_internal() && self.inner >> SPECIAL_SHIFT < CHUNK_SIZE
+    }
+
+    /// Number of present entries reachable through this entry.
+    #[inline]
+    pub fn total(&self) -> u64 {
+        match self.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.total(),
+            Some(NodeOrValue::Value(_)) => 1,
+            None => self.is_zero() as u64,
         }
     }
 
     #[inline]
-    pub fn as_node<'a, 'b>(&'b self) -> Option<&'a mut Node<T>> {
+    pub fn as_node<'a, 'b>(&'b self) -> Option<&'a mut Node<T, MARKS>> {
         if self.is_node() {
-            unsafe { ((self.inner - 2) as *mut Node<T>).as_mut() }
+            unsafe { ((self.inner - NODE_TAG) as *mut Node<T, MARKS>).as_mut() }
         } else {
             None
         }
@@ -251,30 +821,89 @@ impl<T> RawEntry<T> {
     #[inline]
     pub fn as_value<'a, 'b>(&'b self) -> Option<&'a T> {
         if self.is_value() {
-            unsafe { ((self.inner - 1) as *const T).as_ref() }
+            unsafe {
+                ((self.inner & !(Self::VALUE_TAG_MASK | Self::VALUE_TAG)) as *const T).as_ref()
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Get a pointer to the value of a value entry, for the owning wrappers
+    /// to hand out mutable access.
+    #[inline]
+    pub fn as_value_ptr(&self) -> Option<core::ptr::NonNull<T>> {
+        if self.is_value() {
+            core::ptr::NonNull::new(
+                (self.inner & !(Self::VALUE_TAG_MASK | Self::VALUE_TAG)) as *mut T,
+            )
         } else {
             None
         }
     }
 
+    /// Get the value of an entry known to be a value.
+    ///
+    /// # Safety
+    ///
+    /// The entry must be a value entry. This is only checked with debug
+    /// assertions enabled.
+    #[inline]
+    pub unsafe fn as_value_unchecked<'a>(&self) -> &'a T {
+        debug_assert!(self.is_value(), "xarray: not a value");
+        &*((self.inner & !(Self::VALUE_TAG_MASK | Self::VALUE_TAG)) as *const T)
+    }
+
+    /// User tag bits of a value entry.
+    #[inline]
+    pub fn value_tags(&self) -> u8 {
+        ((self.inner & Self::VALUE_TAG_MASK) >> 1) as u8
+    }
+
+    /// The same value entry carrying the user tag bits `tags`.
+    #[inline]
+    pub fn with_value_tags(&self, tags: u8) -> Self {
+        Self::new(
+            (self.inner & !Self::VALUE_TAG_MASK) | ((tags as usize) << 1 & Self::VALUE_TAG_MASK),
+        )
+    }
+
+    /// Returns `true` if the entry leads to at least one present index,
+    /// unlike a node whose subtree is empty.
+    #[inline]
+    pub fn is_populated(&self) -> bool {
+        self.has_value() && self.as_node().is_none_or(|node| node.is_populated())
+    }
+
+    /// Presence of the entry, distinguishing a stored [`None`] from an
+    /// absent one.
+    #[inline]
+    pub fn as_option<'a>(&self) -> Option<Option<&'a T>> {
+        if self.is_zero() {
+            Some(None)
+        } else {
+            self.as_value().map(Some)
+        }
+    }
+
     #[inline]
     pub fn as_sibling(&self) -> Option<u8> {
         if self.is_sibling() {
-            Some((self.inner >> 2).try_into().unwrap())
+            Some((self.inner >> SPECIAL_SHIFT).try_into().unwrap())
         } else {
             None
         }
     }
 
     #[inline]
-    pub fn as_node_or_value<'a, 'b, 'c>(&'c self) -> Option<NodeOrValue<'a, 'b, T>> {
+    pub fn as_node_or_value<'a, 'b, 'c>(&'c self) -> Option<NodeOrValue<'a, 'b, T, MARKS>> {
         self.as_node()
             .map(NodeOrValue::Node)
             .or_else(|| self.as_value().map(NodeOrValue::Value))
     }
 }
 
-pub enum NodeOrValue<'a, 'b, T> {
-    Node(&'a mut Node<T>),
+pub enum NodeOrValue<'a, 'b, T, const MARKS: usize = 3> {
+    Node(&'a mut Node<T, MARKS>),
     Value(&'b T),
 }
@@ -0,0 +1,218 @@
+use crate::node::Node;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Size and alignment of the memory every node of an array with the default
+/// three marks takes, whatever the type of the values, for sizing slab
+/// caches and arenas.
+pub const NODE_LAYOUT: Layout = node_layout::<3>();
+
+/// Size and alignment of the nodes of an array keeping `MARKS` marks per
+/// entry. See [`NODE_LAYOUT`].
+pub const fn node_layout<const MARKS: usize>() -> Layout {
+    Layout::new::<Node<(), MARKS>>()
+}
+
+/// Source of the memory the nodes of an array live in, attached with
+/// [`RawXArray::new_in`] and named by the last type parameter of
+/// [`crate::RawXArray`], [`GlobalBox`] by default.
+///
+/// Lets kernel users back nodes with a slab cache or a fixed arena instead
+/// of the global allocator. Allocators are held by value, so references to
+/// one, as in `RawXArray::new_in(&pool)`, allocate from it as well.
+///
+/// Allocators must be [`Sync`]: a [`crate::FrozenXArray`] moved to another
+/// thread gives its nodes back from there, while other arrays may still be
+/// allocating from the same allocator.
+///
+/// # Safety
+///
+/// [`NodeAlloc::alloc_node`] must return memory valid for reads and writes
+/// of the requested layout, not handed out again before it is given back to
+/// [`NodeAlloc::free_node`].
+///
+/// [`RawXArray::new_in`]: crate::RawXArray::new_in
+pub unsafe trait NodeAlloc: Sync {
+    /// Allocate room for a node of `layout`, which is [`NODE_LAYOUT`] unless
+    /// the array keeps another number of marks, or return [`None`] if out of
+    /// memory, which fails the store with [`crate::XaError::NoMem`].
+    fn alloc_node(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Give back room for a node.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`NodeAlloc::alloc_node`] of this
+    /// allocator for the same `layout` and must no longer be in use.
+    unsafe fn free_node(&self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Whether nodes allocated from `self` may be given back to `other`,
+    /// which moving nodes from one array to another needs.
+    ///
+    /// Zero-sized allocators have no state telling them apart and share
+    /// their nodes; others only share them with themselves unless they
+    /// override this.
+    fn shares_nodes_with(&self, other: &Self) -> bool
+    where
+        Self: Sized,
+    {
+        core::mem::size_of::<Self>() == 0 || core::ptr::eq(self, other)
+    }
+}
+
+unsafe impl<A: NodeAlloc + ?Sized> NodeAlloc for &A {
+    #[inline]
+    fn alloc_node(&self, layout: Layout) -> Option<NonNull<u8>> {
+        (**self).alloc_node(layout)
+    }
+
+    #[inline]
+    unsafe fn free_node(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).free_node(ptr, layout)
+    }
+
+    #[inline]
+    fn shares_nodes_with(&self, other: &Self) -> bool {
+        core::ptr::addr_eq(*self, *other)
+    }
+}
+
+/// Nodes taken from the global allocator, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlobalBox;
+
+unsafe impl NodeAlloc for GlobalBox {
+    #[inline]
+    fn alloc_node(&self, layout: Layout) -> Option<NonNull<u8>> {
+        // SAFETY: nodes are not zero-sized.
+        NonNull::new(unsafe { alloc::alloc::alloc(layout) })
+    }
+
+    #[inline]
+    unsafe fn free_node(&self, ptr: NonNull<u8>, layout: Layout) {
+        alloc::alloc::dealloc(ptr.as_ptr(), layout)
+    }
+}
+
+/// Room for one node of [`NODE_LAYOUT`] within the memory handed to a
+/// [`NodePool`].
+#[repr(transparent)]
+pub struct NodeSlot(MaybeUninit<Node<()>>);
+
+impl NodeSlot {
+    /// An unused slot, for initializing arrays of slots.
+    pub const UNINIT: Self = Self(MaybeUninit::uninit());
+}
+
+/// Nodes carved out of a caller-supplied slice of slots, so an array runs
+/// on targets with no heap at all.
+///
+/// Stores fail with [`crate::XaError::NoMem`] once every slot is in use, or
+/// right away for arrays keeping more marks than slots have room for.
+/// Slots given back are kept on a free list threaded through the slots
+/// themselves, behind a spin lock so arrays on several threads may share
+/// the pool. Stores and removals allocate nothing else, but preloading and
+/// attaching hooks still take memory from the global allocator.
+///
+/// ```
+/// use xarray::{NodePool, NodeSlot, RawXArray};
+///
+/// let mut slots = [NodeSlot::UNINIT; 4];
+/// let pool = NodePool::new(&mut slots);
+/// let mut array = RawXArray::new_in(&pool);
+/// array.insert(1 << 20, &1).unwrap();
+/// assert!(pool.available() < 4);
+/// drop(array);
+/// assert_eq!(pool.available(), 4);
+/// ```
+pub struct NodePool<'p> {
+    slots: NonNull<NodeSlot>,
+    len: usize,
+    /// Number of slots at the front of `slots` handed out at least once.
+    used: Cell<usize>,
+    /// Slots given back, linked through their first word.
+    free: Cell<Option<NonNull<NodeSlot>>>,
+    available: Cell<usize>,
+    /// Held while the fields above are read or written.
+    lock: AtomicBool,
+    _slots: PhantomData<&'p mut [NodeSlot]>,
+}
+
+// SAFETY: the cells are only touched with `lock` held, and the slots are
+// handed out to one node at a time.
+unsafe impl Sync for NodePool<'_> {}
+
+impl<'p> NodePool<'p> {
+    /// Create a pool handing out the slots of `slots`.
+    pub fn new(slots: &'p mut [NodeSlot]) -> Self {
+        let len = slots.len();
+        Self {
+            slots: NonNull::from(slots).cast(),
+            len,
+            used: Cell::new(0),
+            free: Cell::new(None),
+            available: Cell::new(len),
+            lock: AtomicBool::new(false),
+            _slots: PhantomData,
+        }
+    }
+
+    /// Number of slots not in use.
+    #[inline]
+    pub fn available(&self) -> usize {
+        self.locked(|| self.available.get())
+    }
+
+    fn locked<R>(&self, f: impl FnOnce() -> R) -> R {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let result = f();
+        self.lock.store(false, Ordering::Release);
+        result
+    }
+}
+
+unsafe impl NodeAlloc for NodePool<'_> {
+    fn alloc_node(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if layout.size() > NODE_LAYOUT.size() || layout.align() > NODE_LAYOUT.align() {
+            return None;
+        }
+        self.locked(|| {
+            let slot = match self.free.get() {
+                Some(slot) => {
+                    // SAFETY: free slots hold the next free slot in their first
+                    // word.
+                    self.free
+                        .set(unsafe { slot.cast::<Option<NonNull<NodeSlot>>>().read() });
+                    slot
+                }
+                None if self.used.get() < self.len => {
+                    self.used.set(self.used.get() + 1);
+                    // SAFETY: the slot lies within the slice.
+                    unsafe { self.slots.add(self.used.get() - 1) }
+                }
+                None => return None,
+            };
+            self.available.set(self.available.get() - 1);
+            Some(slot.cast())
+        })
+    }
+
+    unsafe fn free_node(&self, ptr: NonNull<u8>, _layout: Layout) {
+        self.locked(|| {
+            ptr.cast::<Option<NonNull<NodeSlot>>>()
+                .write(self.free.get());
+            self.free.set(Some(ptr.cast()));
+            self.available.set(self.available.get() + 1);
+        })
+    }
+}
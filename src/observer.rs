@@ -0,0 +1,19 @@
+/// Change notifications delivered by an array with an attached observer.
+///
+/// Every method has an empty default body, so observers only override the
+/// events they care about. Entries torn down along with the array are not
+/// reported.
+pub trait Observer<T> {
+    /// A value was stored at a previously empty `index`.
+    fn on_insert(&mut self, _index: u64, _value: &T) {}
+
+    /// The value at `index` was replaced by `new`.
+    fn on_overwrite(&mut self, _index: u64, _old: &T, _new: &T) {}
+
+    /// The value at `index` was removed.
+    fn on_remove(&mut self, _index: u64, _value: &T) {}
+
+    /// The mark at position `mark`, as given by [`crate::MarkIndex::index`],
+    /// was set (`set == true`) or cleared on the entry at `index`.
+    fn on_mark(&mut self, _index: u64, _mark: usize, _set: bool) {}
+}
@@ -0,0 +1,91 @@
+use crate::XArrayBoxed;
+use alloc::boxed::Box;
+
+/// Array that remembers the order its entries were inserted in.
+///
+/// Next to the entries keyed by index, a secondary array maps an insertion
+/// sequence number to the index it was stored at. Entries can be walked in
+/// index order with [`XArrayOrdered::iter`] or in insertion order with
+/// [`XArrayOrdered::iter_inserted`]. Sequence numbers are never reused, so
+/// removal leaves a hole in the secondary array that iteration skips.
+pub struct XArrayOrdered<T: 'static> {
+    entries: XArrayBoxed<'static, (u64, T)>,
+    order: XArrayBoxed<'static, u64>,
+    next: u64,
+    len: u64,
+}
+
+impl<T> XArrayOrdered<T> {
+    /// Create an empty array.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            entries: XArrayBoxed::new(),
+            order: XArrayBoxed::new(),
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Number of entries in the array.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the array holds no entry.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the value at `index`.
+    #[inline]
+    pub fn get(&self, index: u64) -> Option<&T> {
+        self.entries.get(index).map(|(_, v)| v)
+    }
+
+    /// Insert `value` at `index`, returning the value it replaces.
+    ///
+    /// A replaced entry counts as a fresh insertion and moves to the back
+    /// of the insertion order.
+    pub fn insert(&mut self, index: u64, value: T) -> Option<T> {
+        let old = self.remove(index);
+        let seq = self.next;
+        self.next += 1;
+        if let Err(err) = self.order.insert(seq, Box::new(index)) {
+            panic!("xarray: {}", err);
+        }
+        if let Err(err) = self.entries.insert(index, Box::new((seq, value))) {
+            panic!("xarray: {}", err);
+        }
+        self.len += 1;
+        old
+    }
+
+    /// Remove the value at `index`, returning it.
+    pub fn remove(&mut self, index: u64) -> Option<T> {
+        let (seq, value) = *self.entries.remove(index)?;
+        self.order.remove(seq);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Iterate over the entries in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &T)> + '_ {
+        self.entries.iter().map(|(index, (_, v))| (index, v))
+    }
+
+    /// Iterate over the entries in the order they were inserted.
+    pub fn iter_inserted(&self) -> impl Iterator<Item = (u64, &T)> + '_ {
+        self.order
+            .iter()
+            .map(|(_, index)| (*index, &self.entries.get(*index).unwrap().1))
+    }
+}
+
+impl<T> Default for XArrayOrdered<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,75 @@
+#[cfg(feature = "linux")]
+use crate::node::CHUNK_SHIFT;
+use crate::node::{Node, Parent, CHUNK_SIZE};
+use crate::{NodeAlloc, RawXArray};
+
+/// Verify the bookkeeping of `node` against its slots.
+///
+/// Checks `count`, `nr_value` and `total`, and that every child links back
+/// to `node` at the right offset and level, which is the next one down in
+/// kernel trees.
+pub fn check_node<T, const MARKS: usize>(node: &mut Node<T, MARKS>) {
+    assert_eq!(
+        node.index() & node.max_index(),
+        0,
+        "xarray: misaligned node"
+    );
+    let (mut count, mut values) = (0, 0);
+    #[cfg(not(feature = "linux"))]
+    let mut total = 0;
+    for offset in 0..CHUNK_SIZE as u8 {
+        let entry = *node.entry(offset);
+        count += entry.has_value() as usize;
+        values += (entry.is_value()
+            || entry
+                .as_sibling()
+                .is_some_and(|sib| node.entry(sib).is_value())) as usize;
+        #[cfg(not(feature = "linux"))]
+        {
+            total += entry.total();
+        }
+        if let Some(child) = entry.as_node() {
+            assert!(
+                child.parent == Parent::node(node),
+                "xarray: child #{} of node {:p} has a stale parent",
+                offset,
+                node
+            );
+            assert_eq!(child.offset, offset, "xarray: child offset mismatch");
+            assert!(child.shift < node.shift, "xarray: child shift mismatch");
+            #[cfg(feature = "linux")]
+            assert_eq!(
+                child.shift as usize + CHUNK_SHIFT,
+                node.shift as usize,
+                "xarray: child #{} of node {:p} skips a level",
+                offset,
+                node as *const _
+            );
+            assert!(
+                node.covers(child.index()) && node.get_offset(child.index()) == offset,
+                "xarray: child #{} of node {:p} lies outside of its slot",
+                offset,
+                node
+            );
+        }
+    }
+    assert_eq!(node.count as usize, count, "xarray: node count mismatch");
+    assert_eq!(node.nr_value as usize, values, "xarray: nr_value mismatch");
+    #[cfg(not(feature = "linux"))]
+    assert_eq!(node.total, total, "xarray: subtree total mismatch");
+}
+
+/// Verify every node on the path from the head down to `index`.
+pub fn check_path<T, const MARKS: usize, A: NodeAlloc>(xa: &RawXArray<T, MARKS, A>, index: u64) {
+    let mut entry = xa.head;
+    if let Some(node) = entry.as_node() {
+        assert!(node.parent.is_null(), "xarray: head node has a parent");
+    }
+    while let Some(node) = entry.as_node() {
+        check_node(node);
+        if !node.covers(index) {
+            break;
+        }
+        entry = *node.entry(node.get_offset(index));
+    }
+}
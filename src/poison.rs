@@ -0,0 +1,81 @@
+use crate::node::{Mark, Node, Parent, RawEntry, CHUNK_SIZE};
+use crate::{node_layout, NodeAlloc};
+use core::ptr::NonNull;
+
+/// Number of freed nodes held back before their memory is released.
+pub const QUARANTINE_SIZE: usize = 16;
+
+/// Byte pattern written over freed nodes (SLUB's `POISON_FREE`).
+const POISON: u8 = 0x6b;
+
+const POISON_WORD: usize = usize::from_ne_bytes([POISON; core::mem::size_of::<usize>()]);
+
+/// Fill a freed node with the poison pattern.
+pub fn poison<T, const MARKS: usize>(node: &mut Node<T, MARKS>) {
+    node.shift = POISON;
+    node.offset = POISON;
+    node.count = POISON;
+    node.nr_value = POISON;
+    #[cfg(not(feature = "linux"))]
+    {
+        node.total = POISON_WORD as u64;
+    }
+    node.parent = Parent::new(POISON_WORD);
+    node.slots = [RawEntry::new(POISON_WORD); CHUNK_SIZE];
+    for mark in node.marks.iter_mut() {
+        *mark = Mark {
+            inner: [POISON_WORD; CHUNK_SIZE.div_ceil(usize::BITS as usize)],
+        };
+    }
+}
+
+/// Panic if `node` has been freed.
+#[inline]
+pub fn check<T, const MARKS: usize>(node: &Node<T, MARKS>) {
+    assert!(
+        !(node.shift == POISON && node.count == POISON && node.offset == POISON),
+        "xarray: access to freed node {:p}",
+        node
+    );
+}
+
+/// Ring of freed, poisoned nodes waiting to be released.
+pub struct Quarantine<T, const MARKS: usize> {
+    nodes: [*mut Node<T, MARKS>; QUARANTINE_SIZE],
+    next: usize,
+}
+
+impl<T, const MARKS: usize> Quarantine<T, MARKS> {
+    pub const fn new() -> Self {
+        Self {
+            nodes: [core::ptr::null_mut(); QUARANTINE_SIZE],
+            next: 0,
+        }
+    }
+
+    /// Poison `node` and quarantine it, releasing the oldest quarantined
+    /// node to `node_alloc` if the ring is full.
+    pub fn push(&mut self, node: &mut Node<T, MARKS>, node_alloc: &dyn NodeAlloc) {
+        poison(node);
+        let old = core::mem::replace(&mut self.nodes[self.next], node);
+        self.next = (self.next + 1) % QUARANTINE_SIZE;
+        if let Some(old) = NonNull::new(old) {
+            unsafe { node_alloc.free_node(old.cast(), node_layout::<MARKS>()) };
+        }
+    }
+
+    /// Release every quarantined node to `node_alloc`.
+    pub fn release(&mut self, node_alloc: &dyn NodeAlloc) {
+        for node in self.nodes.iter_mut() {
+            if let Some(old) = NonNull::new(core::mem::replace(node, core::ptr::null_mut())) {
+                unsafe { node_alloc.free_node(old.cast(), node_layout::<MARKS>()) };
+            }
+        }
+    }
+
+    /// Iterate the quarantined nodes.
+    #[cfg(test)]
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<T, MARKS>> {
+        self.nodes.iter().filter_map(|n| unsafe { n.as_ref() })
+    }
+}
@@ -0,0 +1,130 @@
+use crate::XArrayBoxed;
+use alloc::boxed::Box;
+
+/// Handle to an object of an [`XArrayRegistry`], packing the index the
+/// object is stored at with the generation of that index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Handle(u64);
+
+impl Handle {
+    /// Index the object is stored at.
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Generation of the index when the object was registered.
+    #[inline]
+    pub fn generation(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// The handle as a single integer.
+    #[inline]
+    pub fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Rebuild a handle from [`Handle::to_bits`].
+    #[inline]
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+}
+
+/// Object registry handing out generation-checked handles.
+///
+/// Objects are stored at the lowest vacant index like [`crate::XArraySlab`],
+/// but every index carries a generation that is bumped when its object is
+/// removed. A handle records the generation it was issued under, so a
+/// handle outliving its object no longer resolves once the index is reused.
+pub struct XArrayRegistry<T: 'static> {
+    objects: XArrayBoxed<'static, (u32, T)>,
+    /// Generation for the next object of each vacated index.
+    generations: XArrayBoxed<'static, u32>,
+    len: u64,
+}
+
+impl<T> XArrayRegistry<T> {
+    /// Create an empty registry.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            objects: XArrayBoxed::with_limit(u32::MAX as u64),
+            generations: XArrayBoxed::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of objects in the registry.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the registry holds no object.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Register `value`, returning its handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if all `2^32` indices are taken.
+    pub fn insert(&mut self, value: T) -> Handle {
+        let index = self.objects.next_free(0).expect("xarray: registry is full");
+        let generation = self.generations.remove(index).map_or(0, |g| *g);
+        if let Err(err) = self.objects.insert(index, Box::new((generation, value))) {
+            panic!("xarray: {}", err);
+        }
+        self.len += 1;
+        Handle((generation as u64) << 32 | index)
+    }
+
+    /// Get the object of `handle`, or [`None`] if it has been removed.
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        match self.objects.get(handle.index() as u64) {
+            Some((generation, v)) if *generation == handle.generation() => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if `handle` still resolves to its object.
+    #[inline]
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// Remove the object of `handle`, returning it.
+    ///
+    /// Every handle to the object turns stale. A stale handle could only
+    /// resolve again once its index has been reused `2^32` times.
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        self.get(handle)?;
+        let index = handle.index() as u64;
+        let (generation, value) = *self.objects.remove(index).unwrap();
+        if let Err(err) = self
+            .generations
+            .insert(index, Box::new(generation.wrapping_add(1)))
+        {
+            panic!("xarray: {}", err);
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Iterate over the objects with their handles in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> + '_ {
+        self.objects
+            .iter()
+            .map(|(index, (generation, v))| (Handle((*generation as u64) << 32 | index), v))
+    }
+}
+
+impl<T> Default for XArrayRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
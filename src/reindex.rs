@@ -0,0 +1,456 @@
+use crate::node::{NodeOrValue, RawEntry, CHUNK_SHIFT, CHUNK_SIZE};
+use crate::state::State;
+use crate::xarray_raw::CursorMut;
+use crate::{MarkIndex, NodeAlloc, RawXArray, XaError};
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+/// A unit unlinked from the tree: `(base, order, entry, marks, aux)`.
+type Detached<T, const MARKS: usize> = (u64, u8, RawEntry<T, MARKS>, usize, u8);
+
+/// Last index covered by an entry of `order` starting at `base`.
+fn unit_last(base: u64, order: u8) -> u64 {
+    if order >= u64::BITS as u8 {
+        u64::MAX
+    } else {
+        base + ((1 << order) - 1)
+    }
+}
+
+/// Collect the largest entries under `entry` that lie entirely within
+/// `start..=end` and whose order does not exceed `align`.
+///
+/// `entry` covers `1 << order` indices starting at `base`. Each unit is
+/// pushed as `(base, order)`, in index order.
+fn collect_units<T, const MARKS: usize>(
+    entry: RawEntry<T, MARKS>,
+    base: u64,
+    order: u8,
+    start: u64,
+    end: u64,
+    align: u32,
+    units: &mut Vec<(u64, u8)>,
+) {
+    if !entry.has_value() || entry.is_sibling() {
+        return;
+    }
+    let last = unit_last(base, order);
+    if last < start || base > end {
+        return;
+    }
+    if base >= start && last <= end && order as u32 <= align {
+        units.push((base, order));
+        return;
+    }
+    if let Some(NodeOrValue::Node(node)) = entry.as_node_or_value() {
+        for offset in 0..CHUNK_SIZE as u8 {
+            if offset as u64 > u64::MAX >> node.shift {
+                break;
+            }
+            let child = *node.entry(offset);
+            let child_base = node.slot_index(offset);
+            collect_units(child, child_base, node.shift, start, end, align, units);
+        }
+    }
+}
+
+/// What [`RawXArray::append`] does with an index present in both arrays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Collision {
+    /// Fail with [`XaError::Busy`], leaving both arrays untouched.
+    Fail,
+    /// Keep the entry of the destination, leaving the other one behind.
+    Skip,
+}
+
+/// Units of `other` that [`RawXArray::append`] moves into an array.
+#[derive(Default)]
+struct Moves {
+    /// Entries and subtrees moved by relinking them, as `(base, order)`.
+    units: Vec<(u64, u8)>,
+    /// Multi-order entries spanning several slots, moved entry by entry, as
+    /// `(first, last)`.
+    spans: Vec<(u64, u64)>,
+    /// First index of each entry present in both arrays.
+    collisions: Vec<u64>,
+}
+
+/// Where `start..=end` lands when shifted by `delta`, along with the part
+/// of the destination window outside the source.
+pub(crate) fn shift_target(start: u64, end: u64, delta: i64) -> (u64, Option<(u64, u64)>) {
+    let shifted = |index: u64| {
+        index
+            .checked_add_signed(delta)
+            .expect("xarray: shift destination out of bounds")
+    };
+    let (to, last) = (shifted(start), shifted(end));
+    let overwritten = if delta > 0 {
+        (to.max(end + 1), last)
+    } else {
+        (to, last.min(start - 1))
+    };
+    (to, Some(overwritten).filter(|(first, last)| first <= last))
+}
+
+impl<'a, T, const MARKS: usize, A: NodeAlloc> RawXArray<'a, T, MARKS, A>
+where
+    T: 'a,
+{
+    fn units(&self, start: u64, end: u64, align: u32) -> Vec<(u64, u8)> {
+        let (base, order) = match self.head.as_node() {
+            Some(node) => (node.index(), node.shift + CHUNK_SHIFT as u8),
+            None => (0, 0),
+        };
+        let mut units = Vec::new();
+        collect_units(self.head, base, order, start, end, align, &mut units);
+        units
+    }
+
+    /// Returns `true` if an entry covers an index within `first..=last`.
+    fn occupied(&self, first: u64, last: u64) -> bool {
+        self.count_range(first..=last) != 0 || self.entry_span(first).is_some()
+    }
+
+    /// Sort out the entries under `entry` of `other`, covering `1 << order`
+    /// indices from `base`, by how they move into this array.
+    fn collect_moves(&self, entry: RawEntry<T, MARKS>, base: u64, order: u8, moves: &mut Moves) {
+        if !entry.has_value() || entry.is_sibling() {
+            return;
+        }
+        let last = unit_last(base, order);
+        match entry.as_node_or_value() {
+            _ if !self.occupied(base, last) => moves.units.push((base, order)),
+            Some(NodeOrValue::Node(node)) => {
+                for offset in 0..CHUNK_SIZE as u8 {
+                    if offset as u64 > u64::MAX >> node.shift {
+                        break;
+                    }
+                    let child = *node.entry(offset);
+                    let child_base = node.slot_index(offset);
+                    let sibs = (offset + 1..CHUNK_SIZE as u8)
+                        .take_while(|ofs| node.entry(*ofs).as_sibling() == Some(offset))
+                        .count() as u64;
+                    if sibs == 0 || !child.is_value() {
+                        self.collect_moves(child, child_base, node.shift, moves);
+                        continue;
+                    }
+                    let child_last = child_base + (sibs << node.shift | ((1 << node.shift) - 1));
+                    if self.occupied(child_base, child_last) {
+                        moves.collisions.push(child_base);
+                    } else {
+                        moves.spans.push((child_base, child_last));
+                    }
+                }
+            }
+            _ => moves.collisions.push(base),
+        }
+    }
+
+    /// Move every entry of `other` into this array at the same index,
+    /// carrying its marks along.
+    ///
+    /// Subtrees of `other` covering indices this array holds nothing at are
+    /// relinked whole, so only the entries around the boundaries between
+    /// the two arrays are visited. Entries present in both arrays are
+    /// handled as `on_collision` says; skipped ones are left in `other`.
+    ///
+    /// Fails with [`XaError::OutOfBounds`] if `other` holds an entry past
+    /// [`RawXArray::limit`], or with [`XaError::Busy`] on a collision under
+    /// [`Collision::Fail`], leaving both arrays untouched. Fails with
+    /// [`XaError::NoMem`] if a node cannot be allocated, leaving the entries
+    /// not moved yet in `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` allocates its nodes from another
+    /// [`crate::NodeAlloc`].
+    pub fn append(&mut self, other: &mut Self, on_collision: Collision) -> Result<(), XaError> {
+        assert!(
+            self.node_alloc.shares_nodes_with(&other.node_alloc),
+            "xarray: append across node allocators"
+        );
+        if other.max_index().is_some_and(|max| max > self.limit) {
+            return Err(XaError::OutOfBounds);
+        }
+        let (base, order) = match other.head.as_node() {
+            Some(node) => (node.index(), node.shift + CHUNK_SHIFT as u8),
+            None => (0, 0),
+        };
+        let mut moves = Moves::default();
+        self.collect_moves(other.head, base, order, &mut moves);
+        if on_collision == Collision::Fail && !moves.collisions.is_empty() {
+            return Err(XaError::Busy);
+        }
+        let detached = moves
+            .units
+            .into_iter()
+            .map(|(base, order)| {
+                let mut xas = State::new(base);
+                xas.shift = order;
+                xas.load(other);
+                let (entry, marks, aux) = xas.detach(other);
+                other.forget_splits(base, base | Self::order_mask(order));
+                (base, order, entry, marks, aux)
+            })
+            .collect();
+        self.adopt(other, detached, 0);
+        for (first, last) in moves.spans {
+            let (_, _, value) = other.entry_span(first).unwrap();
+            let mut cursor = self.cursor_mut(first);
+            cursor.store_order((last - first + 1).trailing_zeros() as u8, value)?;
+            let (_, marks) = other.remove_with_marks(first).unwrap();
+            marks.indices().for_each(|mark| cursor.mark(mark));
+        }
+        Ok(())
+    }
+
+    /// Remove every entry in `start..=end`, returning the number of removed
+    /// entries, like the kernel's page cache truncation.
+    ///
+    /// Subtrees lying entirely within the range are unlinked and freed as a
+    /// whole, without visiting their entries unless a removal hook or an
+    /// observer is attached. A multi-order entry reaching past either end of
+    /// the range is removed as a whole, as with [`RawXArray::remove`].
+    pub fn remove_range(&mut self, start: u64, end: u64) -> usize {
+        if start > end {
+            return 0;
+        }
+        let len = self.len();
+        for index in [start, end] {
+            if matches!(self.entry_span(index), Some((first, last, _)) if first < start || last > end)
+            {
+                self.remove(index);
+            }
+        }
+        self.erase_range(start, end);
+        (len - self.len()) as usize
+    }
+
+    /// Remove every entry in `start..=end`, releasing whole subtrees at once
+    /// where they fall inside the range.
+    ///
+    /// Every value unlinked is handed to the release hook, once.
+    pub(crate) fn erase_range(&mut self, start: u64, end: u64) {
+        self.forget_splits(start, end);
+        self.walk_units(start, end, u64::BITS, |xa, base, order| {
+            let mut xas = State::new(base);
+            xas.shift = order;
+            if let (Some(value), Some(release)) =
+                (xas.store(xa, RawEntry::EMPTY).as_value(), xa.release)
+            {
+                release(value);
+            }
+            xas.node.is_restart()
+        });
+    }
+
+    /// Run `f` on each unit covering `start..=end` whose order does not
+    /// exceed `align`, in index order.
+    ///
+    /// `f` returns `true` when it left the tree compressed or shrunk, which
+    /// moves the units it has not visited yet, so they are looked up again
+    /// from the live tree before carrying on.
+    fn walk_units<F>(&mut self, start: u64, end: u64, align: u32, mut f: F)
+    where
+        F: FnMut(&mut Self, u64, u8) -> bool,
+    {
+        let mut from = Some(start);
+        while let Some(first) = from.take() {
+            for (base, order) in self.units(first, end, align) {
+                if f(self, base, order) {
+                    from = unit_last(base, order)
+                        .checked_add(1)
+                        .filter(|next| *next <= end);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Unlink the units covering `start..=end` that keep their alignment
+    /// when moved by `delta`, along with their marks.
+    fn detach_range(&mut self, start: u64, end: u64, delta: u64) -> Vec<Detached<T, MARKS>> {
+        self.forget_splits(start, end);
+        let mut detached = Vec::new();
+        self.walk_units(start, end, delta.trailing_zeros(), |xa, base, order| {
+            let mut xas = State::new(base);
+            xas.shift = order;
+            xas.load(xa);
+            let (entry, marks, aux) = xas.detach(xa);
+            detached.push((base, order, entry, marks, aux));
+            xas.node.is_restart()
+        });
+        detached
+    }
+
+    /// Link units unlinked by [`Self::detach_range`] back in, `delta` indices
+    /// away from where they were.
+    fn graft_all(&mut self, detached: Vec<Detached<T, MARKS>>, delta: u64) {
+        for (base, order, entry, marks, aux) in detached {
+            if let Some(node) = entry.as_node() {
+                node.rebase(delta);
+            }
+            let mut xas = State::new(base.wrapping_add(delta));
+            xas.shift = order;
+            xas.graft(self, entry, marks, aux);
+        }
+    }
+
+    /// Move every entry in `start..=end` to the same offset from `to`,
+    /// carrying its marks along.
+    ///
+    /// Subtrees whose alignment survives the move are relinked rather than
+    /// copied entry by entry. Entries already present at the destination and
+    /// not part of the source are overwritten.
+    fn move_range(&mut self, start: u64, end: u64, to: u64) {
+        let delta = to.wrapping_sub(start);
+        if delta == 0 {
+            return;
+        }
+        let detached = self.detach_range(start, end, delta);
+        self.graft_all(detached, delta);
+    }
+
+    /// Move every entry in `range` out of this array and into `dst`, placing
+    /// `range.start()` at `dst_start`. Marks move along with their entries.
+    ///
+    /// Entries already present in the destination window are removed first.
+    /// Whole subtrees are handed over without visiting their entries when
+    /// `dst_start` shares their alignment with `range.start()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the destination window extends past the limit of `dst`, or
+    /// if `dst` allocates its nodes from another [`crate::NodeAlloc`].
+    pub fn splice(&mut self, range: RangeInclusive<u64>, dst: &mut Self, dst_start: u64) {
+        let (start, end) = range.into_inner();
+        if start > end {
+            return;
+        }
+        let dst_end = dst_start
+            .checked_add(end - start)
+            .filter(|last| *last <= dst.limit)
+            .expect("xarray: splice destination out of bounds");
+        assert!(
+            self.node_alloc.shares_nodes_with(&dst.node_alloc),
+            "xarray: splice across node allocators"
+        );
+        dst.erase_range(dst_start, dst_end);
+        let delta = dst_start.wrapping_sub(start);
+        let detached = self.detach_range(start, end, delta);
+        dst.adopt(self, detached, delta);
+    }
+
+    /// Link units unlinked from `src` in, `delta` indices away from where
+    /// they were, taking over their nodes.
+    fn adopt(&mut self, src: &mut Self, detached: Vec<Detached<T, MARKS>>, delta: u64) {
+        let moved = detached
+            .iter()
+            .filter_map(|(_, _, entry, _, _)| entry.as_node())
+            .map(|node| node.node_count())
+            .sum::<u64>();
+        src.nodes -= moved;
+        self.nodes += moved;
+        self.peak_nodes = core::cmp::max(self.peak_nodes, self.nodes);
+        self.graft_all(detached, delta);
+    }
+
+    /// Move every entry carrying `mark` into a new array at the same index,
+    /// carrying its marks along, and return the new array.
+    ///
+    /// The marked entries are found and unlinked in a single walk that only
+    /// visits the subtrees whose mark bitmaps have `mark` set. The new array
+    /// shares the limit and node allocator of this one but none of its hooks.
+    pub fn partition_by_mark(&mut self, mark: impl MarkIndex) -> Self
+    where
+        A: Clone,
+    {
+        let mut marked = Self::with_marks_in(self.limit, self.node_alloc.clone());
+        let mut iter = self.extract_mut(0, u64::MAX).filter_mark(mark);
+        while let Some((index, _)) = iter.next() {
+            let CursorMut { xa, xas } = iter.as_cursor_mut();
+            let (entry, marks, aux) = xas.detach(xa);
+            State::new(index).graft(&mut marked, entry, marks, aux);
+        }
+        marked
+    }
+
+    /// Move every entry at or above `at` into a new array at the same index,
+    /// carrying its marks along, and return the new array.
+    ///
+    /// Subtrees lying entirely above `at` are relinked into the new array
+    /// without visiting their entries. A multi-order entry covering both
+    /// `at - 1` and `at` stays in this array. The new array shares the
+    /// limit and node allocator of this one but none of its hooks.
+    pub fn split_off(&mut self, at: u64) -> Self
+    where
+        A: Clone,
+    {
+        let mut upper = Self::with_marks_in(self.limit, self.node_alloc.clone());
+        if at <= self.limit {
+            self.splice(at..=self.limit, &mut upper, at);
+        }
+        upper
+    }
+
+    /// Move every entry in `range` by `delta` indices, carrying its marks
+    /// along.
+    ///
+    /// Entries in the destination window that are not part of `range` are
+    /// removed. When `delta` is a multiple of a node's span, whole subtrees
+    /// are relinked instead of moving their entries one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the destination window falls outside of the index space or
+    /// past [`RawXArray::limit`].
+    pub fn shift_range(&mut self, range: RangeInclusive<u64>, delta: i64) {
+        let (start, end) = range.into_inner();
+        if start > end || delta == 0 {
+            return;
+        }
+        let (to, overwritten) = shift_target(start, end, delta);
+        assert!(
+            to + (end - start) <= self.limit,
+            "xarray: shift destination out of bounds"
+        );
+        if let Some((first, last)) = overwritten {
+            self.erase_range(first, last);
+        }
+        self.move_range(start, end, to);
+    }
+
+    /// Open a gap of `n` empty indices at `at`, shifting every entry at or
+    /// above `at` up by `n`.
+    ///
+    /// Entries pushed past [`RawXArray::limit`] are removed. Marks move
+    /// along with their entries.
+    pub fn insert_gap(&mut self, at: u64, n: u64) {
+        let limit = self.limit;
+        if n == 0 || at > limit {
+            return;
+        }
+        if n > limit - at {
+            self.erase_range(at, limit);
+            return;
+        }
+        self.erase_range(limit - n + 1, limit);
+        self.move_range(at, limit - n, at + n);
+    }
+
+    /// Remove the `n` indices starting at `at`, shifting every entry above
+    /// them down by `n`.
+    ///
+    /// Entries inside the removed range are dropped from the array. Marks
+    /// move along with their entries.
+    pub fn remove_gap(&mut self, at: u64, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let end = at.saturating_add(n - 1);
+        self.erase_range(at, end);
+        if end != u64::MAX {
+            self.move_range(end + 1, u64::MAX, at);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::node::{Node, CHUNK_SIZE};
+use crate::RawXArray;
+use alloc::vec::Vec;
+
+/// Occupancy of the nodes found at one depth of the tree, returned by
+/// [`RawXArray::level_report`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelReport {
+    /// Number of nodes at this depth.
+    pub nodes: u64,
+    /// Number of nodes by populated slot count, from 0 to 64.
+    pub occupancy: [u64; CHUNK_SIZE + 1],
+}
+
+impl LevelReport {
+    /// Average number of populated slots per node.
+    pub fn average(&self) -> f64 {
+        let slots = self
+            .occupancy
+            .iter()
+            .enumerate()
+            .map(|(count, nodes)| count as u64 * nodes)
+            .sum::<u64>();
+        slots as f64 / self.nodes as f64
+    }
+
+    /// Smallest populated slot count that at least `pct` percent of the
+    /// nodes do not exceed.
+    pub fn percentile(&self, pct: u8) -> u8 {
+        let rank = (self.nodes * pct.min(100) as u64).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (count, nodes) in self.occupancy.iter().enumerate() {
+            seen += nodes;
+            if seen >= rank {
+                return count as u8;
+            }
+        }
+        CHUNK_SIZE as u8
+    }
+}
+
+fn visit<T>(node: &mut Node<T>, depth: usize, levels: &mut Vec<LevelReport>) {
+    if levels.len() == depth {
+        levels.push(LevelReport {
+            nodes: 0,
+            occupancy: [0; CHUNK_SIZE + 1],
+        });
+    }
+    levels[depth].nodes += 1;
+    levels[depth].occupancy[node.count as usize] += 1;
+    for offset in 0..CHUNK_SIZE as u8 {
+        if let Some(child) = node.entry(offset).as_node() {
+            visit(child, depth + 1, levels);
+        }
+    }
+}
+
+impl<'a, T> RawXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Report the slot occupancy of the nodes at each depth of the tree,
+    /// starting from the root.
+    ///
+    /// A dense array fills most slots of its nodes at every depth; a sparse
+    /// one shows many nodes holding only a few slots.
+    pub fn level_report(&self) -> Vec<LevelReport> {
+        let mut levels = Vec::new();
+        if let Some(node) = self.head.as_node() {
+            visit(node, 0, &mut levels);
+        }
+        levels
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{RawXArray, XaError};
+use core::future::Future;
+use core::ops::{DerefMut, RangeInclusive};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Mutex of an async runtime, locked by awaiting.
+///
+/// Implement it for the runtime's own mutex to share an array between
+/// tasks through [`AsyncXArray`].
+pub trait AsyncMutex {
+    /// The protected data.
+    type Target;
+
+    /// Guard releasing the lock when dropped.
+    type Guard<'g>: DerefMut<Target = Self::Target>
+    where
+        Self: 'g;
+
+    /// Wait for the lock.
+    fn lock(&self) -> impl Future<Output = Self::Guard<'_>>;
+}
+
+/// Future yielding to the executor once.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// Array shared between async tasks behind an [`AsyncMutex`].
+///
+/// Single-entry operations hold the lock for one lookup. Operations over
+/// many entries work in batches, releasing the lock and yielding to the
+/// executor between batches, so a long scan neither blocks the executor
+/// nor starves the other tasks waiting for the lock.
+pub struct AsyncXArray<M> {
+    mutex: M,
+    batch: usize,
+}
+
+impl<'a, T, M> AsyncXArray<M>
+where
+    T: 'a,
+    M: AsyncMutex<Target = RawXArray<'a, T>>,
+{
+    /// Share the array behind `mutex`, working in batches of 64 entries.
+    #[inline]
+    pub fn new(mutex: M) -> Self {
+        Self::with_batch(mutex, 64)
+    }
+
+    /// Share the array behind `mutex`, handling up to `batch` entries per
+    /// lock acquisition.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` is zero.
+    #[inline]
+    pub fn with_batch(mutex: M, batch: usize) -> Self {
+        assert!(batch != 0, "xarray: empty batch");
+        Self { mutex, batch }
+    }
+
+    /// The mutex protecting the array.
+    #[inline]
+    pub fn mutex(&self) -> &M {
+        &self.mutex
+    }
+
+    /// Get value at the index.
+    pub async fn get(&self, index: u64) -> Option<&'a T> {
+        self.mutex.lock().await.get(index)
+    }
+
+    /// Insert value into the index. See [`RawXArray::insert`].
+    pub async fn insert(&self, index: u64, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        self.mutex.lock().await.insert(index, value)
+    }
+
+    /// Remove value at the index, returning the value at the index.
+    pub async fn remove(&self, index: u64) -> Option<&'a T> {
+        self.mutex.lock().await.remove(index)
+    }
+
+    /// Insert every `(index, value)` pair of `entries`, returning the number
+    /// of indices that were absent.
+    pub async fn extend<I>(&self, entries: I) -> usize
+    where
+        I: IntoIterator<Item = (u64, &'a T)>,
+    {
+        let mut entries = entries.into_iter().peekable();
+        let mut inserted = 0;
+        loop {
+            {
+                let mut xa = self.mutex.lock().await;
+                for (index, value) in entries.by_ref().take(self.batch) {
+                    inserted += matches!(xa.insert(index, value), Ok(None)) as usize;
+                }
+            }
+            if entries.peek().is_none() {
+                return inserted;
+            }
+            YieldNow(false).await;
+        }
+    }
+
+    /// Call `f` with every entry within `range` in index order, returning
+    /// the number of visited entries.
+    ///
+    /// Entries are read in batches under the lock and handed to `f` after
+    /// it is released. Changes made between batches by other tasks are
+    /// seen by the later batches.
+    pub async fn for_each<F>(&self, range: RangeInclusive<u64>, mut f: F) -> usize
+    where
+        F: FnMut(u64, &'a T),
+    {
+        let (mut start, end) = range.into_inner();
+        let mut buf = alloc::vec::Vec::with_capacity(self.batch);
+        let mut visited = 0;
+        loop {
+            buf.extend(self.mutex.lock().await.extract(start, end).take(self.batch));
+            visited += buf.len();
+            let next = match buf.last() {
+                Some((index, _)) if buf.len() == self.batch => index.checked_add(1),
+                _ => None,
+            };
+            for (index, value) in buf.drain(..) {
+                f(index, value);
+            }
+            match next {
+                Some(next) if next <= end => start = next,
+                _ => return visited,
+            }
+            YieldNow(false).await;
+        }
+    }
+}
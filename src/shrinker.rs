@@ -0,0 +1,20 @@
+/// Memory-pressure callback consulted by [`RawXArray::shrink`].
+///
+/// Mirrors the scan half of the Linux kernel's shrinker interface: the array
+/// walks its entries and the shrinker picks the ones to evict.
+///
+/// [`RawXArray::shrink`]: crate::RawXArray::shrink
+pub trait Shrinker<T> {
+    /// Decide whether the entry stored at `index` should be evicted.
+    fn scan(&mut self, index: u64, value: &T) -> bool;
+}
+
+impl<T, F> Shrinker<T> for F
+where
+    F: FnMut(u64, &T) -> bool,
+{
+    #[inline]
+    fn scan(&mut self, index: u64, value: &T) -> bool {
+        self(index, value)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::XArrayBoxed;
+use alloc::boxed::Box;
+
+/// Object store handing out compact keys, laid over an [`XArrayBoxed`].
+///
+/// Inserting stores the value at the lowest vacant index and returns that
+/// index as its key, so keys freed by removal are reused before the key
+/// space grows. Vacancies are found through the population count of each
+/// node, skipping full subtrees without visiting their slots.
+pub struct XArraySlab<T: 'static> {
+    xa: XArrayBoxed<'static, T>,
+    len: u64,
+}
+
+impl<T> XArraySlab<T> {
+    /// Create an empty slab.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            xa: XArrayBoxed::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of values in the slab.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the slab holds no value.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Key the next inserted value will be stored under.
+    #[inline]
+    pub fn vacant_key(&self) -> u64 {
+        self.xa.next_free(0).expect("xarray: slab is full")
+    }
+
+    /// Store `value` at the lowest vacant key, returning the key.
+    pub fn insert(&mut self, value: T) -> u64 {
+        let key = self.vacant_key();
+        if let Err(err) = self.xa.insert(key, Box::new(value)) {
+            panic!("xarray: {}", err);
+        }
+        self.len += 1;
+        key
+    }
+
+    /// Get the value stored under `key`.
+    #[inline]
+    pub fn get(&self, key: u64) -> Option<&T> {
+        self.xa.get(key)
+    }
+
+    /// Returns `true` if a value is stored under `key`.
+    #[inline]
+    pub fn contains(&self, key: u64) -> bool {
+        self.xa.get(key).is_some()
+    }
+
+    /// Remove the value stored under `key`, freeing the key for reuse.
+    pub fn remove(&mut self, key: u64) -> Option<T> {
+        let value = self.xa.remove(key)?;
+        self.len -= 1;
+        Some(*value)
+    }
+
+    /// Iterate over the stored values in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &T)> + '_ {
+        self.xa.iter()
+    }
+}
+
+impl<T> Default for XArraySlab<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
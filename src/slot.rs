@@ -0,0 +1,129 @@
+use crate::node::RawEntry;
+use crate::state::State;
+use crate::{RawXArray, XaMark};
+
+/// Handle to a single index of a [`RawXArray`], returned by
+/// [`RawXArray::slot`].
+///
+/// The handle caches the node and offset the index lives at, so repeated
+/// reads, replacements and mark flips do not walk down from the head. An
+/// operation that changes the shape of the tree (removing the last entry of
+/// a node, or storing into an index no node covers yet) invalidates the
+/// cache, and the next operation walks the tree again.
+pub struct Slot<'a, 'b, T> {
+    xa: &'b mut RawXArray<'a, T>,
+    xas: State<'b, T>,
+}
+
+impl<'a, T> RawXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Provides a handle for repeated updates at the index.
+    pub fn slot<'b>(&'b mut self, index: u64) -> Slot<'a, 'b, T> {
+        let mut xas = State::new(index);
+        xas.load(self);
+        Slot { xa: self, xas }
+    }
+}
+
+impl<'a, 'b, T> Slot<'a, 'b, T> {
+    /// Index of the slot.
+    #[inline]
+    pub fn index(&self) -> u64 {
+        self.xas.index
+    }
+
+    /// Returns `true` if the cached position is still usable without
+    /// walking the tree.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.xas.node.get().is_some() || (self.xas.node.is_empty() && self.xas.index == 0)
+    }
+
+    /// Walk the tree again to refresh the cached position.
+    pub fn revalidate(&mut self) {
+        let Self { xa, xas } = self;
+        *xas = State::new(xas.index);
+        xas.load(xa);
+    }
+
+    /// Merge the entry split around the slot back into one once its
+    /// pieces are alike again. See [`CursorMut::split`](crate::CursorMut::split).
+    fn merge_splits(&mut self) {
+        let Self { xa, xas } = self;
+        if !xa.splits.is_empty() && xa.merge_splits(xas.index) {
+            *xas = State::new(xas.index);
+        }
+    }
+
+    fn entry(&mut self) -> RawEntry<T> {
+        if !self.is_valid() {
+            self.revalidate();
+        }
+        match self.xas.node.get() {
+            Some(node) => *node.entry(self.xas.offset),
+            None if self.xas.node.is_empty() => self.xa.head,
+            None => RawEntry::EMPTY,
+        }
+    }
+
+    /// Get the value at the slot.
+    #[inline]
+    pub fn get(&mut self) -> Option<&'a T> {
+        self.entry().as_value()
+    }
+
+    /// Store `value` at the slot, returning the value it replaces.
+    ///
+    /// Marks set on the slot are kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index lies beyond [`RawXArray::limit`].
+    pub fn replace(&mut self, value: &'a T) -> Option<&'a T> {
+        assert!(
+            self.xas.index <= self.xa.limit,
+            "xarray: index out of bounds"
+        );
+        let old = self.entry();
+        let Self { xa, xas } = self;
+        xas.store(xa, RawEntry::value(value));
+        self.merge_splits();
+        old.as_value()
+    }
+
+    /// Remove the value at the slot, returning it.
+    pub fn remove(&mut self) -> Option<&'a T> {
+        let old = self.entry();
+        if old.has_value() {
+            let Self { xa, xas } = self;
+            xas.store(xa, RawEntry::EMPTY);
+            self.merge_splits();
+        }
+        old.as_value()
+    }
+
+    /// Inquire whether the value at the slot has a mark set.
+    pub fn is_marked(&mut self, mark: XaMark) -> bool {
+        self.entry().is_value() && self.xas.is_marked(self.xa, mark)
+    }
+
+    /// Set a mark on the value at the slot.
+    pub fn mark(&mut self, mark: XaMark) {
+        if self.entry().is_value() {
+            let Self { xa, xas } = self;
+            xas.set_mark(xa, mark);
+            self.merge_splits();
+        }
+    }
+
+    /// Remove a mark from the value at the slot.
+    pub fn unmark(&mut self, mark: XaMark) {
+        if self.entry().is_value() {
+            let Self { xa, xas } = self;
+            xas.unset_mark(xa, mark);
+            self.merge_splits();
+        }
+    }
+}
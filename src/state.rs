@@ -1,26 +1,31 @@
 use crate::node::*;
+use crate::xarray_raw::MarkFilter;
 use crate::RawXArray;
-use crate::XaMark;
-use alloc::boxed::Box;
+use crate::ShrinkPolicy;
+use crate::{node_layout, MarkIndex, NodeAlloc, XaError};
+use alloc::vec::Vec;
+use core::ptr::NonNull;
 
-pub enum NodeOrState<'a, T>
+pub enum NodeOrState<'a, T, const MARKS: usize = 3>
 where
     T: 'a,
 {
     Empty,
     Bound,
     Restart,
-    Node(&'a mut Node<T>),
+    /// An operation failed, see [`State::take_error`].
+    Error(XaError),
+    Node(&'a mut Node<T, MARKS>),
 }
 
-impl<'a, T> NodeOrState<'a, T>
+impl<'a, T, const MARKS: usize> NodeOrState<'a, T, MARKS>
 where
     T: 'a,
 {
     #[inline]
-    pub(crate) fn get(&self) -> Option<&'a mut Node<T>> {
+    pub(crate) fn get(&self) -> Option<&'a mut Node<T, MARKS>> {
         if let Self::Node(node) = self {
-            unsafe { (*node as *const Node<T> as *mut Node<T>).as_mut() }
+            unsafe { (*node as *const Node<T, MARKS> as *mut Node<T, MARKS>).as_mut() }
         } else {
             None
         }
@@ -37,23 +42,55 @@ where
     pub(crate) fn is_bound(&self) -> bool {
         matches!(self, Self::Bound)
     }
+    #[inline]
+    pub(crate) fn is_error(&self) -> bool {
+        matches!(self, Self::Error(_))
+    }
 }
 
-pub struct State<'a, T>
+pub struct State<'a, T, const MARKS: usize = 3>
 where
     T: 'a,
 {
     pub index: u64,
     pub shift: u8,
+    /// Number of sibling slots following the canonical slot of a
+    /// multi-order entry, set by [`State::set_order`].
     pub sibs: u8,
     pub offset: u8,
-    pub node: NodeOrState<'a, T>,
+    pub node: NodeOrState<'a, T, MARKS>,
 }
 
-impl<'c, T> State<'c, T>
+impl<'c, T, const MARKS: usize> State<'c, T, MARKS>
 where
     T: 'c,
 {
+    /// Point the state at the entry of `order` covering its index, aligning
+    /// the index down to the start of that entry.
+    pub fn set_order(&mut self, order: u8) {
+        // https://elixir.bootlin.com/linux/latest/source/include/linux/xarray.h#L1630
+        self.index = if order < u64::BITS as u8 {
+            self.index >> order << order
+        } else {
+            0
+        };
+        self.shift = order - order % CHUNK_SHIFT as u8;
+        self.sibs = (1 << (order % CHUNK_SHIFT as u8)) - 1;
+        self.node = NodeOrState::Restart;
+    }
+
+    /// Returns the error the last operation failed with, if any, making the
+    /// state walk again from the head on its next use.
+    pub fn take_error(&mut self) -> Result<(), XaError> {
+        match self.node {
+            NodeOrState::Error(err) => {
+                self.node = NodeOrState::Restart;
+                Err(err)
+            }
+            _ => Ok(()),
+        }
+    }
+
     #[inline]
     pub fn new(index: u64) -> Self {
         State {
@@ -65,19 +102,17 @@ where
         }
     }
 
-    pub fn load(&mut self, xa: &RawXArray<T>) -> RawEntry<T> {
+    pub fn load<A: NodeAlloc>(&mut self, xa: &RawXArray<T, MARKS, A>) -> RawEntry<T, MARKS> {
         let mut entry = self
             .node
             .get()
             .map(|node| *node.entry(self.offset))
             .unwrap_or_else(|| match xa.head.as_node_or_value() {
-                Some(NodeOrValue::Node(node))
-                    if self.index >> node.shift as u64 > CHUNK_MASK as u64 =>
-                {
+                Some(NodeOrValue::Node(node)) if !node.covers(self.index) => {
                     self.node = NodeOrState::Bound;
                     RawEntry::EMPTY
                 }
-                Some(NodeOrValue::Value(_)) if self.index != 0 => {
+                None | Some(NodeOrValue::Value(_)) if xa.head.has_value() && self.index != 0 => {
                     self.node = NodeOrState::Bound;
                     RawEntry::EMPTY
                 }
@@ -91,6 +126,15 @@ where
                 entry = node.as_raw();
                 break;
             }
+            if !node.covers(self.index) {
+                // A compressed node elsewhere in the slot.
+                entry = RawEntry::EMPTY;
+                break;
+            }
+            #[cfg(feature = "instrument")]
+            if let Some(instrument) = xa.instrument.as_ref() {
+                instrument.on_descend(node.shift);
+            }
             entry = self.descend(node);
             if self.node.get().unwrap().shift == 0 {
                 break;
@@ -99,7 +143,136 @@ where
         entry
     }
 
-    pub fn set_mark(&mut self, xa: &mut RawXArray<T>, mark: XaMark) {
+    /// Index a forward walk resumes from once the array may have been
+    /// modified, past every index of the entry the state points to, like
+    /// the kernel's `xas_pause`. [`None`] if the walk is over.
+    pub fn pause(&self) -> Option<u64> {
+        match self.node.get() {
+            _ if self.node.is_restart() => Some(self.index),
+            _ if self.node.is_bound() => None,
+            Some(node) => {
+                let mut offset = self.offset + 1;
+                while offset < CHUNK_SIZE as u8 && node.entry(offset).as_sibling().is_some() {
+                    offset += 1;
+                }
+                let first = node.slot_index(self.offset);
+                first.checked_add(((offset - self.offset) as u64) << node.shift)
+            }
+            None => self.index.checked_add(1),
+        }
+    }
+
+    /// Move to `index` and load the entry there, walking down from the
+    /// lowest node of the current path covering `index` rather than from
+    /// the head.
+    pub fn seek<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        index: u64,
+    ) -> RawEntry<T, MARKS> {
+        let mut node = self.node.get();
+        while let Some(n) = node.as_ref().filter(|n| !n.covers(index)) {
+            node = n.parent.as_node();
+        }
+        self.index = index;
+        self.shift = 0;
+        self.sibs = 0;
+        match node {
+            Some(node) => {
+                self.descend(node);
+            }
+            None => self.node = NodeOrState::Restart,
+        }
+        self.load(xa)
+    }
+
+    /// Rewrite the user tag bits of the value at the state in place.
+    pub fn set_value_tags<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>, tags: u8) {
+        let entry = self.load(xa);
+        if !entry.is_value() {
+            return;
+        }
+        match self.node.get() {
+            Some(node) => *node.entry(self.offset) = entry.with_value_tags(tags),
+            None => xa.head = entry.with_value_tags(tags),
+        }
+    }
+
+    pub fn is_marked<A: NodeAlloc>(
+        &self,
+        xa: &RawXArray<T, MARKS, A>,
+        mark: impl MarkIndex,
+    ) -> bool {
+        assert!(mark.index() < MARKS, "xarray: mark out of range");
+        match self.node.get() {
+            Some(node) => node.mark(mark).get(self.offset as usize),
+            None => xa.is_marked(mark),
+        }
+    }
+
+    /// Marks set on the slot the state points to, as a bitmap indexed by
+    /// [`MarkIndex::index`].
+    pub fn marks<A: NodeAlloc>(&self, xa: &RawXArray<T, MARKS, A>) -> usize {
+        match self.node.get() {
+            Some(node) => node.slot_marks(self.offset),
+            None => xa.marks,
+        }
+    }
+
+    /// User byte of the slot the state points to, always 0 without the
+    /// `aux` feature.
+    pub fn aux<A: NodeAlloc>(&self, xa: &RawXArray<T, MARKS, A>) -> u8 {
+        #[cfg(feature = "aux")]
+        match self.node.get() {
+            Some(node) => node.aux[self.offset as usize],
+            None => xa.head_aux,
+        }
+        #[cfg(not(feature = "aux"))]
+        {
+            let _ = xa;
+            0
+        }
+    }
+
+    /// Set the user byte of the slot the state points to. Does nothing
+    /// without the `aux` feature.
+    pub fn set_aux<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>, aux: u8) {
+        #[cfg(feature = "aux")]
+        match self.node.get() {
+            Some(node) => node.aux[self.offset as usize] = aux,
+            None => xa.head_aux = aux,
+        }
+        #[cfg(not(feature = "aux"))]
+        let _ = (xa, aux);
+    }
+
+    pub fn set_mark<A: NodeAlloc>(
+        &mut self,
+        xa: &mut RawXArray<T, MARKS, A>,
+        mark: impl MarkIndex,
+    ) {
+        if !self.is_marked(xa, mark) {
+            if let Some(observer) = xa.observer.as_mut() {
+                observer.on_mark(self.index, mark.index(), true);
+            }
+        }
+        self.mark_path(xa, mark);
+    }
+
+    pub fn unset_mark<A: NodeAlloc>(
+        &mut self,
+        xa: &mut RawXArray<T, MARKS, A>,
+        mark: impl MarkIndex,
+    ) {
+        if self.is_marked(xa, mark) {
+            if let Some(observer) = xa.observer.as_mut() {
+                observer.on_mark(self.index, mark.index(), false);
+            }
+        }
+        self.unmark_path(xa, mark);
+    }
+
+    fn mark_path<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>, mark: impl MarkIndex) {
         let mut node = self.node.get();
         let mut offset = self.offset;
         while let Some(n) = node {
@@ -107,10 +280,10 @@ where
             offset = n.offset;
             node = n.parent.as_node();
         }
-        xa.marks |= 1 << mark as usize;
+        xa.marks |= 1 << mark.index();
     }
 
-    pub fn unset_mark(&mut self, xa: &mut RawXArray<T>, mark: XaMark) {
+    fn unmark_path<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>, mark: impl MarkIndex) {
         let mut node = self.node.get();
         let mut offset = self.offset;
         while let Some(n) = node {
@@ -121,21 +294,105 @@ where
             offset = n.offset;
             node = n.parent.as_node();
         }
-        xa.marks &= !(1 << mark as usize);
+        xa.marks &= !(1 << mark.index());
     }
 
-    pub fn store(&mut self, xa: &mut RawXArray<T>, mut entry: RawEntry<T>) -> RawEntry<T> {
+    /// Unlink the entry the state points to, keeping its subtree intact.
+    ///
+    /// Returns the entry along with the set of marks it carried, as a bitmap
+    /// indexed by [`MarkIndex::index`], and its user byte.
+    pub fn detach<A: NodeAlloc>(
+        &mut self,
+        xa: &mut RawXArray<T, MARKS, A>,
+    ) -> (RawEntry<T, MARKS>, usize, u8) {
+        xa.generation = xa.generation.wrapping_add(1);
+        let aux = self.aux(xa);
+        self.set_aux(xa, 0);
+        let Some(node) = self.node.get() else {
+            let entry = xa.head;
+            let marks = xa.marks;
+            xa.notify_moved(0, entry, false);
+            xa.head = RawEntry::EMPTY;
+            xa.marks = 0;
+            self.node = NodeOrState::Bound;
+            return (entry, marks, aux);
+        };
+        let offset = self.offset;
+        let entry = *node.entry(offset);
+        let mut marks = 0;
+        for mark in 0..MARKS {
+            if node.mark(mark).get(offset as usize) {
+                marks |= 1 << mark;
+                self.unmark_path(xa, mark);
+            }
+        }
+        let index = node.slot_index(offset);
+        xa.notify_moved(index, entry, false);
+        *node.entry(offset) = RawEntry::EMPTY;
+        if let Some(child) = entry.as_node() {
+            child.parent = Parent::EMPTY;
+            child.offset = 0;
+        }
+        #[cfg(not(feature = "linux"))]
+        self.update_total(-(entry.total() as i64));
+        self.update_node(xa, self.node.get(), -1, -(entry.is_value() as i32));
+        #[cfg(feature = "paranoid")]
+        crate::paranoid::check_path(xa, self.index);
+        (entry, marks, aux)
+    }
+
+    /// Link a detached `entry` in at the state's index and order, restoring
+    /// the `marks` and user byte `aux` it carried.
+    pub fn graft<A: NodeAlloc>(
+        &mut self,
+        xa: &mut RawXArray<T, MARKS, A>,
+        entry: RawEntry<T, MARKS>,
+        marks: usize,
+        aux: u8,
+    ) {
+        self.create(xa, !entry.is_node());
+        if let Some(child) = entry.as_node() {
+            child.parent = self
+                .node
+                .get()
+                .map(|p| Parent::node(p))
+                .unwrap_or(Parent::EMPTY);
+            child.offset = self.offset;
+        }
+        self.store(xa, entry);
+        self.set_aux(xa, aux);
+        for mark in 0..MARKS {
+            if marks & (1 << mark) != 0 {
+                self.mark_path(xa, mark);
+            }
+        }
+    }
+
+    pub fn store<A: NodeAlloc>(
+        &mut self,
+        xa: &mut RawXArray<T, MARKS, A>,
+        mut entry: RawEntry<T, MARKS>,
+    ) -> RawEntry<T, MARKS> {
         // https://elixir.bootlin.com/linux/latest/source/lib/xarray.c#L769
         let mut count = 0;
         let mut values = 0;
+        #[cfg(not(feature = "linux"))]
+        let mut total = 0;
         let (mut first, is_value) = if entry.has_value() {
             (self.create(xa, !entry.is_node()), entry.is_value())
         } else {
             (self.load(xa), false)
         };
-        if self.node.is_bound() || self.node.is_restart() {
+        if self.node.is_bound() || self.node.is_restart() || self.node.is_error() {
             return first;
         }
+        if entry.has_value() {
+            let last = match entry.as_node() {
+                Some(node) => node.find_at_or_before(node.last_index()),
+                None => Some(self.index | ((self.sibs as u64 + 1) << self.shift).wrapping_sub(1)),
+            };
+            xa.high_index = core::cmp::max(xa.high_index, last);
+        }
 
         if matches!(self.node.get(), Some(node) if self.shift < node.shift) {
             self.sibs = 0;
@@ -151,14 +408,37 @@ where
         let mut slot_info = if let Some(node) = self.node.get() {
             if self.sibs != 0 {
                 // xas_squash_marks.
-                todo!()
+                let limit = (offset + self.sibs) as usize;
+                for mark in 0..MARKS {
+                    let marks = node.mark_mut(mark);
+                    if (offset as usize + 1..=limit).any(|ofs| marks.get(ofs)) {
+                        marks.set(offset as usize);
+                        (offset as usize + 1..=limit).for_each(|ofs| marks.unset(ofs));
+                    }
+                }
             }
             Some((node, offset))
         } else {
             None
         };
+        if !entry.has_value() {
+            self.set_aux(xa, 0);
+            // xas_init_marks.
+            for mark in 0..MARKS {
+                if self.is_marked(xa, mark) {
+                    self.unmark_path(xa, mark);
+                }
+            }
+        }
 
         loop {
+            if next != entry {
+                let index = match &slot_info {
+                    Some((node, ofs)) => node.slot_index(*ofs),
+                    None => 0,
+                };
+                xa.notify_replace(index, next, entry);
+            }
             if let Some((slot_node, ofs)) = slot_info {
                 *slot_node.entry(ofs) = entry;
                 slot_info = Some((slot_node, ofs + 1));
@@ -167,6 +447,8 @@ where
             }
 
             let next_has_value = next.has_value();
+            #[cfg(not(feature = "linux"))]
+            let next_total = next.total() as i64;
             match (next.as_node(), self.node.get()) {
                 (Some(next), node) if node.as_ref().map(|n| n.shift != 0).unwrap_or(true) => {
                     xa.free_nodes(next);
@@ -178,6 +460,10 @@ where
             }
             count += (!next_has_value as i32) - (!entry.has_value() as i32);
             values += (!first.is_value() as i32) - (!is_value as i32);
+            #[cfg(not(feature = "linux"))]
+            {
+                total += entry.total() as i64 - next_total;
+            }
             if entry.has_value() {
                 if offset == max {
                     break;
@@ -197,136 +483,275 @@ where
                 first = next;
             }
         }
+        #[cfg(not(feature = "linux"))]
+        self.update_total(total);
         self.update_node(xa, self.node.get(), count, values);
+        #[cfg(feature = "paranoid")]
+        crate::paranoid::check_path(xa, self.index);
         first
     }
 
-    fn create(&mut self, xa: &mut RawXArray<T>, allow_root: bool) -> RawEntry<T> {
+    pub fn create<A: NodeAlloc>(
+        &mut self,
+        xa: &mut RawXArray<T, MARKS, A>,
+        allow_root: bool,
+    ) -> RawEntry<T, MARKS> {
         // https://elixir.bootlin.com/linux/latest/source/lib/xarray.c#L635
         let order = self.shift;
-        let (mut slot, mut entry, mut shift) = if let Some(node) = self.node.get() {
-            let offset = self.offset;
+        let head_marks = xa.marks;
+        let (mut slot, mut shift) = if let Some(node) = self.node.get() {
             let shift = node.shift;
-            let entry = *node.entry(offset);
-            let slot = node.entry(offset);
-            (slot, entry, shift)
+            (node.entry(self.offset), shift)
         } else {
             self.node = NodeOrState::Empty;
-            if let Some(mut shift) = self.expand(xa, xa.head) {
-                if shift == 0 && !allow_root {
-                    shift = CHUNK_SHIFT as u8;
+            if order as usize >= u64::BITS as usize
+                || (self.index == 0 && order == 0 && allow_root && !xa.head.is_node())
+            {
+                return xa.head;
+            }
+            if xa.head.has_value() && !xa.head.is_node() {
+                self.lift(xa);
+                if self.node.is_error() {
+                    return xa.head;
                 }
-                let en = xa.head;
-                (&mut xa.head, en, shift)
-            } else {
-                return RawEntry::EMPTY;
             }
+            (&mut xa.head, u8::MAX)
         };
 
-        while shift > order {
-            shift -= CHUNK_SHIFT as u8;
-            let node = match entry.as_node_or_value() {
-                Some(NodeOrValue::Node(en)) => en,
-                Some(NodeOrValue::Value(_)) => break,
-                None => {
-                    if let Some(en) = self.alloc(shift) {
-                        *slot = RawEntry::node(en);
-                        en
+        loop {
+            let entry = *slot;
+            match entry.as_node_or_value() {
+                Some(NodeOrValue::Node(node)) if node.shift >= order && node.covers(self.index) => {
+                    shift = node.shift;
+                    #[cfg(feature = "instrument")]
+                    if let Some(instrument) = xa.instrument.as_ref() {
+                        instrument.on_descend(shift);
+                    }
+                    let entry = self.descend(node);
+                    if shift <= order {
+                        return entry;
+                    }
+                    slot = self.node.get().unwrap().entry(self.offset);
+                }
+                Some(NodeOrValue::Node(_)) if shift <= order => return entry,
+                Some(NodeOrValue::Node(node)) => {
+                    #[cfg(feature = "stats")]
+                    let at_head = self.node.get().is_none();
+                    if let Some(split) =
+                        self.split(&mut xa.preload, &xa.node_alloc, head_marks, node, order)
+                    {
+                        xa.nodes += 1;
+                        xa.peak_nodes = core::cmp::max(xa.peak_nodes, xa.nodes);
+                        #[cfg(feature = "stats")]
+                        {
+                            xa.stats.allocs += 1;
+                            xa.stats.splits += 1;
+                            xa.stats.expands += at_head as u64;
+                        }
+                        #[cfg(feature = "instrument")]
+                        if let Some(instrument) = xa.instrument.as_ref() {
+                            instrument.on_alloc(split.shift);
+                        }
+                        *slot = RawEntry::node(split);
                     } else {
-                        break;
+                        return entry;
                     }
                 }
-            };
-            entry = self.descend(node);
-            slot = self.node.get().unwrap().entry(self.offset);
+                _ if entry.has_value() || shift <= order => return entry,
+                _ => {
+                    #[cfg(not(feature = "linux"))]
+                    let level = order;
+                    // Kernel trees keep a node at every level below the head.
+                    #[cfg(feature = "linux")]
+                    let level = match self.node.get() {
+                        Some(parent) => parent.shift - CHUNK_SHIFT as u8,
+                        None => {
+                            let mut level = 0;
+                            while level as usize + CHUNK_SHIFT < u64::BITS as usize
+                                && self.index >> (level as usize + CHUNK_SHIFT) != 0
+                            {
+                                level += CHUNK_SHIFT as u8;
+                            }
+                            core::cmp::max(level, order)
+                        }
+                    };
+                    let index = self.index & !((CHUNK_SIZE as u64) << level).wrapping_sub(1);
+                    if let Some(node) = self.alloc(&mut xa.preload, &xa.node_alloc, level, index) {
+                        xa.nodes += 1;
+                        xa.peak_nodes = core::cmp::max(xa.peak_nodes, xa.nodes);
+                        #[cfg(feature = "stats")]
+                        {
+                            xa.stats.allocs += 1;
+                        }
+                        #[cfg(feature = "instrument")]
+                        if let Some(instrument) = xa.instrument.as_ref() {
+                            instrument.on_alloc(node.shift);
+                        }
+                        *slot = RawEntry::node(node);
+                        let entry = self.descend(node);
+                        if level <= order {
+                            return entry;
+                        }
+                        shift = level;
+                        slot = self.node.get().unwrap().entry(self.offset);
+                    } else {
+                        return entry;
+                    }
+                }
+            }
         }
-        entry
     }
 
-    fn max(&mut self) -> u64 {
-        let mut max = self.index;
-        let mask = self.size() - 1;
-        if self.shift > 0 || self.sibs > 0 {
-            max |= mask;
-            if mask == max {
-                max += 1;
+    /// Move a value stored at the head into a leaf node.
+    fn lift<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>) {
+        let head = xa.head;
+        if let Some(node) = self.alloc(&mut xa.preload, &xa.node_alloc, 0, 0) {
+            xa.nodes += 1;
+            xa.peak_nodes = core::cmp::max(xa.peak_nodes, xa.nodes);
+            #[cfg(feature = "stats")]
+            {
+                xa.stats.allocs += 1;
+                xa.stats.expands += 1;
             }
+            #[cfg(feature = "instrument")]
+            if let Some(instrument) = xa.instrument.as_ref() {
+                instrument.on_alloc(node.shift);
+            }
+            node.count = 1;
+            #[cfg(not(feature = "linux"))]
+            {
+                node.total = head.total();
+            }
+            node.nr_value = head.is_value() as u8;
+            *node.entry(0) = head;
+            for m in 0..MARKS {
+                if xa.is_marked(m) {
+                    node.mark_mut(m).set(0);
+                }
+            }
+            #[cfg(feature = "aux")]
+            {
+                node.aux[0] = core::mem::take(&mut xa.head_aux);
+            }
+            xa.head = RawEntry::node(node);
         }
-        max
     }
 
-    fn size(&mut self) -> u64 {
-        (self.sibs as u64 + 1) << self.shift as u64
-    }
-
-    fn expand(&mut self, xa: &mut RawXArray<T>, mut head: RawEntry<T>) -> Option<u8> {
-        let max = self.max();
-        let mut shift = 0;
-        let mut node = None;
-
-        match head.as_node_or_value() {
-            Some(NodeOrValue::Node(n)) => {
-                shift = n.shift + CHUNK_SHIFT as u8;
-                node = Some(n);
+    /// Insert a node between `node` and its parent, covering both `node`
+    /// and the state's index at a level of at least `order`.
+    fn split<'b>(
+        &mut self,
+        preload: &mut Vec<NonNull<Node<T, MARKS>>>,
+        node_alloc: &dyn NodeAlloc,
+        head_marks: usize,
+        node: &mut Node<T, MARKS>,
+        order: u8,
+    ) -> Option<&'b mut Node<T, MARKS>> {
+        #[cfg(not(feature = "linux"))]
+        let (shift, index) = {
+            let mut shift = core::cmp::max(order, node.shift + CHUNK_SHIFT as u8);
+            while shift as usize + CHUNK_SHIFT < u64::BITS as usize
+                && (self.index ^ node.index()) >> (shift as usize + CHUNK_SHIFT) != 0
+            {
+                shift += CHUNK_SHIFT as u8;
             }
-            Some(_) => (),
-            None => {
-                if max == 0 {
-                    return Some(0);
-                }
-                while (max >> shift) as usize >= CHUNK_SIZE {
-                    shift += CHUNK_SHIFT as u8;
+            (
+                shift,
+                self.index & !((CHUNK_SIZE as u64) << shift).wrapping_sub(1),
+            )
+        };
+        // Kernel trees grow one level at a time, and only at the head.
+        #[cfg(feature = "linux")]
+        let (shift, index) = {
+            let shift = core::cmp::max(order, node.shift + CHUNK_SHIFT as u8);
+            (
+                shift,
+                node.index() & !((CHUNK_SIZE as u64) << shift).wrapping_sub(1),
+            )
+        };
+        let split = Node::new(shift, index, &mut self.node)?;
+        let split = self.leak(preload, node_alloc, split)?;
+        let offset = split.get_offset(node.index());
+        split.count = 1;
+        #[cfg(not(feature = "linux"))]
+        {
+            split.total = node.total;
+        }
+        *split.entry(offset) = RawEntry::node(node);
+        for m in 0..MARKS {
+            let marked = match self.node.get() {
+                Some(parent) => {
+                    split.offset = self.offset;
+                    parent.mark(m).get(self.offset as usize)
                 }
-                return Some(shift + CHUNK_SHIFT as u8);
+                None => head_marks & (1 << m) != 0,
+            };
+            if marked {
+                split.mark_mut(m).set(offset as usize);
             }
         }
+        node.parent = Parent::node(split);
+        node.offset = offset;
+        Some(split)
+    }
 
-        while max > head.max_index() {
-            node = self.alloc(shift);
-            if let Some(node) = node.as_mut() {
-                node.count = 1;
-                if head.is_value() {
-                    node.nr_value = 1;
-                }
-                *node.entry(0) = head;
-
-                for m in [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2] {
-                    if xa.is_marked(m) {
-                        node.mark_mut(m).set(0);
-                    }
-                }
+    /// Move `node` into an allocation of `node_alloc`, taking a preloaded
+    /// one first, and failing with [`XaError::NoMem`] if the allocator fails.
+    pub(crate) fn leak<'b>(
+        &mut self,
+        preload: &mut Vec<NonNull<Node<T, MARKS>>>,
+        node_alloc: &dyn NodeAlloc,
+        node: Node<T, MARKS>,
+    ) -> Option<&'b mut Node<T, MARKS>> {
+        let node_ptr = || {
+            node_alloc
+                .alloc_node(node_layout::<MARKS>())
+                .map(NonNull::cast)
+        };
+        let Some(mut ptr) = preload.pop().or_else(node_ptr) else {
+            self.node = NodeOrState::Error(XaError::NoMem);
+            return None;
+        };
+        // SAFETY: the allocation fits a node and is owned by nobody else.
+        unsafe {
+            ptr.as_ptr().write(node);
+            Some(ptr.as_mut())
+        }
+    }
 
-                if let Some(head) = head.as_node() {
-                    head.offset = 0;
-                    head.parent = RawEntry::node(node);
-                }
-                head = RawEntry::node(node);
-                xa.head = head;
-                shift += CHUNK_SHIFT as u8;
-            } else {
-                return None;
-            }
+    fn alloc<'b>(
+        &mut self,
+        preload: &mut Vec<NonNull<Node<T, MARKS>>>,
+        node_alloc: &dyn NodeAlloc,
+        shift: u8,
+        index: u64,
+    ) -> Option<&'b mut Node<T, MARKS>> {
+        let node = Node::new(shift, index, &mut self.node)?;
+        let node = self.leak(preload, node_alloc, node)?;
+        if let Some(p) = self.node.get() {
+            node.offset = self.offset;
+            p.count += 1;
         }
-        self.node = NodeOrState::Node(node.unwrap());
-        Some(shift)
+        Some(node)
     }
 
-    fn alloc<'a, 'b>(&'a mut self, shift: u8) -> Option<&'b mut Node<T>> {
-        Node::new(shift, &mut self.node)
-            .map(|b| Box::leak(Box::new(b)))
-            .map(|node| {
-                if let Some(p) = self.node.get() {
-                    node.offset = self.offset;
-                    p.count += 1;
-                }
-                node
-            })
+    /// Add `total` to the subtree totals of every node up from the state's.
+    #[cfg(not(feature = "linux"))]
+    fn update_total(&mut self, total: i64) {
+        if total == 0 {
+            return;
+        }
+        let mut node = self.node.get();
+        while let Some(n) = node {
+            n.total = n.total.wrapping_add(total as u64);
+            node = n.parent.as_node();
+        }
     }
 
-    fn update_node(
+    fn update_node<A: NodeAlloc>(
         &mut self,
-        xa: &mut RawXArray<T>,
-        node: Option<&mut Node<T>>,
+        xa: &mut RawXArray<T, MARKS, A>,
+        node: Option<&mut Node<T, MARKS>>,
         count: i32,
         values: i32,
     ) {
@@ -342,13 +767,26 @@ where
         }
     }
 
-    fn delete_node(&mut self, xa: &mut RawXArray<T>) {
+    fn delete_node<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>) {
+        match xa.shrink_policy {
+            ShrinkPolicy::Immediate => (),
+            ShrinkPolicy::Deferred(n) => {
+                xa.deferred += 1;
+                if xa.deferred >= n {
+                    xa.compact();
+                    self.node = NodeOrState::Restart;
+                }
+                return;
+            }
+            ShrinkPolicy::Manual => return,
+        }
         let mut node = self.node.get().unwrap();
         while node.count == 0 {
-            let boxed_node = unsafe { Box::from_raw(node) };
-            self.offset = boxed_node.offset;
+            let parent = node.parent;
+            self.offset = node.offset;
+            xa.free_node(node);
 
-            if let Some(p) = boxed_node.parent.as_node() {
+            if let Some(p) = parent.as_node() {
                 *p.entry(self.offset) = RawEntry::EMPTY;
                 p.count -= 1;
                 self.node = NodeOrState::Node(p);
@@ -361,39 +799,72 @@ where
         }
         if node.parent.is_null() {
             self.shrink(xa)
+        } else {
+            self.compress(xa)
         }
     }
 
-    fn shrink(&mut self, xa: &mut RawXArray<T>) {
+    /// Splice out a node left with a single child node, linking the child
+    /// directly into the parent's slot.
+    fn compress<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>) {
+        if cfg!(feature = "linux") {
+            return;
+        }
+        let node = self.node.get().unwrap();
+        if node.count != 1 {
+            return;
+        }
+        let Some(child) = (0..CHUNK_SIZE as u8).find_map(|ofs| node.entry(ofs).as_node()) else {
+            return;
+        };
+        let parent = node.parent.as_node().unwrap();
+        child.parent = node.parent;
+        child.offset = node.offset;
+        *parent.entry(node.offset) = RawEntry::node(child);
+        self.node = NodeOrState::Restart;
+        xa.free_node(node);
+    }
+
+    pub fn shrink<A: NodeAlloc>(&mut self, xa: &mut RawXArray<T, MARKS, A>) {
         let mut node = self.node.get().unwrap();
         while node.count == 1 {
-            let raw_entry = *node.entry(0);
-            let entry = match raw_entry.as_node_or_value() {
-                _ if !raw_entry.has_value() => break,
-                Some(NodeOrValue::Node(node)) => {
-                    if node.shift != 0 {
-                        break;
-                    }
-                    Some(node)
-                }
-                _ => None,
+            let Some(offset) = (0..CHUNK_SIZE as u8).find(|ofs| node.entry(*ofs).has_value())
+            else {
+                break;
+            };
+            let raw_entry = *node.entry(offset);
+            let entry = match raw_entry.as_node() {
+                // The kernel expects the head node to start at index 0.
+                Some(_) if cfg!(feature = "linux") && offset != 0 => break,
+                Some(node) => Some(node),
+                None if node.shift == 0 && node.index() == 0 && offset == 0 => None,
+                None => break,
             };
 
-            self.node = NodeOrState::Bound;
+            self.node = NodeOrState::Restart;
             xa.head = raw_entry;
+            #[cfg(feature = "aux")]
+            if entry.is_none() {
+                xa.head_aux = node.aux[offset as usize];
+            }
+            #[cfg(feature = "stats")]
+            {
+                xa.stats.shrinks += 1;
+            }
 
-            unsafe { drop(Box::from_raw(node)) };
+            xa.free_node(node);
 
             if let Some(node_) = entry {
                 node = node_;
-                node.parent = RawEntry::EMPTY;
+                node.parent = Parent::EMPTY;
+                node.offset = 0;
             } else {
                 break;
             }
         }
     }
 
-    fn descend(&mut self, node: &'c mut Node<T>) -> RawEntry<T> {
+    fn descend(&mut self, node: &'c mut Node<T, MARKS>) -> RawEntry<T, MARKS> {
         let mut offset = node.get_offset(self.index);
         let mut entry = *node.entry(offset);
 
@@ -415,15 +886,9 @@ where
             return;
         }
         if let Some(mut node) = self.node.get() {
-            if self.offset != node.get_offset(self.index) {
-                self.offset += 1;
-            }
-
-            while self.offset == CHUNK_SIZE as u8 {
-                self.offset = node.offset + 1;
+            while !node.covers(self.index) {
                 if let Some(n) = node.parent.as_node() {
-                    self.node = NodeOrState::Node(n);
-                    node = self.node.get().unwrap();
+                    node = n;
                 } else {
                     self.node = NodeOrState::Bound;
                     return;
@@ -431,24 +896,92 @@ where
             }
 
             loop {
-                let entry = *self.node.get().unwrap().entry(self.offset);
-                if let Some(node) = entry.as_node() {
-                    self.offset = node.get_offset(self.index);
-                    self.node = NodeOrState::Node(node);
-                } else {
-                    break;
+                self.offset = node.get_offset(self.index);
+                let entry = *node.entry(self.offset);
+                self.node = NodeOrState::Node(node);
+                match entry.as_node() {
+                    Some(n) if n.covers(self.index) => node = n,
+                    _ => break,
                 }
             }
         }
     }
 
     fn move_index(&mut self, offset: u8) {
-        let shift = self.node.get().unwrap().shift;
-        self.index &= (!(CHUNK_MASK as u64)) << shift;
-        self.index = self.index.overflowing_add((offset as u64) << shift).0;
+        self.index = self.node.get().unwrap().slot_index(offset);
+    }
+
+    /// Move to the slot of the parent node holding the current node.
+    pub fn ascend(&mut self) -> bool {
+        let Some(node) = self.node.get() else {
+            return false;
+        };
+        let Some(parent) = node.parent.as_node() else {
+            return false;
+        };
+        self.offset = node.offset;
+        self.node = NodeOrState::Node(parent);
+        true
+    }
+
+    /// Move to the slot at `offset` of the child node held by the current
+    /// slot.
+    pub fn descend_to(&mut self, offset: u8) -> bool {
+        let Some(node) = self.node.get() else {
+            return false;
+        };
+        match node.entry(self.offset).as_node() {
+            Some(child) if (offset as usize) < CHUNK_SIZE => {
+                self.index = child.slot_index(offset);
+                self.offset = offset;
+                self.node = NodeOrState::Node(child);
+                true
+            }
+            _ => false,
+        }
     }
 
-    pub fn find(&mut self, xa: &RawXArray<T>, end: u64) -> Option<RawEntry<T>> {
+    /// Move to the next populated slot of the current node.
+    pub fn next_slot(&mut self) -> bool {
+        let Some(node) = self.node.get() else {
+            return false;
+        };
+        match (self.offset + 1..CHUNK_SIZE as u8).find(|ofs| node.entry(*ofs).has_value()) {
+            Some(offset) => {
+                self.offset = offset;
+                self.move_index(offset);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// After a lookup that missed, step into the compressed node occupying
+    /// the state's slot if it lies ahead of the index.
+    ///
+    /// Returns whether the scan still has to advance past the current slot.
+    fn enter_ahead<A: NodeAlloc>(&mut self, xa: &RawXArray<T, MARKS, A>) -> bool {
+        let node = match self.node.get() {
+            Some(node) => node.entry(self.offset).as_node(),
+            None if self.node.is_bound() => xa.head.as_node(),
+            None => None,
+        };
+        match node {
+            Some(node) if node.index() > self.index => {
+                self.index = node.index();
+                self.offset = 0;
+                self.node = NodeOrState::Node(node);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    pub fn find<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        end: u64,
+    ) -> Option<RawEntry<T, MARKS>> {
         if self.node.is_bound() {
             return None;
         }
@@ -456,6 +989,7 @@ where
             self.node = NodeOrState::Bound;
             return None;
         }
+        let mut advance = true;
         if self.node.is_empty() {
             self.index = 1;
             self.node = NodeOrState::Bound;
@@ -464,17 +998,19 @@ where
             let entry = self.load(xa);
             if entry.is_value() {
                 return Some(entry);
-            } else if !entry.is_node() {
-                return None;
             }
+            advance = self.enter_ahead(xa);
+            self.node.get()?;
         } else if let Some(node) = self.node.get() {
-            if node.shift == 0 && node.offset != (self.index as usize & CHUNK_MASK) as u8 {
+            if node.shift == 0 && self.offset != (self.index as usize & CHUNK_MASK) as u8 {
                 self.offset = ((self.index as usize - 1) & CHUNK_MASK) as u8 + 1;
             }
         }
 
-        self.offset += 1;
-        self.move_index(self.offset);
+        if advance {
+            self.offset += 1;
+            self.move_index(self.offset);
+        }
         while self.node.get().is_some() && self.index <= end {
             let node = self.node.get().unwrap();
             if self.offset == CHUNK_SIZE as u8 {
@@ -484,11 +1020,15 @@ where
                 } else {
                     NodeOrState::Empty
                 };
+                if self.node.get().is_some() {
+                    self.move_index(self.offset);
+                }
                 continue;
             }
 
             let entry = *node.entry(self.offset);
             if let Some(node) = entry.as_node() {
+                self.index = node.index();
                 self.node = NodeOrState::Node(node);
                 self.offset = 0;
                 continue;
@@ -509,12 +1049,12 @@ where
         None
     }
 
-    pub fn find_marked(
+    pub fn find_marked<A: NodeAlloc>(
         &mut self,
-        xa: &RawXArray<T>,
+        xa: &RawXArray<T, MARKS, A>,
         end: u64,
-        mark: XaMark,
-    ) -> Option<RawEntry<T>> {
+        mark: impl MarkIndex,
+    ) -> Option<RawEntry<T, MARKS>> {
         if self.index > end {
             self.node = NodeOrState::Restart;
             return None;
@@ -525,12 +1065,13 @@ where
             return None;
         } else if self.node.get().is_none() {
             self.node = NodeOrState::Empty;
-            if self.index > xa.head.as_node().map(|n| n.max_index()).unwrap_or(0) {
+            if self.index > xa.head.as_node().map(|n| n.last_index()).unwrap_or(0) {
                 self.node = NodeOrState::Bound;
                 return None;
             }
             if let Some(node) = xa.head.as_node() {
-                self.offset = (self.index >> node.shift as u64).try_into().unwrap();
+                self.index = core::cmp::max(self.index, node.index());
+                self.offset = node.get_offset(self.index);
                 self.node = NodeOrState::Node(node);
             } else {
                 if xa.is_marked(mark) {
@@ -558,6 +1099,7 @@ where
                 if matches!(self.node, NodeOrState::Empty) {
                     break;
                 }
+                self.move_index(self.offset);
                 advance = false;
                 continue;
             }
@@ -589,6 +1131,12 @@ where
 
             let entry = node.entry(self.offset);
             if let Some(node) = entry.as_node() {
+                if node.last_index() < self.index {
+                    // A compressed node behind the index.
+                    advance = true;
+                    continue;
+                }
+                self.index = core::cmp::max(self.index, node.index());
                 self.offset = node.get_offset(self.index);
                 self.node = NodeOrState::Node(node);
             } else {
@@ -603,7 +1151,11 @@ where
         None
     }
 
-    pub fn get_next(&mut self, xa: &RawXArray<T>, end: u64) -> Option<RawEntry<T>> {
+    pub fn get_next<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        end: u64,
+    ) -> Option<RawEntry<T, MARKS>> {
         match self.node.get() {
             _ if self.offset != (self.index as usize & CHUNK_MASK) as u8 => self.find(xa, end),
             None => self.find(xa, end),
@@ -627,17 +1179,55 @@ where
         }
     }
 
-    pub fn get_next_marked(
+    /// Move to the next present entry not carrying `mark`.
+    ///
+    /// Only leaf bitmaps tell which entries carry a mark, as a mark on a
+    /// branch only says that some entry below has it. The rest of a leaf is
+    /// skipped at once when every entry left in it is marked.
+    pub fn get_next_unmarked<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        mark: impl MarkIndex,
+        end: u64,
+    ) -> Option<RawEntry<T, MARKS>> {
+        loop {
+            let entry = self.get_next(xa, end)?;
+            let Some(node) = self.node.get() else {
+                return (!xa.is_marked(mark)).then_some(entry);
+            };
+            if !node.mark(mark).get(self.offset as usize) {
+                return Some(entry);
+            }
+            if node.shift == 0
+                && (self.offset + 1..CHUNK_SIZE as u8).all(|offset| {
+                    !node.entry(offset).has_value() || node.mark(mark).get(offset as usize)
+                })
+            {
+                if self.index | CHUNK_MASK as u64 >= end {
+                    return None;
+                }
+                self.index |= CHUNK_MASK as u64;
+                self.offset = CHUNK_MASK as u8;
+            }
+        }
+    }
+
+    pub fn get_next_marked<A: NodeAlloc>(
         &mut self,
-        xa: &RawXArray<T>,
-        mark: XaMark,
+        xa: &RawXArray<T, MARKS, A>,
+        mark: impl MarkIndex,
         end: u64,
-    ) -> Option<RawEntry<T>> {
+    ) -> Option<RawEntry<T, MARKS>> {
         match self.node.get() {
+            _ if self.node.is_bound() => None,
             None => self.find_marked(xa, end, mark),
             Some(node) if node.shift > 0 => self.find_marked(xa, end, mark),
             Some(node) => {
                 let offset = node.find_mark(self.offset + 1, mark);
+                if offset == CHUNK_SIZE as u8 && self.index | CHUNK_MASK as u64 == u64::MAX {
+                    self.node = NodeOrState::Bound;
+                    return None;
+                }
                 self.offset = offset;
                 self.index = (self.index & !CHUNK_MASK as u64) + offset as u64;
                 if self.index > end {
@@ -655,4 +1245,195 @@ where
             }
         }
     }
+
+    /// Move to the nearest value entry before the index, or at the index
+    /// if the state has not been walked yet, stopping below `start`.
+    ///
+    /// The state lands on the first index of the entry found, or wraps to
+    /// just below `start` if there is none. Each call descends from the
+    /// head, following the populated slots closest to the index, so no slot
+    /// is visited twice on the way down.
+    pub fn find_prev<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        start: u64,
+    ) -> Option<RawEntry<T, MARKS>> {
+        if self.node.is_bound() {
+            return None;
+        }
+        let mut index = if self.node.is_restart() {
+            Some(self.index)
+        } else {
+            self.index.checked_sub(1)
+        };
+        while let Some(last) = index.filter(|last| *last >= start) {
+            let found = match xa.head.as_node_or_value() {
+                Some(NodeOrValue::Node(node)) => node.find_at_or_before(last),
+                _ => xa.head.has_value().then_some(0),
+            };
+            let Some(found) = found.filter(|found| *found >= start) else {
+                break;
+            };
+            self.index = found;
+            self.node = NodeOrState::Restart;
+            let entry = self.load(xa);
+            if let Some(node) = self.node.get() {
+                self.index = node.slot_index(self.offset);
+            }
+            if entry.is_value() {
+                return Some(entry);
+            }
+            index = self.index.checked_sub(1);
+        }
+        self.index = start.wrapping_sub(1);
+        self.node = NodeOrState::Bound;
+        None
+    }
+
+    /// Move to the nearest entry carrying `mark` before the index, or at
+    /// the index if the state has not been walked yet, stopping below
+    /// `start`.
+    ///
+    /// Like [`State::find_prev`], but only follows marked slots, so the
+    /// unmarked entries in between are never visited.
+    pub fn find_prev_marked<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        start: u64,
+        mark: impl MarkIndex,
+    ) -> Option<RawEntry<T, MARKS>> {
+        if self.node.is_bound() {
+            return None;
+        }
+        let last = if self.node.is_restart() {
+            Some(self.index)
+        } else {
+            self.index.checked_sub(1)
+        };
+        let found =
+            last.filter(|last| *last >= start)
+                .and_then(|last| match xa.head.as_node_or_value() {
+                    Some(NodeOrValue::Node(node)) => node.find_marked_at_or_before(last, mark),
+                    _ => (xa.head.has_value() && xa.is_marked(mark)).then_some(0),
+                });
+        let Some(found) = found.filter(|found| *found >= start) else {
+            self.index = start.wrapping_sub(1);
+            self.node = NodeOrState::Bound;
+            return None;
+        };
+        self.index = found;
+        self.node = NodeOrState::Restart;
+        let entry = self.load(xa);
+        if let Some(node) = self.node.get() {
+            self.index = node.slot_index(self.offset);
+        }
+        Some(entry)
+    }
+
+    /// Move to the next value entry passing `filter`, no further than
+    /// `end`, or to the entry at the index if the state has not been
+    /// walked yet.
+    ///
+    /// Like [`State::find_prev_marked`], each call descends from the head,
+    /// only following the slots whose marks may lead to a passing entry.
+    pub fn find_next_filtered<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        end: u64,
+        filter: &MarkFilter,
+    ) -> Option<RawEntry<T, MARKS>> {
+        if self.node.is_bound() {
+            return None;
+        }
+        let first = if self.node.is_restart() {
+            // Start from the first index of an entry straddling the index.
+            self.load(xa);
+            match self.node.get() {
+                Some(node) => Some(node.slot_index(self.offset)),
+                None => Some(self.index),
+            }
+        } else {
+            self.index.checked_add(1)
+        };
+        let found = first.filter(|first| *first <= end).and_then(|first| {
+            match xa.head.as_node_or_value() {
+                Some(NodeOrValue::Node(node)) => node.find_filtered_at_or_after(first, filter),
+                _ => (first == 0 && xa.head.is_value() && filter.passes(xa.marks)).then_some(0),
+            }
+        });
+        let Some(found) = found.filter(|found| *found <= end) else {
+            self.node = NodeOrState::Bound;
+            return None;
+        };
+        self.index = found;
+        self.node = NodeOrState::Restart;
+        Some(self.load(xa))
+    }
+
+    /// Move to the nearest value entry passing `filter` before the index,
+    /// or at the index if the state has not been walked yet, stopping below
+    /// `start`. See [`State::find_next_filtered`].
+    pub fn find_prev_filtered<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        start: u64,
+        filter: &MarkFilter,
+    ) -> Option<RawEntry<T, MARKS>> {
+        if self.node.is_bound() {
+            return None;
+        }
+        let last = if self.node.is_restart() {
+            Some(self.index)
+        } else {
+            self.index.checked_sub(1)
+        };
+        let found =
+            last.filter(|last| *last >= start)
+                .and_then(|last| match xa.head.as_node_or_value() {
+                    Some(NodeOrValue::Node(node)) => node.find_filtered_at_or_before(last, filter),
+                    _ => (xa.head.is_value() && filter.passes(xa.marks)).then_some(0),
+                });
+        let Some(found) = found.filter(|found| *found >= start) else {
+            self.index = start.wrapping_sub(1);
+            self.node = NodeOrState::Bound;
+            return None;
+        };
+        self.index = found;
+        self.node = NodeOrState::Restart;
+        let entry = self.load(xa);
+        if let Some(node) = self.node.get() {
+            self.index = node.slot_index(self.offset);
+        }
+        Some(entry)
+    }
+
+    /// Move to the nearest value entry before the index, no lower than
+    /// `start`. See [`State::find_prev`].
+    ///
+    /// Steps back within the current leaf without walking the tree.
+    pub fn get_prev<A: NodeAlloc>(
+        &mut self,
+        xa: &RawXArray<T, MARKS, A>,
+        start: u64,
+    ) -> Option<RawEntry<T, MARKS>> {
+        match self.node.get() {
+            Some(node)
+                if node.shift == 0 && self.offset == (self.index as usize & CHUNK_MASK) as u8 =>
+            {
+                while self.offset > 0 && self.index > start {
+                    let entry = *node.entry(self.offset - 1);
+                    if entry.is_internal() {
+                        break;
+                    }
+                    self.index -= 1;
+                    self.offset -= 1;
+                    if !entry.is_null() {
+                        return Some(entry);
+                    }
+                }
+                self.find_prev(xa, start)
+            }
+            _ => self.find_prev(xa, start),
+        }
+    }
 }
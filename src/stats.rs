@@ -0,0 +1,17 @@
+/// Counters of structural changes made to an array, returned by
+/// [`RawXArray::stats`].
+///
+/// [`RawXArray::stats`]: crate::RawXArray::stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Nodes allocated.
+    pub allocs: u64,
+    /// Nodes released.
+    pub frees: u64,
+    /// Levels added above the root.
+    pub expands: u64,
+    /// Levels removed from the top of the tree.
+    pub shrinks: u64,
+    /// Nodes inserted between a compressed node and its parent.
+    pub splits: u64,
+}
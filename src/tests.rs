@@ -19,8 +19,8 @@ fn test_simple_insert() {
     let p1 = 1;
     let p2 = 2;
     let mut array: RawXArray<u64> = RawXArray::new();
-    assert!(array.insert(1, &p1).is_none());
-    assert!(array.insert(2, &p2).is_none());
+    assert!(array.insert(1, &p1).unwrap().is_none());
+    assert!(array.insert(2, &p2).unwrap().is_none());
     assert_eq!(array.get(1), Some(&p1));
     assert_eq!(array.get(2), Some(&p2));
 }
@@ -30,7 +30,7 @@ fn test_simple_remove() {
     let p1 = 1;
     let mut array: RawXArray<u64> = RawXArray::new();
     for i in 0..100000 {
-        assert!(array.insert(i, &p1).is_none());
+        assert!(array.insert(i, &p1).unwrap().is_none());
     }
     for i in 0..100000 {
         assert_eq!(array.remove(i), Some(&p1));
@@ -43,18 +43,18 @@ fn test_simple_mark() {
     let p = 1;
     let mut array: RawXArray<u64> = RawXArray::new();
     let mut cursor = array.cursor_mut(0);
-    assert_eq!(cursor.insert(&p), None);
+    assert_eq!(cursor.insert(&p), Ok(None));
     cursor.mark(XaMark::Mark0);
     assert!(array.is_marked(XaMark::Mark0));
 
     let mut cursor = array.cursor_mut(1);
-    assert_eq!(cursor.insert(&p), None);
+    assert_eq!(cursor.insert(&p), Ok(None));
     cursor.mark(XaMark::Mark1);
     assert!(array.is_marked(XaMark::Mark0));
     assert!(array.is_marked(XaMark::Mark1));
 
     let mut cursor = array.cursor_mut(2);
-    assert_eq!(cursor.insert(&p), None);
+    assert_eq!(cursor.insert(&p), Ok(None));
     cursor.mark(XaMark::Mark1);
     assert!(array.is_marked(XaMark::Mark0));
     assert!(array.is_marked(XaMark::Mark1));
@@ -83,8 +83,8 @@ fn test_range() {
         assert_eq!(array.is_empty(), true);
 
         for (idx, i) in indice.iter().enumerate().take(TCNT) {
-            assert_eq!(array.insert(*i, &indice[idx]), None);
-            assert!(array.insert(*i, &indice[idx]).is_some());
+            assert_eq!(array.insert(*i, &indice[idx]), Ok(None));
+            assert!(array.insert(*i, &indice[idx]).unwrap().is_some());
             inserted.push((idx, *i));
         }
 
@@ -109,8 +109,8 @@ fn test_sparse_insert() {
         let mut inserted = Vec::new();
         assert_eq!(array.is_empty(), true);
         for (idx, i) in indice.iter().enumerate().take(TCNT) {
-            assert_eq!(array.insert(*i, &indice[idx]), None);
-            assert!(array.insert(*i, &indice[idx]).is_some());
+            assert_eq!(array.insert(*i, &indice[idx]), Ok(None));
+            assert!(array.insert(*i, &indice[idx]).unwrap().is_some());
             inserted.push((idx, *i));
             for (_idx, _i) in &inserted {
                 assert_eq!(array.get(*_i), Some(&indice[*_idx]));
@@ -137,8 +137,8 @@ fn test_dense_insert() {
 
         let mut inserted = Vec::new();
         for (idx, i) in indice.iter().enumerate() {
-            assert_eq!(array.insert(*i, &indice[idx]), None);
-            assert!(array.insert(*i, &indice[idx]).is_some());
+            assert_eq!(array.insert(*i, &indice[idx]), Ok(None));
+            assert!(array.insert(*i, &indice[idx]).unwrap().is_some());
             inserted.push((idx, *i));
             for (_idx, _i) in &inserted {
                 assert_eq!(array.get(*_i), Some(&indice[*_idx]));
@@ -171,13 +171,17 @@ fn test_random_insert() {
     {
         let mut array: RawXArray<u64> = RawXArray::new();
 
-        assert_eq!(array.is_empty(), true);
+        assert!(array.is_empty());
 
         for i in 0..TCNT as u64 {
             if rng.gen::<u8>() % 2 == 0 {
                 // insert
-                test_eq(array.insert(i, &arv[i as usize]), None, seed);
-                test_eq(array.insert(i, &arv[i as usize]).is_some(), true, seed);
+                test_eq(array.insert(i, &arv[i as usize]), Ok(None), seed);
+                test_eq(
+                    array.insert(i, &arv[i as usize]).unwrap().is_some(),
+                    true,
+                    seed,
+                );
                 inserted.push(i);
             } else {
                 inserted.shuffle(&mut rng);
@@ -224,7 +228,7 @@ fn test_mark() {
             let idx = idx as u64;
             let mut cursor = array.cursor_mut(idx);
 
-            assert_eq!(cursor.insert(i), None);
+            assert_eq!(cursor.insert(i), Ok(None));
             if idx & 1 == 0 {
                 cursor.mark(XaMark::Mark0);
                 marked.insert(idx);
@@ -255,7 +259,7 @@ fn test_mark2() {
             let idx = idx as u64;
             let mut cursor = array.cursor_mut(idx);
 
-            assert_eq!(cursor.insert(i), None);
+            assert_eq!(cursor.insert(i), Ok(None));
             if idx & 1 == 0 {
                 cursor.mark(XaMark::Mark0);
                 marked.insert(idx);
@@ -288,7 +292,7 @@ fn test_mark3() {
             let idx = idx as u64;
             let mut cursor = array.cursor_mut(idx);
 
-            assert_eq!(cursor.insert(Box::new(*i)), None);
+            assert_eq!(cursor.insert(Box::new(*i)), Ok(None));
             if idx & 1 == 0 {
                 cursor.mark(XaMark::Mark0);
                 marked.insert(idx);
@@ -318,8 +322,8 @@ fn test_next_allocated() {
         assert_eq!(array.is_empty(), true);
 
         for (idx, i) in indice.iter().enumerate() {
-            assert_eq!(array.insert(*i, &indice[idx]), None);
-            assert!(array.insert(*i, &indice[idx]).is_some());
+            assert_eq!(array.insert(*i, &indice[idx]), Ok(None));
+            assert!(array.insert(*i, &indice[idx]).unwrap().is_some());
             println!("{}", i);
         }
 
@@ -332,3 +336,4787 @@ fn test_next_allocated() {
         }
     }
 }
+
+#[test]
+fn test_count_range() {
+    let mut seed_gen = thread_rng();
+    let mut seed = [0; 32];
+    (0..32).for_each(|i| seed[i] = seed_gen.gen::<u8>());
+    let mut rng = StdRng::from_seed(seed);
+
+    let v = 0u64;
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut present = std::collections::BTreeSet::new();
+    assert_eq!(array.count_range(0..=u64::MAX), 0);
+
+    for _ in 0..TCNT {
+        let i = rng.gen::<u64>() % 0x100000;
+        array.insert(i, &v).unwrap();
+        present.insert(i);
+    }
+    for i in present.iter().copied().step_by(3).collect::<Vec<_>>() {
+        assert_eq!(array.remove(i), Some(&v));
+        present.remove(&i);
+    }
+
+    assert_eq!(array.count_range(0..=u64::MAX), present.len() as u64);
+    for _ in 0..TCNT {
+        let a = rng.gen::<u64>() % 0x110000;
+        let b = rng.gen::<u64>() % 0x110000;
+        let (start, end) = (a.min(b), a.max(b));
+        assert_eq!(
+            array.count_range(start..=end),
+            present.range(start..=end).count() as u64,
+            "seed: {:?}",
+            seed
+        );
+    }
+
+    let mut array: RawXArray<u64> = RawXArray::new();
+    array.insert(0, &v).unwrap();
+    assert_eq!(array.count_range(0..=0), 1);
+    assert_eq!(array.count_range(1..=10), 0);
+    array.insert(u64::MAX, &v).unwrap();
+    assert_eq!(array.count_range(0..=u64::MAX), 2);
+    assert_eq!(array.count_range(1..=u64::MAX), 1);
+}
+
+#[test]
+fn test_min_max_index() {
+    let v = 0u64;
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.min_index(), None);
+    assert_eq!(array.max_index(), None);
+
+    array.insert(0, &v).unwrap();
+    assert_eq!(array.min_index(), Some(0));
+    assert_eq!(array.max_index(), Some(0));
+    array.remove(0);
+
+    let mut rng = thread_rng();
+    let mut present = std::collections::BTreeSet::new();
+    for _ in 0..TCNT {
+        let i = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        array.insert(i, &v).unwrap();
+        present.insert(i);
+        assert_eq!(array.min_index(), present.first().copied());
+        assert_eq!(array.max_index(), present.last().copied());
+    }
+    while let Some(i) = present.pop_last() {
+        array.remove(i);
+        assert_eq!(array.min_index(), present.first().copied());
+        assert_eq!(array.max_index(), present.last().copied());
+    }
+}
+
+#[test]
+fn test_next_prev_present() {
+    let v = 0u64;
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.next_present(0), None);
+    assert_eq!(array.prev_present(u64::MAX), None);
+
+    array.insert(0, &v).unwrap();
+    assert_eq!(array.next_present(0), None);
+    assert_eq!(array.prev_present(0), None);
+    assert_eq!(array.prev_present(u64::MAX), Some(0));
+
+    let mut rng = thread_rng();
+    let mut present = std::collections::BTreeSet::new();
+    present.insert(0);
+    for _ in 0..TCNT {
+        let i = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        array.insert(i, &v).unwrap();
+        present.insert(i);
+    }
+    array.insert(u64::MAX, &v).unwrap();
+    present.insert(u64::MAX);
+
+    for _ in 0..TCNT {
+        let i = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        assert_eq!(
+            array.next_present(i),
+            present.range(i..).find(|n| **n != i).copied()
+        );
+        assert_eq!(
+            array.prev_present(i),
+            present.range(..i).next_back().copied()
+        );
+    }
+    for i in present.iter() {
+        let next = array.next_present(*i);
+        assert_eq!(next, present.range(i..).nth(1).copied());
+        if let Some(next) = next {
+            assert_eq!(array.prev_present(next), Some(*i));
+        }
+    }
+}
+
+#[test]
+fn test_from_dense() {
+    let values = (0..TCNT as u64).collect::<Vec<_>>();
+    let array = RawXArray::from_slice(&values);
+    assert_eq!(array.count_range(0..=u64::MAX), TCNT as u64);
+    for (i, v) in values.iter().enumerate() {
+        assert_eq!(array.get(i as u64), Some(v));
+    }
+    assert_eq!(array.get(TCNT as u64), None);
+
+    let options = (0..TCNT as u64)
+        .map(|i| (i % 3 == 0).then_some(i))
+        .collect::<Vec<_>>();
+    let array = RawXArray::from(options.as_slice());
+    assert_eq!(array.count_range(0..=u64::MAX), (TCNT as u64).div_ceil(3));
+    for (i, v) in options.iter().enumerate() {
+        assert_eq!(array.get(i as u64), v.as_ref());
+    }
+
+    let boxed = (0..TCNT as u64)
+        .map(|i| (i % 5 != 0).then(|| Box::new(i)))
+        .collect::<Vec<_>>();
+    let array = XArrayBoxed::from(boxed);
+    for i in 0..TCNT as u64 {
+        assert_eq!(array.get(i), (i % 5 != 0).then_some(&i));
+    }
+
+    // Leaves are filled whole and linked up level by level, ending up with
+    // the nodes inserting one by one would have built.
+    let mut inserted: RawXArray<u64> = RawXArray::new();
+    for (i, v) in values.iter().enumerate() {
+        inserted.insert(i as u64, v).unwrap();
+    }
+    let mut array = RawXArray::from_slice(&values);
+    assert_eq!(array.node_count(), inserted.node_count());
+    assert_eq!(array.high_index(), Some(TCNT as u64 - 1));
+    assert!(array.leaves().all(|(first, leaf)| {
+        leaf.iter()
+            .enumerate()
+            .all(|(i, slot)| slot.value() == values.get(first as usize + i))
+    }));
+    for i in (0..TCNT as u64).rev() {
+        assert_eq!(array.remove(i), Some(&values[i as usize]));
+    }
+    assert_eq!(array.node_count(), 0);
+
+    let (p1, p2) = (1, 2);
+    assert!(RawXArray::<u64>::from_slice(&[]).is_empty());
+    let single = [Some(p1)];
+    let array = RawXArray::from(single.as_slice());
+    assert_eq!((array.node_count(), array.get(0)), (0, Some(&p1)));
+    let sparse = [Some(p1), None, None, Some(p2)];
+    let sparse = (0..5000)
+        .map(|i| sparse.get(i).copied().flatten())
+        .collect::<Vec<_>>();
+    let array = RawXArray::from(sparse.as_slice());
+    assert_eq!(array.node_count(), 1);
+    assert_eq!((array.get(0), array.get(3)), (Some(&p1), Some(&p2)));
+    let tail = (0..5000)
+        .map(|i| (i >= 4500).then_some(i))
+        .collect::<Vec<_>>();
+    let mut array = RawXArray::from(tail.as_slice());
+    assert_eq!(array.count_range(0..=u64::MAX), 500);
+    assert_eq!(array.extract(0, u64::MAX).next(), Some((4500, &4500)));
+    assert_eq!(array.insert(4, &p2), Ok(None));
+    array.cursor_mut(4).mark(XaMark::Mark0);
+    assert!(array.cursor(4).is_marked(XaMark::Mark0));
+    assert_eq!(array.extract(0, u64::MAX).nth(1), Some((4500, &4500)));
+}
+
+#[test]
+fn test_to_vec() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert!(array.to_vec().is_empty());
+
+    let mut rng = thread_rng();
+    let mut present = std::collections::BTreeMap::new();
+    for i in v.iter() {
+        let index = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        if array.insert(index, i).unwrap().is_none() {
+            present.insert(index, i);
+        }
+    }
+    let vec = array.to_vec();
+    assert_eq!(vec.capacity(), present.len());
+    assert_eq!(vec, present.into_iter().collect::<Vec<_>>());
+
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in (1..TCNT as u64).step_by(7) {
+        array.insert(i * 3, Box::new(i)).unwrap();
+    }
+    let vec = array.into_vec();
+    assert_eq!(
+        vec,
+        (1..TCNT as u64)
+            .step_by(7)
+            .map(|i| (i * 3, Box::new(i)))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_shrinker() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array = RawXArray::from_slice(&v);
+    assert_eq!(array.shrink(TCNT), 0);
+
+    array.set_shrinker(|_, v: &u64| v % 2 == 1);
+    assert_eq!(array.shrink(10), 5);
+    assert_eq!(array.shrink(10), 5);
+    assert_eq!(array.get(19), None);
+    assert_eq!(array.get(21), Some(&21));
+    assert_eq!(array.shrink(TCNT), TCNT / 2 - 10);
+    assert_eq!(array.count_range(0..=u64::MAX), TCNT as u64 / 2);
+    assert_eq!(array.shrink(TCNT), 0);
+    assert!(array.take_shrinker().is_some());
+
+    let rc = std::rc::Rc::new(());
+    let mut array: XArrayBoxed<std::rc::Rc<()>> = XArrayBoxed::new();
+    for i in 0..100 {
+        array.insert(i * 100, Box::new(rc.clone())).unwrap();
+    }
+    array.set_shrinker(|i, _: &std::rc::Rc<()>| i % 300 == 0);
+    assert_eq!(array.shrink(100), 34);
+    assert_eq!(std::rc::Rc::strong_count(&rc), 67);
+    drop(array);
+    assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+}
+
+#[test]
+fn test_on_remove() {
+    use std::cell::RefCell;
+
+    let removed = RefCell::new(Vec::new());
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    {
+        let mut array = RawXArray::from_slice(&v);
+        array.set_on_remove(|i, v: &u64| {
+            assert_eq!(i, *v);
+            removed.borrow_mut().push(i)
+        });
+        array.remove(3);
+        array.remove(3);
+        array.remove(100);
+        assert_eq!(*removed.borrow(), [3, 100]);
+
+        array.set_shrinker(|i, _: &u64| i < 10);
+        array.shrink(10);
+        assert_eq!(*removed.borrow(), [3, 100, 0, 1, 2, 4, 5, 6, 7, 8, 9]);
+        removed.borrow_mut().clear();
+    }
+    assert_eq!(removed.borrow().len(), TCNT - 11);
+    assert!(removed
+        .borrow()
+        .iter()
+        .copied()
+        .eq((10..TCNT as u64).filter(|i| *i != 100)));
+
+    let removed = std::rc::Rc::new(RefCell::new(Vec::new()));
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..10 {
+        array.insert(i * 1000, Box::new(i)).unwrap();
+    }
+    let r = removed.clone();
+    array.set_on_remove(move |i, v| r.borrow_mut().push((i, *v)));
+    assert_eq!(array.remove(2000), Some(Box::new(2)));
+    drop(array);
+    assert_eq!(removed.borrow()[0], (2000, 2));
+    assert_eq!(
+        removed.borrow()[1..],
+        (0..10)
+            .filter(|i| *i != 2)
+            .map(|i| (i * 1000, i))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_observer() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Insert(u64, u64),
+        Remove(u64, u64),
+        Mark(u64, usize, bool),
+    }
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+    impl Observer<u64> for Recorder {
+        fn on_insert(&mut self, index: u64, value: &u64) {
+            self.0.borrow_mut().push(Event::Insert(index, *value));
+        }
+        fn on_remove(&mut self, index: u64, value: &u64) {
+            self.0.borrow_mut().push(Event::Remove(index, *value));
+        }
+        fn on_mark(&mut self, index: u64, mark: usize, set: bool) {
+            self.0.borrow_mut().push(Event::Mark(index, mark, set));
+        }
+    }
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let v = [1u64, 2, 3];
+    let mut array: RawXArray<u64> = RawXArray::new();
+    array.set_observer(Recorder(events.clone()));
+    array.insert(0, &v[0]).unwrap();
+    array.insert(0, &v[1]).unwrap();
+    array.insert(500, &v[1]).unwrap();
+    let mut cursor = array.cursor_mut(500);
+    cursor.mark(XaMark::Mark1);
+    cursor.mark(XaMark::Mark1);
+    cursor.unmark(XaMark::Mark1);
+    cursor.unmark(XaMark::Mark1);
+    array.remove(0);
+    array.remove(7);
+    assert!(array.take_observer().is_some());
+    array.remove(500);
+
+    assert_eq!(
+        *events.borrow(),
+        [
+            Event::Insert(0, 1),
+            Event::Insert(500, 2),
+            Event::Mark(500, XaMark::Mark1 as usize, true),
+            Event::Mark(500, XaMark::Mark1 as usize, false),
+            Event::Remove(0, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_transaction() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    for i in (0..TCNT).step_by(2) {
+        let mut cursor = array.cursor_mut(i as u64);
+        cursor.insert(&v[i]).unwrap();
+        if i % 4 == 0 {
+            cursor.mark(XaMark::Mark2);
+        }
+    }
+    let snapshot = array.to_vec();
+    let snapshot = snapshot
+        .into_iter()
+        .map(|(i, v)| (i, *v))
+        .collect::<Vec<_>>();
+
+    let r: Result<(), ()> = array.transaction(|tx| {
+        for (i, value) in v.iter().enumerate() {
+            if i % 2 == 0 {
+                assert_eq!(tx.remove(i as u64), Some(value));
+            } else {
+                assert_eq!(tx.insert(i as u64, value), Ok(None));
+            }
+        }
+        assert_eq!(tx.get(0), None);
+        Err(())
+    });
+    assert!(r.is_err());
+    assert!(array
+        .iter()
+        .map(|(i, v)| (i, *v))
+        .eq(snapshot.iter().copied()));
+    assert!(array
+        .iter()
+        .filter_mark(XaMark::Mark2)
+        .map(|(i, _)| i)
+        .eq((0..TCNT as u64).step_by(4)));
+
+    let r: Result<u64, ()> = array.transaction(|tx| {
+        tx.remove(0);
+        tx.insert(1, &v[1]).unwrap();
+        Ok(7)
+    });
+    assert_eq!(r, Ok(7));
+    assert_eq!(array.get(0), None);
+    assert_eq!(array.get(1), Some(&v[1]));
+}
+
+#[test]
+fn test_gap() {
+    use std::collections::BTreeMap;
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut model = BTreeMap::new();
+    let keys = (0..TCNT as u64)
+        .chain((0..64).map(|i| u64::MAX - i * 3))
+        .chain((0..64).map(|i| 1 << 40 | i << 12));
+    for (i, key) in keys.enumerate() {
+        let value = &v[i % TCNT];
+        let mut cursor = array.cursor_mut(key);
+        cursor.insert(value).unwrap();
+        if i % 3 == 0 {
+            cursor.mark(XaMark::Mark1);
+        }
+        model.insert(key, (value, i % 3 == 0));
+    }
+
+    let ops = [
+        (true, 100, 1),
+        (true, 0, 64),
+        (false, 10, 7),
+        (true, 4096, 4096),
+        (false, 1 << 40, 1 << 12),
+        (false, 0, 64),
+        (true, 3, 1 << 32),
+        (false, 1, u64::MAX),
+    ];
+    for (insert, at, n) in ops {
+        let (below, mut above): (Vec<_>, Vec<_>) = core::mem::take(&mut model)
+            .into_iter()
+            .partition(|(key, _)| *key < at);
+        if insert {
+            array.insert_gap(at, n);
+            above.retain(|(key, _)| key.checked_add(n).is_some());
+            above.iter_mut().for_each(|(key, _)| *key += n);
+        } else {
+            array.remove_gap(at, n);
+            above.retain(|(key, _)| key - at >= n);
+            above.iter_mut().for_each(|(key, _)| *key -= n);
+        }
+        model.extend(below.into_iter().chain(above));
+
+        assert!(array.iter().eq(model.iter().map(|(i, (v, _))| (*i, *v))));
+        assert!(array
+            .iter()
+            .filter_mark(XaMark::Mark1)
+            .map(|(i, _)| i)
+            .eq(model.iter().filter(|(_, (_, m))| *m).map(|(i, _)| *i)));
+    }
+    assert_eq!(model.len(), 1);
+
+    let mut boxed = XArrayBoxed::new();
+    for i in 0..TCNT as u64 {
+        boxed.insert(i, Box::new(i)).unwrap();
+    }
+    boxed.remove_gap(0, 64);
+    boxed.insert_gap(u64::MAX - 64, 128);
+    assert!(boxed
+        .iter()
+        .map(|(i, v)| (i, *v))
+        .eq((0..TCNT as u64 - 64).map(|i| (i, i + 64))));
+}
+
+#[test]
+fn test_splice() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut src: RawXArray<u64> = RawXArray::new();
+    let mut dst: RawXArray<u64> = RawXArray::new();
+    for (i, value) in v.iter().enumerate() {
+        let mut cursor = src.cursor_mut(i as u64);
+        cursor.insert(value).unwrap();
+        if i % 5 == 0 {
+            cursor.mark(XaMark::Mark0);
+        }
+        dst.insert(1 << 20 | i as u64, value).unwrap();
+    }
+
+    // Aligned: whole leaves change hands.
+    src.splice(64..=1023, &mut dst, 1 << 20);
+    // Unaligned: entries are moved one by one.
+    src.splice(1024..=1100, &mut dst, 3);
+    assert_eq!(src.count_range(0..=u64::MAX), TCNT as u64 - 960 - 77);
+    assert_eq!(src.get(64), None);
+    assert_eq!(src.get(1100), None);
+    assert_eq!(src.get(1101), Some(&1101));
+
+    let expected = (3..80)
+        .map(|i| (i, i + 1021))
+        .chain((1 << 20..(1 << 20) + 960).map(|i| (i, i - (1 << 20) + 64)))
+        .chain(((1 << 20) + 960..(1 << 20) + TCNT as u64).map(|i| (i, i - (1 << 20))))
+        .collect::<Vec<_>>();
+    assert!(dst
+        .iter()
+        .map(|(i, v)| (i, *v))
+        .eq(expected.iter().copied()));
+    assert!(dst
+        .iter()
+        .filter_mark(XaMark::Mark0)
+        .map(|(i, _)| i)
+        .eq(expected
+            .iter()
+            .filter(|(i, v)| *v % 5 == 0 && *i < (1 << 20) + 960)
+            .map(|(i, _)| *i)));
+
+    let mut src = XArrayBoxed::new();
+    let mut dst = XArrayBoxed::new();
+    for i in 0..TCNT as u64 {
+        src.insert(i, Box::new(i)).unwrap();
+        dst.insert(i, Box::new(i + 1)).unwrap();
+    }
+    src.splice(0..=TCNT as u64 - 1, &mut dst, 0);
+    assert!(src.is_empty());
+    assert!(dst
+        .iter()
+        .map(|(i, v)| (i, *v))
+        .eq((0..TCNT as u64).map(|i| (i, i))));
+}
+
+#[test]
+fn test_shift_range() {
+    use std::collections::BTreeMap;
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut model = BTreeMap::new();
+    for (i, value) in v.iter().enumerate().step_by(3) {
+        let mut cursor = array.cursor_mut(i as u64);
+        cursor.insert(value).unwrap();
+        if i % 2 == 0 {
+            cursor.mark(XaMark::Mark0);
+        }
+        model.insert(i as u64, (value, i % 2 == 0));
+    }
+
+    let ops = [
+        (0, 1023, 4096),
+        (4096, 5119, -4096),
+        (100, 2000, 64),
+        (64, 3000, -33),
+        (0, u64::MAX / 2, 1 << 40),
+        (1 << 40, (1 << 40) + 500, -(1 << 40) + 1000),
+    ];
+    for (start, end, delta) in ops {
+        array.shift_range(start..=end, delta);
+        let moved = model
+            .range(start..=end)
+            .map(|(i, v)| (i.checked_add_signed(delta).unwrap(), *v))
+            .collect::<Vec<_>>();
+        let dst = start.checked_add_signed(delta).unwrap()..=end.checked_add_signed(delta).unwrap();
+        model.retain(|i, _| !(start..=end).contains(i) && !dst.contains(i));
+        model.extend(moved);
+
+        assert!(array.iter().eq(model.iter().map(|(i, (v, _))| (*i, *v))));
+        assert!(array
+            .iter()
+            .filter_mark(XaMark::Mark0)
+            .map(|(i, _)| i)
+            .eq(model.iter().filter(|(_, (_, m))| *m).map(|(i, _)| *i)));
+    }
+}
+
+#[test]
+fn test_deque() {
+    use std::collections::VecDeque;
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut deque = XArrayDeque::new();
+    let mut model = VecDeque::new();
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    for value in v.iter() {
+        match rng.gen_range(0, 4) {
+            0 => {
+                deque.push_front(value);
+                model.push_front(value);
+            }
+            1 => assert_eq!(deque.pop_front(), model.pop_front()),
+            2 => assert_eq!(deque.pop_back(), model.pop_back()),
+            _ => {
+                deque.push_back(value);
+                model.push_back(value);
+            }
+        }
+        assert_eq!(deque.len(), model.len() as u64);
+        assert_eq!(deque.front(), model.front().copied());
+        assert_eq!(deque.back(), model.back().copied());
+    }
+    for (i, value) in model.iter().enumerate() {
+        assert_eq!(deque.get(i as u64), Some(*value));
+    }
+    assert_eq!(deque.get(model.len() as u64), None);
+    assert!(deque.iter().eq(model.iter().copied()));
+}
+
+#[test]
+fn test_option_storage() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.insert_option(0, None), None);
+    assert_eq!(array.get_option(0), Some(None));
+    assert_eq!(array.get_option(1), None);
+    assert_eq!(array.get(0), None);
+    assert_eq!(array.insert_option(0, Some(&v[0])), Some(None));
+
+    for (i, value) in v.iter().enumerate().skip(1) {
+        let value = (i % 3 != 0).then_some(value);
+        assert_eq!(array.insert_option(i as u64, value), None);
+    }
+    assert_eq!(array.get_option(0), Some(None));
+    for (i, value) in v.iter().enumerate().skip(1) {
+        let value = (i % 3 != 0).then_some(value);
+        assert_eq!(array.get_option(i as u64), Some(value));
+    }
+    assert_eq!(array.count_range(0..=u64::MAX), TCNT as u64);
+    assert_eq!(array.max_index(), Some(TCNT as u64 - 1));
+    assert!(array
+        .iter()
+        .map(|(i, _)| i)
+        .eq((0..TCNT as u64).filter(|i| i % 3 != 0)));
+
+    for i in (0..TCNT as u64).rev() {
+        let expected = (i % 3 != 0).then_some(&v[i as usize]);
+        assert_eq!(array.remove_option(i), Some(expected));
+        assert_eq!(array.get_option(i), None);
+    }
+    assert!(array.is_empty());
+
+    let mut boxed = XArrayBoxed::new();
+    boxed.insert_option(5, None);
+    boxed.insert_option(6, Some(Box::new(6)));
+    assert_eq!(boxed.remove_option(5), Some(None));
+    assert_eq!(boxed.remove_option(6), Some(Some(Box::new(6))));
+    assert_eq!(boxed.remove_option(6), None);
+}
+
+#[test]
+fn test_get_or_insert_with() {
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..TCNT as u64 {
+        assert_eq!(*array.get_or_insert_with(i * 7, || Box::new(i)), i);
+    }
+    for i in 0..TCNT as u64 {
+        assert_eq!(*array.get_or_insert_with(i * 7, || unreachable!()), i);
+    }
+    assert!(array
+        .iter()
+        .map(|(i, v)| (i, *v))
+        .eq((0..TCNT as u64).map(|i| (i * 7, i))));
+}
+
+#[test]
+fn test_freeze() {
+    use std::sync::Arc;
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array = RawXArray::from_slice(&v);
+    array.set_on_remove(|_, _| unreachable!());
+    let frozen = Arc::new(array.freeze());
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            let frozen = frozen.clone();
+            s.spawn(move || {
+                for i in (t..TCNT as u64).step_by(4) {
+                    assert_eq!(frozen.get(i), Some(&i));
+                }
+                assert_eq!(frozen.iter().count(), TCNT);
+            });
+        }
+    });
+    let mut array = Arc::into_inner(frozen).unwrap().thaw();
+    assert_eq!(array.remove(0), Some(&0));
+}
+
+#[cfg(not(feature = "linux"))]
+#[test]
+fn test_path_compression() {
+    use crate::node::RawEntry;
+    use std::collections::BTreeMap;
+
+    fn nodes<T>(entry: RawEntry<T>, head: bool) -> usize {
+        let Some(node) = entry.as_node() else {
+            return 0;
+        };
+        let children = (0..64)
+            .filter_map(|ofs| node.entry(ofs).as_node())
+            .collect::<Vec<_>>();
+        assert!(head || node.count > 1 || children.is_empty());
+        1 + children
+            .into_iter()
+            .map(|child| nodes(RawEntry::node(child), false))
+            .sum::<usize>()
+    }
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    array.insert(1 << 63, &v[0]).unwrap();
+    assert_eq!(nodes(array.head, true), 1);
+    array.insert(u64::MAX, &v[1]).unwrap();
+    assert_eq!(nodes(array.head, true), 3);
+    assert!(array.iter().eq([(1 << 63, &v[0]), (u64::MAX, &v[1])]));
+    array.remove(1 << 63);
+    assert_eq!(nodes(array.head, true), 1);
+    assert_eq!(array.get(u64::MAX), Some(&v[1]));
+    array.remove(u64::MAX);
+    assert!(array.is_empty());
+
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut model = BTreeMap::new();
+    for value in v.iter() {
+        let key = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        let mut cursor = array.cursor_mut(key);
+        if cursor.insert(value).unwrap().is_none() {
+            if value % 3 == 0 {
+                cursor.mark(XaMark::Mark0);
+            }
+            model.insert(key, value);
+        }
+    }
+    for round in 0..2 {
+        assert!(nodes(array.head, true) <= 2 * model.len());
+        assert!(array.iter().eq(model.iter().map(|(k, v)| (*k, *v))));
+        assert!(array.iter().filter_mark(XaMark::Mark0).eq(model
+            .iter()
+            .filter(|(_, v)| **v % 3 == 0)
+            .map(|(k, v)| (*k, *v))));
+        for _ in 0..64 {
+            let (a, b) = (rng.gen::<u64>(), rng.gen::<u64>());
+            let (a, b) = (a.min(b), a.max(b));
+            assert_eq!(array.count_range(a..=b), model.range(a..=b).count() as u64);
+            assert!(array
+                .extract(a, b)
+                .eq(model.range(a..=b).map(|(k, v)| (*k, *v))));
+            assert_eq!(
+                array.next_present(a),
+                model.range(a + 1..).next().map(|(k, _)| *k)
+            );
+            assert_eq!(
+                array.prev_present(b),
+                model.range(..b).next_back().map(|(k, _)| *k)
+            );
+        }
+        if round == 0 {
+            let mut keys = model.keys().copied().collect::<Vec<_>>();
+            keys.shuffle(&mut rng);
+            for key in keys.into_iter().take(model.len() / 2) {
+                assert_eq!(array.remove(key), model.remove(&key));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_hashmap() {
+    use std::collections::HashMap;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    // Hash every key into eight buckets so that probing is exercised.
+    #[derive(Default)]
+    struct Coarse(u64);
+
+    impl Hasher for Coarse {
+        fn finish(&self) -> u64 {
+            (self.0 & 7).wrapping_sub(4)
+        }
+        fn write(&mut self, bytes: &[u8]) {
+            self.0 = bytes
+                .iter()
+                .fold(self.0, |h, b| h.wrapping_mul(31) + *b as u64);
+        }
+    }
+
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut map: XArrayHashMap<u64, u64, BuildHasherDefault<Coarse>> = XArrayHashMap::default();
+    let mut model = HashMap::new();
+    map.insert(u64::MAX, 0);
+    let pinned = map.get(&u64::MAX).unwrap() as *const u64;
+    for i in 0..TCNT as u64 {
+        let key = rng.gen_range(0, 256);
+        if rng.gen::<bool>() {
+            assert_eq!(map.insert(key, i), model.insert(key, i));
+        } else {
+            assert_eq!(map.remove(&key), model.remove(&key));
+        }
+        assert_eq!(map.len(), model.len() as u64 + 1);
+    }
+    for key in 0..256 {
+        assert_eq!(map.get(&key), model.get(&key));
+        assert_eq!(map.contains_key(&key), model.contains_key(&key));
+    }
+    assert_eq!(map.get(&u64::MAX).unwrap() as *const u64, pinned);
+    let mut entries = map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+    entries.sort();
+    let mut expected = model.into_iter().collect::<Vec<_>>();
+    expected.push((u64::MAX, 0));
+    expected.sort();
+    assert_eq!(entries, expected);
+}
+
+#[test]
+fn test_interval_map() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut map = XArrayIntervalMap::new();
+    let mut model: Vec<Interval> = Vec::new();
+    type Interval<'a> = (std::ops::Range<u64>, &'a u64);
+    fn covering<'a>(model: &[Interval<'a>], index: u64) -> Option<Interval<'a>> {
+        model.iter().find(|(r, _)| r.contains(&index)).cloned()
+    }
+    for value in v.iter().take(TCNT / 4) {
+        let start = rng.gen_range(0, 1 << 16);
+        let range = start..start + rng.gen_range(1, 256);
+        if rng.gen_range(0, 4) == 0 {
+            assert_eq!(map.remove(range.start), covering(&model, range.start));
+            model.retain(|(r, _)| !r.contains(&range.start));
+        } else {
+            let found = model
+                .iter()
+                .filter(|(r, _)| r.start < range.end && range.start < r.end)
+                .min_by_key(|(r, _)| r.start)
+                .cloned();
+            assert_eq!(map.insert(range.clone(), value), found);
+            if found.is_none() {
+                model.push((range, value));
+            }
+        }
+        assert_eq!(map.len(), model.len() as u64);
+    }
+    model.sort_by_key(|(r, _)| r.start);
+    assert!(map.iter().eq(model.iter().cloned()));
+    for _ in 0..1024 {
+        let index = rng.gen_range(0, 1 << 16);
+        assert_eq!(map.get(index), covering(&model, index));
+        let range = index..index + rng.gen_range(1, 1024);
+        assert!(map.overlapping(range.clone()).eq(model
+            .iter()
+            .filter(|(r, _)| r.start < range.end && range.start < r.end)
+            .cloned()));
+    }
+}
+
+#[test]
+fn test_lpm() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mask = |len: u8| u64::MAX.checked_shl(64 - len as u32).unwrap_or(0);
+    let mut table = XArrayLpm::new();
+    let mut model = Vec::new();
+    let lens = [0u8, 8, 16, 20, 24, 32, 63, 64];
+    for value in v.iter().take(TCNT / 4) {
+        let len = *lens.choose(&mut rng).unwrap();
+        let key = rng.gen::<u64>() & 0xffff_0000_0000_0000 & mask(len);
+        if model.iter().any(|(k, l, _)| (*k, *l) == (key, len)) {
+            assert!(table.insert(key, len, value).unwrap().is_some());
+        } else {
+            assert_eq!(table.insert(key, len, value), Ok(None));
+            model.push((key, len, value));
+        }
+    }
+    let lookup = |model: &Vec<(u64, u8, &u64)>, key: u64, max: u8| {
+        model
+            .iter()
+            .filter(|(k, l, _)| *l <= max && key & mask(*l) == *k)
+            .max_by_key(|(_, l, _)| *l)
+            .map(|(_, l, v)| (*l, **v))
+    };
+    for _ in 0..4096 {
+        let key = rng.gen::<u64>() & 0xffff_00ff_ffff_ffff;
+        let max = rng.gen_range(0, 65);
+        assert_eq!(
+            table.lookup_lpm(key, max).map(|(l, v)| (l, *v)),
+            lookup(&model, key, max)
+        );
+    }
+    model.shuffle(&mut rng);
+    for (key, len, value) in model.drain(..) {
+        assert_eq!(table.lookup_lpm(key, len).map(|(l, _)| l), Some(len));
+        assert_eq!(table.remove(key, len), Some(value));
+    }
+    assert!(table.is_empty());
+    assert_eq!(table.lookup_lpm(0, 64), None);
+}
+
+#[test]
+fn test_tag_if_tagged() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut keys = Vec::new();
+    for value in v.iter() {
+        let key = rng.gen_range(0, 1 << 20);
+        let mut cursor = array.cursor_mut(key);
+        if cursor.insert(value).unwrap().is_none() && rng.gen::<bool>() {
+            cursor.mark(XaMark::Mark0);
+            keys.push(key);
+        }
+    }
+    keys.sort();
+    let (start, end) = (1 << 18, 3 << 18);
+    let expected = keys
+        .iter()
+        .copied()
+        .filter(|k| (start..=end).contains(k))
+        .collect::<Vec<_>>();
+
+    let mut from = start;
+    let mut batches = 0;
+    loop {
+        let (tagged, resume) =
+            array.tag_if_tagged(from..=end, XaMark::Mark0, XaMark::Mark1, true, 100);
+        batches += 1;
+        match resume {
+            Some(index) => {
+                assert_eq!(tagged, 100);
+                from = index;
+            }
+            None => break,
+        }
+    }
+    assert_eq!(batches, expected.len().div_ceil(100).max(1));
+    assert!(array
+        .iter()
+        .filter_mark(XaMark::Mark1)
+        .map(|(k, _)| k)
+        .eq(expected.iter().copied()));
+    assert!(array
+        .iter()
+        .filter_mark(XaMark::Mark0)
+        .map(|(k, _)| k)
+        .eq(keys.iter().copied().filter(|k| !(start..=end).contains(k))));
+
+    let (tagged, resume) = array.tag_if_tagged(
+        0..=u64::MAX,
+        XaMark::Mark0,
+        XaMark::Mark2,
+        false,
+        usize::MAX,
+    );
+    assert_eq!((tagged, resume), (keys.len() - expected.len(), None));
+    assert_eq!(
+        array.iter().filter_mark(XaMark::Mark2).count(),
+        array.iter().filter_mark(XaMark::Mark0).count()
+    );
+}
+
+#[test]
+fn test_erase_marked() {
+    use std::boxed::Box;
+    use std::collections::BTreeMap;
+
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    let mut model = BTreeMap::new();
+    for i in 0..TCNT as u64 {
+        let key = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        let mut cursor = array.cursor_mut(key);
+        if cursor.insert(Box::new(i)).unwrap().is_none() {
+            let mark = rng.gen_range(0, 3) == 0;
+            if mark {
+                cursor.mark(XaMark::Mark1);
+            }
+            model.insert(key, (i, mark));
+        }
+    }
+    let expected = model
+        .iter()
+        .filter(|(_, (_, mark))| *mark)
+        .map(|(_, (i, _))| *i)
+        .collect::<Vec<_>>();
+    let erased = array.erase_marked(XaMark::Mark1);
+    assert!(erased.into_iter().map(|b| *b).eq(expected.iter().copied()));
+    assert!(!array.is_marked(XaMark::Mark1));
+    assert!(array.iter().map(|(k, v)| (k, *v)).eq(model
+        .iter()
+        .filter(|(_, (_, m))| !*m)
+        .map(|(k, (i, _))| (*k, *i))));
+    assert_eq!(array.erase_marked(XaMark::Mark1).len(), 0);
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut raw: RawXArray<u64> = RawXArray::from_slice(&v);
+    for i in (0..TCNT as u64).step_by(2) {
+        raw.cursor_mut(i).mark(XaMark::Mark0);
+    }
+    assert_eq!(raw.erase_marked(XaMark::Mark0), TCNT / 2);
+    assert!(raw.iter().map(|(k, _)| k).eq((1..TCNT as u64).step_by(2)));
+}
+
+#[test]
+fn test_slot() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    array.insert(0, &v[0]).unwrap();
+    array.insert(1 << 40, &v[1]).unwrap();
+
+    let mut slot = array.slot(1 << 40);
+    assert!(slot.is_valid());
+    for value in v.iter().skip(2).take(64) {
+        let old = slot.get().unwrap();
+        assert_eq!(slot.replace(value), Some(old));
+        slot.mark(XaMark::Mark0);
+        assert!(slot.is_marked(XaMark::Mark0));
+        slot.unmark(XaMark::Mark0);
+        assert!(!slot.is_marked(XaMark::Mark0));
+        assert!(slot.is_valid());
+    }
+    slot.mark(XaMark::Mark2);
+    assert_eq!(slot.remove(), Some(&v[65]));
+    assert!(!slot.is_valid());
+    assert_eq!(slot.get(), None);
+    assert!(!slot.is_marked(XaMark::Mark2));
+    assert_eq!(slot.replace(&v[66]), None);
+    assert!(slot.is_valid());
+    assert_eq!(array.get(1 << 40), Some(&v[66]));
+    assert_eq!(array.get(0), Some(&v[0]));
+    assert!(!array.is_marked(XaMark::Mark2));
+
+    let mut slot = array.slot(0);
+    assert_eq!(slot.replace(&v[67]), Some(&v[0]));
+    slot.mark(XaMark::Mark1);
+    assert_eq!(
+        array.iter().filter_mark(XaMark::Mark1).next(),
+        Some((0, &v[67]))
+    );
+
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut slot = array.slot(7);
+    assert_eq!(slot.remove(), None);
+    assert_eq!(slot.replace(&v[0]), None);
+    assert_eq!(slot.replace(&v[1]), Some(&v[0]));
+    assert!(array.iter().eq([(7, &v[1])]));
+}
+
+#[test]
+fn test_insert_at_next_free() {
+    use std::collections::BTreeSet;
+
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut model = BTreeSet::new();
+    let mut cursor = array.cursor_mut(0);
+    assert_eq!(cursor.insert_at_next_free(&v[0]), Some(0));
+    assert_eq!(cursor.insert_at_next_free(&v[1]), Some(1));
+    assert_eq!(cursor.key(), 1);
+    model.extend([0, 1]);
+    for value in v.iter().skip(2) {
+        let start = if rng.gen::<bool>() {
+            rng.gen_range(0, 1 << 12)
+        } else {
+            rng.gen::<u64>() >> rng.gen_range(0, 64)
+        };
+        let expected = (start..).find(|i| !model.contains(i)).unwrap();
+        let mut cursor = array.cursor_mut(start);
+        assert_eq!(cursor.insert_at_next_free(value), Some(expected));
+        assert_eq!(cursor.current(), Some(value));
+        model.insert(expected);
+    }
+    assert!(array.iter().map(|(k, _)| k).eq(model.iter().copied()));
+
+    let mut array: RawXArray<u64> = RawXArray::new();
+    array.insert(u64::MAX, &v[0]).unwrap();
+    assert_eq!(array.cursor_mut(u64::MAX).insert_at_next_free(&v[1]), None);
+    assert_eq!(
+        array.cursor_mut(u64::MAX - 1).insert_at_next_free(&v[1]),
+        Some(u64::MAX - 1)
+    );
+}
+
+#[test]
+fn test_structural_navigation() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    for value in v.iter() {
+        let key = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        array.insert(key, value).unwrap();
+    }
+
+    let mut cursor = array.cursor(array.min_index().unwrap());
+    assert_eq!(cursor.node_shift(), Some(0));
+    while cursor.ascend() {}
+    assert!(cursor.is_internal());
+    let mut walked = Vec::new();
+    'walk: loop {
+        if cursor.is_internal() {
+            let shift = cursor.node_shift().unwrap();
+            assert!(cursor.descend(0));
+            assert_eq!(cursor.offset(), 0);
+            assert!(cursor.node_shift().unwrap() < shift);
+            continue;
+        }
+        if let Some(value) = cursor.current() {
+            walked.push((cursor.key(), value));
+        }
+        while !cursor.next_slot() {
+            if !cursor.ascend() {
+                break 'walk;
+            }
+        }
+    }
+    assert!(walked.into_iter().eq(array.iter()));
+
+    let mut cursor = array.cursor(array.max_index().unwrap());
+    assert!(!cursor.next_slot());
+    assert!(!cursor.is_internal());
+    assert!(!cursor.descend(0));
+}
+
+#[test]
+fn test_limit() {
+    use std::boxed::Box;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::with_limit(999);
+    assert_eq!(array.limit(), 999);
+    assert_eq!(array.insert(999, &v[0]), Ok(None));
+    assert_eq!(array.insert(999, &v[1]), Ok(Some(&v[0])));
+    assert_eq!(array.insert(1000, &v[1]), Err(XaError::OutOfBounds));
+    assert_eq!(array.insert(u64::MAX, &v[1]), Err(XaError::OutOfBounds));
+    assert_eq!(array.store(1 << 40, &v[1]), Err(XaError::OutOfBounds));
+    assert!(catch_unwind(AssertUnwindSafe(|| array.slot(1000).replace(&v[1]))).is_err());
+    assert!(array.iter().eq([(999, &v[0])]));
+    assert!(array.head.as_node().unwrap().last_index() < 1 << 12);
+
+    let mut cursor = array.cursor_mut(998);
+    assert_eq!(cursor.insert_at_next_free(&v[2]), Some(998));
+    assert_eq!(cursor.insert_at_next_free(&v[3]), None);
+
+    array.insert_gap(990, 5);
+    assert!(array.iter().eq([]));
+    array.insert(10, &v[4]).unwrap();
+    assert!(catch_unwind(AssertUnwindSafe(|| array.shift_range(0..=10, 990))).is_err());
+    array.shift_range(0..=10, 989);
+    assert!(array.iter().eq([(999, &v[4])]));
+
+    let mut boxed: XArrayBoxed<u64> = XArrayBoxed::with_limit(63);
+    assert_eq!(boxed.insert(63, Box::new(1)), Ok(None));
+    assert_eq!(boxed.insert(64, Box::new(2)), Err(XaError::OutOfBounds));
+    assert_eq!(format!("{}", XaError::OutOfBounds), "index out of bounds");
+}
+
+#[test]
+fn test_level_report() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let array = RawXArray::from_slice(&v);
+    let report = array.level_report();
+    assert_eq!(report.len(), 2);
+    assert_eq!((report[0].nodes, report[1].nodes), (1, 64));
+    assert_eq!(report[1].occupancy[64], 64);
+    assert_eq!(report[1].average(), 64.0);
+    assert_eq!(report[1].percentile(50), 64);
+
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: RawXArray<u64> = RawXArray::new();
+    for value in v.iter() {
+        array.insert(rng.gen_range(0, 1 << 24), value).unwrap();
+    }
+    let report = array.level_report();
+    assert_eq!(report[0].nodes, 1);
+    let leaves = report.last().unwrap();
+    assert_eq!(leaves.nodes, leaves.occupancy.iter().sum::<u64>());
+    assert!(leaves.percentile(10) <= leaves.percentile(50));
+    assert!(leaves.percentile(50) <= leaves.percentile(100));
+    assert!(leaves.average() >= 1.0 && leaves.average() < 4.0);
+    assert!(RawXArray::<u64>::new().level_report().is_empty());
+}
+
+#[cfg(not(feature = "linux"))]
+#[test]
+fn test_entry_encoding() {
+    use crate::node::RawEntry;
+
+    for offset in 0..64 {
+        let sibling = RawEntry::<u64>::sibling(offset);
+        assert!(sibling.is_internal() && sibling.is_sibling());
+        assert!(!sibling.is_node() && !sibling.is_value());
+        assert_eq!(sibling.as_sibling(), Some(offset));
+    }
+    let zero = RawEntry::<u64>::ZERO;
+    assert!(zero.is_internal() && !zero.is_node() && !zero.is_sibling());
+    // Node pointers are told apart by their tag alone, wherever they live.
+    for address in [8usize, 64, 4096, usize::MAX & !7] {
+        let entry = RawEntry::<u64>::new(address | 2);
+        assert!(entry.is_internal() && entry.is_node());
+        assert!(!entry.is_sibling() && !entry.is_value() && !entry.is_zero());
+    }
+    let value = 7u64;
+    let entry = RawEntry::<u64>::value(&value);
+    assert!(entry.is_value() && !entry.is_internal());
+    assert_eq!(entry.as_value(), Some(&value));
+}
+
+#[cfg(not(feature = "linux"))]
+#[test]
+fn test_value_tags() {
+    assert_eq!(RawXArray::<u64>::VALUE_TAG_BITS, 2);
+    assert_eq!(RawXArray::<u32>::VALUE_TAG_BITS, 1);
+    assert_eq!(RawXArray::<u8>::VALUE_TAG_BITS, 0);
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    array.insert(0, &v[0]).unwrap();
+    {
+        let mut cursor = array.cursor_mut(0);
+        cursor.set_tag(1);
+    }
+    assert_eq!(array.tags(0), Some(0b10));
+    assert_eq!(array.get(0), Some(&v[0]));
+
+    for (i, value) in v.iter().enumerate().skip(1) {
+        array.insert(i as u64, value).unwrap();
+    }
+    {
+        let mut cursor = array.cursor_mut(3);
+        cursor.mark(XaMark::Mark0);
+        cursor.set_tag(0);
+        cursor.set_tag(1);
+        assert_eq!(cursor.tags(), Some(0b11));
+        cursor.clear_tag(1);
+        assert_eq!(cursor.current(), Some(&v[3]));
+    }
+    assert_eq!(array.tags(3), Some(0b01));
+    assert_eq!(array.tags(4), Some(0));
+    assert_eq!(array.tags(TCNT as u64), None);
+    let marked = array
+        .iter()
+        .filter_mark(XaMark::Mark0)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    assert_eq!(marked, [3]);
+    for (i, value) in array.iter() {
+        assert_eq!(*value, i);
+    }
+
+    // Replacing a value drops its tags.
+    array.remove(3);
+    array.insert(3, &v[3]).unwrap();
+    assert_eq!(array.tags(3), Some(0));
+}
+
+#[test]
+fn test_ordered() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array = XArrayOrdered::new();
+    let mut model: Vec<(u64, u64)> = Vec::new();
+    for i in 0..TCNT as u64 {
+        let index = rng.gen_range(0, 256);
+        let old = model
+            .iter()
+            .position(|(k, _)| *k == index)
+            .map(|at| model.remove(at).1);
+        if rng.gen::<bool>() {
+            assert_eq!(array.insert(index, i), old);
+            model.push((index, i));
+        } else {
+            assert_eq!(array.remove(index), old);
+        }
+        assert_eq!(array.len(), model.len() as u64);
+    }
+    let inserted = array
+        .iter_inserted()
+        .map(|(k, v)| (k, *v))
+        .collect::<Vec<_>>();
+    assert_eq!(inserted, model);
+    model.sort();
+    let sorted = array.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>();
+    assert_eq!(sorted, model);
+    for (index, value) in model {
+        assert_eq!(array.get(index), Some(&value));
+    }
+}
+
+#[test]
+fn test_slab() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut slab = XArraySlab::new();
+    let mut model: Vec<Option<u64>> = Vec::new();
+    for i in 0..TCNT as u64 {
+        let live = model.iter().filter(|v| v.is_some()).count();
+        if live == 0 || rng.gen_range(0, 3) != 0 {
+            let vacant = model
+                .iter()
+                .position(Option::is_none)
+                .unwrap_or(model.len());
+            assert_eq!(slab.vacant_key(), vacant as u64);
+            assert_eq!(slab.insert(i), vacant as u64);
+            if vacant == model.len() {
+                model.push(Some(i));
+            } else {
+                model[vacant] = Some(i);
+            }
+        } else {
+            let key = rng.gen_range(0, model.len());
+            assert_eq!(slab.remove(key as u64), model[key].take());
+        }
+        assert_eq!(
+            slab.len(),
+            model.iter().filter(|v| v.is_some()).count() as u64
+        );
+    }
+    for (key, value) in model.iter().enumerate() {
+        assert_eq!(slab.get(key as u64), value.as_ref());
+        assert_eq!(slab.contains(key as u64), value.is_some());
+    }
+    let values = slab.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>();
+    let expected = model
+        .iter()
+        .enumerate()
+        .filter_map(|(k, v)| v.map(|v| (k as u64, v)))
+        .collect::<Vec<_>>();
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn test_registry() {
+    let mut registry = XArrayRegistry::new();
+    let a = registry.insert(1u64);
+    let b = registry.insert(2u64);
+    assert_eq!((a.index(), a.generation()), (0, 0));
+    assert_eq!((b.index(), b.generation()), (1, 0));
+    assert_eq!(registry.remove(a), Some(1));
+    assert_eq!(registry.remove(a), None);
+
+    // The index is reused under a new generation; the old handle is stale.
+    let c = registry.insert(3);
+    assert_eq!((c.index(), c.generation()), (0, 1));
+    assert_eq!(registry.get(a), None);
+    assert!(!registry.contains(a));
+    assert_eq!(registry.get(c), Some(&3));
+    assert_eq!(registry.remove(a), None);
+    assert_eq!(registry.get(c), Some(&3));
+    assert_eq!(Handle::from_bits(c.to_bits()), c);
+
+    let handles = registry.iter().map(|(h, v)| (h, *v)).collect::<Vec<_>>();
+    assert_eq!(handles, [(c, 3), (b, 2)]);
+    assert_eq!(registry.len(), 2);
+
+    let mut live = Vec::new();
+    for i in 0..TCNT as u64 {
+        live.push((registry.insert(i), i));
+        if i % 3 == 0 {
+            let (handle, value) = live.swap_remove(live.len() / 2);
+            assert_eq!(registry.remove(handle), Some(value));
+            assert_eq!(registry.get(handle), None);
+        }
+    }
+    for (handle, value) in live {
+        assert_eq!(registry.get(handle), Some(&value));
+    }
+}
+
+#[test]
+fn test_cache() {
+    let mut cache = XArrayCache::new();
+    for i in 0..TCNT as u64 {
+        cache.insert(i, i);
+    }
+    cache.invalidate(3);
+    assert!(cache.is_stale(3));
+    assert_eq!(cache.get(3), None);
+    assert_eq!(cache.peek(3), Some(&3));
+    assert_eq!(*cache.get_or_refresh(3, |i, old| i + old.unwrap() + 1), 7);
+    assert!(!cache.is_stale(3));
+    assert_eq!(cache.get(3), Some(&7));
+    assert_eq!(*cache.get_or_refresh(3, |_, _| unreachable!()), 7);
+    assert_eq!(
+        *cache.get_or_refresh(TCNT as u64, |i, old| i + old.map_or(1, |_| 0)),
+        TCNT as u64 + 1
+    );
+
+    cache.invalidate_range(100..=199);
+    assert!(!cache.is_stale(99) && !cache.is_stale(200));
+    assert_eq!(cache.revalidate(|_, old| old * 2), 100);
+    for i in 0..TCNT as u64 {
+        let expected = match i {
+            3 => 7,
+            100..=199 => i * 2,
+            _ => i,
+        };
+        assert_eq!(cache.get(i), Some(&expected));
+    }
+    assert_eq!(cache.revalidate(|_, _| unreachable!()), 0);
+    cache.invalidate(5);
+    assert_eq!(cache.remove(5), Some(5));
+    assert!(!cache.is_stale(5));
+
+    // Entries may borrow from the caller, and storing over a stale entry
+    // makes it fresh.
+    let words = [1u64, 2];
+    let mut cache = XArrayCache::new();
+    assert_eq!(cache.insert(0, &words[0]), None);
+    cache.invalidate(0);
+    assert_eq!(cache.insert(0, &words[1]), Some(&words[0]));
+    assert!(!cache.is_stale(0));
+    assert_eq!(cache.get(0), Some(&&words[1]));
+}
+
+#[test]
+fn test_shrink_policy() {
+    use std::collections::BTreeMap;
+
+    let v = (0..4096).collect::<Vec<u64>>();
+    for policy in [
+        ShrinkPolicy::Immediate,
+        ShrinkPolicy::Deferred(16),
+        ShrinkPolicy::Manual,
+    ] {
+        let seed = thread_rng().gen::<[u8; 32]>();
+        let mut rng = StdRng::from_seed(seed);
+        let mut array: RawXArray<u64> = RawXArray::new();
+        array.set_shrink_policy(policy);
+        assert_eq!(array.shrink_policy(), policy);
+        let mut model = BTreeMap::new();
+        for _ in 0..TCNT {
+            let index = rng.gen_range(0, v.len());
+            if rng.gen_range(0, 3) == 0 {
+                array.insert(index as u64, &v[index]).unwrap();
+                model.insert(index as u64, &v[index]);
+            } else {
+                assert_eq!(array.remove(index as u64), model.remove(&(index as u64)));
+            }
+            assert_eq!(array.is_empty(), model.is_empty());
+            assert_eq!(array.min_index(), model.keys().next().copied());
+            assert_eq!(array.max_index(), model.keys().next_back().copied());
+        }
+        for _ in 0..2 {
+            assert_eq!(
+                array.to_vec(),
+                model.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+            );
+            assert_eq!(array.count_range(0..=u64::MAX), model.len() as u64);
+            for index in 0..v.len() as u64 {
+                assert_eq!(array.get(index), model.get(&index).copied());
+                assert_eq!(
+                    array.next_present(index),
+                    model.range(index + 1..).next().map(|(k, _)| *k)
+                );
+            }
+            array.compact();
+        }
+        let indices = model.keys().copied().collect::<Vec<_>>();
+        for index in indices {
+            array.remove(index);
+        }
+        assert!(array.is_empty());
+        array.compact();
+        assert!(array.level_report().is_empty());
+    }
+}
+
+#[test]
+fn test_borrowed_lifetimes() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let (got, current, ranged, mutated, collected) = {
+        let mut array = RawXArray::from_slice(&v);
+        let got = array.get(1).unwrap();
+        let current = array.cursor(2).current().unwrap();
+        let ranged = array.extract(3, 3).next().unwrap().1;
+        let mutated = array.iter_mut().nth(4).unwrap().1;
+        let collected = array.to_vec()[5].1;
+        (got, current, ranged, mutated, collected)
+    };
+    assert_eq!(
+        [*got, *current, *ranged, *mutated, *collected],
+        [1, 2, 3, 4, 5]
+    );
+}
+
+#[test]
+fn test_async_shared() {
+    use std::future::Future;
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Lock<'a>(Mutex<RawXArray<'a, u64>>);
+
+    impl<'a> AsyncMutex for Lock<'a> {
+        type Target = RawXArray<'a, u64>;
+        type Guard<'g>
+            = MutexGuard<'g, RawXArray<'a, u64>>
+        where
+            Self: 'g;
+
+        async fn lock(&self) -> Self::Guard<'_> {
+            self.0.lock().unwrap()
+        }
+    }
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    // Run `future` to completion, returning its output and how many times
+    // it yielded.
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        let mut yields = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, yields),
+                Poll::Pending => yields += 1,
+            }
+        }
+    }
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let shared = AsyncXArray::with_batch(Lock(Mutex::new(RawXArray::new())), 100);
+    assert_eq!(block_on(shared.insert(7, &v[7])).0, Ok(None));
+    assert_eq!(block_on(shared.get(7)).0, Some(&7));
+    assert_eq!(block_on(shared.remove(7)).0, Some(&7));
+
+    let (inserted, yields) = block_on(shared.extend((0..1000).map(|i| (i, &v[i as usize]))));
+    assert_eq!((inserted, yields), (1000, 9));
+    assert_eq!(block_on(shared.extend([(0, &v[1])])).0, 0);
+
+    let mut seen = Vec::new();
+    let (visited, yields) = block_on(shared.for_each(50..=849, |i, v| seen.push((i, *v))));
+    assert_eq!((visited, yields), (800, 7));
+    assert_eq!(seen, (50..850).map(|i| (i, i)).collect::<Vec<_>>());
+    assert_eq!(block_on(shared.for_each(0..=u64::MAX, |_, _| ())).0, 1000);
+    assert_eq!(block_on(shared.for_each(5000..=6000, |_, _| ())), (0, 0));
+    assert!(!shared.mutex().0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_view() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    for i in (0..TCNT).step_by(3) {
+        array.insert(i as u64 * 7, &v[i]).unwrap();
+    }
+    array.cursor_mut(21).mark(XaMark::Mark1);
+    {
+        let view = unsafe { XArrayView::<u64>::from_raw_head(array.raw_head()) };
+        assert_eq!(view.to_vec(), array.to_vec());
+        assert_eq!(view.get(21), Some(&3));
+        assert_eq!(view.get(22), None);
+        assert!(view.is_marked(XaMark::Mark1) && !view.is_marked(XaMark::Mark0));
+        let marked = view.iter().filter_mark(XaMark::Mark1).collect::<Vec<_>>();
+        assert_eq!(marked, [(21, &3)]);
+    }
+    // Dropping the view leaves the nodes to their owner.
+    assert_eq!(array.get(21), Some(&3));
+
+    let single = RawXArray::from_slice(&v[..1]);
+    let view = unsafe { XArrayView::<u64>::from_raw_head(single.raw_head()) };
+    assert_eq!(view.to_vec(), [(0, &0)]);
+    let empty = unsafe { XArrayView::<u64>::from_raw_head(0) };
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn test_watermarks() {
+    fn nodes(array: &RawXArray<u64>) -> u64 {
+        array.level_report().iter().map(|level| level.nodes).sum()
+    }
+
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(
+        (array.node_count(), array.peak_nodes(), array.high_index()),
+        (0, 0, None)
+    );
+    let mut keys = Vec::new();
+    let mut peak = 0;
+    for value in v.iter() {
+        let key = rng.gen_range(0, 1 << 20);
+        array.insert(key, value).unwrap();
+        keys.push(key);
+        if rng.gen::<bool>() {
+            array.remove(keys.swap_remove(rng.gen_range(0, keys.len())));
+        }
+        assert_eq!(array.node_count(), nodes(&array));
+        peak = peak.max(array.node_count());
+    }
+    assert!(array.peak_nodes() >= peak);
+    let high = array.high_index().unwrap();
+    assert!(array.max_index().unwrap() <= high);
+
+    let mut other: RawXArray<u64> = RawXArray::new();
+    array.splice(0..=(1 << 19) - 1, &mut other, 1 << 30);
+    assert_eq!(array.node_count(), nodes(&array));
+    assert_eq!(other.node_count(), nodes(&other));
+    assert_eq!(other.high_index(), other.max_index());
+
+    for key in keys {
+        array.remove(key);
+    }
+    assert_eq!(array.node_count(), 0);
+    assert!(array.peak_nodes() >= peak);
+    assert_eq!(array.high_index(), Some(high));
+    array.reset_watermarks();
+    assert_eq!((array.peak_nodes(), array.high_index()), (0, None));
+}
+
+#[test]
+fn test_partition_by_mark() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    let mut dirty = Vec::new();
+    let mut clean = Vec::new();
+    for i in 0..TCNT as u64 {
+        let index = i * 3 + rng.gen_range(0, 3);
+        array.insert(index, Box::new(i)).unwrap();
+        let mut cursor = array.cursor_mut(index);
+        if i % 5 == 0 {
+            cursor.mark(XaMark::Mark1);
+        }
+        if rng.gen::<bool>() {
+            cursor.mark(XaMark::Mark0);
+            dirty.push((index, i));
+        } else {
+            clean.push((index, i));
+        }
+    }
+    let flush = array.partition_by_mark(XaMark::Mark0);
+    let entries = |array: &XArrayBoxed<u64>| array.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>();
+    assert_eq!(entries(&flush), dirty);
+    assert_eq!(entries(&array), clean);
+    assert!(!array.is_marked(XaMark::Mark0));
+    let marked = |array: &XArrayBoxed<u64>, mark| {
+        array
+            .iter()
+            .filter_mark(mark)
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(marked(&flush, XaMark::Mark0).len(), dirty.len());
+    assert!(marked(&flush, XaMark::Mark1).iter().all(|v| v % 5 == 0));
+    assert!(marked(&array, XaMark::Mark1).iter().all(|v| v % 5 == 0));
+    assert_eq!(
+        marked(&flush, XaMark::Mark1).len() + marked(&array, XaMark::Mark1).len(),
+        TCNT.div_ceil(5)
+    );
+}
+
+#[test]
+fn test_filter_unmarked() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    let mut model = Vec::new();
+    for i in 0..TCNT as u64 {
+        let index = i * 2 + rng.gen_range(0, 2);
+        array.insert(index, Box::new(i)).unwrap();
+        // Whole leaves are marked now and then to take the skipping path.
+        let marked = (index >> 6) % 4 == 1 || rng.gen_range(0, 3) == 0;
+        if marked {
+            array.cursor_mut(index).mark(XaMark::Mark2);
+        }
+        model.push((index, i, marked));
+    }
+    let unmarked = |start: u64, end: u64| {
+        model
+            .iter()
+            .filter(|(index, _, marked)| !marked && (start..=end).contains(index))
+            .map(|&(index, i, _)| (index, i))
+            .collect::<Vec<_>>()
+    };
+    let found = array
+        .iter()
+        .filter_unmarked(XaMark::Mark2)
+        .map(|(k, v)| (k, *v))
+        .collect::<Vec<_>>();
+    assert_eq!(found, unmarked(0, u64::MAX));
+    for _ in 0..100 {
+        let start = rng.gen_range(0, TCNT as u64 * 2);
+        let end = rng.gen_range(start, TCNT as u64 * 2);
+        let found = array
+            .extract(start, end)
+            .filter_unmarked(XaMark::Mark2)
+            .map(|(k, v)| (k, *v))
+            .collect::<Vec<_>>();
+        assert_eq!(found, unmarked(start, end));
+    }
+}
+
+#[test]
+fn test_merge_splits() {
+    let (p1, p2) = (1, 2);
+    let removed = std::cell::Cell::new(0);
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.insert_order(1 << 12, 12, &p1), Ok(None));
+    array.cursor_mut(1 << 12).mark(XaMark::Mark0);
+    array.set_on_remove(|_, _| unreachable!());
+    // Merging frees the nodes below the entry without dropping the pieces.
+    array.release = Some(|_| unreachable!());
+    let span = |array: &RawXArray<u64>, i| array.entry_span(i).map(|(f, l, _)| (f, l));
+
+    // Splitting one level at a time, then putting the value back into every
+    // piece, merges the pieces outwards into the original entry.
+    assert_eq!(array.cursor_mut(5000).split(6), Ok(()));
+    assert_eq!(array.cursor_mut(4096).split(0), Ok(()));
+    assert_eq!(span(&array, 4096), Some((4096, 4096)));
+    array.take_on_remove();
+    array.set_on_remove(|_, _| removed.set(removed.get() + 1));
+    assert_eq!(array.store(4100, &p2), Ok(Some(&p1)));
+    assert_eq!(removed.replace(0), 1);
+    array.cursor_mut(4101).unmark(XaMark::Mark0);
+    assert_eq!(array.store(4100, &p1), Ok(Some(&p2)));
+    assert_eq!(span(&array, 4100), Some((4100, 4100)));
+    array.cursor_mut(4101).mark(XaMark::Mark0);
+    assert_eq!(span(&array, 4100), Some((1 << 12, (1 << 13) - 1)));
+    assert_eq!(array.extract(0, u64::MAX).count(), 1);
+    assert!(array.cursor((1 << 13) - 1).is_marked(XaMark::Mark0));
+    assert_eq!(removed.get(), 1);
+    assert!(array.splits.is_empty());
+
+    // Storing a different value over every piece merges them as well.
+    assert_eq!(array.cursor_mut(5000).split(6), Ok(()));
+    for i in ((1 << 12)..(1 << 13)).step_by(64) {
+        array.cursor_mut(i).unmark(XaMark::Mark0);
+        assert_eq!(span(&array, i), Some((i, i + 63)));
+        array.store(i, &p2).unwrap();
+    }
+    assert_eq!(span(&array, 5000), Some((1 << 12, (1 << 13) - 1)));
+    assert_eq!(array.get(8000), Some(&p2));
+    assert!(!array.is_marked(XaMark::Mark0));
+
+    // Removing every piece forgets the split, and stores over the whole
+    // range replace it.
+    assert_eq!(array.cursor_mut(5000).split(6), Ok(()));
+    for i in ((1 << 12)..(1 << 13)).step_by(64) {
+        assert_eq!(array.remove(i), Some(&p2));
+    }
+    assert!(array.splits.is_empty());
+    assert!(array.is_empty());
+    assert_eq!(array.insert_order(1 << 12, 12, &p1), Ok(None));
+    assert_eq!(array.cursor_mut(5000).split(6), Ok(()));
+    array.release = None;
+    assert_eq!(array.cursor_mut(0).store_order(16, &p2), Ok(None));
+    assert!(array.splits.is_empty());
+
+    // A split is dropped once its pieces are gone, even while the entry it
+    // was split from stays apart.
+    assert_eq!(array.cursor_mut(0).split(12), Ok(()));
+    assert_eq!(array.cursor_mut(4096).split(6), Ok(()));
+    assert_eq!(array.splits.len(), 2);
+    array.take_on_remove();
+    for i in (4096..8192).step_by(64) {
+        assert_eq!(array.remove(i), Some(&p2));
+    }
+    assert_eq!(array.splits.len(), 1);
+    array.clear();
+    assert!(array.splits.is_empty());
+    assert_eq!(array.node_count(), 0);
+}
+
+#[test]
+fn test_store_policy() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let v = (0..300u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let removals = Rc::new(Cell::new(0));
+    let hook = removals.clone();
+    array.set_on_remove(move |_, _| hook.set(hook.get() + 1));
+    array.insert(66, &v[66]).unwrap();
+    array.insert(70, &v[70]).unwrap();
+    assert_eq!(
+        array.store_order_with(76, 1, &v[76], StorePolicy::Force),
+        Ok(vec![])
+    );
+    assert_eq!(array.get(77), Some(&v[76]));
+    array.cursor_mut(70).mark(XaMark::Mark0);
+
+    // Failing leaves the range as it was.
+    assert_eq!(
+        array.store_order_with(64, 4, &v[1], StorePolicy::Fail),
+        Err(XaError::Busy)
+    );
+    assert_eq!(array.iter().count(), 3);
+    assert_eq!(array.get(64), None);
+    assert_eq!(
+        array.store_order_with(80, 4, &v[2], StorePolicy::Fail),
+        Ok(vec![])
+    );
+    assert_eq!(array.extract(80, 95).count(), 1);
+    assert_eq!(array.get(95), Some(&v[2]));
+    assert_eq!(
+        array.store_order_with(u64::MAX, 4, &v[2], StorePolicy::Fail),
+        Ok(vec![])
+    );
+    assert_eq!(array.remove(u64::MAX), Some(&v[2]));
+    assert_eq!(array.get(u64::MAX - 15), None);
+    removals.set(0);
+
+    // Overwriting hands back every displaced entry, and still tells the
+    // hook about them.
+    assert_eq!(
+        array.store_order_with(64, 4, &v[3], StorePolicy::Overwrite),
+        Ok(vec![(66, &v[66]), (70, &v[70]), (76, &v[76])])
+    );
+    assert_eq!(removals.get(), 3);
+    assert_eq!(array.extract(64, 79).count(), 1);
+    assert_eq!(array.get(79), Some(&v[3]));
+    assert!(array.cursor(64).is_marked(XaMark::Mark0));
+    assert_eq!(
+        array.store_order_with(96, 4, &v[4], StorePolicy::Overwrite),
+        Ok(vec![])
+    );
+
+    // Forcing replaces them without collecting them.
+    assert_eq!(
+        array.store_order_with(64, 5, &v[5], StorePolicy::Force),
+        Ok(vec![])
+    );
+    assert_eq!(removals.get(), 5);
+    assert_eq!(array.extract(64, 95).count(), 1);
+    assert_eq!(array.get(96), Some(&v[4]));
+    assert_eq!(array.iter().count(), 2);
+
+    let mut array: RawXArray<u64> = RawXArray::with_limit(200);
+    for policy in [
+        StorePolicy::Fail,
+        StorePolicy::Overwrite,
+        StorePolicy::Force,
+    ] {
+        assert_eq!(
+            array.store_order_with(192, 4, &v[6], policy),
+            Err(XaError::OutOfBounds)
+        );
+    }
+    assert!(array.is_empty());
+}
+
+#[test]
+fn test_zip_iter() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut a: XArrayBoxed<u64> = XArrayBoxed::new();
+    let mut b: XArrayBoxed<u32> = XArrayBoxed::new();
+    let mut model = std::collections::BTreeMap::new();
+    for i in 0..TCNT as u64 {
+        let index = rng.gen_range(0, TCNT as u64 * 8);
+        let entry = model.entry(index).or_insert((None, None));
+        if rng.gen::<bool>() {
+            a.remove(index);
+            a.insert(index, Box::new(i)).unwrap();
+            entry.0 = Some(i);
+        } else {
+            b.remove(index);
+            b.insert(index, Box::new(i as u32)).unwrap();
+            entry.1 = Some(i as u32);
+        }
+    }
+    for _ in 0..100 {
+        let start = rng.gen_range(0, TCNT as u64 * 8);
+        let end = rng.gen_range(start, TCNT as u64 * 8);
+        let found = crate::zip_iter(a.as_raw(), b.as_raw(), start..=end)
+            .map(|(index, a, b)| (index, (a.copied(), b.copied())))
+            .collect::<Vec<_>>();
+        let expected = model
+            .range(start..=end)
+            .map(|(k, v)| (*k, *v))
+            .collect::<Vec<_>>();
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn test_get_unchecked() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    array.insert(0, Box::new(0)).unwrap();
+    assert_eq!(unsafe { *array.as_raw().get_unchecked(0) }, 0);
+    let mut indices = vec![0];
+    for i in 1..TCNT as u64 {
+        let index = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        if array.get(index).is_none() {
+            array.insert(index, Box::new(i)).unwrap();
+            indices.push(index);
+        }
+    }
+    for &index in &indices {
+        assert_eq!(
+            unsafe { array.as_raw().get_unchecked(index) },
+            array.get(index).unwrap()
+        );
+    }
+    for i in 0..crate::node::CHUNK_SIZE as u64 {
+        array.remove(0x4000 + i);
+        array.insert(0x4000 + i, Box::new(i)).unwrap();
+    }
+    let mut cursor = array.cursor(0x4000);
+    assert!(cursor.current().is_some());
+    let sum = (0..crate::node::CHUNK_SIZE as u8)
+        .map(|offset| unsafe { *cursor.leaf_get_unchecked(offset) })
+        .sum::<u64>();
+    assert_eq!(sum, (0..crate::node::CHUNK_SIZE as u64).sum());
+}
+
+#[test]
+fn test_leaves() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    array.insert(0, Box::new(0)).unwrap();
+    assert_eq!(array.as_raw().leaves().count(), 0);
+    for i in 1..TCNT as u64 {
+        let index = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        if array.get(index).is_none() {
+            array.insert(index, Box::new(i)).unwrap();
+        }
+    }
+    let mut found = Vec::new();
+    for (base, slots) in array.as_raw().leaves() {
+        assert_eq!(base % 64, 0);
+        assert!(slots.iter().any(|slot| slot.is_value()));
+        found.extend(
+            slots
+                .iter()
+                .enumerate()
+                .filter_map(|(offset, slot)| Some((base + offset as u64, *slot.value()?))),
+        );
+    }
+    let expected = array.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>();
+    assert_eq!(found, expected);
+
+    // Multi-order entries above the leaves are stepped over.
+    let p = 1;
+    let mut array: RawXArray<u64> = RawXArray::new();
+    for (index, order) in [(0, 7), (130, 1)] {
+        assert_eq!(
+            array.store_order_with(index, order, &p, StorePolicy::Force),
+            Ok(vec![])
+        );
+    }
+    let leaves = array
+        .leaves()
+        .map(|(base, slots)| (base, slots.iter().filter(|slot| slot.is_value()).count()))
+        .collect::<Vec<_>>();
+    assert_eq!(leaves, vec![(128, 1)]);
+}
+
+#[cfg(feature = "critical-section")]
+#[test]
+fn test_critical_section() {
+    static VALUES: [u64; 256] = {
+        let mut values = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            values[i] = i as u64;
+            i += 1;
+        }
+        values
+    };
+    let xa = std::sync::Arc::new(CsXArray::new());
+    let handles = (0..4)
+        .map(|t| {
+            let xa = xa.clone();
+            std::thread::spawn(move || {
+                for i in (t..256).step_by(4) {
+                    assert_eq!(xa.insert(i as u64 * 7, &VALUES[i]), Ok(None));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    for (i, value) in VALUES.iter().enumerate() {
+        assert_eq!(xa.get(i as u64 * 7), Some(value));
+    }
+    assert_eq!(xa.remove(7), Some(&1));
+    xa.set_mark(14, XaMark::Mark1);
+    assert!(xa.read(|xa| xa.cursor(14).is_marked(XaMark::Mark1)));
+    xa.clear_mark(14, XaMark::Mark1);
+    assert!(!xa.read(|xa| xa.is_marked(XaMark::Mark1)));
+    assert_eq!(xa.read(|xa| xa.iter().count()), 255);
+}
+
+#[cfg(feature = "aux")]
+#[test]
+fn test_aux() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    // The head value keeps its byte when moving into a leaf and back.
+    array.insert(0, Box::new(0)).unwrap();
+    array.cursor_mut(0).set_aux(7);
+    array.insert(1 << 20, Box::new(1)).unwrap();
+    assert_eq!(array.cursor(0).aux(), Some(7));
+    array.remove(1 << 20);
+    assert_eq!(array.cursor(0).aux(), Some(7));
+    array.remove(0);
+    array.insert(0, Box::new(0)).unwrap();
+    assert_eq!(array.cursor(0).aux(), Some(0));
+    array.remove(0);
+
+    let mut model = std::collections::BTreeMap::new();
+    for i in 0..TCNT as u64 {
+        let index = rng.gen_range(0, TCNT as u64 * 4);
+        if array.get(index).is_none() {
+            array.insert(index, Box::new(i)).unwrap();
+            let aux = rng.gen::<u8>();
+            array.cursor_mut(index).set_aux(aux);
+            model.insert(index, aux);
+        }
+    }
+    assert_eq!(array.cursor_mut(TCNT as u64 * 4).aux(), None);
+    let found = array
+        .iter()
+        .with_aux()
+        .map(|(index, _, aux)| (index, aux))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        model.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>()
+    );
+    array.shift_range(0..=TCNT as u64 * 4, 100);
+    for (&index, &aux) in model.iter() {
+        assert_eq!(array.cursor(index + 100).aux(), Some(aux));
+    }
+    array.shift_range(100..=TCNT as u64 * 4 + 100, -100);
+    for (&index, _) in model.iter().step_by(3) {
+        array.remove(index);
+        array.insert(index, Box::new(0)).unwrap();
+        assert_eq!(array.cursor(index).aux(), Some(0));
+    }
+}
+
+#[test]
+fn test_bookmark() {
+    let values = (0..1024u64).collect::<Vec<_>>();
+    let mut xa = RawXArray::new();
+    for i in (0..1024).step_by(3) {
+        xa.insert(i as u64, &values[i]).unwrap();
+    }
+    let mut cursor = xa.cursor(300);
+    let start = cursor.bookmark();
+    assert_eq!(start.index(), 300);
+    cursor.next_allocated();
+    cursor.next_allocated();
+    assert_eq!(cursor.key(), 306);
+    cursor.restore(&start);
+    assert_eq!((cursor.key(), cursor.current()), (300, Some(&300)));
+
+    // Removing through the cursor frees nodes, so the saved node is no
+    // longer trusted and the cursor walks back down.
+    let mut cursor = xa.cursor_mut(0);
+    let start = cursor.bookmark();
+    while cursor.key() < 900 {
+        cursor.remove();
+        cursor.next_allocated();
+    }
+    cursor.restore(&start);
+    assert_eq!((cursor.key(), cursor.current()), (0, None));
+    cursor.next_allocated();
+    assert_eq!((cursor.key(), cursor.current()), (900, Some(&900)));
+}
+
+#[test]
+fn test_watches() {
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct Counter(AtomicUsize);
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let values = (0..16u64).collect::<Vec<_>>();
+    let counter = Arc::new(Counter(AtomicUsize::new(0)));
+    let waker = counter.clone().into();
+    let mut cx = Context::from_waker(&waker);
+    let watches = Watches::new();
+    let mut xa = RawXArray::new();
+    xa.set_observer(watches.clone());
+
+    let mut slot = Box::pin(watches.changed(5..=5));
+    let mut range = Box::pin(watches.changed(8..=15));
+    assert_eq!(slot.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(range.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(watches.len(), 2);
+
+    xa.insert(4, &values[4]).unwrap();
+    assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+    xa.insert(5, &values[5]).unwrap();
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    assert_eq!(slot.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert_eq!(range.as_mut().poll(&mut cx), Poll::Pending);
+
+    xa.cursor_mut(4).mark(XaMark::Mark0);
+    xa.insert(9, &values[9]).unwrap();
+    xa.cursor_mut(9).mark(XaMark::Mark0);
+    assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+    assert_eq!(range.as_mut().poll(&mut cx), Poll::Ready(()));
+    assert!(watches.is_empty());
+
+    let dropped = watches.changed(0..=u64::MAX);
+    let mut polled = Box::pin(watches.changed(0..=u64::MAX));
+    assert_eq!(polled.as_mut().poll(&mut cx), Poll::Pending);
+    drop(polled);
+    drop(dropped);
+    assert!(watches.is_empty());
+    xa.remove(9);
+    assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_remove_with_marks() {
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..TCNT as u64 {
+        array.insert(i * 5, Box::new(i)).unwrap();
+        let mut cursor = array.cursor_mut(i * 5);
+        if i % 2 == 0 {
+            cursor.mark(XaMark::Mark0);
+        }
+        if i % 3 == 0 {
+            cursor.mark(XaMark::Mark2);
+        }
+    }
+    assert_eq!(array.remove_with_marks(1), None);
+    let mut moved: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..TCNT as u64 {
+        let (value, marks) = array.remove_with_marks(i * 5).unwrap();
+        assert_eq!(*value, i);
+        assert_eq!(marks.contains(XaMark::Mark0), i % 2 == 0);
+        assert!(!marks.contains(XaMark::Mark1));
+        assert_eq!(marks.contains(XaMark::Mark2), i % 3 == 0);
+        assert_eq!(marks.is_empty(), i % 2 != 0 && i % 3 != 0);
+        moved.insert(i, value).unwrap();
+        let mut cursor = moved.cursor_mut(i);
+        marks.iter().for_each(|mark| cursor.mark(mark));
+    }
+    assert!(array.is_empty());
+    assert!(!array.is_marked(XaMark::Mark0));
+    assert_eq!(
+        moved.iter().filter_mark(XaMark::Mark0).count(),
+        TCNT.div_ceil(2)
+    );
+    assert_eq!(
+        moved.iter().filter_mark(XaMark::Mark2).count(),
+        TCNT.div_ceil(3)
+    );
+}
+
+#[test]
+fn test_present_bitmap() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    let mut bitmap = [0u64; 8];
+    array.insert(0, Box::new(0)).unwrap();
+    array.as_raw().present_bitmap(0..=9, &mut bitmap);
+    assert_eq!(bitmap[0], 1);
+    let mut present = std::collections::BTreeSet::from([0]);
+    for i in 0..TCNT as u64 {
+        let index = rng.gen_range(0, 4096);
+        if array.get(index).is_none() {
+            array.insert(index, Box::new(i)).unwrap();
+            present.insert(index);
+        }
+    }
+    // A stored `None` holds no value.
+    array.insert_option(5000, None);
+    for _ in 0..200 {
+        let start = rng.gen_range(0, 5100);
+        let end = rng.gen_range(start, start + 512);
+        bitmap.fill(u64::MAX);
+        array.as_raw().present_bitmap(start..=end, &mut bitmap);
+        for i in 0..512 {
+            let index = start + i;
+            let expected = index <= end && present.contains(&index);
+            assert_eq!(bitmap[i as usize / 64] >> (i % 64) & 1 == 1, expected);
+        }
+    }
+}
+
+#[test]
+fn test_prefault() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let values = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut indices = (0..TCNT)
+        .map(|_| rng.gen::<u64>() >> rng.gen_range(0, 64))
+        .collect::<Vec<_>>();
+    indices.push(0);
+    let mut xa = RawXArray::with_limit(u64::MAX - 1);
+    assert_eq!(xa.prefault([1, u64::MAX]), Err(XaError::OutOfBounds));
+    assert_eq!(xa.node_count(), 0);
+    xa.prefault(indices.iter().copied()).unwrap();
+    assert!(xa.is_empty());
+    assert_eq!(xa.iter().count(), 0);
+    let nodes = xa.node_count();
+    indices.shuffle(&mut rng);
+    for (index, value) in indices.iter().zip(&values) {
+        xa.insert(*index, value).unwrap();
+    }
+    assert_eq!(xa.node_count(), nodes);
+    assert_eq!(xa.peak_nodes(), nodes);
+    for (index, value) in indices.iter().zip(&values) {
+        if xa.get(*index) == Some(value) {
+            xa.remove(*index);
+        }
+    }
+    // Prefaulted nodes off the removed paths are left for compaction.
+    xa.compact();
+    assert_eq!(xa.node_count(), 0);
+}
+
+#[test]
+fn test_dump_to() {
+    use std::string::String;
+
+    let values = (0..256u64).map(|i| i * 1000 + 7).collect::<Vec<_>>();
+    let mut xa = RawXArray::new();
+    for (i, value) in values.iter().enumerate() {
+        xa.insert(i as u64 * 3, value).unwrap();
+    }
+    let dump = |opts: &DumpOptions| {
+        let mut out = String::new();
+        xa.dump_to(&mut out, opts).unwrap();
+        out
+    };
+    let full = dump(&DumpOptions::default());
+    assert!(full.starts_with("XArray {\n") && full.ends_with("}\n"));
+    assert!(values.iter().all(|v| full.contains(&format!(": {},", v))));
+
+    let ranged = dump(&DumpOptions {
+        range: 300..=330,
+        ..Default::default()
+    });
+    for (i, v) in values.iter().enumerate() {
+        let index = i as u64 * 3;
+        assert_eq!(
+            ranged.contains(&format!(": {},", v)),
+            (300..=330).contains(&index)
+        );
+    }
+
+    let shallow = dump(&DumpOptions {
+        max_depth: 0,
+        ..Default::default()
+    });
+    assert!(shallow.contains("Node"));
+    assert!(!values
+        .iter()
+        .any(|v| shallow.contains(&format!(": {},", v))));
+
+    let few = dump(&DumpOptions {
+        max_entries: 2,
+        ..Default::default()
+    });
+    assert!(few.contains("... 20 more,"));
+
+    for max_bytes in [0, 1, 10, 100, 1000] {
+        let cut = dump(&DumpOptions {
+            max_bytes,
+            ..Default::default()
+        });
+        assert_eq!(cut, full[..max_bytes]);
+    }
+}
+
+#[test]
+fn test_multi_order() {
+    let (p1, p2, p3) = (1, 2, 3);
+    let mut array: RawXArray<u64> = RawXArray::new();
+    for order in [0, 3, 6, 9, 13] {
+        let base = 1 << 20;
+        let last = base | ((1 << order) - 1);
+        assert_eq!(array.insert_order(last, order, &p1), Ok(None));
+        for index in [base, base + (1 << order) / 2, last] {
+            assert_eq!(array.get(index), Some(&p1));
+        }
+        assert_eq!(array.get(base - 1), None);
+        assert_eq!(array.get(last + 1), None);
+        assert_eq!(unsafe { *array.get_unchecked(last) }, 1);
+        assert_eq!(
+            array.extract(0, u64::MAX).collect::<Vec<_>>(),
+            vec![(base, &p1)]
+        );
+        let mut bitmap = [0u64; 2];
+        array.present_bitmap(last - 63..=last + 64, &mut bitmap);
+        assert_eq!(bitmap[0], u64::MAX << (64 - (1u64 << order).min(64)));
+        assert_eq!(bitmap[1], 0);
+        // Conflicts leave the entry untouched.
+        assert_eq!(array.insert_order(last, order, &p2), Ok(Some(&p1)));
+        assert_eq!(array.insert_order(last, order + 1, &p2), Ok(Some(&p1)));
+        // Removing any index within the entry removes all of it.
+        assert_eq!(array.remove(base + (1 << order) / 2), Some(&p1));
+        assert_eq!(array.get(base), None);
+        assert_eq!(array.get(last), None);
+        assert_eq!(array.node_count(), 0);
+    }
+
+    // Storing over smaller entries replaces them and squashes their marks.
+    for i in 0..8 {
+        assert!(array.insert(64 + i, &p1).unwrap().is_none());
+    }
+    array.cursor_mut(66).mark(XaMark::Mark1);
+    let mut cursor = array.cursor_mut(70);
+    assert_eq!(cursor.store_order(3, &p2), Ok(Some(&p1)));
+    assert_eq!(cursor.current(), Some(&p2));
+    assert_eq!(array.get(71), Some(&p2));
+    assert!(array.cursor(71).is_marked(XaMark::Mark1));
+    assert!(!array.cursor(71).is_marked(XaMark::Mark0));
+    assert_eq!(array.extract(0, 200).count(), 1);
+
+    // An entry covering every index.
+    assert_eq!(array.cursor_mut(0).store_order(64, &p3), Ok(None));
+    assert_eq!(array.get(0), Some(&p3));
+    assert_eq!(array.get(u64::MAX), Some(&p3));
+    assert_eq!(array.remove(1 << 40), Some(&p3));
+    assert_eq!(array.get(0), None);
+    assert_eq!(array.node_count(), 0);
+
+    let mut array: RawXArray<u64> = RawXArray::with_limit(127);
+    assert_eq!(array.insert_order(64, 6, &p1), Ok(None));
+    assert_eq!(array.insert_order(128, 7, &p1), Err(XaError::OutOfBounds));
+}
+
+#[test]
+fn test_store() {
+    let (p1, p2) = (1, 2);
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.store(3, &p1), Ok(None));
+    array.cursor_mut(3).mark(XaMark::Mark0);
+    assert_eq!(array.store(3, &p2), Ok(Some(&p1)));
+    assert_eq!(array.get(3), Some(&p2));
+    assert!(array.cursor(3).is_marked(XaMark::Mark0));
+    assert_eq!(array.cursor_mut(3).store(&p1), Ok(Some(&p2)));
+    assert_eq!(array.get(3), Some(&p1));
+
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..TCNT as u64 {
+        assert!(array.store(i * 7, Box::new(i)).unwrap().is_none());
+    }
+    for i in 0..TCNT as u64 {
+        assert_eq!(array.store(i * 7, Box::new(i + 1)), Ok(Some(Box::new(i))));
+    }
+    for i in 0..TCNT as u64 {
+        assert_eq!(array.get(i * 7), Some(&(i + 1)));
+    }
+}
+
+#[test]
+fn test_alloc() {
+    let v = [0, 1, 2, 3];
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.alloc(&v[0]), Ok(0));
+    assert_eq!(array.alloc(&v[1]), Ok(1));
+    array.insert_option(2, None);
+    assert_eq!(array.alloc(&v[2]), Ok(3));
+    array.remove(1);
+    assert_eq!(array.alloc(&v[3]), Ok(1));
+    assert_eq!(array.get(1), Some(&v[3]));
+
+    let limit = XaLimit { min: 10, max: 12 };
+    for i in 10..=12 {
+        assert_eq!(array.alloc_in(&v[0], limit), Ok(i));
+    }
+    assert_eq!(array.alloc_in(&v[0], limit), Err(XaError::Busy));
+    assert_eq!(array.alloc_in(&v[0], XaLimit::B32), Ok(4));
+
+    let mut array: RawXArray<u64> = RawXArray::with_limit(1);
+    assert_eq!(array.alloc(&v[0]), Ok(0));
+    assert_eq!(array.alloc(&v[0]), Ok(1));
+    assert_eq!(array.alloc(&v[0]), Err(XaError::Busy));
+
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..TCNT as u64 {
+        assert_eq!(array.alloc(Box::new(i)), Ok(i));
+    }
+    for i in (0..TCNT as u64).step_by(3) {
+        array.remove(i);
+    }
+    for i in (0..TCNT as u64).step_by(3) {
+        assert_eq!(array.alloc(Box::new(i)), Ok(i));
+    }
+    let limit = XaLimit { min: 5, max: 5 };
+    assert_eq!(array.alloc_in(Box::new(0), limit), Err(XaError::Busy));
+}
+
+#[test]
+fn test_reserve() {
+    let (p1, p2) = (1, 2);
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.reserve(1000), Ok(()));
+    let nodes = array.node_count();
+    assert_eq!(array.get(1000), None);
+    assert_eq!(array.get_option(1000), Some(None));
+    assert_eq!(array.insert(1000, &p1), Err(XaError::Busy));
+    assert_eq!(array.get(1000), None);
+    assert_eq!(array.store(1000, &p2), Ok(None));
+    assert_eq!(array.node_count(), nodes);
+    assert_eq!(array.get(1000), Some(&p2));
+    // Reserving or releasing a value is a no-op.
+    assert_eq!(array.reserve(1000), Ok(()));
+    array.release(1000);
+    assert_eq!(array.get(1000), Some(&p2));
+
+    assert_eq!(array.reserve(5), Ok(()));
+    array.release(5);
+    assert_eq!(array.get_option(5), None);
+    assert_eq!(array.insert(5, &p1), Ok(None));
+    assert_eq!(array.get(5), Some(&p1));
+    array.remove(5);
+    array.remove(1000);
+    assert_eq!(array.node_count(), 0);
+
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::with_limit(10);
+    assert_eq!(array.reserve(11), Err(XaError::OutOfBounds));
+    assert_eq!(array.reserve(3), Ok(()));
+    assert_eq!(array.insert(3, Box::new(3)), Err(XaError::Busy));
+    assert_eq!(array.alloc(Box::new(0)), Ok(0));
+    array.release(3);
+    assert_eq!(array.insert(3, Box::new(3)), Ok(None));
+}
+
+#[test]
+fn test_store_range() {
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..16u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut model = std::collections::BTreeMap::new();
+    for i in 0..64 {
+        let start = rng.gen_range(0, 20000);
+        let end = start + rng.gen_range(0, 3000);
+        let value = &v[i % v.len()];
+        assert_eq!(array.store_range(start, end, value), Ok(()));
+        for index in start..=end {
+            model.insert(index, value);
+        }
+    }
+    for index in 0..24000 {
+        assert_eq!(array.get(index), model.get(&index).copied(), "{seed:?}");
+    }
+    assert!(array.node_count() < 1000);
+    for index in 0..24000 {
+        array.remove(index);
+    }
+    assert_eq!(array.node_count(), 0);
+
+    assert_eq!(array.store_range(0, u64::MAX, &v[1]), Ok(()));
+    assert_eq!(array.get(u64::MAX), Some(&v[1]));
+    assert_eq!(array.node_count(), 1);
+    assert_eq!(array.store_range(1, u64::MAX - 1, &v[2]), Ok(()));
+    assert_eq!(array.get(0), Some(&v[1]));
+    assert_eq!(array.get(1 << 40), Some(&v[2]));
+    assert_eq!(array.get(u64::MAX), Some(&v[1]));
+    assert!(array.node_count() < 32);
+
+    // Entries straddling the range keep their value and marks outside.
+    array.cursor_mut(1 << 40).mark(XaMark::Mark2);
+    assert_eq!(array.store_range(1 << 40, (1 << 40) + 2, &v[3]), Ok(()));
+    assert_eq!(array.get((1 << 40) - 1), Some(&v[2]));
+    assert_eq!(array.get((1 << 40) + 2), Some(&v[3]));
+    assert_eq!(array.get((1 << 40) + 3), Some(&v[2]));
+    assert!(array.cursor((1 << 40) + 3).is_marked(XaMark::Mark2));
+    assert!(!array.cursor(1 << 40).is_marked(XaMark::Mark2));
+    assert_eq!(array.get(u64::MAX - 1), Some(&v[2]));
+
+    // Only the entries wholly within the range leave the array.
+    let removed = std::rc::Rc::new(core::cell::RefCell::new(Vec::new()));
+    let hook = removed.clone();
+    array.set_on_remove(move |_, value: &u64| hook.borrow_mut().push(*value));
+    let at = (1 << 40) - 1;
+    assert_eq!(array.store_range(at, at + 2, &v[4]), Ok(()));
+    assert_eq!(array.get(at - 1), Some(&v[2]));
+    assert_eq!(array.get(at + 3), Some(&v[3]));
+    assert!(removed.borrow().is_empty());
+    assert_eq!(array.store_range(at - 1, at + 3, &v[5]), Ok(()));
+    assert_eq!(*removed.borrow(), [4, 4, 3]);
+    assert_eq!(array.get(at - 2), Some(&v[2]));
+    assert_eq!(array.get(at + 4), Some(&v[2]));
+    drop(array.take_on_remove());
+
+    let mut array: RawXArray<u64> = RawXArray::with_limit(100);
+    assert_eq!(array.store_range(5, 3, &v[0]), Ok(()));
+    assert!(array.is_empty());
+    assert_eq!(array.store_range(5, 101, &v[0]), Err(XaError::OutOfBounds));
+    assert_eq!(array.store_range(5, 100, &v[0]), Ok(()));
+    assert!((5..=100).all(|i| array.get(i) == Some(&v[0])));
+}
+
+#[test]
+fn test_split() {
+    let (p1, p2) = (1, 2);
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.insert_order(1 << 12, 12, &p1), Ok(None));
+    assert!(array.insert(0, &p2).unwrap().is_none());
+    array.cursor_mut(1 << 12).mark(XaMark::Mark0);
+    array.set_on_remove(|_, _| unreachable!());
+
+    // Too large a split, or one into larger pieces, leaves the entry as is.
+    let mut cursor = array.cursor_mut(5000);
+    assert_eq!(cursor.split(12), Ok(()));
+    assert_eq!(array.extract(1, u64::MAX).count(), 1);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _ = array.cursor_mut(5000).split(5);
+    }));
+    assert!(result.is_err());
+
+    assert_eq!(array.cursor_mut(5000).split(6), Ok(()));
+    let pieces = array
+        .extract(1, u64::MAX)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        pieces,
+        ((1 << 12)..(1 << 13)).step_by(64).collect::<Vec<_>>()
+    );
+    assert!(pieces
+        .iter()
+        .all(|&i| array.cursor(i + 63).is_marked(XaMark::Mark0)));
+    assert_eq!(array.cursor_mut(1 << 12).split(0), Ok(()));
+    assert_eq!(array.extract(1, (1 << 12) + 63).count(), 64);
+    assert!(array.cursor((1 << 12) + 7).is_marked(XaMark::Mark0));
+    assert_eq!(array.get(0), Some(&p2));
+
+    array.take_on_remove();
+    assert_eq!(array.remove(5000), Some(&p1));
+    assert_eq!(array.get(5000 & !63), None);
+    assert_eq!(array.get((5000 & !63) - 1), Some(&p1));
+    assert_eq!(array.get((5000 | 63) + 1), Some(&p1));
+    for i in 1..1 << 13 {
+        array.remove(i);
+    }
+    array.remove(0);
+    assert_eq!(array.node_count(), 0);
+}
+
+#[test]
+fn test_squash_marks() {
+    let (p1, p2) = (1, 2);
+    let mut array: RawXArray<u64> = RawXArray::new();
+    for i in 0..256 {
+        assert!(array.insert(i, &p1).unwrap().is_none());
+    }
+    array.cursor_mut(3).mark(XaMark::Mark0);
+    array.cursor_mut(6).mark(XaMark::Mark1);
+    array.cursor_mut(100).mark(XaMark::Mark2);
+
+    // Sibling slots fold their marks into the canonical slot.
+    assert_eq!(array.cursor_mut(0).store_order(3, &p2), Ok(Some(&p1)));
+    for i in 0..8 {
+        assert!(array.cursor(i).is_marked(XaMark::Mark0));
+        assert!(array.cursor(i).is_marked(XaMark::Mark1));
+        assert!(!array.cursor(i).is_marked(XaMark::Mark2));
+    }
+    assert!(!array.cursor(8).is_marked(XaMark::Mark0));
+
+    // So do the subtrees replaced by an entry above the leaves.
+    assert_eq!(array.cursor_mut(0).store_order(8, &p2), Ok(None));
+    assert_eq!(array.extract(0, u64::MAX).count(), 1);
+    for mark in [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2] {
+        assert!(array.cursor(255).is_marked(mark));
+        assert!(array.is_marked(mark));
+    }
+    assert_eq!(array.remove(128), Some(&p2));
+    for mark in [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2] {
+        assert!(!array.is_marked(mark));
+    }
+    assert_eq!(array.node_count(), 0);
+}
+
+/// Allocator failing node-sized allocations on threads that ask for it.
+struct FailingAlloc;
+
+std::thread_local! {
+    static FAIL_NODES: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+    static NODES_LEFT: core::cell::Cell<usize> = const { core::cell::Cell::new(usize::MAX) };
+    static NODES_LIVE: core::cell::Cell<isize> = const { core::cell::Cell::new(0) };
+}
+
+fn node_sized(layout: core::alloc::Layout) -> bool {
+    layout.size() == core::mem::size_of::<crate::node::Node<u64>>()
+}
+
+unsafe impl core::alloc::GlobalAlloc for FailingAlloc {
+    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+        if node_sized(layout) {
+            let fail = FAIL_NODES.try_with(|fail| fail.get()).unwrap_or(false)
+                || NODES_LEFT
+                    .try_with(|left| left.replace(left.get().saturating_sub(1)) == 0)
+                    .unwrap_or(false);
+            if fail {
+                return core::ptr::null_mut();
+            }
+            let _ = NODES_LIVE.try_with(|live| live.set(live.get() + 1));
+        }
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
+        if node_sized(layout) {
+            let _ = NODES_LIVE.try_with(|live| live.set(live.get() - 1));
+        }
+        std::alloc::System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: FailingAlloc = FailingAlloc;
+
+#[test]
+fn test_nomem() {
+    let v = (0..4u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.insert(0, &v[0]), Ok(None));
+    FAIL_NODES.with(|fail| fail.set(true));
+    // Moving the head value into a leaf needs a node.
+    assert_eq!(array.insert(1, &v[1]), Err(XaError::NoMem));
+    assert_eq!(array.get(0), Some(&v[0]));
+    assert_eq!(array.get(1), None);
+    assert_eq!(array.store(0, &v[1]), Ok(Some(&v[0])));
+    FAIL_NODES.with(|fail| fail.set(false));
+    assert_eq!(array.insert(1, &v[1]), Ok(None));
+    let nodes = array.node_count();
+
+    FAIL_NODES.with(|fail| fail.set(true));
+    assert_eq!(array.insert(1 << 20, &v[2]), Err(XaError::NoMem));
+    assert_eq!(array.store(1 << 30, &v[2]), Err(XaError::NoMem));
+    assert_eq!(array.reserve(1 << 40), Err(XaError::NoMem));
+    assert_eq!(array.prefault([1 << 50]), Err(XaError::NoMem));
+    assert_eq!(array.insert_order(1 << 12, 6, &v[2]), Err(XaError::NoMem));
+    assert_eq!(array.store_range(100, 300, &v[2]), Err(XaError::NoMem));
+    assert_eq!(array.store_range(1, 2, &v[2]), Err(XaError::NoMem));
+    assert_eq!(array.get(1), Some(&v[1]));
+    // Slots of existing leaves need no allocation.
+    assert_eq!(array.insert(2, &v[2]), Ok(None));
+    assert_eq!(array.alloc(&v[3]), Ok(3));
+    FAIL_NODES.with(|fail| fail.set(false));
+
+    assert_eq!(array.node_count(), nodes);
+    assert!(array.extract(0, u64::MAX).map(|(i, _)| i).eq([0, 1, 2, 3]));
+    assert_eq!(array.insert(1 << 20, &v[2]), Ok(None));
+    assert_eq!(array.get(1 << 20), Some(&v[2]));
+
+    // Building from a slice frees the nodes it built before failing.
+    let values = (0..TCNT as u64).collect::<Vec<_>>();
+    let live = NODES_LIVE.with(|live| live.get());
+    // Failing on a leaf, then on the node linking the leaves.
+    for nodes in [10, TCNT / 64] {
+        NODES_LEFT.with(|left| left.set(nodes));
+        assert!(matches!(
+            RawXArray::try_from_slice(&values),
+            Err(XaError::NoMem)
+        ));
+        NODES_LEFT.with(|left| left.set(usize::MAX));
+        assert_eq!(NODES_LIVE.with(|live| live.get()), live);
+    }
+    assert_eq!(
+        RawXArray::try_from_slice(&values).unwrap().len(),
+        TCNT as u64
+    );
+
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    array.insert(5, Box::new(5)).unwrap();
+    FAIL_NODES.with(|fail| fail.set(true));
+    assert_eq!(array.store(1 << 20, Box::new(0)), Err(XaError::NoMem));
+    assert_eq!(array.insert(1 << 20, Box::new(0)), Err(XaError::NoMem));
+    assert_eq!(array.store(5, Box::new(6)), Ok(Some(Box::new(5))));
+    FAIL_NODES.with(|fail| fail.set(false));
+    assert_eq!(array.get(5), Some(&6));
+}
+
+#[test]
+fn test_preload() {
+    let v = (0..4u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert!(array.insert(0, &v[0]).unwrap().is_none());
+    let max = RawXArray::<u64>::MAX_STORE_NODES;
+    assert_eq!(array.preload(max), Ok(()));
+    assert_eq!(array.preloaded(), max);
+
+    // Nothing may panic while allocations fail, so results are checked
+    // after allocations are back.
+    FAIL_NODES.with(|fail| fail.set(true));
+    let grow = array.preload(max + 1);
+    let stored = array.store(u64::MAX, &v[1]);
+    let left = array.preloaded();
+    let mut last = Ok(None);
+    for i in 1..64 {
+        last = array.store(i << 40, &v[2]);
+        if last.is_err() {
+            break;
+        }
+    }
+    FAIL_NODES.with(|fail| fail.set(false));
+    assert_eq!(grow, Err(XaError::NoMem));
+    assert_eq!(stored, Ok(None));
+    assert!(left < max);
+    assert_eq!(last, Err(XaError::NoMem));
+    assert_eq!(array.preloaded(), 0);
+    assert_eq!(array.get(0), Some(&v[0]));
+    assert_eq!(array.get(u64::MAX), Some(&v[1]));
+
+    assert_eq!(array.preload(3), Ok(()));
+    assert_eq!(array.preload(1), Ok(()));
+    assert_eq!(array.preloaded(), 1);
+    assert_eq!(array.preload(0), Ok(()));
+    assert_eq!(array.preloaded(), 0);
+    assert_eq!(array.preload(5), Ok(()));
+}
+
+#[test]
+fn test_node_alloc() {
+    use core::alloc::Layout;
+    use core::ptr::NonNull;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Global allocator counting live nodes, up to a budget.
+    struct Counting {
+        live: AtomicUsize,
+        budget: AtomicUsize,
+    }
+
+    impl Counting {
+        fn live(&self) -> usize {
+            self.live.load(Ordering::Relaxed)
+        }
+
+        fn set_budget(&self, budget: usize) {
+            self.budget.store(budget, Ordering::Relaxed)
+        }
+    }
+
+    unsafe impl NodeAlloc for Counting {
+        fn alloc_node(&self, layout: Layout) -> Option<NonNull<u8>> {
+            if self.live() == self.budget.load(Ordering::Relaxed) {
+                return None;
+            }
+            self.live.fetch_add(1, Ordering::Relaxed);
+            GlobalBox.alloc_node(layout)
+        }
+
+        unsafe fn free_node(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live.fetch_sub(1, Ordering::Relaxed);
+            GlobalBox.free_node(ptr, layout)
+        }
+    }
+
+    let nodes = Counting {
+        live: AtomicUsize::new(0),
+        budget: AtomicUsize::new(usize::MAX),
+    };
+    let v = (0..1024u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new_in(&nodes);
+    for (i, value) in v.iter().enumerate() {
+        assert!(array.insert(i as u64 * 37, value).unwrap().is_none());
+    }
+    assert_eq!(nodes.live() as u64, array.node_count());
+    assert_eq!(array.preload(2), Ok(()));
+    assert_eq!(nodes.live() as u64, array.node_count() + 2);
+
+    let mut other = array.partition_by_mark(XaMark::Mark0);
+    array.splice(0..=37 * 100, &mut other, 1 << 32);
+    assert_eq!(other.get((1 << 32) + 37), Some(&v[1]));
+    drop(other);
+
+    assert_eq!(array.preload(0), Ok(()));
+    nodes.set_budget(nodes.live() + RawXArray::<u64>::MAX_STORE_NODES);
+    assert_eq!(array.store(1 << 40, &v[0]), Ok(None));
+    nodes.set_budget(nodes.live());
+    assert_eq!(array.store(1 << 50, &v[0]), Err(XaError::NoMem));
+    for i in (101..1024).rev() {
+        assert_eq!(array.remove(i * 37), Some(&v[i as usize]));
+    }
+    drop(array);
+    assert_eq!(nodes.live(), 0);
+
+    // Only arrays on the same allocator may move nodes between them.
+    let others = Counting {
+        live: AtomicUsize::new(0),
+        budget: AtomicUsize::new(usize::MAX),
+    };
+    assert!(GlobalBox.shares_nodes_with(&GlobalBox));
+    let shares = <&Counting as NodeAlloc>::shares_nodes_with;
+    assert!(shares(&&nodes, &&nodes));
+    assert!(!shares(&&nodes, &&others));
+    let mut array = RawXArray::new_in(&nodes);
+    array.insert(0, &v[0]).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        array.splice(0..=0, &mut RawXArray::new_in(&others), 0)
+    }));
+    assert!(result.is_err());
+    assert_eq!(array.get(0), Some(&v[0]));
+}
+
+#[test]
+fn test_node_pool() {
+    let v = (0..256u64).collect::<Vec<_>>();
+    let mut slots = [NodeSlot::UNINIT; 8];
+    let pool = NodePool::new(&mut slots);
+    let mut array = RawXArray::new_in(&pool);
+    let mut stored = Vec::new();
+    for (i, value) in v.iter().enumerate() {
+        let index = (i as u64) << 6;
+        match array.insert(index, value) {
+            Ok(None) => stored.push(index),
+            Err(err) => {
+                assert_eq!(err, XaError::NoMem);
+                break;
+            }
+            Ok(Some(_)) => unreachable!(),
+        }
+    }
+    assert_eq!(pool.available(), 0);
+    assert!(stored.len() > 1);
+    for (i, index) in stored.iter().enumerate() {
+        assert_eq!(array.get(*index), Some(&v[i]));
+    }
+
+    // Slots freed by removals are handed out again, unless poisoning holds
+    // them back in quarantine.
+    #[cfg(not(feature = "poison"))]
+    {
+        for index in stored.iter().skip(1) {
+            assert!(array.remove(*index).is_some());
+        }
+        assert!(pool.available() > 0);
+        for (i, index) in stored.iter().enumerate().skip(1) {
+            assert_eq!(array.insert(*index, &v[i]), Ok(None));
+        }
+    }
+    drop(array);
+    assert_eq!(pool.available(), 8);
+
+    // Frozen arrays sharing the pool give their nodes back from the threads
+    // they are dropped on.
+    std::thread::scope(|scope| {
+        for (t, value) in v.iter().take(2).enumerate() {
+            let index = (t as u64) << 12;
+            let mut array = RawXArray::new_in(&pool);
+            array.insert(index, value).unwrap();
+            let frozen = array.freeze();
+            scope.spawn(move || {
+                assert_eq!(frozen.get(index), Some(value));
+                drop(frozen);
+            });
+        }
+    });
+    assert_eq!(pool.available(), 8);
+}
+
+#[test]
+fn test_pinned_and_intrusive() {
+    use core::marker::PhantomPinned;
+    use core::pin::Pin;
+    use core::ptr::NonNull;
+
+    struct SelfRef {
+        value: u64,
+        this: *const SelfRef,
+        _pin: PhantomPinned,
+    }
+
+    let mut array: XArrayPinned<SelfRef> = XArrayPinned::new();
+    for i in 0..100 {
+        let mut node = Box::pin(SelfRef {
+            value: i,
+            this: core::ptr::null(),
+            _pin: PhantomPinned,
+        });
+        // SAFETY: the field is not structurally pinned.
+        unsafe {
+            let this = &*node as *const SelfRef;
+            node.as_mut().get_unchecked_mut().this = this;
+        }
+        assert!(array.insert(i * 1000, node).unwrap().is_none());
+    }
+    for i in 0..100 {
+        let node = array.get(i * 1000).unwrap();
+        assert_eq!(node.value, i);
+        assert!(core::ptr::eq(node.this, node));
+    }
+    let removed: Pin<Box<SelfRef>> = array.remove(5000).unwrap();
+    assert!(core::ptr::eq(removed.this, &*removed));
+
+    let mut values = (0..64u64).collect::<Vec<_>>();
+    let mut array: XArray<u64, Intrusive<u64>> = XArray::new();
+    for (i, value) in values.iter_mut().enumerate() {
+        // SAFETY: `values` outlives the array and is not touched meanwhile.
+        let value = unsafe { Intrusive::new(NonNull::from(value)) };
+        assert!(array.insert(i as u64, value).unwrap().is_none());
+    }
+    let removed = array.remove(3).unwrap();
+    assert_eq!(removed.as_non_null(), NonNull::from(&values[3]));
+    assert_eq!(array.get(63), Some(&63));
+    drop(array);
+    assert_eq!(values.iter().sum::<u64>(), 63 * 64 / 2);
+}
+
+#[test]
+fn test_borrowed_values() {
+    struct Named<'s> {
+        name: &'s str,
+    }
+
+    let names = (0..100).map(|i| format!("name-{i}")).collect::<Vec<_>>();
+    let mut array: XArrayBoxed<Named> = XArrayBoxed::new();
+    for (i, name) in names.iter().enumerate() {
+        assert!(array
+            .insert(i as u64, Box::new(Named { name }))
+            .unwrap()
+            .is_none());
+    }
+    let first = array.get(0).unwrap();
+    assert_eq!(first.name, "name-0");
+    let removed = array.remove(42).unwrap();
+    assert_eq!(removed.name, "name-42");
+    assert_eq!(
+        array
+            .iter()
+            .map(|(_, named)| named.name.len())
+            .sum::<usize>(),
+        names.iter().map(|name| name.len()).sum::<usize>() - removed.name.len()
+    );
+    drop(array);
+    assert_eq!(names.len(), 100);
+}
+
+#[test]
+fn test_owned_subtree_release() {
+    use std::rc::Rc;
+
+    let rc = Rc::new(());
+    let mut array: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+    for i in 0..200 {
+        array.insert(i * 3, Box::new(rc.clone())).unwrap();
+    }
+    array.insert(1 << 20, Box::new(rc.clone())).unwrap();
+    assert_eq!(Rc::strong_count(&rc), 202);
+
+    // Storing over the nodes below index 4095 drops the values they hold.
+    let value = Box::new(rc.clone());
+    let ptr: *const Rc<()> = &*value;
+    let displaced = array.cursor_mut(0).store_order(12, value).unwrap();
+    assert_eq!(displaced.len(), 200);
+    drop(displaced);
+    assert_eq!(Rc::strong_count(&rc), 3);
+    assert!(core::ptr::eq(array.get(599).unwrap(), ptr));
+
+    drop(array);
+    assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+#[test]
+fn test_clear() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let removed = Cell::new(0);
+    let mut array = RawXArray::from_slice(&v);
+    array.set_on_remove(|_, _: &u64| removed.set(removed.get() + 1));
+    array.cursor_mut(5).mark(XaMark::Mark0);
+    array.clear();
+    assert_eq!(removed.get(), TCNT);
+    assert_eq!(array.node_count(), 0);
+    assert!(array.is_empty());
+    assert!(array.iter().filter_mark(XaMark::Mark0).next().is_none());
+    assert_eq!(array.insert(1 << 30, &v[1]), Ok(None));
+    assert_eq!(array.get(1 << 30), Some(&1));
+
+    let rc = Rc::new(());
+    let mut boxed: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+    boxed.insert(0, Box::new(rc.clone())).unwrap();
+    boxed.clear();
+    assert_eq!(Rc::strong_count(&rc), 1);
+    for i in 0..TCNT as u64 {
+        boxed.insert(i * 7, Box::new(rc.clone())).unwrap();
+    }
+    boxed.clear();
+    assert_eq!(Rc::strong_count(&rc), 1);
+    assert_eq!(boxed.node_count(), 0);
+}
+
+#[test]
+fn test_len() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.len(), 0);
+    for (i, value) in v.iter().enumerate() {
+        array.insert(i as u64 * 5, value).unwrap();
+        assert_eq!(array.len(), i as u64 + 1);
+    }
+    array.insert(0, &v[1]).unwrap();
+    assert_eq!(array.len(), TCNT as u64);
+    array.remove(5);
+    assert_eq!(array.len(), TCNT as u64 - 1);
+    assert_eq!(array.reserve(1), Ok(()));
+    assert_eq!(array.len(), TCNT as u64);
+    assert_eq!(array.cursor_mut(1 << 40).store_order(8, &v[0]), Ok(None));
+    assert_eq!(array.len(), TCNT as u64 + 1);
+
+    let mut iter = array.iter();
+    assert_eq!(iter.size_hint(), (0, Some(TCNT + 1)));
+    iter.nth(9);
+    assert_eq!(iter.size_hint().1, Some(TCNT - 9));
+    assert_eq!(iter.count(), TCNT - 10);
+    let range = array.extract(100, 199);
+    assert_eq!(range.size_hint().1, Some(20));
+    assert_eq!(range.count(), 20);
+    assert_eq!(array.extract(1 << 41, u64::MAX).size_hint(), (0, Some(0)));
+
+    let mut boxed: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..10 {
+        boxed.insert(i, Box::new(i)).unwrap();
+    }
+    assert_eq!(boxed.len(), 10);
+    assert_eq!(boxed.extract_mut(2, 5).size_hint(), (0, Some(4)));
+}
+
+#[test]
+fn test_get_mut() {
+    let mut array: XArrayBoxed<(u64, Vec<u64>)> = XArrayBoxed::new();
+    for i in 0..100 {
+        array.insert(i * 3, Box::new((i, Vec::new()))).unwrap();
+    }
+    let nodes = array.node_count();
+    for i in 0..100 {
+        let (n, list) = array.get_mut(i * 3).unwrap();
+        *n += 1;
+        list.push(i);
+    }
+    assert!(array.get_mut(1).is_none());
+    assert_eq!(array.node_count(), nodes);
+    assert_eq!(array.get(30), Some(&(11, vec![10])));
+
+    let mut cursor = array.cursor_mut(0);
+    while let Some((n, _)) = cursor.current_mut() {
+        *n *= 2;
+        cursor.next();
+        cursor.next();
+        cursor.next();
+    }
+    assert!(cursor.current_mut().is_none());
+    assert!(array.iter().all(|(i, (n, _))| *n == (i / 3 + 1) * 2));
+}
+
+#[test]
+fn test_entry() {
+    use crate::xarray::Entry;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    for i in 0..10 {
+        array
+            .entry(i % 4 * 100)
+            .and_modify(|v| **v += 1)
+            .or_insert_with(|| Box::new(0));
+    }
+    assert_eq!(array.get(0), Some(&2));
+    assert_eq!(array.get(100), Some(&2));
+    assert_eq!(array.get(300), Some(&1));
+    assert_eq!(array.entry(300).key(), 300);
+
+    array.cursor_mut(100).mark(XaMark::Mark0);
+    let seven = Box::new(7);
+    array.entry(100).and_modify(|v| *v = seven);
+    assert_eq!(array.get(100), Some(&7));
+    assert!(array.cursor(100).is_marked(XaMark::Mark0));
+
+    match array.entry(200) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(*entry.get(), 1);
+            *entry.get_mut() = 5;
+            assert_eq!(*entry.insert(Box::new(6)), 5);
+            assert_eq!(*entry.remove(), 6);
+        }
+        Entry::Vacant(_) => panic!("index 200 is occupied"),
+    }
+    match array.entry(200) {
+        Entry::Vacant(entry) => assert_eq!(*entry.insert(Box::new(8)), 8),
+        Entry::Occupied(_) => panic!("index 200 was removed"),
+    }
+    if let Entry::Occupied(entry) = array.entry(0) {
+        *entry.into_mut() = 9;
+    }
+    assert_eq!(array.get(0), Some(&9));
+    assert_eq!(array.get(200), Some(&8));
+
+    array.reserve(500).unwrap();
+    assert!(matches!(array.entry(500), Entry::Vacant(_)));
+    assert_eq!(*array.entry(500).or_insert(Box::new(1)), 1);
+    assert_eq!(array.get(500), Some(&1));
+
+    let counter = Rc::new(());
+    let mut shared: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+    shared.insert(3, Box::new(counter.clone())).unwrap();
+    let replacement = Box::new(counter.clone());
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        shared.entry(3).and_modify(|v| {
+            *v = replacement;
+            panic!("modify");
+        });
+    }));
+    assert!(result.is_err());
+    assert_eq!(Rc::strong_count(&counter), 2);
+    assert!(shared.get(3).is_some());
+    drop(shared);
+    assert_eq!(Rc::strong_count(&counter), 1);
+
+    // The hook reads the values it is shown, which must still be alive.
+    let seen = Rc::new(core::cell::RefCell::new(Vec::new()));
+    let hook = seen.clone();
+    let mut array: XArrayBoxed<Vec<u64>> = XArrayBoxed::new();
+    array.set_on_remove(move |index, v: &Vec<u64>| hook.borrow_mut().push((index, v.clone())));
+    array.insert(4, Box::new(vec![1, 2])).unwrap();
+    let replacement = Box::new(vec![3]);
+    array.entry(4).and_modify(|v| *v = replacement);
+    array.entry(4).and_modify(|v| v.push(4));
+    assert!(seen.borrow().is_empty());
+    assert_eq!(array.get(4), Some(&vec![3, 4]));
+    drop(array.remove(4));
+    assert_eq!(*seen.borrow(), vec![(4, vec![3, 4])]);
+}
+
+#[test]
+fn test_retain() {
+    use std::rc::Rc;
+
+    let v = (0..1000u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    for i in &v {
+        array.insert(i * 7, i).unwrap();
+    }
+    let nodes = array.node_count();
+    assert_eq!(array.retain(|index, v| index % 2 == 0 && *v < 600), 700);
+    assert_eq!(array.len(), 300);
+    assert!(array.node_count() < nodes);
+    assert!(array
+        .iter()
+        .all(|(i, v)| i == v * 7 && v % 2 == 0 && *v < 600));
+    assert_eq!(array.retain(|_, _| false), 300);
+    assert!(array.is_empty());
+    assert_eq!(array.node_count(), 0);
+
+    let counter = Rc::new(());
+    let mut array: XArrayBoxed<(u64, Rc<()>)> = XArrayBoxed::new();
+    for i in 0..500 {
+        array.insert(i * 3, Box::new((i, counter.clone()))).unwrap();
+    }
+    assert_eq!(array.retain(|_, (n, _)| n % 5 != 0), 100);
+    assert_eq!(Rc::strong_count(&counter), 401);
+    let removed = array.retain_mut(|index, (n, _)| {
+        *n += index;
+        *n % 8 == 0
+    });
+    assert_eq!(removed, 200);
+    assert_eq!(Rc::strong_count(&counter), 201);
+    assert!(array.iter().all(|(i, (n, _))| *n == i / 3 * 4));
+}
+
+#[test]
+fn test_drain() {
+    use std::rc::Rc;
+
+    let counter = Rc::new(());
+    let mut array: XArrayBoxed<(u64, Rc<()>)> = XArrayBoxed::new();
+    for i in 0..1000 {
+        array.insert(i * 5, Box::new((i, counter.clone()))).unwrap();
+    }
+
+    let mut drain = array.drain_range(100, 1099);
+    assert_eq!(drain.size_hint(), (0, Some(200)));
+    let (index, value) = drain.next().unwrap();
+    assert_eq!((index, value.0), (100, 20));
+    assert_eq!(drain.size_hint(), (0, Some(199)));
+    let drained = drain.take(49).collect::<Vec<_>>();
+    assert_eq!(drained.len(), 49);
+    assert!(drained.iter().all(|(i, v)| *i == v.0 * 5));
+    assert_eq!(Rc::strong_count(&counter), 851);
+    drop((value, drained));
+    assert_eq!(Rc::strong_count(&counter), 801);
+    assert_eq!(array.len(), 800);
+    assert!(array.extract(100, 1099).next().is_none());
+    assert_eq!(array.get(1100).map(|v| v.0), Some(220));
+
+    let mut other: XArrayBoxed<(u64, Rc<()>)> = XArrayBoxed::new();
+    for (index, value) in array.drain() {
+        other.insert(index, value).unwrap();
+    }
+    assert!(array.is_empty());
+    assert_eq!(array.node_count(), 0);
+    assert_eq!(other.len(), 800);
+    assert_eq!(Rc::strong_count(&counter), 801);
+    drop(other);
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn test_remove_range() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let v = (0..10000u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    for i in &v {
+        array.insert(*i, i).unwrap();
+    }
+    array.cursor_mut(6000).mark(XaMark::Mark0);
+    let removals = Rc::new(Cell::new(0));
+    let hook = removals.clone();
+    array.set_on_remove(move |_, _| hook.set(hook.get() + 1));
+
+    let nodes = array.node_count();
+    assert_eq!(array.remove_range(100, 8999), 8900);
+    assert_eq!(removals.get(), 8900);
+    assert_eq!(array.len(), 1100);
+    assert!(array.node_count() < nodes);
+    assert!(!array.is_marked(XaMark::Mark0));
+    assert_eq!(array.extract(100, 8999).count(), 0);
+    assert_eq!(array.get(99), Some(&99));
+    assert_eq!(array.get(9000), Some(&9000));
+    assert_eq!(array.remove_range(200, 300), 0);
+    assert_eq!(array.remove_range(1, 0), 0);
+
+    // Multi-order entries reaching past the range go as a whole.
+    array.remove_range(0, u64::MAX);
+    assert!(array.is_empty());
+    assert_eq!(array.node_count(), 0);
+    array.store_range(0, 127, &v[1]).unwrap();
+    array.store_range(128, 255, &v[2]).unwrap();
+    array.insert(300, &v[3]).unwrap();
+    assert_eq!(array.remove_range(100, 200), 2);
+    assert_eq!(array.to_vec(), vec![(300, &3)]);
+
+    let counter = Rc::new(());
+    let mut boxed: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+    for i in 0..5000 {
+        boxed.insert(i * 2, Box::new(counter.clone())).unwrap();
+    }
+    let mut order = boxed.cursor_mut(1 << 20);
+    order.store_order(8, Box::new(counter.clone())).unwrap();
+    assert_eq!(boxed.remove_range(1000, (1 << 20) + 7), 4501);
+    assert_eq!(Rc::strong_count(&counter), 501);
+    assert_eq!(boxed.len(), 500);
+    drop(boxed);
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn test_remove_range_compressed() {
+    use rand::Rng;
+    use std::rc::Rc;
+
+    let v = [1048886u64, 1099511626083];
+    let mut array = RawXArray::new();
+    for i in &v {
+        array.insert(*i, i).unwrap();
+    }
+    assert_eq!(array.remove_range(3138, u64::MAX - 3271), 2);
+    assert!(array.is_empty());
+    assert_eq!(array.node_count(), 0);
+
+    let counter = Rc::new(());
+    let mut boxed: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+    for i in v {
+        boxed.insert(i, Box::new(counter.clone())).unwrap();
+    }
+    assert_eq!(boxed.remove_range(3138, u64::MAX - 3271), 2);
+    assert!(boxed.is_empty());
+    assert_eq!(Rc::strong_count(&counter), 1);
+
+    // Sparse subtrees collapse as their neighbours go, moving the rest.
+    let mut rng = rand::thread_rng();
+    for _ in 0..50 {
+        let mut boxed: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+        let mut indices = (0..20)
+            .map(|_| rng.gen::<u64>() >> rng.gen_range(0, 64))
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        for i in &indices {
+            boxed.insert(*i, Box::new(counter.clone())).unwrap();
+        }
+        let start = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        let end = start.saturating_add(rng.gen::<u64>() >> rng.gen_range(0, 64));
+        let inside = indices.iter().filter(|i| (start..=end).contains(i)).count();
+        assert_eq!(boxed.remove_range(start, end), inside);
+        assert_eq!(boxed.len(), (indices.len() - inside) as u64);
+        assert_eq!(Rc::strong_count(&counter), 1 + indices.len() - inside);
+        assert!(indices
+            .iter()
+            .all(|i| boxed.get(*i).is_some() != (start..=end).contains(i)));
+
+        // Closing a gap erases and moves units the same way.
+        let left = boxed.iter().map(|(i, _)| i).collect::<Vec<_>>();
+        let (at, n) = (start / 2, (start / 2).max(1));
+        boxed.remove_gap(at, n);
+        let kept = left
+            .iter()
+            .filter(|i| !(at..at + n).contains(i))
+            .map(|i| if *i >= at + n { i - n } else { *i })
+            .collect::<Vec<_>>();
+        assert_eq!(boxed.iter().map(|(i, _)| i).collect::<Vec<_>>(), kept);
+        assert_eq!(Rc::strong_count(&counter), 1 + kept.len());
+        drop(boxed);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+}
+
+#[test]
+fn test_split_off() {
+    use std::rc::Rc;
+
+    let v = (0..5000u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    for i in &v {
+        array.insert(i * 3, i).unwrap();
+    }
+    array.cursor_mut(9000).mark(XaMark::Mark1);
+    let nodes = array.node_count();
+    let upper = array.split_off(4096);
+    assert_eq!(array.len() + upper.len(), 5000);
+    assert_eq!(array.node_count() + upper.node_count(), nodes);
+    assert!(array.iter().all(|(i, v)| i < 4096 && i == v * 3));
+    assert!(upper.iter().all(|(i, v)| i >= 4096 && i == v * 3));
+    assert_eq!(upper.get(4098), Some(&1366));
+    assert!(upper.cursor(9000).is_marked(XaMark::Mark1));
+    assert!(!array.is_marked(XaMark::Mark1));
+    assert!(array.split_off(1 << 40).is_empty());
+    let all = array.split_off(0);
+    assert!(array.is_empty());
+    assert_eq!(all.len(), 1366);
+
+    let counter = Rc::new(());
+    let mut boxed: XArrayBoxed<Rc<()>> = XArrayBoxed::with_limit(999);
+    for i in 0..1000 {
+        boxed.insert(i, Box::new(counter.clone())).unwrap();
+    }
+    let upper = boxed.split_off(10);
+    assert_eq!((boxed.len(), upper.len()), (10, 990));
+    assert!(boxed.split_off(1000).is_empty());
+    drop(upper);
+    assert_eq!(Rc::strong_count(&counter), 11);
+    drop(boxed);
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn test_append() {
+    use std::rc::Rc;
+
+    let v = (0..20000u64).collect::<Vec<_>>();
+    let mut global = RawXArray::new();
+    let mut staging = RawXArray::new();
+    for i in 0..10000 {
+        global.insert(i, &v[i as usize]).unwrap();
+        staging.insert(i + 10000, &v[i as usize + 10000]).unwrap();
+    }
+    staging.cursor_mut(15000).mark(XaMark::Mark2);
+    let nodes = global.node_count() + staging.node_count();
+    assert_eq!(global.append(&mut staging, Collision::Fail), Ok(()));
+    assert!(staging.is_empty());
+    assert_eq!(staging.node_count(), 0);
+    assert!(global.node_count() <= nodes);
+    assert_eq!(global.len(), 20000);
+    assert!(global.iter().all(|(i, v)| i == *v));
+    assert!(global.cursor(15000).is_marked(XaMark::Mark2));
+
+    // Collisions leave both arrays untouched, or are left behind.
+    for i in (19990..20010).step_by(5) {
+        staging.insert(i, &v[1]).unwrap();
+    }
+    staging
+        .store_range(1 << 20, (1 << 20) + 127, &v[2])
+        .unwrap();
+    assert_eq!(
+        global.append(&mut staging, Collision::Fail),
+        Err(XaError::Busy)
+    );
+    assert_eq!((global.len(), staging.len()), (20000, 5));
+    assert_eq!(global.append(&mut staging, Collision::Skip), Ok(()));
+    assert_eq!(global.len(), 20003);
+    assert_eq!(staging.to_vec(), vec![(19990, &1), (19995, &1)]);
+    assert_eq!(global.get(20005), Some(&1));
+    assert_eq!(global.get((1 << 20) + 100), Some(&2));
+
+    let mut bounded = RawXArray::with_limit(100);
+    staging.insert(101, &v[3]).unwrap();
+    assert_eq!(
+        bounded.append(&mut staging, Collision::Skip),
+        Err(XaError::OutOfBounds)
+    );
+    assert_eq!(staging.len(), 3);
+
+    let counter = Rc::new(());
+    let mut a: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+    let mut b: XArrayBoxed<Rc<()>> = XArrayBoxed::new();
+    for i in 0..1000 {
+        a.insert(i * 2, Box::new(counter.clone())).unwrap();
+        b.insert(i * 3, Box::new(counter.clone())).unwrap();
+    }
+    a.append(&mut b, Collision::Skip).unwrap();
+    assert_eq!((a.len(), b.len()), (1666, 334));
+    assert_eq!(Rc::strong_count(&counter), 2001);
+    drop((a, b));
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn test_range_prev_next() {
+    let v = (0..1000u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert_eq!(array.range_next(0), None);
+    assert_eq!(array.range_prev(u64::MAX), None);
+    array.insert(0, &v[0]).unwrap();
+    assert_eq!(array.range_next(0), Some((0, &0)));
+    assert_eq!(array.range_next(1), None);
+    assert_eq!(array.range_prev(u64::MAX), Some((0, &0)));
+
+    for i in 1..1000 {
+        array.insert(i * i * 1000, &v[i as usize]).unwrap();
+    }
+    assert_eq!(array.range_next(1), Some((1000, &1)));
+    assert_eq!(array.range_next(4000), Some((4000, &2)));
+    assert_eq!(array.range_next(4001), Some((9000, &3)));
+    assert_eq!(array.range_prev(8999), Some((4000, &2)));
+    assert_eq!(array.range_prev(9000), Some((9000, &3)));
+    assert_eq!(array.range_next(999 * 999 * 1000 + 1), None);
+    assert_eq!(array.range_prev(u64::MAX), Some((998001000, &999)));
+
+    array.reserve(5000).unwrap();
+    assert_eq!(array.range_next(4001), Some((9000, &3)));
+    assert_eq!(array.range_prev(8999), Some((4000, &2)));
+    array.store_range(20000, 20255, &v[7]).unwrap();
+    assert_eq!(array.range_next(19999), Some((20000, &7)));
+    assert_eq!(array.range_prev(24999), Some((20255, &7)));
+    assert_eq!(array.range_next(20100), Some((20100, &7)));
+
+    let mut rng = StdRng::seed_from_u64(17);
+    for _ in 0..200 {
+        let index = rng.gen_range(0, 1_000_000_000);
+        let next = array.iter().find(|(i, _)| *i >= index);
+        let prev = array.iter().filter(|(i, _)| *i <= index).last();
+        if !(20000..=20255).contains(&index) {
+            assert_eq!(array.range_next(index), next);
+            assert_eq!(array.range_prev(index), prev);
+        }
+    }
+}
+
+#[test]
+fn test_first_last() {
+    let v = (0..1000u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert_eq!((array.first(), array.last()), (None, None));
+    array.reserve(3).unwrap();
+    array.reserve(1 << 50).unwrap();
+    assert_eq!((array.first(), array.last()), (None, None));
+    for i in 10..1000 {
+        array.insert(i * 977, &v[i as usize]).unwrap();
+    }
+    assert_eq!(array.first(), Some((9770, &10)));
+    assert_eq!(array.last(), Some((999 * 977, &999)));
+
+    let mut boxed: XArrayBoxed<u64> = XArrayBoxed::new();
+    assert!(boxed.first_entry().is_none());
+    assert!(boxed.pop_last().is_none());
+    for i in 0..100 {
+        boxed.insert(i * i, Box::new(i)).unwrap();
+    }
+    *boxed.first_entry().unwrap().get_mut() += 1000;
+    assert_eq!(boxed.last_entry().unwrap().key(), 99 * 99);
+    assert_eq!(boxed.pop_first(), Some((0, Box::new(1000))));
+    assert_eq!(boxed.pop_last(), Some((99 * 99, Box::new(99))));
+    let mut popped = Vec::new();
+    while let Some((index, value)) = boxed.pop_last() {
+        popped.push((index, *value));
+    }
+    assert_eq!(popped.len(), 98);
+    assert!(popped.windows(2).all(|w| w[0].0 > w[1].0));
+    assert!(boxed.is_empty());
+    assert_eq!(boxed.node_count(), 0);
+}
+
+#[test]
+fn test_reverse_iter() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert_eq!(array.iter().next_back(), None);
+    array.insert(0, &v[0]).unwrap();
+    assert_eq!(array.iter().rev().collect::<Vec<_>>(), vec![(0, &0)]);
+
+    let mut rng = StdRng::seed_from_u64(7);
+    for _ in 0..2000 {
+        let index = match rng.gen_range(0, 3) {
+            0 => rng.gen_range(0, 4096),
+            1 => rng.gen_range(0, 1 << 20),
+            _ => rng.gen::<u64>(),
+        };
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+    }
+    array.reserve(12345).unwrap();
+    array.store_range(1 << 30, (1 << 30) + 4095, &v[1]).unwrap();
+    for i in (0..4096).step_by(3) {
+        array.cursor_mut(i).mark(XaMark::Mark0);
+    }
+
+    let forward = array.iter().collect::<Vec<_>>();
+    let mut backward = array.iter().rev().collect::<Vec<_>>();
+    backward.reverse();
+    assert_eq!(forward, backward);
+    for (start, end) in [(0, 4095), (100, 1 << 20), (4000, 4000), (1 << 30, u64::MAX)] {
+        let forward = array.extract(start, end).collect::<Vec<_>>();
+        let mut backward = array.extract(start, end).rev().collect::<Vec<_>>();
+        backward.reverse();
+        assert_eq!(forward, backward);
+    }
+    let marked = array.iter().filter_mark(XaMark::Mark0).collect::<Vec<_>>();
+    let mut backward = array
+        .iter()
+        .filter_mark(XaMark::Mark0)
+        .rev()
+        .collect::<Vec<_>>();
+    backward.reverse();
+    assert_eq!(marked, backward);
+    let unmarked = array.extract(0, 4095).filter_unmarked(XaMark::Mark0);
+    assert!(unmarked.rev().all(|(i, _)| i % 3 != 0));
+
+    // Both ends meet without yielding an entry twice.
+    let mut iter = array.iter();
+    let mut front = Vec::new();
+    let mut back = Vec::new();
+    loop {
+        let (item, side) = if rng.gen() {
+            (iter.next(), &mut front)
+        } else {
+            (iter.next_back(), &mut back)
+        };
+        match item {
+            Some(item) => side.push(item),
+            None => break,
+        }
+    }
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+    front.extend(back.into_iter().rev());
+    assert_eq!(front, forward);
+
+    let mut cursor = array.cursor(u64::MAX);
+    let mut indices = Vec::new();
+    cursor.prev_allocated();
+    while cursor.current().is_some() {
+        indices.push(cursor.key());
+        cursor.prev_allocated();
+    }
+    assert_eq!(cursor.key(), u64::MAX);
+    indices.reverse();
+    assert_eq!(indices, forward.iter().map(|(i, _)| *i).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_reverse_marked() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    array.insert(0, &v[0]).unwrap();
+    assert_eq!(array.iter().filter_mark(XaMark::Mark2).next_back(), None);
+    array.cursor_mut(0).mark(XaMark::Mark2);
+    assert_eq!(
+        array.iter().filter_mark(XaMark::Mark2).next_back(),
+        Some((0, &0))
+    );
+
+    let mut rng = StdRng::seed_from_u64(11);
+    let mut indices = Vec::new();
+    for _ in 0..3000 {
+        let index = match rng.gen_range(0, 3) {
+            0 => rng.gen_range(0, 8192),
+            1 => rng.gen_range(0, 1 << 24),
+            _ => rng.gen::<u64>() >> 1,
+        };
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+        indices.push(index);
+    }
+    for index in indices.iter().copied() {
+        if rng.gen_range(0, 7) == 0 {
+            array.cursor_mut(index).mark(XaMark::Mark1);
+        }
+    }
+    array.store_range(1 << 62, (1 << 62) + 255, &v[1]).unwrap();
+    array.cursor_mut(1 << 62).mark(XaMark::Mark1);
+
+    let marked = array
+        .iter()
+        .filter_mark(XaMark::Mark1)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    assert!(marked.contains(&(1 << 62)));
+    let mut backward = array
+        .iter()
+        .filter_mark(XaMark::Mark1)
+        .rev()
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    backward.reverse();
+    assert_eq!(marked, backward);
+
+    // The last marked entry at or before an index, from within the range
+    // of a multi-order entry too.
+    for index in indices.iter().map(|i| i + 1).chain([(1 << 62) + 100, 5]) {
+        let expected = marked.iter().rev().find(|i| **i <= index).copied();
+        let found = array
+            .extract(0, index)
+            .filter_mark(XaMark::Mark1)
+            .next_back()
+            .map(|(i, _)| i);
+        assert_eq!(found, expected);
+    }
+    assert_eq!(array.iter().filter_mark(XaMark::Mark0).next_back(), None);
+}
+
+#[test]
+fn test_gaps() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert_eq!(array.gaps(0, u64::MAX).collect::<Vec<_>>(), [(0, u64::MAX)]);
+    array.insert(0, &v[0]).unwrap();
+    assert_eq!(array.gaps(0, 10).collect::<Vec<_>>(), [(1, 10)]);
+    assert_eq!(array.gaps(0, 0).count(), 0);
+
+    let mut rng = StdRng::seed_from_u64(5);
+    for _ in 0..600 {
+        let index = rng.gen_range(0, 6000);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+    }
+    for i in 0..1000 {
+        array.insert(i, &v[i as usize]).unwrap();
+    }
+    array.reserve(4500).unwrap();
+    array.store_range(5120, 5183, &v[1]).unwrap();
+    array.insert(1 << 40, &v[0]).unwrap();
+
+    for (start, end) in [(0, 8191), (999, 1001), (1500, 2500), (5100, 5200)] {
+        let mut expected = Vec::<(u64, u64)>::new();
+        for i in (start..=end).filter(|i| array.count_range(*i..=*i) == 0) {
+            match expected.last_mut() {
+                Some((_, last)) if *last + 1 == i => *last = i,
+                _ => expected.push((i, i)),
+            }
+        }
+        assert_eq!(array.gaps(start, end).collect::<Vec<_>>(), expected);
+    }
+    assert!(array.gaps(0, 8191).all(|(first, _)| first > 999));
+    assert_eq!(
+        array.gaps(6000, u64::MAX).collect::<Vec<_>>(),
+        [(6000, (1 << 40) - 1), ((1 << 40) + 1, u64::MAX)]
+    );
+
+    let mut limited = RawXArray::with_limit(99);
+    limited.insert(50, &v[50]).unwrap();
+    assert_eq!(
+        limited.gaps(0, u64::MAX).collect::<Vec<_>>(),
+        [(0, 49), (51, 99)]
+    );
+}
+
+#[test]
+fn test_find_free_range() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert_eq!(array.find_free_range(1, 1), Some(0));
+    assert_eq!(array.find_free_range(u64::MAX, 1), Some(0));
+    for i in (0..2048).filter(|i| i % 100 != 99) {
+        array.insert(i, &v[i as usize]).unwrap();
+    }
+    assert_eq!(array.next_free(0), Some(99));
+    assert_eq!(array.find_free_range(1, 1), Some(99));
+    assert_eq!(array.find_free_range(1, 7), Some(399));
+    assert_eq!(array.find_free_range(2, 1), Some(2048));
+    assert_eq!(array.find_free_range(64, 64), Some(2048));
+    assert_eq!(array.find_free_range(64, 1000), Some(3000));
+
+    array.reserve(2100).unwrap();
+    assert_eq!(array.find_free_range(64, 64), Some(2112));
+    array.store_range(4096, 8191, &v[0]).unwrap();
+    assert_eq!(array.find_free_range(2048, 2048), Some(8192));
+    assert_eq!(array.find_free_range(u64::MAX - 8191, 1), Some(8192));
+    assert_eq!(array.find_free_range(u64::MAX - 8191, 3), None);
+
+    let mut limited = RawXArray::with_limit(127);
+    limited.insert(60, &v[0]).unwrap();
+    assert_eq!(limited.find_free_range(64, 64), Some(64));
+    assert_eq!(limited.find_free_range(67, 1), Some(61));
+    assert_eq!(limited.find_free_range(68, 1), None);
+}
+
+#[test]
+fn test_is_range_empty() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert!(array.is_range_empty(0..=u64::MAX));
+    array.reserve(0).unwrap();
+    assert!(!array.is_range_empty(0..=0));
+    assert!(array.is_range_empty(1..=u64::MAX));
+    #[allow(clippy::reversed_empty_ranges)]
+    let reversed = 5..=4;
+    assert!(array.is_range_empty(reversed));
+
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..300 {
+        let index = rng.gen_range(0, 1 << 16);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+    }
+    array.store_range(1 << 20, (1 << 20) + 4095, &v[0]).unwrap();
+    array.insert(u64::MAX, &v[0]).unwrap();
+    for _ in 0..2000 {
+        let start: u64 = match rng.gen_range(0, 3) {
+            0 => rng.gen_range(0, 1 << 16),
+            1 => rng.gen_range((1 << 20) - 100, (1 << 20) + 4200),
+            _ => rng.gen(),
+        };
+        let end = start.saturating_add(rng.gen_range(0, 600));
+        assert_eq!(
+            array.is_range_empty(start..=end),
+            array.count_range(start..=end) == 0,
+            "{}..={}",
+            start,
+            end
+        );
+    }
+    assert!(!array.is_range_empty((1 << 20) + 4095..=(1 << 20) + 5000));
+    assert!(array.is_range_empty((1 << 20) + 4096..=u64::MAX - 1));
+}
+
+#[test]
+fn test_mark_count() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[derive(Clone, Copy)]
+    enum PageMark {
+        Dirty,
+        Writeback,
+        Towrite,
+        Accessed,
+        Pinned,
+    }
+    impl MarkIndex for PageMark {
+        fn index(self) -> usize {
+            self as usize
+        }
+    }
+
+    assert!(node_layout::<0>().size() < NODE_LAYOUT.size());
+    assert!(node_layout::<5>().size() > NODE_LAYOUT.size());
+    let v = (0..4096u64).collect::<Vec<_>>();
+
+    let mut plain = RawXArray::<u64, 0>::with_marks();
+    for i in (0..4096).step_by(3) {
+        plain.insert(i, &v[i as usize]).unwrap();
+    }
+    assert_eq!(plain.len(), 1366);
+    assert_eq!(plain.iter().next_back(), Some((4095, &4095)));
+    assert!(catch_unwind(AssertUnwindSafe(|| plain.cursor_mut(3).mark(0))).is_err());
+
+    let mut array = RawXArray::<u64, 5>::with_marks();
+    array.insert(0, &v[0]).unwrap();
+    array.cursor_mut(0).mark(PageMark::Pinned);
+    assert!(array.is_marked(PageMark::Pinned));
+    assert!(!array.is_marked(PageMark::Dirty));
+    for i in 1..4096 {
+        array.insert(i, &v[i as usize]).unwrap();
+    }
+    assert!(array.cursor(0).is_marked(PageMark::Pinned));
+    for i in (0..4096).step_by(7) {
+        array.cursor_mut(i).mark(PageMark::Pinned);
+    }
+    for i in (0..4096).step_by(5) {
+        array.cursor_mut(i).mark(PageMark::Dirty);
+    }
+    let pinned = array.iter().filter_mark(PageMark::Pinned).map(|(i, _)| i);
+    assert!(pinned.eq((0..4096).step_by(7)));
+    assert_eq!(
+        array
+            .extract(0, 100)
+            .filter_mark(PageMark::Pinned)
+            .next_back(),
+        Some((98, &98))
+    );
+    assert_eq!(
+        array.iter().filter_unmarked(PageMark::Pinned).count(),
+        4096 - 586
+    );
+
+    let (_, marks) = array.remove_with_marks(35).unwrap();
+    assert_eq!(marks.indices().collect::<Vec<_>>(), [0, 4]);
+    array.store_range(70, 79, &v[1]).unwrap();
+    assert!(!array.cursor(70).is_marked(PageMark::Pinned));
+    array.cursor_mut(70).mark(PageMark::Towrite);
+
+    // Marks past the third move along with their entries.
+    let mut upper = array.split_off(2048);
+    assert!(upper.cursor(2051).is_marked(PageMark::Pinned));
+    assert!(upper.cursor(2050).is_marked(PageMark::Dirty));
+    let marked = upper.partition_by_mark(PageMark::Pinned);
+    assert_eq!(marked.len(), 293);
+    assert!(marked.iter().all(|(i, _)| i % 7 == 0));
+    array.append(&mut upper, Collision::Fail).unwrap();
+    assert!(array
+        .iter()
+        .filter_mark(PageMark::Pinned)
+        .all(|(i, _)| i < 2048));
+    assert!(array.cursor(2050).is_marked(PageMark::Dirty));
+    assert!(array.cursor(75).is_marked(PageMark::Towrite));
+    assert_eq!(array.erase_marked(PageMark::Dirty), 758);
+    assert!(!array.is_marked(PageMark::Writeback));
+    assert!(!array.is_marked(PageMark::Accessed));
+    assert!(catch_unwind(AssertUnwindSafe(|| array.cursor_mut(1).mark(5))).is_err());
+
+    // Pool slots are sized for the default nodes.
+    let mut slots = [NodeSlot::UNINIT; 4];
+    let pool = NodePool::new(&mut slots);
+    let mut pooled = RawXArray::<u64, 5, _>::with_marks_in(u64::MAX, &pool);
+    assert_eq!(pooled.insert(1 << 20, &v[0]), Err(XaError::NoMem));
+    let mut pooled = RawXArray::<u64, 1, _>::with_marks_in(u64::MAX, &pool);
+    assert_eq!(pooled.insert(1 << 20, &v[0]), Ok(None));
+    drop(pooled);
+    assert_eq!(pool.available(), 4);
+}
+
+#[test]
+fn test_mark_range() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut head = RawXArray::new();
+    head.insert(0, &v[0]).unwrap();
+    head.set_mark_range(1..=10, XaMark::Mark0);
+    assert!(!head.is_marked(XaMark::Mark0));
+    head.set_mark_range(0..=10, XaMark::Mark0);
+    assert!(head.cursor(0).is_marked(XaMark::Mark0));
+    head.clear_mark_range(0..=0, XaMark::Mark0);
+    assert!(!head.is_marked(XaMark::Mark0));
+
+    // Marking in bulk agrees with marking entry by entry.
+    let mut rng = StdRng::seed_from_u64(17);
+    let (mut bulk, mut single) = (RawXArray::new(), RawXArray::new());
+    for array in [&mut bulk, &mut single] {
+        let mut rng = StdRng::seed_from_u64(23);
+        for _ in 0..2000 {
+            let index = rng.gen_range(0, 1 << 14);
+            array.insert(index, &v[(index % 4096) as usize]).unwrap();
+        }
+        array.reserve(1 << 15).unwrap();
+        array.store_range(20000, 20099, &v[1]).unwrap();
+        array.insert(1 << 40, &v[2]).unwrap();
+    }
+    for _ in 0..300 {
+        let start = match rng.gen_range(0, 3) {
+            0 => rng.gen_range(0, 1 << 14),
+            1 => rng.gen_range(19950, 20150),
+            _ => rng.gen_range(0, 1 << 41),
+        };
+        let end = start + rng.gen_range(0, 3000);
+        let mark = [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2][rng.gen_range(0, 3)];
+        let set = rng.gen_range(0, 3) != 0;
+        if set {
+            bulk.set_mark_range(start..=end, mark);
+        } else {
+            bulk.clear_mark_range(start..=end, mark);
+        }
+        let mut iter = single.extract_mut(start, end);
+        while iter.next().is_some() {
+            match set {
+                true => iter.as_cursor_mut().mark(mark),
+                false => iter.as_cursor_mut().unmark(mark),
+            }
+        }
+        for mark in [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2] {
+            assert_eq!(bulk.is_marked(mark), single.is_marked(mark));
+            let marked = bulk.iter().filter_mark(mark).map(|(i, _)| i);
+            assert!(marked.eq(single.iter().filter_mark(mark).map(|(i, _)| i)));
+        }
+    }
+    bulk.clear_mark_range(0..=u64::MAX, XaMark::Mark1);
+    assert!(!bulk.is_marked(XaMark::Mark1));
+    assert_eq!(bulk.iter().filter_mark(XaMark::Mark1).count(), 0);
+    bulk.set_mark_range(0..=u64::MAX, XaMark::Mark1);
+    assert_eq!(
+        bulk.iter().filter_mark(XaMark::Mark1).count(),
+        bulk.iter().count()
+    );
+}
+
+#[test]
+fn test_get_mark() {
+    let v = (0..8u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    array.insert(0, &v[0]).unwrap();
+    array.cursor_mut(0).mark(XaMark::Mark1);
+    assert!(array.get_mark(0, XaMark::Mark1));
+    assert!(!array.get_mark(0, XaMark::Mark0));
+    assert!(!array.get_mark(1, XaMark::Mark1));
+
+    array.insert(5, &v[5]).unwrap();
+    array.store_range(64, 127, &v[6]).unwrap();
+    array.reserve(300).unwrap();
+    let mut cursor = array.cursor_mut(5);
+    assert!(!cursor.is_marked(XaMark::Mark2));
+    cursor.mark(XaMark::Mark2);
+    assert!(cursor.is_marked(XaMark::Mark2));
+    array.cursor_mut(100).mark(XaMark::Mark2);
+    let mut cursor = array.cursor_mut(300);
+    cursor.mark(XaMark::Mark2);
+    assert!(!cursor.is_marked(XaMark::Mark2));
+    assert!(array.get_mark(0, XaMark::Mark1));
+    assert!(array.get_mark(5, XaMark::Mark2));
+    // Every index of a multi-index entry shares its marks.
+    assert!((64..128).all(|i| array.get_mark(i, XaMark::Mark2)));
+    assert!(!array.get_mark(128, XaMark::Mark2));
+    assert!(!array.get_mark(300, XaMark::Mark2));
+    assert!(!array.get_mark(1 << 50, XaMark::Mark2));
+    assert!(array.cursor(127).is_marked(XaMark::Mark2));
+}
+
+#[test]
+fn test_count_marked() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert_eq!(array.count_marked(0..=u64::MAX, XaMark::Mark0), 0);
+    array.insert(0, &v[0]).unwrap();
+    array.cursor_mut(0).mark(XaMark::Mark0);
+    assert_eq!(array.count_marked(0..=0, XaMark::Mark0), 1);
+    assert_eq!(array.count_marked(1..=9, XaMark::Mark0), 0);
+    assert_eq!(array.count_marked(0..=9, XaMark::Mark1), 0);
+
+    let mut rng = StdRng::seed_from_u64(29);
+    for _ in 0..3000 {
+        let index = rng.gen_range(0, 1 << 16);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+    }
+    array.store_range(1 << 17, (1 << 17) + 255, &v[1]).unwrap();
+    array.reserve(1 << 18).unwrap();
+    array.set_mark_range((1 << 17)..=(1 << 17), XaMark::Mark2);
+    array.set_mark_range(1000..=20000, XaMark::Mark2);
+    array.set_mark_range(0..=u64::MAX, XaMark::Mark1);
+    for index in (0..1 << 16).step_by(7) {
+        array.cursor_mut(index).mark(XaMark::Mark0);
+    }
+    for _ in 0..300 {
+        let start = rng.gen_range(0, 1 << 18);
+        let end = start + rng.gen_range(0, 1 << 14);
+        for mark in [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2] {
+            assert_eq!(
+                array.count_marked(start..=end, mark),
+                array.extract(start, end).filter_mark(mark).count() as u64
+            );
+        }
+    }
+    assert_eq!(
+        array.count_marked(0..=u64::MAX, XaMark::Mark1),
+        array.iter().count() as u64
+    );
+    assert_eq!(array.count_marked(20001..=(1 << 17) - 1, XaMark::Mark2), 0);
+    assert_eq!(
+        array.count_marked((1 << 17) + 9..=(1 << 17) + 9, XaMark::Mark2),
+        1
+    );
+}
+
+#[test]
+fn test_filter_marks() {
+    const MARKS: [XaMark; 3] = [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2];
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(31);
+    let mut array = RawXArray::new();
+    for _ in 0..2000 {
+        let index = rng.gen_range(0, 1 << 14);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+    }
+    array.store_range(1 << 15, (1 << 15) + 127, &v[1]).unwrap();
+    array
+        .store_range((1 << 15) + 130, (1 << 15) + 131, &v[2])
+        .unwrap();
+    array.reserve(1 << 16).unwrap();
+    for mark in MARKS {
+        for _ in 0..800 {
+            array.cursor_mut(rng.gen_range(0, 1 << 14)).mark(mark);
+        }
+        array.set_mark_range(rng.gen_range(0, 1 << 14)..=1 << 15, mark);
+        array.clear_mark_range(rng.gen_range(0, 1 << 14)..=(1 << 15) + 130, mark);
+    }
+
+    let pick = |rng: &mut StdRng| {
+        MARKS
+            .into_iter()
+            .filter(|_| rng.gen_range(0, 3) == 0)
+            .fold(MarkSet::EMPTY, MarkSet::with)
+    };
+    for _ in 0..200 {
+        let (all, any, none) = (pick(&mut rng), pick(&mut rng), pick(&mut rng));
+        let start = rng.gen_range(0, (1 << 15) + 200);
+        let end = start + rng.gen_range(0, 1 << 13);
+        let passes = |index: u64| {
+            let marked = |mark| array.get_mark(index, mark);
+            MARKS.into_iter().all(|m| !all.contains(m) || marked(m))
+                && (any.is_empty() || any.iter().any(marked))
+                && none.iter().all(|m| !marked(m))
+        };
+        let mut range = array.extract(start, end).filter_all(all);
+        if !any.is_empty() {
+            range = range.filter_any(any);
+        }
+        for mark in none.iter() {
+            range = range.filter_unmarked(mark);
+        }
+        let expected = array
+            .extract(start, end)
+            .filter(|(i, _)| passes(*i))
+            .collect::<Vec<_>>();
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        // Meet in the middle to check both ends stay apart.
+        loop {
+            let step = match rng.gen_range(0, 2) {
+                0 => range.next().map(|e| front.push(e)),
+                _ => range.next_back().map(|e| back.push(e)),
+            };
+            if step.is_none() {
+                break;
+            }
+        }
+        front.extend(range.by_ref());
+        front.extend(back.into_iter().rev());
+        assert_eq!(front, expected);
+
+        let mut range = array.extract_mut(start, end).filter_all(all);
+        if !any.is_empty() {
+            range = range.filter_any(any);
+        }
+        for mark in none.iter() {
+            range = range.filter_unmarked(mark);
+        }
+        assert!(range.eq(expected.iter().copied()));
+    }
+
+    // An entry straddling the start is yielded whole, and only once.
+    let straddling = array
+        .extract((1 << 15) + 5, (1 << 15) + 200)
+        .filter_unmarked(XaMark::Mark0)
+        .filter_unmarked(XaMark::Mark1)
+        .map(|(i, _)| i);
+    assert!(straddling.eq([1 << 15, (1 << 15) + 130]));
+
+    // Filtering on one mark twice asks for both.
+    let both = array
+        .iter()
+        .filter_mark(XaMark::Mark0)
+        .filter_mark(XaMark::Mark1)
+        .map(|(i, _)| i);
+    assert!(both
+        .eq(array.iter().map(|(i, _)| i).filter(|i| {
+            array.get_mark(*i, XaMark::Mark0) && array.get_mark(*i, XaMark::Mark1)
+        })));
+
+    let mut owned = XArray::<u64, Box<u64>>::new();
+    for i in 0..100 {
+        owned.insert(i, Box::new(i)).unwrap();
+    }
+    for i in (0..100).step_by(3) {
+        owned.cursor_mut(i).mark(XaMark::Mark0);
+    }
+    for i in (0..100).step_by(5) {
+        owned.cursor_mut(i).mark(XaMark::Mark2);
+    }
+    let either = owned
+        .extract_mut(0, 99)
+        .filter_any(MarkSet::EMPTY.with(XaMark::Mark0).with(XaMark::Mark2))
+        .map(|(i, _)| i);
+    assert!(either.eq((0..100).filter(|i| i % 3 == 0 || i % 5 == 0)));
+}
+
+#[test]
+fn test_take_marked() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(37);
+    let mut array = RawXArray::new();
+    for _ in 0..2000 {
+        let index = rng.gen_range(0, 1 << 16);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+        if rng.gen_range(0, 3) == 0 {
+            array.cursor_mut(index).mark(XaMark::Mark1);
+        }
+    }
+    array.store_range(1 << 17, (1 << 17) + 63, &v[1]).unwrap();
+    array.cursor_mut(1 << 17).mark(XaMark::Mark1);
+
+    let mut cursor = array.cursor_mut(1 << 17);
+    assert!(cursor.test_and_clear_mark(XaMark::Mark1));
+    assert!(!cursor.test_and_clear_mark(XaMark::Mark1));
+    assert!(!cursor.is_marked(XaMark::Mark1));
+    cursor.mark(XaMark::Mark1);
+    assert!(!array
+        .cursor_mut((1 << 17) + 64)
+        .test_and_clear_mark(XaMark::Mark1));
+
+    let marked = array.iter().filter_mark(XaMark::Mark1).collect::<Vec<_>>();
+    let (start, end) = (1000, 40000);
+    let taken = array
+        .extract_mut(start, end)
+        .take_marked(XaMark::Mark1)
+        .collect::<Vec<_>>();
+    let (inside, outside): (Vec<_>, Vec<_>) =
+        marked.iter().partition(|(i, _)| (start..=end).contains(i));
+    assert_eq!(taken, inside);
+    assert!(array.iter().filter_mark(XaMark::Mark1).eq(outside));
+    assert_eq!(array.count_marked(start..=end, XaMark::Mark1), 0);
+
+    let rest = array
+        .extract_mut(0, u64::MAX)
+        .take_marked(XaMark::Mark1)
+        .count();
+    assert_eq!(rest + taken.len(), marked.len());
+    assert!(!array.is_marked(XaMark::Mark1));
+
+    let mut owned = XArray::<u64, Box<u64>>::new();
+    for i in 0..100 {
+        owned.insert(i, Box::new(i)).unwrap();
+        if i % 7 == 0 {
+            owned.cursor_mut(i).mark(XaMark::Mark0);
+        }
+    }
+    let dirty = owned.extract_mut(0, 99).take_marked(XaMark::Mark0);
+    assert!(dirty.map(|(i, _)| i).eq((0..100).step_by(7)));
+    assert!(!owned.is_marked(XaMark::Mark0));
+}
+
+#[test]
+fn test_clear_mark_all() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Cleared(Rc<RefCell<Vec<u64>>>);
+    impl Observer<u64> for Cleared {
+        fn on_mark(&mut self, index: u64, mark: usize, set: bool) {
+            if mark == XaMark::Mark2 as usize && !set {
+                self.0.borrow_mut().push(index);
+            }
+        }
+    }
+
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    array.insert(0, &v[0]).unwrap();
+    array.cursor_mut(0).mark(XaMark::Mark2);
+    array.clear_mark_all(XaMark::Mark2);
+    assert!(!array.is_marked(XaMark::Mark2));
+    assert!(!array.get_mark(0, XaMark::Mark2));
+
+    let mut rng = StdRng::seed_from_u64(41);
+    for _ in 0..3000 {
+        let index = rng.gen_range(0, 1 << 20);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+        if rng.gen_range(0, 4) == 0 {
+            array.cursor_mut(index).mark(XaMark::Mark2);
+        }
+        if rng.gen_range(0, 2) == 0 {
+            array.cursor_mut(index).mark(XaMark::Mark0);
+        }
+    }
+    array.store_range(1 << 21, (1 << 21) + 63, &v[1]).unwrap();
+    array.cursor_mut(1 << 21).mark(XaMark::Mark2);
+    let marked = array
+        .iter()
+        .filter_mark(XaMark::Mark2)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let others = array.count_marked(0..=u64::MAX, XaMark::Mark0);
+
+    let cleared = Rc::new(RefCell::new(Vec::new()));
+    array.set_observer(Cleared(cleared.clone()));
+    array.clear_mark_all(XaMark::Mark2);
+    assert_eq!(*cleared.borrow(), marked);
+    assert!(!array.is_marked(XaMark::Mark2));
+    assert_eq!(array.iter().filter_mark(XaMark::Mark2).count(), 0);
+    assert_eq!(array.count_marked(0..=u64::MAX, XaMark::Mark0), others);
+
+    // Marks set afterwards are found again.
+    array.cursor_mut(1 << 21).mark(XaMark::Mark2);
+    assert!(array
+        .iter()
+        .filter_mark(XaMark::Mark2)
+        .map(|(i, _)| i)
+        .eq([1 << 21]));
+}
+
+#[test]
+fn test_cursor_marks() {
+    const MARKS: [XaMark; 3] = [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2];
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut array = RawXArray::new();
+    assert!(array.cursor(0).marks().is_empty());
+    array.insert(0, &v[0]).unwrap();
+    array.cursor_mut(0).mark(XaMark::Mark2);
+    assert!(array.cursor(0).marks().iter().eq([XaMark::Mark2]));
+
+    let mut rng = StdRng::seed_from_u64(43);
+    for _ in 0..1000 {
+        let index = rng.gen_range(0, 1 << 16);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+        for mark in MARKS {
+            if rng.gen_range(0, 2) == 0 {
+                array.cursor_mut(index).mark(mark);
+            }
+        }
+    }
+    array.store_range(1 << 17, (1 << 17) + 63, &v[1]).unwrap();
+    array.cursor_mut(1 << 17).mark(XaMark::Mark1);
+    array.reserve(1 << 18).unwrap();
+    for index in array.iter().map(|(i, _)| i).collect::<Vec<_>>() {
+        let expected = MARKS
+            .into_iter()
+            .filter(|mark| array.get_mark(index, *mark))
+            .fold(MarkSet::EMPTY, MarkSet::with);
+        assert_eq!(array.cursor(index).marks(), expected);
+        assert_eq!(array.cursor_mut(index).marks(), expected);
+    }
+    assert!(array
+        .cursor((1 << 17) + 40)
+        .marks()
+        .iter()
+        .eq([XaMark::Mark1]));
+    assert!(array.cursor(1 << 18).marks().is_empty());
+    assert!(array.cursor(1 << 40).marks().is_empty());
+}
+
+#[test]
+fn test_replace_marks() {
+    let v = (0..16u64).collect::<Vec<_>>();
+    let both = MarkSet::EMPTY.with(XaMark::Mark0).with(XaMark::Mark2);
+    let mut array = RawXArray::new();
+    for index in [0, 7, 1 << 20] {
+        array.insert(index, &v[1]).unwrap();
+        array.cursor_mut(index).mark(XaMark::Mark0);
+        array.cursor_mut(index).mark(XaMark::Mark2);
+
+        let mut cursor = array.cursor_mut(index);
+        assert_eq!(cursor.replace_keep_marks(&v[2]), Ok(Some(&v[1])));
+        assert_eq!(cursor.marks(), both);
+        assert_eq!(cursor.replace_clear_marks(&v[3]), Ok(Some(&v[2])));
+        assert!(cursor.marks().is_empty());
+        assert_eq!(cursor.current(), Some(&v[3]));
+    }
+    assert!(!array.is_marked(XaMark::Mark0));
+    assert!(!array.is_marked(XaMark::Mark2));
+
+    // Other entries sharing the nodes keep their marks.
+    array.insert(8, &v[4]).unwrap();
+    array.cursor_mut(8).mark(XaMark::Mark0);
+    array.cursor_mut(7).mark(XaMark::Mark0);
+    array.cursor_mut(7).replace_clear_marks(&v[5]).unwrap();
+    assert!(array
+        .iter()
+        .filter_mark(XaMark::Mark0)
+        .map(|(i, _)| i)
+        .eq([8]));
+    assert_eq!(array.cursor_mut(9).replace_clear_marks(&v[6]), Ok(None));
+    assert_eq!(array.get(9), Some(&v[6]));
+
+    let mut owned = XArray::<u64, Box<u64>>::new();
+    owned.insert(3, Box::new(1)).unwrap();
+    owned.cursor_mut(3).mark(XaMark::Mark1);
+    assert_eq!(
+        owned.cursor_mut(3).replace_keep_marks(Box::new(2)),
+        Ok(Some(Box::new(1)))
+    );
+    assert!(owned.cursor_mut(3).is_marked(XaMark::Mark1));
+    assert_eq!(
+        owned.cursor_mut(3).replace_clear_marks(Box::new(3)),
+        Ok(Some(Box::new(2)))
+    );
+    assert!(!owned.is_marked(XaMark::Mark1));
+    assert_eq!(owned.get(3), Some(&3));
+}
+
+#[test]
+fn test_cursor_seek() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(47);
+    let mut array = RawXArray::new();
+    let mut cursor = array.cursor(5);
+    cursor.seek(0);
+    assert_eq!(cursor.current(), None);
+    array.insert(0, &v[0]).unwrap();
+    let mut cursor = array.cursor(5);
+    assert_eq!(cursor.current(), None);
+    cursor.seek(0);
+    assert_eq!(cursor.current(), Some(&v[0]));
+
+    for _ in 0..3000 {
+        let index = rng.gen_range(0, 1 << 18);
+        array.insert(index, &v[(index % 4096) as usize]).unwrap();
+    }
+    array.store_range(1 << 19, (1 << 19) + 63, &v[1]).unwrap();
+    array.reserve(1 << 20).unwrap();
+    array.insert(1 << 40, &v[2]).unwrap();
+    let mut index = 0u64;
+    let mut cursor = array.cursor(0);
+    for _ in 0..5000 {
+        index = match rng.gen_range(0, 4) {
+            0 => rng.gen_range(0, 1 << 18),
+            1 => index ^ rng.gen_range(0, 64),
+            2 => rng.gen_range((1 << 19) - 8, (1 << 19) + 72),
+            _ => [1 << 20, 1 << 40, (1 << 40) + 1, u64::MAX][rng.gen_range(0, 4)],
+        };
+        cursor.seek(index);
+        let mut fresh = array.cursor(index);
+        assert_eq!(cursor.current(), fresh.current());
+        assert_eq!(cursor.current_option(), fresh.current_option());
+        cursor.next_allocated();
+        fresh.next_allocated();
+        assert_eq!(cursor.key(), fresh.key());
+        assert_eq!(cursor.current(), fresh.current());
+        index = cursor.key();
+    }
+
+    let mut cursor = array.cursor_mut(0);
+    for index in [7, 8, 1 << 17, 9, 1 << 30] {
+        cursor.seek(index);
+        cursor.store(&v[3]).unwrap();
+        cursor.mark(XaMark::Mark1);
+    }
+    cursor.seek(8);
+    cursor.remove();
+    cursor.seek(1 << 30);
+    cursor.remove();
+    // The path left behind by a removal freeing nodes is still sound.
+    cursor.seek(9);
+    assert_eq!(cursor.current(), Some(&v[3]));
+    assert!(array
+        .iter()
+        .filter_mark(XaMark::Mark1)
+        .map(|(i, _)| i)
+        .eq([7, 9, 1 << 17]));
+
+    #[cfg(feature = "instrument")]
+    {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct Descents(Rc<Cell<u64>>);
+        impl Instrument for Descents {
+            fn on_descend(&self, _shift: u8) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+        let descents = Rc::new(Cell::new(0));
+        array.set_instrument(Descents(descents.clone()));
+        let mut cursor = array.cursor(1 << 17);
+        assert_eq!(cursor.current(), Some(&v[3]));
+        let walk = descents.replace(0);
+        cursor.seek((1 << 17) + 3);
+        cursor.current();
+        assert_eq!(descents.get(), 0);
+        cursor.seek((1 << 17) - 1);
+        cursor.current();
+        assert!(descents.get() < walk);
+    }
+}
+
+#[test]
+fn test_pause_resume() {
+    use std::collections::BTreeMap;
+
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(53);
+    for round in 0..40 {
+        let mut array = RawXArray::new();
+        let mut model = BTreeMap::new();
+        for _ in 0..rng.gen_range(1, 2000) {
+            let index = rng.gen_range(0, 1 << 16);
+            array.insert(index, &v[(index % 4096) as usize]).unwrap();
+            model.insert(index, index);
+        }
+        for index in [1u64 << 17, 1 << 18] {
+            array.store_range(index, index + 127, &v[1]).unwrap();
+            model.extend((index..index + 128).map(|i| (i, 1)));
+        }
+        let marked = model.keys().copied().filter(|_| rng.gen_range(0, 2) == 0);
+        for index in marked.collect::<Vec<_>>() {
+            array.cursor_mut(index).mark(XaMark::Mark0);
+        }
+        let end = rng.gen_range(1 << 15, 1 << 19);
+        let filter = round % 2 == 0;
+
+        // Walk a few entries, pause, shake the array up, and resume.
+        let start = rng.gen_range(0, 1 << 15);
+        let mut range = array.extract(start, end);
+        if filter {
+            range = range.filter_mark(XaMark::Mark0);
+        }
+        let steps = rng.gen_range(0, 50);
+        let last = range.by_ref().take(steps).last().map(|(i, _)| i);
+        let token = range.pause();
+        for _ in 0..rng.gen_range(0, 3000) {
+            let index = rng.gen_range(0, 1 << 16);
+            match rng.gen_range(0, 2) {
+                0 => {
+                    array.remove(index);
+                    model.remove(&index);
+                }
+                _ => {
+                    array.store(index, &v[2]).unwrap();
+                    model.insert(index, 2);
+                }
+            }
+        }
+        array.store_range(1 << 17, (1 << 17) + 127, &v[3]).unwrap();
+        model.extend(((1 << 17)..(1 << 17) + 128).map(|i| (i, 3)));
+
+        let resumed = array.resume(token).map(|(i, _)| i).collect::<Vec<_>>();
+        let from = match last {
+            Some(last) if last >= 1 << 17 => (last | 127) + 1,
+            Some(last) => last + 1,
+            None => start,
+        };
+        let expected = array
+            .extract(from, end)
+            .filter(|(i, _)| !filter || array.get_mark(*i, XaMark::Mark0))
+            .map(|(i, _)| i);
+        assert!(expected.eq(resumed.iter().copied()));
+        let mut range = array.resume_mut(token);
+        assert!(range.by_ref().map(|(i, _)| i).eq(resumed.iter().copied()));
+        assert_eq!(range.pause().next_index(), None);
+    }
+
+    // Pausing an exhausted iteration, or one on the last index, ends it.
+    let mut array = RawXArray::new();
+    array.insert(u64::MAX, &v[0]).unwrap();
+    let mut range = array.iter();
+    assert_eq!(range.next(), Some((u64::MAX, &v[0])));
+    let token = range.pause();
+    assert_eq!(token.next_index(), None);
+    assert_eq!(array.resume(token).next(), None);
+    assert_eq!(array.resume(token).next_back(), None);
+}
+
+#[test]
+fn test_remove_and_advance() {
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(59);
+    let policies = [
+        ShrinkPolicy::Immediate,
+        ShrinkPolicy::Deferred(3),
+        ShrinkPolicy::Manual,
+    ];
+    for round in 0..12 {
+        let mut array = RawXArray::new();
+        array.set_shrink_policy(policies[round % 3]);
+        for _ in 0..rng.gen_range(1, 1500) {
+            let index = match rng.gen_range(0, 3) {
+                0 => rng.gen_range(0, 64),
+                1 => rng.gen_range(0, 1 << 16),
+                _ => rng.gen_range(0, u64::MAX),
+            };
+            array.insert(index, &v[(index % 4096) as usize]).unwrap();
+        }
+        array.store_range(1 << 17, (1 << 17) + 127, &v[1]).unwrap();
+        array.reserve(1 << 18).unwrap();
+        array.insert(u64::MAX, &v[2]).unwrap();
+        let entries = array.iter().collect::<Vec<_>>();
+
+        // Drain about half of the entries while walking them.
+        let drop = entries
+            .iter()
+            .map(|_| rng.gen_range(0, 2) == 0)
+            .collect::<Vec<_>>();
+        let mut seen = Vec::new();
+        let mut cursor = array.cursor_mut(0);
+        cursor.next_allocated();
+        while let Some(value) = cursor.current() {
+            seen.push((cursor.key(), value));
+            if drop[seen.len() - 1] {
+                assert_eq!(cursor.remove_and_advance(), Some(value));
+            }
+            cursor.next_allocated();
+            if seen.len() == entries.len() {
+                break;
+            }
+        }
+        assert_eq!(seen, entries);
+        let kept = entries
+            .iter()
+            .zip(&drop)
+            .filter(|(_, drop)| !**drop)
+            .map(|(entry, _)| *entry);
+        assert!(array.iter().eq(kept));
+        assert!(array.get(1 << 18).is_none());
+        let kept = array.iter().collect::<Vec<_>>();
+        array.compact();
+        assert!(array.iter().eq(kept));
+    }
+
+    // Draining everything leaves an empty array.
+    let mut array = RawXArray::new();
+    for i in (0..5000).step_by(7) {
+        array.insert(i, &v[0]).unwrap();
+    }
+    let mut cursor = array.cursor_mut(0);
+    cursor.next_allocated();
+    while cursor.current().is_some() {
+        cursor.remove_and_advance();
+        cursor.next_allocated();
+    }
+    assert!(array.is_empty());
+
+    let mut owned = XArray::<u64, Box<u64>>::new();
+    for i in 0..10 {
+        owned.insert(i * 64, Box::new(i)).unwrap();
+    }
+    let mut cursor = owned.cursor_mut(128);
+    assert_eq!(cursor.remove_and_advance(), Some(Box::new(2)));
+    assert_eq!(cursor.remove_and_advance(), None);
+    cursor.next_allocated();
+    assert_eq!(cursor.key(), 192);
+    assert_eq!(owned.len(), 9);
+}
+
+#[cfg(feature = "linux")]
+#[test]
+fn test_linux_layout() {
+    use crate::node::{Node, RawEntry};
+    use core::mem::offset_of;
+
+    // struct xa_node on 64-bit kernels.
+    assert_eq!(offset_of!(Node<u64>, shift), 0);
+    assert_eq!(offset_of!(Node<u64>, offset), 1);
+    assert_eq!(offset_of!(Node<u64>, count), 2);
+    assert_eq!(offset_of!(Node<u64>, nr_value), 3);
+    assert_eq!(offset_of!(Node<u64>, parent), 8);
+    assert_eq!(offset_of!(Node<u64>, array), 16);
+    assert_eq!(offset_of!(Node<u64>, private_list), 24);
+    assert_eq!(offset_of!(Node<u64>, slots), 40);
+    assert_eq!(offset_of!(Node<u64>, marks), 552);
+    assert_eq!(core::mem::size_of::<Node<u64>>(), 576);
+
+    let value = 7u64;
+    assert_eq!(
+        RawEntry::<u64>::value(&value).inner,
+        &value as *const u64 as usize
+    );
+    assert_eq!(RawEntry::<u64>::sibling(5).inner, 5 << 2 | 2);
+    assert_eq!(RawEntry::<u64>::ZERO.inner, 257 << 2 | 2);
+
+    // Every node sits one level below its parent, and the head starts at 0.
+    fn check<T>(node: &mut Node<T>) {
+        for ofs in 0..64 {
+            if let Some(child) = node.entry(ofs).as_node() {
+                assert!(child
+                    .parent
+                    .as_node()
+                    .is_some_and(|p| core::ptr::eq(p, node)));
+                assert_eq!(child.shift + 6, node.shift);
+                assert_eq!(child.offset, ofs);
+                check(child);
+            }
+        }
+    }
+
+    let seed = thread_rng().gen::<[u8; 32]>();
+    let mut rng = StdRng::from_seed(seed);
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    let mut keys = Vec::new();
+    for value in v.iter() {
+        let key = rng.gen::<u64>() >> rng.gen_range(0, 64);
+        array.insert(key, value).unwrap();
+        keys.push(key);
+        if rng.gen_range(0, 4) == 0 {
+            array.remove(keys.swap_remove(rng.gen_range(0, keys.len())));
+        }
+        if let Some(head) = array.head.as_node() {
+            assert!(head.parent.is_null());
+            check(head);
+        }
+    }
+
+    // Moving subtrees links them back in one level below their new parent.
+    let mut other = RawXArray::new();
+    array.splice(1 << 20..=(1 << 40) - 1, &mut other, 1 << 44);
+    array.shift_range(0..=(1 << 19) - 1, 1 << 12);
+    array.insert_gap(1 << 8, 1 << 18);
+    other.remove_gap(0, 1 << 43);
+    for xa in [&array, &other] {
+        if let Some(head) = xa.head.as_node() {
+            assert!(head.parent.is_null());
+            check(head);
+        }
+    }
+}
+
+#[cfg(feature = "linux")]
+#[test]
+fn test_linux_view() {
+    use crate::node::{Node, Parent, RawEntry};
+    use crate::state::NodeOrState;
+
+    // Nodes as lib/xarray.c leaves them: the owning array set, the
+    // `private_list` initialized to point at itself, and nothing else.
+    fn kernel_node(shift: u8, array: usize) -> Box<Node<u64>> {
+        let mut node = Box::new(Node::new(shift, 0, &mut NodeOrState::Empty).unwrap());
+        let list = core::ptr::addr_of!(node.private_list) as usize;
+        node.private_list = [list, list];
+        node.array = array;
+        node
+    }
+
+    let v = (0..256u64).collect::<Vec<_>>();
+    let array = &v as *const _ as usize;
+    let mut head = kernel_node(6, array);
+    let mut leaves = [kernel_node(0, array), kernel_node(0, array)];
+    for (leaf, offset) in leaves.iter_mut().zip([0u8, 3]) {
+        leaf.parent = Parent::node(&head);
+        leaf.offset = offset;
+        *head.entry(offset) = RawEntry::node(leaf);
+        head.count += 1;
+    }
+    let [low, high] = &mut leaves;
+    for offset in [1u8, 5] {
+        *low.entry(offset) = RawEntry::value(&v[offset as usize]);
+    }
+    *high.entry(7) = RawEntry::value(&v[199]);
+    *high.entry(10) = RawEntry::value(&v[202]);
+    *high.entry(11) = RawEntry::sibling(10);
+    (low.count, low.nr_value, high.count, high.nr_value) = (2, 2, 3, 3);
+    high.mark_mut(XaMark::Mark0).set(7);
+    head.mark_mut(XaMark::Mark0).set(3);
+
+    let view = unsafe { XArrayView::<u64>::from_raw_head(RawEntry::node(&head).inner) };
+    assert_eq!(view.to_vec(), [(1, &1), (5, &5), (199, &199), (202, &202)]);
+    assert_eq!(view.get(5), Some(&5));
+    assert_eq!(view.get(203), Some(&202));
+    assert_eq!(view.get(6), None);
+    assert_eq!(view.get(4096), None);
+    assert_eq!(view.len(), 4);
+    assert!(!view.is_empty());
+    assert_eq!(view.count_range(0..=63), 2);
+    assert_eq!(view.count_range(6..=200), 1);
+    assert_eq!(
+        view.entry_span(203).map(|(first, last, _)| (first, last)),
+        Some((202, 203))
+    );
+    assert!(view.is_marked(XaMark::Mark0));
+    let marked = view.iter().filter_mark(XaMark::Mark0).collect::<Vec<_>>();
+    assert_eq!(marked, [(199, &199)]);
+    assert_eq!(view.iter().next_back(), Some((202, &202)));
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn test_stats() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array: RawXArray<u64> = RawXArray::new();
+    assert_eq!(array.stats(), Stats::default());
+    array.insert(0, &v[0]).unwrap();
+    assert_eq!(array.stats(), Stats::default());
+    array.insert(1, &v[1]).unwrap();
+    assert_eq!(
+        array.stats(),
+        Stats {
+            allocs: 1,
+            expands: 1,
+            ..Stats::default()
+        }
+    );
+    array.insert(1 << 20, &v[2]).unwrap();
+    // Without path compression, the kernel layout links a node per level.
+    let expected = if cfg!(feature = "linux") {
+        (7, 3, 4)
+    } else {
+        (3, 1, 2)
+    };
+    let stats = array.stats();
+    assert_eq!((stats.allocs, stats.splits, stats.expands), expected);
+    array.remove(1 << 20);
+    array.remove(1);
+    let stats = array.stats();
+    assert_eq!((stats.frees, stats.shrinks), (expected.0, expected.2));
+    assert!(array.iter().eq([(0, &v[0])]));
+
+    for (i, value) in v.iter().enumerate() {
+        array.insert(i as u64 * 7, value).unwrap();
+    }
+    for i in 0..TCNT as u64 {
+        array.remove(i * 7);
+    }
+    let stats = array.stats();
+    assert_eq!(stats.allocs, stats.frees);
+}
+
+#[cfg(feature = "instrument")]
+#[test]
+fn test_instrument() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Counter {
+        allocs: Cell<u64>,
+        frees: Cell<u64>,
+        descents: Cell<u64>,
+    }
+
+    struct Hooks(Rc<Counter>);
+
+    impl Instrument for Hooks {
+        fn on_alloc(&self, _shift: u8) {
+            self.0.allocs.set(self.0.allocs.get() + 1);
+        }
+        fn on_free(&self, _shift: u8) {
+            self.0.frees.set(self.0.frees.get() + 1);
+        }
+        fn on_descend(&self, _shift: u8) {
+            self.0.descents.set(self.0.descents.get() + 1);
+        }
+    }
+
+    let v = (0..4096u64).collect::<Vec<_>>();
+    let counter = Rc::new(Counter::default());
+    let mut array: RawXArray<u64> = RawXArray::new();
+    array.set_instrument(Hooks(counter.clone()));
+    for (i, value) in v.iter().enumerate() {
+        array.insert(i as u64, value).unwrap();
+    }
+    assert_eq!(counter.allocs.get(), 65);
+    counter.descents.set(0);
+    assert_eq!(array.get(4000), Some(&v[4000]));
+    assert_eq!(counter.descents.get(), 2);
+    for i in 0..4096 {
+        array.remove(i);
+    }
+    assert_eq!(counter.frees.get(), 65);
+    assert!(array.take_instrument().is_some());
+    array.insert(1 << 30, &v[0]).unwrap();
+    assert_eq!(counter.allocs.get(), 65);
+}
+
+#[cfg(feature = "poison")]
+#[test]
+fn test_poison_quarantine() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array = RawXArray::from_slice(&v);
+    for i in 0..TCNT as u64 {
+        array.remove(i);
+    }
+    assert!(array.is_empty());
+    let quarantined = array.quarantine.nodes().collect::<Vec<_>>();
+    assert_eq!(quarantined.len(), crate::poison::QUARANTINE_SIZE);
+    for node in quarantined {
+        let r = std::panic::catch_unwind(|| node.mark(XaMark::Mark0).get(0));
+        assert!(r.is_err());
+    }
+}
+
+#[cfg(feature = "paranoid")]
+#[test]
+fn test_paranoid_detects_miscount() {
+    let v = (0..TCNT as u64).collect::<Vec<_>>();
+    let mut array = RawXArray::from_slice(&v);
+    array.head.as_node().unwrap().count -= 1;
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| array.remove(0)));
+    assert!(r.is_err());
+}
@@ -0,0 +1,95 @@
+use crate::{MarkSet, RawXArray, XaError};
+use alloc::vec::Vec;
+
+enum Undo<'a, T> {
+    Inserted(u64),
+    Removed(u64, &'a T, MarkSet),
+}
+
+/// Journaled view of an array handed out by [`RawXArray::transaction`].
+///
+/// Every insertion and removal made through the transaction records its
+/// inverse, which is replayed in reverse order if the transaction is rolled
+/// back.
+pub struct Transaction<'a, 'b, T> {
+    xa: &'b mut RawXArray<'a, T>,
+    journal: Vec<Undo<'a, T>>,
+}
+
+impl<'a, 'b, T> Transaction<'a, 'b, T> {
+    /// Get value at the index.
+    #[inline]
+    pub fn get(&self, index: u64) -> Option<&'a T> {
+        self.xa.get(index)
+    }
+
+    /// Insert value into the index.
+    ///
+    /// If the xarray does not contains the value at the index,
+    /// [`None`] is returned. See [`RawXArray::insert`].
+    pub fn insert(&mut self, index: u64, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        let old = self.xa.insert(index, value)?;
+        if old.is_none() {
+            self.journal.push(Undo::Inserted(index));
+        }
+        Ok(old)
+    }
+
+    /// Remove value at the index, returning the value at the index.
+    ///
+    /// Marks of the removed entry are restored on rollback.
+    pub fn remove(&mut self, index: u64) -> Option<&'a T> {
+        let (value, marks) = self.xa.remove_with_marks(index)?;
+        self.journal.push(Undo::Removed(index, value, marks));
+        Some(value)
+    }
+
+    fn rollback(&mut self) {
+        while let Some(undo) = self.journal.pop() {
+            match undo {
+                Undo::Inserted(index) => {
+                    self.xa.remove(index);
+                }
+                Undo::Removed(index, value, marks) => {
+                    let mut cursor = self.xa.cursor_mut(index);
+                    if let Err(err) = cursor.insert(value) {
+                        panic!("xarray: {}", err);
+                    }
+                    for m in marks.iter() {
+                        cursor.mark(m);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, 'b, T> Drop for Transaction<'a, 'b, T> {
+    fn drop(&mut self) {
+        self.rollback();
+    }
+}
+
+impl<'a, T> RawXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Run `f` against a journaled view of the array.
+    ///
+    /// If `f` returns [`Err`] (or panics), every insertion and removal made
+    /// through the [`Transaction`] is undone before returning.
+    pub fn transaction<F, R, E>(&mut self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut Transaction<'a, '_, T>) -> Result<R, E>,
+    {
+        let mut tx = Transaction {
+            xa: self,
+            journal: Vec::new(),
+        };
+        let result = f(&mut tx);
+        if result.is_ok() {
+            tx.journal.clear();
+        }
+        result
+    }
+}
@@ -0,0 +1,64 @@
+use crate::node::RawEntry;
+use crate::{RawXArray, XaMark};
+use core::mem::ManuallyDrop;
+
+/// Read-only view over an array whose nodes are owned by someone else,
+/// such as another language across FFI.
+///
+/// The view gives out shared access only and never frees the nodes it
+/// walks, so lookups and iteration work in place without copying.
+pub struct XArrayView<'a, T> {
+    xa: ManuallyDrop<RawXArray<'a, T>>,
+}
+
+impl<'a, T> XArrayView<'a, T>
+where
+    T: 'a,
+{
+    /// Wrap the head entry `head` of a foreign array, as returned by
+    /// [`RawXArray::raw_head`].
+    ///
+    /// # Safety
+    ///
+    /// `head` must be a head entry in this crate's encoding and node layout.
+    /// The nodes and values reachable from it must stay alive and must not
+    /// be modified for `'a`.
+    ///
+    /// With the `linux` feature, that is the layout of the kernel's
+    /// `struct xa_node`, as built by C code: only the fields the kernel
+    /// keeps are read, and nothing is read from `private_list`.
+    pub unsafe fn from_raw_head(head: usize) -> Self {
+        let mut xa = RawXArray::new();
+        xa.head = RawEntry::new(head);
+        if let Some(node) = xa.head.as_node() {
+            for mark in [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2] {
+                if node.mark_mut(mark).any() {
+                    xa.marks |= 1 << mark as usize;
+                }
+            }
+        }
+        Self {
+            xa: ManuallyDrop::new(xa),
+        }
+    }
+}
+
+impl<'a, T> core::ops::Deref for XArrayView<'a, T> {
+    type Target = RawXArray<'a, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.xa
+    }
+}
+
+impl<'a, T> RawXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Get the head entry of the array as an integer, to hand the array
+    /// over to [`XArrayView::from_raw_head`].
+    #[inline]
+    pub fn raw_head(&self) -> usize {
+        self.head.inner
+    }
+}
@@ -0,0 +1,141 @@
+use crate::Observer;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::future::Future;
+use core::ops::RangeInclusive;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+struct Watch {
+    id: u64,
+    range: RangeInclusive<u64>,
+    waker: Option<Waker>,
+    fired: bool,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: u64,
+    watches: Vec<Watch>,
+}
+
+/// Watches over indices of an array, waking tasks when an entry appears,
+/// changes, is removed or has a mark changed there.
+///
+/// Attach a clone with [`crate::RawXArray::set_observer`] and await
+/// [`Watches::changed`] instead of polling `get()`. Every watch fires once;
+/// await again to keep watching.
+#[derive(Clone, Default)]
+pub struct Watches {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Watches {
+    /// Create an empty set of watches.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for the next change to an entry within `range`.
+    ///
+    /// The watch is registered on the first poll, so changes made before
+    /// that are not seen.
+    pub fn changed(&self, range: RangeInclusive<u64>) -> Changed {
+        let mut inner = self.inner.borrow_mut();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        Changed {
+            inner: self.inner.clone(),
+            id,
+            range: Some(range),
+        }
+    }
+
+    /// Number of pending watches.
+    pub fn len(&self) -> usize {
+        self.inner.borrow().watches.len()
+    }
+
+    /// Returns `true` if no watch is pending.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn fire(&self, index: u64) {
+        let mut wakers = Vec::new();
+        for watch in self.inner.borrow_mut().watches.iter_mut() {
+            if !watch.fired && watch.range.contains(&index) {
+                watch.fired = true;
+                wakers.extend(watch.waker.take());
+            }
+        }
+        // Woken tasks may register new watches right away.
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Observer<T> for Watches {
+    fn on_insert(&mut self, index: u64, _value: &T) {
+        self.fire(index);
+    }
+
+    fn on_overwrite(&mut self, index: u64, _old: &T, _new: &T) {
+        self.fire(index);
+    }
+
+    fn on_remove(&mut self, index: u64, _value: &T) {
+        self.fire(index);
+    }
+
+    fn on_mark(&mut self, index: u64, _mark: usize, _set: bool) {
+        self.fire(index);
+    }
+}
+
+/// Future returned by [`Watches::changed`].
+pub struct Changed {
+    inner: Rc<RefCell<Inner>>,
+    id: u64,
+    /// Range still to be registered on the first poll.
+    range: Option<RangeInclusive<u64>>,
+}
+
+impl Future for Changed {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let id = self.id;
+        let range = self.range.take();
+        let mut inner = self.inner.borrow_mut();
+        if let Some(range) = range {
+            inner.watches.push(Watch {
+                id,
+                range,
+                waker: Some(cx.waker().clone()),
+                fired: false,
+            });
+            return Poll::Pending;
+        }
+        let Some(pos) = inner.watches.iter().position(|w| w.id == id) else {
+            return Poll::Ready(());
+        };
+        if inner.watches[pos].fired {
+            inner.watches.swap_remove(pos);
+            Poll::Ready(())
+        } else {
+            inner.watches[pos].waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Changed {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.inner.borrow_mut().watches.retain(|w| w.id != id);
+    }
+}
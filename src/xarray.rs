@@ -1,58 +1,415 @@
-use crate::{xarray_raw, RawXArray, XaMark};
+use crate::xarray_raw::{MarkFilter, RemoveHook};
+use crate::{
+    xarray_raw, AllocError, Collision, GlobalBox, MarkSet, NodeAlloc, RawXArray, Shrinker,
+    StorePolicy, XaError, XaLimit, XaMark,
+};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use core::ptr::NonNull;
 
+/// Pointer owning, or standing for, a value held by an [`XArray`].
+///
+/// Entries keep their type in the low bits of the pointer, so `T` must be
+/// aligned to at least [`VALUE_ALIGN`] bytes. Building an [`XArray`] over a
+/// less aligned `T` fails to compile.
 pub trait OwnedPointer<T> {
     // Construct self from raw pointer.
     fn from_raw(t: *mut T) -> Self;
     // Consume and leaks self into raw pointer.
-    fn into_raw(self) -> &'static T;
+    fn into_raw<'a>(self) -> &'a T
+    where
+        T: 'a;
 }
 
-/// eXtensible Array (XArray) with Boxed element.
-#[repr(transparent)]
-pub struct XArray<T: 'static, V: OwnedPointer<T>> {
-    inner: RawXArray<'static, T>,
-    _l: core::marker::PhantomData<V>,
-}
+/// Alignment required of the values held by an [`XArray`].
+pub const VALUE_ALIGN: usize = if cfg!(feature = "linux") { 4 } else { 2 };
 
-impl<'a, T: 'static, V: OwnedPointer<T>> core::ops::Deref for XArray<T, V> {
-    type Target = RawXArray<'static, T>;
+/// Pointer to a value whose allocation the array does not manage, for
+/// intrusive data structures where the values live elsewhere.
+///
+/// Dropping an `Intrusive` does nothing, so values removed from an
+/// [`XArray`] or left in it when it is dropped stay where they are.
+pub struct Intrusive<T>(NonNull<T>);
 
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+impl<T> Intrusive<T> {
+    /// Wrap `ptr` to hand it over to an [`XArray`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid `T` that is neither moved, freed nor
+    /// mutated for as long as it is held by the array or references handed
+    /// out by the array are in use.
+    #[inline]
+    pub const unsafe fn new(ptr: NonNull<T>) -> Self {
+        Self(ptr)
+    }
+
+    /// Get the wrapped pointer.
+    #[inline]
+    pub const fn as_non_null(&self) -> NonNull<T> {
+        self.0
     }
 }
 
-impl<'a, T: 'static, V: OwnedPointer<T>> core::ops::DerefMut for XArray<T, V> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.inner
+impl<T> OwnedPointer<T> for Intrusive<T> {
+    fn from_raw(t: *mut T) -> Self {
+        Self(NonNull::new(t).expect("xarray: null value pointer"))
+    }
+    fn into_raw<'a>(self) -> &'a T
+    where
+        T: 'a,
+    {
+        // SAFETY: the pointee outlives its stay in the array, see `new`.
+        unsafe { self.0.as_ref() }
     }
 }
 
-impl<'a, T: 'static, V: OwnedPointer<T>> Drop for XArray<T, V> {
-    fn drop(&mut self) {
-        for (_, v) in self.inner.iter() {
-            let _ = V::from_raw(v as *const _ as *mut T);
-        }
+/// eXtensible Array (XArray) with Boxed element.
+///
+/// Values may borrow from storage that outlives `'a`, such as an arena,
+/// as with [`RawXArray<'a, T>`].
+#[repr(transparent)]
+pub struct XArray<'a, T: 'a, V: OwnedPointer<T>, A: NodeAlloc = GlobalBox> {
+    inner: RawXArray<'a, T, 3, A>,
+    _l: core::marker::PhantomData<V>,
+}
+
+impl<'a, T: 'a, V: OwnedPointer<T>> From<Vec<Option<V>>> for XArray<'a, T, V> {
+    /// Create new XArray Object from a dense vector, storing each [`Some`]
+    /// element at the index of its position and skipping [`None`]s.
+    fn from(values: Vec<Option<V>>) -> Self {
+        let values = values.into_iter().map(|value| value.map(V::into_raw));
+        Self::from_inner(RawXArray::from_dense(values))
     }
 }
 
-impl<T: 'static, V: OwnedPointer<T>> XArray<T, V> {
+/// Drop a value torn down along with the nodes of an array.
+fn release<T, V: OwnedPointer<T>>(value: &T) {
+    drop(V::from_raw(value as *const _ as *mut T));
+}
+
+impl<'a, T: 'a, V: OwnedPointer<T>> XArray<'a, T, V> {
     /// Create new XArrayBoxed Object.
     #[inline]
     pub fn new() -> Self {
+        Self::with_limit(u64::MAX)
+    }
+
+    /// Create new XArrayBoxed Object that never stores an entry past
+    /// `limit`. See [`RawXArray::with_limit`].
+    #[inline]
+    pub fn with_limit(limit: u64) -> Self {
+        Self::from_inner(RawXArray::with_limit(limit))
+    }
+}
+
+impl<'a, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> XArray<'a, T, V, A> {
+    /// Create new XArrayBoxed Object whose nodes are allocated from
+    /// `node_alloc`. See [`RawXArray::new_in`].
+    #[inline]
+    pub fn new_in(node_alloc: A) -> Self {
+        Self::from_inner(RawXArray::new_in(node_alloc))
+    }
+
+    fn from_inner(mut inner: RawXArray<'a, T, 3, A>) -> Self {
+        const {
+            assert!(
+                core::mem::align_of::<T>() >= VALUE_ALIGN,
+                "xarray: values are not aligned enough"
+            )
+        };
+        inner.release = Some(release::<T, V>);
         Self {
-            inner: RawXArray::new(),
+            inner,
             _l: core::marker::PhantomData,
         }
     }
+
+    /// Get the value at the index.
+    ///
+    /// Unlike [`RawXArray::get`], the returned reference borrows the array,
+    /// as the array owns its values and drops them along with itself:
+    ///
+    /// ```compile_fail
+    /// use xarray::XArrayBoxed;
+    ///
+    /// let mut array: XArrayBoxed<'static, u64> = XArrayBoxed::new();
+    /// array.insert(0, Box::new(1)).unwrap();
+    /// let value: &'static u64 = array.get(0).unwrap();
+    /// drop(array);
+    /// assert_eq!(*value, 1);
+    /// ```
+    #[inline]
+    pub fn get(&self, index: u64) -> Option<&T> {
+        self.inner.get(index)
+    }
+
+    /// Get the entry at the index, telling a stored [`None`] apart from an
+    /// absent index. See [`RawXArray::get_option`].
+    #[inline]
+    pub fn get_option(&self, index: u64) -> Option<Option<&T>> {
+        self.inner.get_option(index)
+    }
+
+    /// Get the present value with the lowest index, along with its index.
+    #[inline]
+    pub fn first(&self) -> Option<(u64, &T)> {
+        self.inner.first()
+    }
+
+    /// Get the present value with the highest index, along with its index.
+    #[inline]
+    pub fn last(&self) -> Option<(u64, &T)> {
+        self.inner.last()
+    }
+
+    /// Get the first value at or after `index`. See
+    /// [`RawXArray::range_next`].
+    #[inline]
+    pub fn range_next(&self, index: u64) -> Option<(u64, &T)> {
+        self.inner.range_next(index)
+    }
+
+    /// Get the last value at or before `index`. See
+    /// [`RawXArray::range_prev`].
+    #[inline]
+    pub fn range_prev(&self, index: u64) -> Option<(u64, &T)> {
+        self.inner.range_prev(index)
+    }
+
+    /// Provides a cursor at the index, whose values borrow the array.
+    #[inline]
+    pub fn cursor(&self, index: u64) -> xarray_raw::Cursor<'_, '_, T, 3, A> {
+        self.inner.cursor(index)
+    }
+
+    /// Extract range iterator starting from `start` to `end` (inclusive),
+    /// whose values borrow the array.
+    #[inline]
+    pub fn extract(&self, start: u64, end: u64) -> xarray_raw::Range<'_, '_, T, 3, A> {
+        self.inner.extract(start, end)
+    }
+
+    /// Get iterator of the XArray, whose values borrow the array.
+    #[inline]
+    pub fn iter(&self) -> xarray_raw::Range<'_, '_, T, 3, A> {
+        self.inner.iter()
+    }
+
+    /// Get the maximum index the array can store an entry at.
+    #[inline]
+    pub fn limit(&self) -> u64 {
+        self.inner.limit()
+    }
+
+    /// Number of nodes currently allocated by the array.
+    #[inline]
+    pub fn node_count(&self) -> u64 {
+        self.inner.node_count()
+    }
+
+    /// Returns `true` if the array holds no entry. See
+    /// [`RawXArray::is_empty`].
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Number of present entries. See [`RawXArray::len`].
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.inner.len()
+    }
+
+    /// Returns `true` if any entry carries `mark`.
+    #[inline]
+    pub fn is_marked(&self, mark: XaMark) -> bool {
+        self.inner.is_marked(mark)
+    }
+
+    /// Returns `true` if the value at the index carries `mark`.
+    #[inline]
+    pub fn get_mark(&self, index: u64, mark: XaMark) -> bool {
+        self.inner.get_mark(index, mark)
+    }
+
+    /// Get the lowest present index. See [`RawXArray::min_index`].
+    #[inline]
+    pub fn min_index(&self) -> Option<u64> {
+        self.inner.min_index()
+    }
+
+    /// Get the highest present index. See [`RawXArray::max_index`].
+    #[inline]
+    pub fn max_index(&self) -> Option<u64> {
+        self.inner.max_index()
+    }
+
+    /// Get the lowest absent index at or after `index`. See
+    /// [`RawXArray::next_free`].
+    #[inline]
+    pub fn next_free(&self, index: u64) -> Option<u64> {
+        self.inner.next_free(index)
+    }
+
+    /// Count the present entries within `range`. See
+    /// [`RawXArray::count_range`].
+    #[inline]
+    pub fn count_range(&self, range: RangeInclusive<u64>) -> u64 {
+        self.inner.count_range(range)
+    }
+
+    /// Count the entries within `range` carrying `mark`. See
+    /// [`RawXArray::count_marked`].
+    #[inline]
+    pub fn count_marked(&self, range: RangeInclusive<u64>, mark: XaMark) -> u64 {
+        self.inner.count_marked(range, mark)
+    }
+
+    /// Returns `true` if no index within `range` is present. See
+    /// [`RawXArray::is_range_empty`].
+    #[inline]
+    pub fn is_range_empty(&self, range: RangeInclusive<u64>) -> bool {
+        self.inner.is_range_empty(range)
+    }
+
+    /// Borrow the underlying raw array, for the read-only operations that
+    /// are not forwarded here. Values obtained through it borrow the array:
+    ///
+    /// ```compile_fail
+    /// use xarray::{RawXArray, XArrayBoxed};
+    ///
+    /// let mut array: XArrayBoxed<'static, u64> = XArrayBoxed::new();
+    /// array.insert(0, Box::new(1)).unwrap();
+    /// let value: &'static u64 = RawXArray::get(array.as_raw(), 0).unwrap();
+    /// drop(array);
+    /// assert_eq!(*value, 1);
+    /// ```
+    ///
+    /// Nor does the array dereference to its raw array, whose updates would
+    /// bypass the ownership of the values:
+    ///
+    /// ```compile_fail
+    /// use xarray::{RawXArray, XArrayBoxed};
+    ///
+    /// let array: XArrayBoxed<u64> = XArrayBoxed::new();
+    /// RawXArray::get(&array, 0);
+    /// ```
+    #[inline]
+    pub fn as_raw(&self) -> &RawXArray<'_, T, 3, A> {
+        &self.inner
+    }
+
+    /// Register a shrinker that picks the entries evicted by
+    /// [`XArray::shrink`]. See [`RawXArray::set_shrinker`].
+    #[inline]
+    pub fn set_shrinker<S>(&mut self, shrinker: S)
+    where
+        S: Shrinker<T> + 'a,
+    {
+        self.inner.set_shrinker(shrinker)
+    }
+
+    /// Unregister the shrinker, returning it.
+    #[inline]
+    pub fn take_shrinker(&mut self) -> Option<Box<dyn Shrinker<T> + 'a>> {
+        self.inner.take_shrinker()
+    }
+
+    /// Register a callback invoked with the index and value of every entry
+    /// that is removed or overwritten. See [`RawXArray::set_on_remove`].
+    #[inline]
+    pub fn set_on_remove<F>(&mut self, f: F)
+    where
+        F: FnMut(u64, &T) + 'a,
+    {
+        self.inner.set_on_remove(f)
+    }
+
+    /// Unregister the removal callback, returning it.
+    #[inline]
+    pub fn take_on_remove(&mut self) -> Option<RemoveHook<'a, T>> {
+        self.inner.take_on_remove()
+    }
+
+    /// Reserve the index. See [`RawXArray::reserve`].
+    #[inline]
+    pub fn reserve(&mut self, index: u64) -> Result<(), XaError> {
+        self.inner.reserve(index)
+    }
+
+    /// Release the index if it is reserved. See [`RawXArray::release`].
+    #[inline]
+    pub fn release(&mut self, index: u64) {
+        self.inner.release(index)
+    }
+
     /// Insert value into the index.
     ///
     /// If the xarray does not contains the value at the index,
-    /// [`None`] is returned.
-    /// value is the reference of T, which outlives than self.
+    /// [`None`] is returned. Otherwise, or if the insert fails, the value
+    /// is dropped. See [`RawXArray::insert`].
+    pub fn insert(&mut self, index: u64, value: V) -> Result<Option<&T>, XaError> {
+        let raw = V::into_raw(value);
+        let result = self.inner.insert(index, raw);
+        if !matches!(result, Ok(None)) {
+            drop(V::from_raw(raw as *const _ as *mut T));
+        }
+        result
+    }
+
+    /// Store value into the index, replacing the entry there, and return
+    /// the value it replaces. The value is dropped on failure. See
+    /// [`RawXArray::store`].
+    pub fn store(&mut self, index: u64, value: V) -> Result<Option<V>, XaError> {
+        let raw = V::into_raw(value);
+        match self.inner.store(index, raw) {
+            Ok(old) => Ok(old.map(|n| V::from_raw(n as *const _ as *mut _))),
+            Err(err) => {
+                drop(V::from_raw(raw as *const _ as *mut T));
+                Err(err)
+            }
+        }
+    }
+
+    /// Store value at the lowest absent index, returning that index. See
+    /// [`RawXArray::alloc`].
     #[inline]
-    pub fn insert(&mut self, index: u64, value: V) -> Option<&'static T> {
-        self.cursor_mut(index).insert(value)
+    pub fn alloc(&mut self, value: V) -> Result<u64, AllocError> {
+        self.alloc_in(
+            value,
+            XaLimit {
+                min: 0,
+                max: u64::MAX,
+            },
+        )
+    }
+
+    /// Store value at the lowest absent index within `limit`, returning
+    /// that index. The value is dropped on failure. See
+    /// [`RawXArray::alloc_in`].
+    pub fn alloc_in(&mut self, value: V, limit: XaLimit) -> Result<u64, AllocError> {
+        let index = self
+            .inner
+            .next_free(limit.min)
+            .filter(|index| *index <= limit.max)
+            .ok_or(XaError::Busy)?;
+        self.store(index, value)?;
+        Ok(index)
+    }
+
+    /// Get the value at the index, inserting the one computed from `f` if
+    /// the index is absent.
+    ///
+    /// Unlike [`RawXArray::get_or_with`], the returned reference borrows
+    /// the array, as the array owns its values.
+    pub fn get_or_insert_with<F>(&mut self, index: u64, f: F) -> &T
+    where
+        F: FnOnce() -> V,
+    {
+        let f = move || V::into_raw(f());
+        self.inner.cursor_mut(index).current_or_insert(f).1
     }
 
     /// Remove value at the index, returning the value at the index.
@@ -61,59 +418,414 @@ impl<T: 'static, V: OwnedPointer<T>> XArray<T, V> {
         self.cursor_mut(index).remove()
     }
 
+    /// Remove value at the index, returning it along with the marks it
+    /// carried.
+    #[inline]
+    pub fn remove_with_marks(&mut self, index: u64) -> Option<(V, MarkSet)> {
+        self.inner
+            .remove_with_marks(index)
+            .map(|(n, marks)| (V::from_raw(n as *const _ as *mut _), marks))
+    }
+
+    /// Insert `value` into the index if it is absent, keeping a [`None`] as
+    /// a present entry without a value. See [`RawXArray::insert_option`].
+    pub fn insert_option(&mut self, index: u64, value: Option<V>) -> Option<Option<&T>> {
+        if let Some(current) = self.inner.get_option(index) {
+            return Some(current);
+        }
+        self.inner.insert_option(index, value.map(V::into_raw))
+    }
+
+    /// Remove the entry at the index, whether it holds a value or a stored
+    /// [`None`], returning it.
+    pub fn remove_option(&mut self, index: u64) -> Option<Option<V>> {
+        self.inner
+            .remove_option(index)
+            .map(|v| v.map(|n| V::from_raw(n as *const _ as *mut _)))
+    }
+
+    /// Consume the array, collecting every present entry into a vector
+    /// ordered by index.
+    pub fn into_vec(mut self) -> Vec<(u64, V)> {
+        let mut vec = Vec::with_capacity(self.inner.len() as usize);
+        vec.extend(
+            self.inner
+                .iter()
+                .map(|(i, v)| (i, V::from_raw(v as *const _ as *mut T))),
+        );
+        self.inner.release = None;
+        vec
+    }
+
+    /// Remove every entry, dropping the values. See [`RawXArray::clear`].
+    #[inline]
+    pub fn clear(&mut self) {
+        self.inner.clear()
+    }
+
+    /// Scan up to `nr` entries under memory pressure, dropping the ones the
+    /// registered shrinker picks. Returns the number of evicted entries.
+    pub fn shrink(&mut self, nr: usize) -> usize {
+        self.inner
+            .shrink_with(nr, |_, v| drop(V::from_raw(v as *const _ as *mut T)))
+    }
+
+    /// Remove every entry carrying `mark`, returning the removed values in
+    /// index order. See [`RawXArray::erase_marked`].
+    pub fn erase_marked(&mut self, mark: XaMark) -> Vec<V> {
+        let mut values = Vec::new();
+        self.inner.erase_marked_with(mark, |_, v| {
+            values.push(V::from_raw(v as *const _ as *mut T))
+        });
+        values
+    }
+
+    /// Drop every value for which `keep` returns `false`, returning the
+    /// number of dropped values. See [`RawXArray::retain`].
+    pub fn retain<F>(&mut self, keep: F) -> usize
+    where
+        F: FnMut(u64, &T) -> bool,
+    {
+        self.inner
+            .retain_with(keep, |_, v| drop(V::from_raw(v as *const _ as *mut T)))
+    }
+
+    /// Move every entry carrying `mark` into a new array at the same index.
+    /// See [`RawXArray::partition_by_mark`].
+    pub fn partition_by_mark(&mut self, mark: XaMark) -> Self
+    where
+        A: Clone,
+    {
+        Self::from_inner(self.inner.partition_by_mark(mark))
+    }
+
+    /// Move every entry of `other` into this array at the same index. See
+    /// [`RawXArray::append`].
+    #[inline]
+    pub fn append(&mut self, other: &mut Self, on_collision: Collision) -> Result<(), XaError> {
+        self.inner.append(&mut other.inner, on_collision)
+    }
+
+    /// Move every entry at or above `at` into a new array at the same index.
+    /// See [`RawXArray::split_off`].
+    pub fn split_off(&mut self, at: u64) -> Self
+    where
+        A: Clone,
+    {
+        Self::from_inner(self.inner.split_off(at))
+    }
+
+    /// Open a gap of `n` empty indices at `at`, dropping the entries pushed
+    /// past [`RawXArray::limit`]. See [`RawXArray::insert_gap`].
+    pub fn insert_gap(&mut self, at: u64, n: u64) {
+        self.inner.insert_gap(at, n);
+    }
+
+    /// Remove the `n` indices starting at `at`, dropping their entries. See
+    /// [`RawXArray::remove_gap`].
+    pub fn remove_gap(&mut self, at: u64, n: u64) {
+        self.inner.remove_gap(at, n);
+    }
+
+    /// Remove every entry in `start..=end`, dropping their values, and
+    /// return the number of removed entries. See [`RawXArray::remove_range`].
+    pub fn remove_range(&mut self, start: u64, end: u64) -> usize {
+        if start > end {
+            return 0;
+        }
+        let mut removed = 0;
+        for index in [start, end] {
+            if matches!(self.inner.entry_span(index), Some((first, last, _)) if first < start || last > end)
+            {
+                drop(self.remove(index));
+                removed += 1;
+            }
+        }
+        removed + self.inner.remove_range(start, end)
+    }
+
+    /// Move every entry in `range` into `dst` starting at `dst_start`,
+    /// dropping the entries it replaces. See [`RawXArray::splice`].
+    pub fn splice(&mut self, range: RangeInclusive<u64>, dst: &mut Self, dst_start: u64) {
+        self.inner.splice(range, &mut dst.inner, dst_start);
+    }
+
+    /// Move every entry in `range` by `delta` indices, dropping the entries
+    /// it replaces. See [`RawXArray::shift_range`].
+    pub fn shift_range(&mut self, range: RangeInclusive<u64>, delta: i64) {
+        self.inner.shift_range(range, delta);
+    }
+
     /// Provides a cursor with editing operations at the index.
     #[inline]
-    pub fn cursor_mut(&mut self, index: u64) -> CursorMut<T, V> {
+    pub fn cursor_mut(&mut self, index: u64) -> CursorMut<'a, '_, T, V, A> {
         CursorMut {
             inner: self.inner.cursor_mut(index),
             _v: core::marker::PhantomData,
         }
     }
 
-    /// Extract range iterator starting from `start` to `end` (inclusive).
-    pub fn extract_mut(&mut self, start: u64, end: u64) -> RangeMut<T, V> {
+    /// Extract range iterator starting from `start` to `end` (inclusive),
+    /// whose values borrow the array.
+    pub fn extract_mut(&mut self, start: u64, end: u64) -> RangeMut<'a, '_, T, V, A> {
         RangeMut {
             cursor: self.cursor_mut(start),
             end,
-            mark: None,
+            filter: MarkFilter::default(),
         }
     }
-}
 
-#[repr(transparent)]
-pub struct CursorMut<'a, T: 'static, V: OwnedPointer<T>> {
-    inner: xarray_raw::CursorMut<'static, 'a, T>,
-    _v: core::marker::PhantomData<V>,
-}
+    /// Get iterator of the XArray, whose values borrow the array.
+    #[inline]
+    pub fn iter_mut(&mut self) -> RangeMut<'a, '_, T, V, A> {
+        self.extract_mut(0, u64::MAX)
+    }
 
-impl<'a, T: 'static, V: OwnedPointer<T>> core::ops::Deref for CursorMut<'a, T, V> {
-    type Target = xarray_raw::CursorMut<'static, 'a, T>;
+    /// Remove every value, yielding them in index order. See
+    /// [`XArray::drain_range`].
+    #[inline]
+    pub fn drain(&mut self) -> Drain<'a, '_, T, V, A> {
+        self.drain_range(0, u64::MAX)
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+    /// Remove the values from `start` to `end` (inclusive), yielding them
+    /// in index order.
+    ///
+    /// Each value is removed as it is yielded, and nodes are freed as they
+    /// empty. Values not yet yielded when the iterator is dropped are
+    /// dropped as well.
+    pub fn drain_range(&mut self, start: u64, end: u64) -> Drain<'a, '_, T, V, A> {
+        Drain {
+            range: self.extract_mut(start, end),
+        }
     }
 }
 
-impl<'a, T: 'static, V: OwnedPointer<T>> core::ops::DerefMut for CursorMut<'a, T, V> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.inner
+impl<'a, T: 'a, A: NodeAlloc> XArray<'a, T, Box<T>, A> {
+    /// Get a mutable reference to the value at the index.
+    ///
+    /// The array uniquely owns its boxes, so values can be changed in place
+    /// instead of being removed and inserted again. Every reference the
+    /// array gives out borrows it, so none can be held across the call:
+    ///
+    /// ```compile_fail
+    /// use xarray::XArrayBoxed;
+    ///
+    /// let mut array: XArrayBoxed<u64> = XArrayBoxed::new();
+    /// array.insert(0, Box::new(1)).unwrap();
+    /// let value = array.get(0).unwrap();
+    /// *array.get_mut(0).unwrap() = 2;
+    /// assert_eq!(*value, 1);
+    /// ```
+    #[inline]
+    pub fn get_mut(&mut self, index: u64) -> Option<&mut T> {
+        // SAFETY: the box is owned by the array, and every reference to the
+        // values borrows the array, which is borrowed mutably here.
+        self.inner
+            .cursor_mut(index)
+            .current_ptr()
+            .map(|mut v| unsafe { v.as_mut() })
     }
+
+    /// Drop every value for which `keep` returns `false`, letting `keep`
+    /// change the values it keeps. Returns the number of dropped values.
+    pub fn retain_mut<F>(&mut self, mut keep: F) -> usize
+    where
+        F: FnMut(u64, &mut T) -> bool,
+    {
+        let mut count = 0;
+        let mut iter = self.extract_mut(0, u64::MAX);
+        while let Some((index, _)) = iter.next() {
+            let cursor = iter.as_cursor_mut();
+            if !keep(index, cursor.current_mut().unwrap()) {
+                cursor.remove();
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+#[repr(transparent)]
+pub struct CursorMut<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc = GlobalBox> {
+    inner: xarray_raw::CursorMut<'a, 'b, T, 3, A>,
+    _v: core::marker::PhantomData<V>,
 }
 
-impl<'a, T: 'static, V: OwnedPointer<T>> CursorMut<'a, T, V> {
-    pub fn current_or_insert<F>(&mut self, f: F) -> (bool, &'static T)
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> CursorMut<'a, 'b, T, V, A> {
+    pub fn current_or_insert<F>(&mut self, f: F) -> (bool, &T)
     where
         F: FnOnce() -> V,
     {
         self.inner.current_or_insert(move || V::into_raw(f()))
     }
 
+    /// Get the value at the cursor, borrowed from the array.
+    #[inline]
+    pub fn current(&mut self) -> Option<&T> {
+        self.inner.current()
+    }
+
+    /// Returns a key that the cursor is currently pointing to.
+    #[inline]
+    pub fn key(&mut self) -> u64 {
+        self.inner.key()
+    }
+
+    /// Move the cursor to the next index.
+    #[inline]
+    pub fn next(&mut self) {
+        self.inner.next()
+    }
+
+    /// Move the cursor to next allocated value.
+    #[inline]
+    pub fn next_allocated(&mut self) {
+        self.inner.next_allocated()
+    }
+
+    /// Move the cursor to the previous allocated value. See
+    /// [`xarray_raw::CursorMut::prev_allocated`].
+    #[inline]
+    pub fn prev_allocated(&mut self) {
+        self.inner.prev_allocated()
+    }
+
+    /// Move the cursor to `index`. See [`xarray_raw::CursorMut::seek`].
+    #[inline]
+    pub fn seek(&mut self, index: u64) {
+        self.inner.seek(index)
+    }
+
+    /// Inquire whether the element that the cursor is currently pointing
+    /// to has `mark` set.
+    #[inline]
+    pub fn is_marked(&mut self, mark: XaMark) -> bool {
+        self.inner.is_marked(mark)
+    }
+
+    /// Get the set of marks the element that the cursor is currently
+    /// pointing to carries, empty if there is no value.
+    #[inline]
+    pub fn marks(&mut self) -> MarkSet {
+        self.inner.marks()
+    }
+
+    /// Set `mark` on the element that the cursor is currently pointing to.
+    #[inline]
+    pub fn mark(&mut self, mark: XaMark) {
+        self.inner.mark(mark)
+    }
+
+    /// Remove `mark` from the element that the cursor is currently pointing
+    /// to.
+    #[inline]
+    pub fn unmark(&mut self, mark: XaMark) {
+        self.inner.unmark(mark)
+    }
+
+    /// Remove `mark` from the element that the cursor is currently pointing
+    /// to, returning whether it was set.
+    #[inline]
+    pub fn test_and_clear_mark(&mut self, mark: XaMark) -> bool {
+        self.inner.test_and_clear_mark(mark)
+    }
+
+    /// Get the user byte of the element that the cursor is currently
+    /// pointing to. See [`xarray_raw::CursorMut::aux`].
+    #[cfg(feature = "aux")]
+    #[inline]
+    pub fn aux(&mut self) -> Option<u8> {
+        self.inner.aux()
+    }
+
+    /// Set the user byte of the element that the cursor is currently
+    /// pointing to. See [`xarray_raw::CursorMut::set_aux`].
+    #[cfg(feature = "aux")]
+    #[inline]
+    pub fn set_aux(&mut self, aux: u8) {
+        self.inner.set_aux(aux)
+    }
+
     /// Insert a new value into the xarray at the cursor.
     ///
     /// If the xarray does not contains the value at the index,
-    /// [`None`] is returned.
-    pub fn insert(&mut self, value: V) -> Option<&'static T> {
-        self.inner.insert(V::into_raw(value))
+    /// [`None`] is returned. Otherwise, or if the insert fails, the value
+    /// is dropped. See [`xarray_raw::CursorMut::insert`].
+    pub fn insert(&mut self, value: V) -> Result<Option<&T>, XaError> {
+        let raw = V::into_raw(value);
+        let result = self.inner.insert(raw);
+        if !matches!(result, Ok(None)) {
+            drop(V::from_raw(raw as *const _ as *mut T));
+        }
+        result
+    }
+
+    /// Store value as a single entry covering the `2^order` indices around
+    /// the cursor, aligned to `2^order`, and return every value it
+    /// displaces in index order. See
+    /// [`xarray_raw::CursorMut::store_order_with`].
+    ///
+    /// On failure nothing is displaced, and the value is dropped.
+    pub fn store_order(&mut self, order: u8, value: V) -> Result<Vec<V>, XaError> {
+        let raw = V::into_raw(value);
+        // Displaced values are handed back instead of being released along
+        // with the nodes that held them.
+        let release = self.inner.xa.release.take();
+        let result = self
+            .inner
+            .store_order_with(order, raw, StorePolicy::Overwrite);
+        self.inner.xa.release = release;
+        match result {
+            Ok(displaced) => Ok(displaced
+                .into_iter()
+                .map(|(_, n)| V::from_raw(n as *const _ as *mut _))
+                .collect()),
+            Err(err) => {
+                drop(V::from_raw(raw as *const _ as *mut T));
+                Err(err)
+            }
+        }
+    }
+
+    /// Remove the current element, returning it, and move the cursor past
+    /// it. See [`xarray_raw::CursorMut::remove_and_advance`].
+    pub fn remove_and_advance(&mut self) -> Option<V> {
+        self.inner
+            .remove_and_advance()
+            .map(|n| V::from_raw(n as *const _ as *mut _))
+    }
+
+    /// Store value at the cursor, replacing the entry there and keeping its
+    /// marks, and return the value it replaces. See
+    /// [`xarray_raw::CursorMut::replace_keep_marks`].
+    ///
+    /// The value is dropped on failure.
+    pub fn replace_keep_marks(&mut self, value: V) -> Result<Option<V>, XaError> {
+        let raw = V::into_raw(value);
+        match self.inner.replace_keep_marks(raw) {
+            Ok(old) => Ok(old.map(|n| V::from_raw(n as *const _ as *mut _))),
+            Err(err) => {
+                drop(V::from_raw(raw as *const _ as *mut T));
+                Err(err)
+            }
+        }
+    }
+
+    /// Store value at the cursor, replacing the entry there and clearing
+    /// its marks, and return the value it replaces. See
+    /// [`xarray_raw::CursorMut::replace_clear_marks`].
+    ///
+    /// The value is dropped on failure.
+    pub fn replace_clear_marks(&mut self, value: V) -> Result<Option<V>, XaError> {
+        let raw = V::into_raw(value);
+        match self.inner.replace_clear_marks(raw) {
+            Ok(old) => Ok(old.map(|n| V::from_raw(n as *const _ as *mut _))),
+            Err(err) => {
+                drop(V::from_raw(raw as *const _ as *mut T));
+                Err(err)
+            }
+        }
     }
 
     /// Remove the current element from the xarray.
@@ -127,31 +839,310 @@ impl<'a, T: 'static, V: OwnedPointer<T>> CursorMut<'a, T, V> {
     }
 }
 
-pub struct RangeMut<'b, T: 'static, V: OwnedPointer<T>>
-where
-    T: 'static,
-{
-    cursor: CursorMut<'b, T, V>,
-    end: u64,
-    mark: Option<XaMark>,
+impl<'a, 'b, T: 'a, A: NodeAlloc> CursorMut<'a, 'b, T, Box<T>, A> {
+    /// Get a mutable reference to the value at the cursor. See
+    /// [`XArray::get_mut`].
+    #[inline]
+    pub fn current_mut(&mut self) -> Option<&mut T> {
+        // SAFETY: the box is owned by the array, and every reference to the
+        // values borrows the array, which the cursor borrows mutably.
+        self.inner.current_ptr().map(|mut v| unsafe { v.as_mut() })
+    }
 }
 
-impl<'b, T: 'static, V: OwnedPointer<T>> RangeMut<'b, T, V> {
-    pub fn filter_mark(mut self, mark: XaMark) -> Self {
-        if self.mark.is_some() {
-            panic!("Multiple mark cannot be filtered at once");
+/// View into a single index of an [`XArray`], either holding a value or
+/// not, returned by [`XArray::entry`].
+///
+/// The index is looked up once; the operations on the entry reuse the
+/// position found, instead of walking the tree again.
+pub enum Entry<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc = GlobalBox> {
+    Occupied(OccupiedEntry<'a, 'b, T, V, A>),
+    Vacant(VacantEntry<'a, 'b, T, V, A>),
+}
+
+/// Index of an [`XArray`] holding a value. See [`Entry`].
+pub struct OccupiedEntry<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc = GlobalBox> {
+    cursor: CursorMut<'a, 'b, T, V, A>,
+}
+
+/// Index of an [`XArray`] holding no value. See [`Entry`].
+///
+/// A reserved index is vacant as well; inserting fills it.
+pub struct VacantEntry<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc = GlobalBox> {
+    cursor: CursorMut<'a, 'b, T, V, A>,
+}
+
+impl<'a, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> XArray<'a, T, V, A> {
+    /// Get the entry at the index for in-place manipulation.
+    pub fn entry(&mut self, index: u64) -> Entry<'a, '_, T, V, A> {
+        let mut cursor = self.cursor_mut(index);
+        if cursor.current().is_some() {
+            Entry::Occupied(OccupiedEntry { cursor })
+        } else {
+            Entry::Vacant(VacantEntry { cursor })
+        }
+    }
+
+    /// Get the entry at the lowest index holding a value.
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'a, '_, T, V, A>> {
+        let (index, _) = self.first()?;
+        Some(OccupiedEntry {
+            cursor: self.cursor_mut(index),
+        })
+    }
+
+    /// Get the entry at the highest index holding a value.
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'a, '_, T, V, A>> {
+        let (index, _) = self.last()?;
+        Some(OccupiedEntry {
+            cursor: self.cursor_mut(index),
+        })
+    }
+
+    /// Remove the value at the lowest index holding one, returning it along
+    /// with that index.
+    #[inline]
+    pub fn pop_first(&mut self) -> Option<(u64, V)> {
+        self.first_entry()
+            .map(|entry| (entry.key(), entry.remove()))
+    }
+
+    /// Remove the value at the highest index holding one, returning it
+    /// along with that index.
+    #[inline]
+    pub fn pop_last(&mut self) -> Option<(u64, V)> {
+        self.last_entry().map(|entry| (entry.key(), entry.remove()))
+    }
+}
+
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> Entry<'a, 'b, T, V, A> {
+    /// Index of the entry.
+    #[inline]
+    pub fn key(&self) -> u64 {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Get the value of the entry, inserting `value` if it is vacant.
+    #[inline]
+    pub fn or_insert(self, value: V) -> &'b T {
+        self.or_insert_with(|| value)
+    }
+
+    /// Get the value of the entry, inserting the one computed from `f` if
+    /// it is vacant.
+    pub fn or_insert_with<F>(self, f: F) -> &'b T
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Call `f` on the value of the entry if it is occupied.
+    ///
+    /// `f` may replace the value altogether; the replacement is stored at
+    /// the index, keeping its marks. The old value is dropped within `f`, so
+    /// the removal hook and observer are not told about the replacement.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        if let Entry::Occupied(entry) = &mut self {
+            entry.modify(f);
         }
-        self.mark = Some(mark);
         self
     }
+}
+
+/// Owned value taken out of the slot of an [`OccupiedEntry`], put back on
+/// drop, so a panic in [`Entry::and_modify`] does not leave the slot
+/// pointing at a dropped value.
+struct WriteBack<'c, 'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> {
+    cursor: &'c mut xarray_raw::CursorMut<'a, 'b, T, 3, A>,
+    value: core::mem::ManuallyDrop<V>,
+}
+
+impl<'c, 'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> Drop for WriteBack<'c, 'a, 'b, T, V, A> {
+    fn drop(&mut self) {
+        // SAFETY: the value is not used again.
+        let value = V::into_raw(unsafe { core::mem::ManuallyDrop::take(&mut self.value) });
+        if self.cursor.current_ptr() != Some(NonNull::from(value)) {
+            // The slot still points at the value dropped by the replacement,
+            // which the hooks must not see.
+            let on_remove = self.cursor.xa.on_remove.take();
+            let observer = self.cursor.xa.observer.take();
+            // Storing over a present value allocates nothing.
+            let _ = self.cursor.store(value);
+            self.cursor.xa.on_remove = on_remove;
+            self.cursor.xa.observer = observer;
+        }
+    }
+}
 
-    pub fn as_cursor_mut(&mut self) -> &mut CursorMut<'b, T, V> {
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> OccupiedEntry<'a, 'b, T, V, A> {
+    /// Index of the entry.
+    #[inline]
+    pub fn key(&self) -> u64 {
+        self.cursor.inner.xas.index
+    }
+
+    /// Get the value of the entry.
+    #[inline]
+    pub fn get(&mut self) -> &T {
+        self.cursor.inner.current().unwrap()
+    }
+
+    /// Turn the entry into a reference to its value, borrowing the array.
+    #[inline]
+    pub fn into_ref(mut self) -> &'b T {
+        self.cursor.inner.current().unwrap()
+    }
+
+    /// Replace the value of the entry, returning the old one.
+    ///
+    /// Marks set on the index are kept.
+    #[inline]
+    pub fn insert(&mut self, value: V) -> V {
+        // Storing over a present value allocates nothing.
+        let old = self.cursor.inner.store(V::into_raw(value));
+        V::from_raw(old.ok().flatten().unwrap() as *const _ as *mut _)
+    }
+
+    /// Remove the value of the entry, returning it.
+    #[inline]
+    pub fn remove(mut self) -> V {
+        self.cursor.remove().unwrap()
+    }
+
+    fn modify<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut V),
+    {
+        let raw = self.cursor.inner.current_ptr().unwrap();
+        let mut guard = WriteBack::<T, V, A> {
+            cursor: &mut self.cursor.inner,
+            value: core::mem::ManuallyDrop::new(V::from_raw(raw.as_ptr())),
+        };
+        f(&mut guard.value);
+    }
+}
+
+impl<'a, 'b, T: 'a, A: NodeAlloc> OccupiedEntry<'a, 'b, T, Box<T>, A> {
+    /// Get a mutable reference to the value of the entry.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.cursor.current_mut().unwrap()
+    }
+
+    /// Turn the entry into a mutable reference to its value, borrowing the
+    /// array.
+    #[inline]
+    pub fn into_mut(mut self) -> &'b mut T {
+        // SAFETY: the box is owned by the array, and every reference to the
+        // values borrows the array, which the entry borrows mutably for `'b`.
+        unsafe { self.cursor.inner.current_ptr().unwrap().as_mut() }
+    }
+}
+
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> VacantEntry<'a, 'b, T, V, A> {
+    /// Index of the entry.
+    #[inline]
+    pub fn key(&self) -> u64 {
+        self.cursor.inner.xas.index
+    }
+
+    /// Insert `value` into the entry, returning a reference to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index lies beyond [`RawXArray::limit`], or if a node
+    /// cannot be allocated.
+    #[inline]
+    pub fn insert(mut self, value: V) -> &'b T {
+        let value = V::into_raw(value);
+        if let Err(err) = self.cursor.inner.store(value) {
+            panic!("xarray: {}", err);
+        }
+        value
+    }
+}
+
+pub struct RangeMut<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc = GlobalBox> {
+    cursor: CursorMut<'a, 'b, T, V, A>,
+    end: u64,
+    filter: MarkFilter,
+}
+
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> RangeMut<'a, 'b, T, V, A> {
+    pub fn filter_mark(self, mark: XaMark) -> Self {
+        self.filter_all(MarkSet::EMPTY.with(mark))
+    }
+
+    /// Only yield the entries that do not carry `mark`.
+    pub fn filter_unmarked(mut self, mark: XaMark) -> Self {
+        self.filter.exclude::<3>(MarkSet::EMPTY.with(mark));
+        self
+    }
+
+    /// Only yield the entries that carry every mark of `marks`. See
+    /// [`xarray_raw::Range::filter_all`].
+    pub fn filter_all(mut self, marks: MarkSet) -> Self {
+        self.filter.require::<3>(marks);
+        self
+    }
+
+    /// Only yield the entries that carry at least one mark of `marks`. See
+    /// [`xarray_raw::Range::filter_any`].
+    pub fn filter_any(mut self, marks: MarkSet) -> Self {
+        self.filter.require_any::<3>(marks);
+        self
+    }
+
+    /// Yield the entries that carry `mark`, removing it from each as it is
+    /// yielded. See [`xarray_raw::RangeMut::take_marked`].
+    pub fn take_marked(
+        self,
+        mark: XaMark,
+    ) -> impl Iterator<Item = (u64, &'b T)> + use<'a, 'b, T, V, A>
+    where
+        V: 'b,
+    {
+        let mut range = self.filter_mark(mark);
+        core::iter::from_fn(move || {
+            let item = range.next()?;
+            range.cursor.test_and_clear_mark(mark);
+            Some(item)
+        })
+    }
+
+    /// Set `mark` on the entry last yielded.
+    #[inline]
+    pub fn mark(&mut self, mark: XaMark) {
+        self.cursor.mark(mark);
+    }
+
+    /// Remove `mark` from the entry last yielded.
+    #[inline]
+    pub fn unmark(&mut self, mark: XaMark) {
+        self.cursor.unmark(mark);
+    }
+
+    /// The cursor at the entry last yielded, which may remove it, so it is
+    /// kept from callers still holding the references yielded.
+    pub(crate) fn as_cursor_mut(&mut self) -> &mut CursorMut<'a, 'b, T, V, A> {
         &mut self.cursor
     }
 }
 
-impl<'b, T: 'static, V: OwnedPointer<T>> core::iter::Iterator for RangeMut<'b, T, V> {
-    type Item = (u64, &'static T);
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> core::iter::Iterator
+    for RangeMut<'a, 'b, T, V, A>
+{
+    type Item = (u64, &'b T);
 
     fn next(&mut self) -> Option<Self::Item> {
         let Self {
@@ -161,18 +1152,48 @@ impl<'b, T: 'static, V: OwnedPointer<T>> core::iter::Iterator for RangeMut<'b, T
                     ..
                 },
             end,
-            mark,
+            filter,
         } = self;
 
         if xas.index > *end {
             return None;
         }
 
-        if let Some(mark) = *mark {
-            xas.get_next_marked(xa, mark, *end)
-        } else {
-            xas.get_next(xa, *end)
-        }
-        .map(|n| (xas.index, n.as_value().unwrap()))
+        filter
+            .next(xa, xas, *end)
+            .map(|n| (xas.index, n.as_value().unwrap()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let xarray_raw::CursorMut { xa, xas } = &self.cursor.inner;
+        (0, Some(xarray_raw::remaining(xa, xas, self.end)))
+    }
+}
+
+/// Iterator removing values from an [`XArray`], created by
+/// [`XArray::drain`] and [`XArray::drain_range`].
+pub struct Drain<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc = GlobalBox> {
+    range: RangeMut<'a, 'b, T, V, A>,
+}
+
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> core::iter::Iterator
+    for Drain<'a, 'b, T, V, A>
+{
+    type Item = (u64, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, _) = self.range.next()?;
+        Some((index, self.range.as_cursor_mut().remove().unwrap()))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'a, 'b, T: 'a, V: OwnedPointer<T>, A: NodeAlloc> Drop for Drain<'a, 'b, T, V, A> {
+    fn drop(&mut self) {
+        self.for_each(drop);
     }
 }
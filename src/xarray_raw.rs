@@ -1,97 +1,1886 @@
-pub(crate) use super::node::{Node, NodeOrValue, RawEntry, CHUNK_MASK, CHUNK_SIZE};
-pub(crate) use super::state::State;
+pub(crate) use super::node::{
+    Node, NodeOrValue, Parent, RawEntry, CHUNK_MASK, CHUNK_SHIFT, CHUNK_SIZE,
+};
+pub(crate) use super::state::{NodeOrState, State};
+use crate::{node_layout, AllocError, GlobalBox, NodeAlloc, Observer, Shrinker, XaError};
 
 use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use core::ptr::NonNull;
 
 /// eXtensible Array (XArray).
 ///
 /// Array abtraction of Linux kernel's radix tree.
-pub struct RawXArray<'a, T>
+///
+/// The array stores `&'a T` references into storage that outlives it, such
+/// as an arena. Every value handed back, through lookups, cursors and
+/// iterators alike, carries the same `'a`, so it stays usable after the
+/// array is gone:
+///
+/// ```
+/// use xarray::RawXArray;
+///
+/// let arena = [1u64, 2, 3];
+/// let first = {
+///     let mut xa = RawXArray::new();
+///     for (i, v) in arena.iter().enumerate() {
+///         xa.insert(i as u64, v).unwrap();
+///     }
+///     xa.iter().next().unwrap().1
+/// };
+/// assert_eq!(*first, 1);
+/// ```
+///
+/// Every entry carries `MARKS` marks, three by default as in the kernel,
+/// named by [`XaMark`] or by any other [`MarkIndex`]. Each mark takes a
+/// bitmap in every node, so arrays using no mark save that room as
+/// `RawXArray<T, 0>`.
+///
+/// The storage, in turn, must outlive the array:
+///
+/// ```compile_fail
+/// use xarray::RawXArray;
+///
+/// let mut xa = RawXArray::new();
+/// {
+///     let arena = [1u64, 2, 3];
+///     xa.insert(0, &arena[0]).unwrap();
+/// }
+/// assert_eq!(xa.get(0), Some(&1));
+/// ```
+pub struct RawXArray<'a, T, const MARKS: usize = 3, A: NodeAlloc = GlobalBox>
 where
     T: 'a,
 {
     pub(crate) marks: usize,
-    pub(crate) head: RawEntry<T>,
+    pub(crate) head: RawEntry<T, MARKS>,
+    pub(crate) shrinker: Option<Box<dyn Shrinker<T> + 'a>>,
+    pub(crate) shrink_index: u64,
+    pub(crate) shrink_policy: ShrinkPolicy,
+    pub(crate) deferred: u32,
+    pub(crate) nodes: u64,
+    pub(crate) peak_nodes: u64,
+    pub(crate) high_index: Option<u64>,
+    /// Bumped whenever a node is freed or moved, so positions saved in a
+    /// [`Bookmark`] know whether their node can still be trusted.
+    pub(crate) generation: u64,
+    pub(crate) limit: u64,
+    pub(crate) on_remove: Option<RemoveHook<'a, T>>,
+    pub(crate) observer: Option<Box<dyn Observer<T> + 'a>>,
+    /// Drops the values torn down along with the nodes holding them, set by
+    /// the owning [`crate::XArray`].
+    pub(crate) release: Option<fn(&T)>,
+    /// Allocations set aside by [`RawXArray::preload`] for later nodes.
+    pub(crate) preload: Vec<NonNull<Node<T, MARKS>>>,
+    /// Entries broken up by [`CursorMut::split`], as `(first, order)`,
+    /// merged back once their pieces are alike again.
+    pub(crate) splits: BTreeSet<(u64, u8)>,
+    /// Bit `order` is set while an entry of that order may be in `splits`.
+    pub(crate) split_orders: u128,
+    /// Where nodes are allocated from and given back to.
+    pub(crate) node_alloc: A,
+    /// User byte of a value stored at the head.
+    #[cfg(feature = "aux")]
+    pub(crate) head_aux: u8,
+    #[cfg(feature = "poison")]
+    pub(crate) quarantine: crate::poison::Quarantine<T, MARKS>,
+    #[cfg(feature = "stats")]
+    pub(crate) stats: crate::Stats,
+    #[cfg(feature = "instrument")]
+    pub(crate) instrument: Option<Box<dyn crate::Instrument + 'a>>,
     _entry_lt: core::marker::PhantomData<&'a ()>,
 }
 
+/// How the pieces of an entry broken up into smaller ones stand.
+enum Pieces<'a, T> {
+    /// They differ in value, marks or user byte, or some are missing.
+    Apart,
+    /// None is left, or an entry was stored over the whole range.
+    Gone,
+    /// Every index holds the value, with the same marks and user byte.
+    Alike(&'a T, u8),
+}
+
+/// Walk over the slots of a split entry, checking that its pieces follow
+/// each other and are alike.
+struct Scan<'a, T> {
+    first: u64,
+    last: u64,
+    /// First index the next piece has to start at.
+    next: u64,
+    /// Value, marks and user byte of the pieces seen so far.
+    alike: Option<(&'a T, usize, u8)>,
+}
+
+impl<'a, T> Scan<'a, T> {
+    /// Visit the slots of `node` within the range, descending into the
+    /// nodes below them.
+    fn node<const MARKS: usize>(&mut self, node: &Node<T, MARKS>) -> Result<(), Pieces<'a, T>> {
+        let (base, end) = (node.index(), node.last_index());
+        if self.first > end || self.last < base {
+            return Ok(());
+        }
+        let slot_index = |offset: u8| base.wrapping_add((offset as u64) << node.shift);
+        let from = node.get_offset(core::cmp::max(self.first, base));
+        let to = node.get_offset(core::cmp::min(self.last, end));
+        for offset in from..=to {
+            let entry = node.slots[offset as usize];
+            if let Some(canonical) = entry.as_sibling() {
+                if slot_index(canonical) < self.first {
+                    return Err(Pieces::Gone);
+                }
+                continue;
+            }
+            let value = match entry.as_node_or_value() {
+                Some(NodeOrValue::Node(child)) => {
+                    self.node(child)?;
+                    continue;
+                }
+                Some(NodeOrValue::Value(value)) => value,
+                None => continue,
+            };
+            let sibs = node.slots[offset as usize + 1..]
+                .iter()
+                .take_while(|entry| entry.as_sibling() == Some(offset))
+                .count() as u64;
+            let start = slot_index(offset);
+            let last = start + (sibs << node.shift | ((1 << node.shift) - 1));
+            if start < self.first || last > self.last || (start, last) == (self.first, self.last) {
+                return Err(Pieces::Gone);
+            }
+            if start != self.next {
+                return Err(Pieces::Apart);
+            }
+            let marks = node.slot_marks(offset);
+            #[cfg(feature = "aux")]
+            let aux = node.aux[offset as usize];
+            #[cfg(not(feature = "aux"))]
+            let aux = 0;
+            match self.alike {
+                None => self.alike = Some((value, marks, aux)),
+                Some((v, m, a)) if core::ptr::eq(v, value) && (m, a) == (marks, aux) => (),
+                Some(_) => return Err(Pieces::Apart),
+            }
+            self.next = last.wrapping_add(1);
+        }
+        Ok(())
+    }
+}
+
+/// Callback registered with [`RawXArray::set_on_remove`].
+pub type RemoveHook<'a, T> = Box<dyn FnMut(u64, &T) + 'a>;
+
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum XaMark {
     Mark0 = 0,
     Mark1 = 1,
     Mark2 = 2,
 }
 
-impl<'a, T> RawXArray<'a, T>
-where
-    T: 'a,
-{
-    /// Create new XArray Object.
-    #[inline]
-    pub const fn new() -> Self {
-        Self {
-            marks: 0,
-            head: RawEntry::EMPTY,
-            _entry_lt: core::marker::PhantomData,
+impl MarkIndex for XaMark {
+    #[inline]
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// One of the `MARKS` marks an array keeps per entry, taken by every method
+/// dealing with marks.
+///
+/// Implemented by [`XaMark`] for the three marks of the default array, and
+/// by `usize` for marks picked at run time. Arrays with another number of
+/// marks name theirs with an enum of their own:
+///
+/// ```
+/// use xarray::{MarkIndex, RawXArray};
+///
+/// #[derive(Clone, Copy)]
+/// enum PageMark {
+///     Dirty,
+///     Writeback,
+///     Towrite,
+///     Accessed,
+///     Pinned,
+/// }
+///
+/// impl MarkIndex for PageMark {
+///     fn index(self) -> usize {
+///         self as usize
+///     }
+/// }
+///
+/// let mut array = RawXArray::<u64, 5>::with_marks();
+/// array.insert(7, &7).unwrap();
+/// array.cursor_mut(7).mark(PageMark::Pinned);
+/// assert!(array.cursor(7).is_marked(PageMark::Pinned));
+/// assert!(!array.cursor(7).is_marked(PageMark::Dirty));
+/// ```
+pub trait MarkIndex: Copy {
+    /// Position of the mark, below the `MARKS` of the array it is used
+    /// with, or the methods taking it panic.
+    fn index(self) -> usize;
+}
+
+impl MarkIndex for usize {
+    #[inline]
+    fn index(self) -> usize {
+        self
+    }
+}
+
+/// Set of marks carried by an entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MarkSet(usize);
+
+impl MarkSet {
+    /// The set holding no mark.
+    pub const EMPTY: Self = Self(0);
+
+    /// Returns `true` if `mark` is in the set.
+    #[inline]
+    pub fn contains(&self, mark: impl MarkIndex) -> bool {
+        self.0 & (1 << mark.index()) != 0
+    }
+
+    /// Add `mark` to the set.
+    #[inline]
+    pub fn insert(&mut self, mark: impl MarkIndex) {
+        self.0 |= 1 << mark.index();
+    }
+
+    /// The set with `mark` added, for building sets in one expression.
+    ///
+    /// ```
+    /// use xarray::{MarkSet, XaMark};
+    ///
+    /// let marks = MarkSet::EMPTY.with(XaMark::Mark0).with(XaMark::Mark2);
+    /// assert!(marks.contains(XaMark::Mark2) && !marks.contains(XaMark::Mark1));
+    /// ```
+    #[inline]
+    pub fn with(mut self, mark: impl MarkIndex) -> Self {
+        self.insert(mark);
+        self
+    }
+
+    /// Returns `true` if the set holds no mark.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterate over the [`XaMark`]s in the set.
+    pub fn iter(&self) -> impl Iterator<Item = XaMark> {
+        let set = *self;
+        [XaMark::Mark0, XaMark::Mark1, XaMark::Mark2]
+            .into_iter()
+            .filter(move |mark| set.contains(*mark))
+    }
+
+    /// Iterate over the positions of the marks in the set, for arrays
+    /// naming their marks otherwise than with [`XaMark`].
+    pub fn indices(&self) -> impl Iterator<Item = usize> {
+        let set = *self;
+        (0..usize::BITS as usize).filter(move |mark| set.contains(*mark))
+    }
+}
+
+/// Marks the entries yielded by a filtered range iterator must carry or
+/// lack, as bitmaps indexed by [`MarkIndex::index`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct MarkFilter {
+    /// Marks to carry, every one of them.
+    all: usize,
+    /// Marks to carry at least one of, unless empty.
+    any: usize,
+    /// Marks to lack.
+    none: usize,
+}
+
+impl MarkFilter {
+    pub(crate) fn require<const MARKS: usize>(&mut self, marks: MarkSet) {
+        Self::check::<MARKS>(marks);
+        self.all |= marks.0;
+    }
+
+    pub(crate) fn require_any<const MARKS: usize>(&mut self, marks: MarkSet) {
+        Self::check::<MARKS>(marks);
+        assert!(
+            self.any == 0,
+            "xarray: alternative marks are already filtered"
+        );
+        self.any = marks.0;
+    }
+
+    pub(crate) fn exclude<const MARKS: usize>(&mut self, marks: MarkSet) {
+        Self::check::<MARKS>(marks);
+        self.none |= marks.0;
+    }
+
+    fn check<const MARKS: usize>(marks: MarkSet) {
+        assert!(
+            marks.0.checked_shr(MARKS as u32).unwrap_or(0) == 0,
+            "xarray: mark out of range"
+        );
+    }
+
+    /// Whether a slot carrying `marks` may lead to entries passing the
+    /// filter. A mark on a branch only says that some entry below has it,
+    /// so the marks to lack are left to the entries themselves.
+    #[inline]
+    pub(crate) fn may_pass(&self, marks: usize) -> bool {
+        marks & self.all == self.all && (self.any == 0 || marks & self.any != 0)
+    }
+
+    /// Whether an entry carrying `marks` passes the filter.
+    #[inline]
+    pub(crate) fn passes(&self, marks: usize) -> bool {
+        self.may_pass(marks) && marks & self.none == 0
+    }
+
+    /// The single mark the filter is made of, and whether it is to be
+    /// carried, for the walks specialized for one mark.
+    fn single(&self) -> Option<(usize, bool)> {
+        match (self.all | self.any, self.none) {
+            (mark, 0) if mark.is_power_of_two() => Some((mark.trailing_zeros() as usize, true)),
+            (0, mark) if mark.is_power_of_two() => Some((mark.trailing_zeros() as usize, false)),
+            _ => None,
+        }
+    }
+
+    /// Move `xas` to the next entry passing the filter, no further than
+    /// `end`.
+    pub(crate) fn next<T, const MARKS: usize, A: NodeAlloc>(
+        &self,
+        xa: &RawXArray<T, MARKS, A>,
+        xas: &mut State<T, MARKS>,
+        end: u64,
+    ) -> Option<RawEntry<T, MARKS>> {
+        match self.single() {
+            _ if *self == Self::default() => xas.get_next(xa, end),
+            Some((mark, true)) => xas.get_next_marked(xa, mark, end),
+            Some((mark, false)) => xas.get_next_unmarked(xa, mark, end),
+            None => xas.find_next_filtered(xa, end, self),
+        }
+    }
+
+    /// Move `xas` to the nearest entry passing the filter before its
+    /// index, no lower than `start`.
+    pub(crate) fn prev<T, const MARKS: usize, A: NodeAlloc>(
+        &self,
+        xa: &RawXArray<T, MARKS, A>,
+        xas: &mut State<T, MARKS>,
+        start: u64,
+    ) -> Option<RawEntry<T, MARKS>> {
+        match self.single() {
+            _ if *self == Self::default() => xas.get_prev(xa, start),
+            Some((mark, true)) => xas.find_prev_marked(xa, start, mark),
+            Some((mark, false)) => loop {
+                let entry = xas.get_prev(xa, start)?;
+                if !xas.is_marked(xa, mark) {
+                    break Some(entry);
+                }
+            },
+            None => xas.find_prev_filtered(xa, start, self),
+        }
+    }
+}
+
+/// Inclusive range of indices [`RawXArray::alloc_in`] may hand out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XaLimit {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl XaLimit {
+    /// Every index fitting in 32 bits, like the kernel's `xa_limit_32b`.
+    pub const B32: Self = Self {
+        min: 0,
+        max: u32::MAX as u64,
+    };
+}
+
+/// When the nodes left empty or with a single child by a removal are
+/// released, set with [`RawXArray::set_shrink_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShrinkPolicy {
+    /// Free empty nodes, collapse single-child nodes and lower the root as
+    /// soon as a removal allows it.
+    #[default]
+    Immediate,
+    /// Leave the tree as is on removal, compacting it once every given
+    /// number of removals.
+    Deferred(u32),
+    /// Leave the tree as is until [`RawXArray::compact`] is called.
+    Manual,
+}
+
+/// What a multi-order store does with the entries already within its
+/// range, passed to [`CursorMut::store_order_with`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorePolicy {
+    /// Fail with [`XaError::Busy`], storing nothing.
+    Fail,
+    /// Replace them, handing every displaced entry back with its index.
+    Overwrite,
+    /// Replace them, reporting them only to the removal hook and the
+    /// observer, like [`CursorMut::store_order`].
+    Force,
+}
+
+impl<'a, T> RawXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Create new XArray Object.
+    #[inline]
+    pub const fn new() -> Self {
+        Self::with_limit(u64::MAX)
+    }
+
+    /// Create new XArray Object that never stores an entry past `limit`.
+    ///
+    /// Storing beyond the limit fails with [`XaError::OutOfBounds`] instead
+    /// of growing the tree to cover an index the caller never meant to use.
+    #[inline]
+    pub const fn with_limit(limit: u64) -> Self {
+        Self::with_limit_in(limit, GlobalBox)
+    }
+
+    /// Create new XArray Object from a dense slice, storing each element at
+    /// the index of its position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a node cannot be allocated.
+    pub fn from_slice(values: &'a [T]) -> Self {
+        Self::from_dense(values.iter().map(Some))
+    }
+
+    /// Create new XArray Object from a dense slice, storing each element at
+    /// the index of its position, failing with [`XaError::NoMem`] if a node
+    /// cannot be allocated.
+    pub fn try_from_slice(values: &'a [T]) -> Result<Self, XaError> {
+        Self::try_from_dense(values.iter().map(Some))
+    }
+}
+
+impl<'a, T, const MARKS: usize> RawXArray<'a, T, MARKS>
+where
+    T: 'a,
+{
+    /// Number of user tag bits each value entry can carry, from 0 to 2
+    /// depending on the alignment of `T`.
+    pub const VALUE_TAG_BITS: u8 = RawEntry::<T>::VALUE_TAG_BITS;
+
+    /// Create new XArray Object keeping `MARKS` marks per entry, for arrays
+    /// with another number of marks than the default three.
+    ///
+    /// ```
+    /// use xarray::RawXArray;
+    ///
+    /// let array = RawXArray::<u64, 0>::with_marks();
+    /// assert!(array.is_empty());
+    /// ```
+    #[inline]
+    pub const fn with_marks() -> Self {
+        Self::with_marks_in(u64::MAX, GlobalBox)
+    }
+
+    /// Build an array holding each [`Some`] element of `values` at the index
+    /// of its position. See [`RawXArray::try_from_dense`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a node cannot be allocated.
+    pub(crate) fn from_dense(values: impl IntoIterator<Item = Option<&'a T>>) -> Self {
+        Self::try_from_dense(values).unwrap_or_else(|err| panic!("xarray: {}", err))
+    }
+
+    /// Build an array holding each [`Some`] element of `values` at the index
+    /// of its position.
+    ///
+    /// Whole leaves are filled in one pass and linked up level by level,
+    /// instead of walking down from the head for every element.
+    ///
+    /// Fails with [`XaError::NoMem`] if a node cannot be allocated, freeing
+    /// the nodes built so far.
+    pub(crate) fn try_from_dense(
+        values: impl IntoIterator<Item = Option<&'a T>>,
+    ) -> Result<Self, XaError> {
+        fn fill<T, const MARKS: usize>(node: &mut Node<T, MARKS>, offset: usize, value: &T) {
+            node.slots[offset] = RawEntry::value(value);
+            node.count += 1;
+            node.nr_value += 1;
+            #[cfg(not(feature = "linux"))]
+            {
+                node.total += 1;
+            }
+        }
+
+        /// Free the subtrees built so far, none of them linked to the head.
+        fn abandon<'n, T: 'n, const MARKS: usize, A: NodeAlloc>(
+            xa: &mut RawXArray<T, MARKS, A>,
+            nodes: impl IntoIterator<Item = (u64, &'n mut Node<T, MARKS>)>,
+            err: XaError,
+        ) -> XaError {
+            nodes.into_iter().for_each(|(_, node)| xa.free_nodes(node));
+            err
+        }
+
+        let mut xa = Self::with_marks();
+        // Nodes of the level being built, along with their first index.
+        let mut level: Vec<(u64, &mut Node<T, MARKS>)> = Vec::new();
+        let mut last = 0;
+        for (index, value) in (0..).zip(values) {
+            let Some(value) = value else {
+                continue;
+            };
+            last = index;
+            // A value at index 0 stays at the head unless others follow.
+            if index == 0 {
+                xa.head = RawEntry::value(value);
+                continue;
+            }
+            let first = index & !(CHUNK_MASK as u64);
+            if level.last().is_none_or(|(base, _)| *base != first) {
+                match xa.new_node(0, first) {
+                    Ok(leaf) => level.push((first, leaf)),
+                    Err(err) => return Err(abandon(&mut xa, level, err)),
+                }
+            }
+            let (_, leaf) = level.last_mut().unwrap();
+            fill(leaf, index as usize & CHUNK_MASK, value);
+        }
+        xa.high_index = (xa.head.is_value() || !level.is_empty()).then_some(last);
+        if level.is_empty() {
+            return Ok(xa);
+        }
+        if let Some(value) = xa.head.as_value() {
+            if level[0].0 != 0 {
+                match xa.new_node(0, 0) {
+                    Ok(leaf) => level.insert(0, (0, leaf)),
+                    Err(err) => return Err(abandon(&mut xa, level, err)),
+                }
+            }
+            fill(level[0].1, 0, value);
+        }
+
+        let mut shift = 0;
+        while shift as usize + CHUNK_SHIFT < u64::BITS as usize
+            && last >> (shift as usize + CHUNK_SHIFT) != 0
+        {
+            shift += CHUNK_SHIFT as u8;
+            let mut parents: Vec<(u64, &mut Node<T, MARKS>)> = Vec::new();
+            let mut children = level.into_iter();
+            while let Some((base, child)) = children.next() {
+                let first = base & !((CHUNK_SIZE as u64) << shift).wrapping_sub(1);
+                if parents.last().is_none_or(|(index, _)| *index != first) {
+                    match xa.new_node(shift, first) {
+                        Ok(parent) => parents.push((first, parent)),
+                        Err(err) => {
+                            let built = parents.into_iter().chain([(base, child)]);
+                            return Err(abandon(&mut xa, built.chain(children), err));
+                        }
+                    }
+                }
+                let (_, parent) = parents.last_mut().unwrap();
+                let offset = (base >> shift) as usize & CHUNK_MASK;
+                child.parent = Parent::node(parent);
+                child.offset = offset as u8;
+                parent.slots[offset] = RawEntry::node(child);
+                parent.count += 1;
+                #[cfg(not(feature = "linux"))]
+                {
+                    parent.total += child.total;
+                }
+            }
+            level = parents;
+        }
+        xa.head = RawEntry::node(level.pop().unwrap().1);
+        Ok(xa)
+    }
+}
+
+impl<'a, T, A: NodeAlloc> RawXArray<'a, T, 3, A>
+where
+    T: 'a,
+{
+    /// Create new XArray Object whose nodes are allocated from `node_alloc`.
+    #[inline]
+    pub const fn new_in(node_alloc: A) -> Self {
+        Self::with_limit_in(u64::MAX, node_alloc)
+    }
+
+    /// Create new XArray Object that never stores an entry past `limit`,
+    /// whose nodes are allocated from `node_alloc`.
+    #[inline]
+    pub const fn with_limit_in(limit: u64, node_alloc: A) -> Self {
+        Self::with_marks_in(limit, node_alloc)
+    }
+}
+
+impl<'a, T, const MARKS: usize, A: NodeAlloc> RawXArray<'a, T, MARKS, A>
+where
+    T: 'a,
+{
+    /// Create new XArray Object keeping `MARKS` marks per entry, that never
+    /// stores an entry past `limit`, whose nodes are allocated from
+    /// `node_alloc`.
+    #[inline]
+    pub const fn with_marks_in(limit: u64, node_alloc: A) -> Self {
+        const {
+            assert!(
+                MARKS <= usize::BITS as usize,
+                "xarray: more marks than bits in a word"
+            )
+        };
+        Self {
+            marks: 0,
+            head: RawEntry::EMPTY,
+            shrinker: None,
+            shrink_index: 0,
+            shrink_policy: ShrinkPolicy::Immediate,
+            deferred: 0,
+            nodes: 0,
+            peak_nodes: 0,
+            high_index: None,
+            generation: 0,
+            limit,
+            on_remove: None,
+            observer: None,
+            release: None,
+            preload: Vec::new(),
+            splits: BTreeSet::new(),
+            split_orders: 0,
+            node_alloc,
+            #[cfg(feature = "aux")]
+            head_aux: 0,
+            #[cfg(feature = "poison")]
+            quarantine: crate::poison::Quarantine::new(),
+            #[cfg(feature = "stats")]
+            stats: crate::Stats {
+                allocs: 0,
+                frees: 0,
+                expands: 0,
+                shrinks: 0,
+                splits: 0,
+            },
+            #[cfg(feature = "instrument")]
+            instrument: None,
+            _entry_lt: core::marker::PhantomData,
+        }
+    }
+
+    /// Get the maximum index the array can store an entry at.
+    #[inline]
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Get the counters of structural changes made to the array.
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn stats(&self) -> crate::Stats {
+        self.stats
+    }
+
+    /// Number of nodes currently allocated by the array.
+    #[inline]
+    pub fn node_count(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Highest number of nodes allocated at once since the array was
+    /// created or [`RawXArray::reset_watermarks`] was last called.
+    #[inline]
+    pub fn peak_nodes(&self) -> u64 {
+        self.peak_nodes
+    }
+
+    /// Highest index populated since the array was created or
+    /// [`RawXArray::reset_watermarks`] was last called, even if it has been
+    /// removed since.
+    #[inline]
+    pub fn high_index(&self) -> Option<u64> {
+        self.high_index
+    }
+
+    /// Restart the watermarks from the current state of the array.
+    pub fn reset_watermarks(&mut self) {
+        self.peak_nodes = self.nodes;
+        self.high_index = self.max_index();
+    }
+
+    /// Determine if an array has any present entries.
+    ///
+    /// With the `linux` feature, nodes keep no population count, so this
+    /// walks the tree down to the first present entry, visiting every node
+    /// left empty along the way.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        !self.head.is_populated()
+    }
+
+    /// Number of present entries, counting a multi-order entry once and
+    /// including reserved entries and stored [`None`]s.
+    ///
+    /// Every node keeps the count of its subtree up to date, so this does
+    /// not walk the tree. With the `linux` feature, nodes keep no such
+    /// count, and this walks every node, in time linear in their number.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.head.total()
+    }
+
+    /// Get the user tag bits of the value at the index, or [`None`] if
+    /// there is no value.
+    #[inline]
+    pub fn tags(&self, index: u64) -> Option<u8> {
+        let entry = State::new(index).load(self);
+        entry.is_value().then(|| entry.value_tags())
+    }
+
+    /// Inquire whether any entry in this array has a mark set.
+    #[inline]
+    pub fn is_marked(&self, mark: impl MarkIndex) -> bool {
+        assert!(mark.index() < MARKS, "xarray: mark out of range");
+        self.marks & (1 << mark.index()) != 0
+    }
+
+    /// Inquire whether the entry at `index` has `mark` set, false if there is
+    /// no value at the index.
+    #[inline]
+    pub fn get_mark(&self, index: u64, mark: impl MarkIndex) -> bool {
+        self.cursor(index).is_marked(mark)
+    }
+
+    /// Get value at the index.
+    ///
+    /// If the xarray contains the value at the index, return [`Some`].
+    /// Otherwise, return [`None`].
+    #[inline]
+    pub fn get(&self, index: u64) -> Option<&'a T> {
+        self.cursor(index).current()
+    }
+
+    /// Get value at the index without checking that it is present.
+    ///
+    /// The walk skips the bound checks of [`RawXArray::get`], which are
+    /// kept as debug assertions only.
+    ///
+    /// # Safety
+    ///
+    /// The array must hold a value at `index`.
+    #[inline]
+    pub unsafe fn get_unchecked(&self, index: u64) -> &'a T {
+        let mut entry = self.head;
+        while let Some(node) = entry.as_node() {
+            debug_assert!(node.covers(index), "xarray: index out of bound");
+            entry = *node.entry(node.get_offset(index));
+            if let Some(offset) = entry.as_sibling() {
+                entry = *node.entry(offset);
+            }
+        }
+        entry.as_value_unchecked()
+    }
+
+    /// Insert value into the index.
+    ///
+    /// If the xarray does not contains the value at the index,
+    /// [`None`] is returned.
+    /// value is the reference of T, which outlives than self.
+    ///
+    /// Fails if the index lies beyond [`RawXArray::limit`]. See
+    /// [`CursorMut::insert`].
+    #[inline]
+    pub fn insert<'b>(&'b mut self, index: u64, value: &'a T) -> Result<Option<&'a T>, XaError>
+    where
+        'a: 'b,
+    {
+        self.cursor_mut(index).insert(value)
+    }
+
+    /// Reserve the index. See [`CursorMut::reserve`].
+    #[inline]
+    pub fn reserve(&mut self, index: u64) -> Result<(), XaError> {
+        self.cursor_mut(index).reserve()
+    }
+
+    /// Release the index if it is reserved. See [`CursorMut::release`].
+    #[inline]
+    pub fn release(&mut self, index: u64) {
+        self.cursor_mut(index).release()
+    }
+
+    /// Store `value` into the index, replacing the entry there, like the
+    /// kernel's `xa_store`. Returns the value it replaces, if any.
+    ///
+    /// Marks set on the index are kept.
+    ///
+    /// Fails if the index lies beyond [`RawXArray::limit`] or a node cannot
+    /// be allocated. See [`CursorMut::store`].
+    #[inline]
+    pub fn store(&mut self, index: u64, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        self.cursor_mut(index).store(value)
+    }
+
+    /// Insert `value` as a single entry covering the `2^order` indices
+    /// around `index`, aligned to `2^order`, if none of them holds a value.
+    /// See [`CursorMut::store_order`].
+    ///
+    /// Otherwise nothing is stored, and the value at the lowest index
+    /// within the range is returned.
+    pub fn insert_order(
+        &mut self,
+        index: u64,
+        order: u8,
+        value: &'a T,
+    ) -> Result<Option<&'a T>, XaError> {
+        assert!(order <= u64::BITS as u8, "xarray: order out of range");
+        let mask = Self::order_mask(order);
+        if let Some((_, v)) = self.extract(index & !mask, index | mask).next() {
+            return Ok(Some(v));
+        }
+        self.cursor_mut(index).store_order(order, value)
+    }
+
+    /// Store `value` as a single entry covering the `2^order` indices
+    /// around `index`, handling the entries already there as `policy`
+    /// says. See [`CursorMut::store_order_with`].
+    #[inline]
+    pub fn store_order_with(
+        &mut self,
+        index: u64,
+        order: u8,
+        value: &'a T,
+        policy: StorePolicy,
+    ) -> Result<Vec<(u64, &'a T)>, XaError> {
+        self.cursor_mut(index)
+            .store_order_with(order, value, policy)
+    }
+
+    /// Store `value` as a single entry covering every index from `start` to
+    /// `end`, like the kernel's `xa_store_range`.
+    ///
+    /// The range is laid out as a few multi-order entries, the largest
+    /// alignment allows, instead of one slot per index. Every entry within
+    /// the range is replaced, and the replaced values are reported to the
+    /// removal hook and the observer. Nothing is stored if `start > end`.
+    ///
+    /// Multi-order entries reaching past either end of the range keep
+    /// their value and marks outside of it. Removing an index later removes
+    /// the multi-order entry covering it, which may span only part of the
+    /// range.
+    ///
+    /// Fails with [`XaError::OutOfBounds`] if `end` lies beyond the limit of
+    /// the array, or with [`XaError::NoMem`] if a node cannot be allocated.
+    /// Every node the stores may need is allocated up front, so nothing is
+    /// changed on failure.
+    pub fn store_range(&mut self, start: u64, end: u64, value: &'a T) -> Result<(), XaError> {
+        if start > end {
+            return Ok(());
+        }
+        if end > self.limit {
+            return Err(XaError::OutOfBounds);
+        }
+        // Entries straddling either end, to be cut down to their part
+        // outside of the range.
+        let mut straddling: Vec<(u64, u64, &'a T)> = Vec::new();
+        for index in [start, end] {
+            match self.entry_span(index) {
+                Some(span @ (first, last, _))
+                    if (first < start || last > end)
+                        && straddling.iter().all(|&(f, ..)| f != first) =>
+                {
+                    straddling.push(span)
+                }
+                _ => (),
+            }
+        }
+        let outside = |first: u64, last: u64| {
+            [
+                (first < start).then(|| (first, start - 1)),
+                (last > end).then(|| (end + 1, last)),
+            ]
+            .into_iter()
+            .flatten()
+        };
+        let stores = spans(start, end).count()
+            + straddling
+                .iter()
+                .flat_map(|&(first, last, _)| outside(first, last))
+                .map(|(first, last)| spans(first, last).count())
+                .sum::<usize>();
+        let preloaded = self.preload.len();
+        if let Err(err) = self.preload(preloaded + stores * Self::MAX_STORE_NODES) {
+            self.trim_preload(preloaded);
+            return Err(err);
+        }
+        // Cut the entries straddling either end of the range down to their
+        // part outside of it. Their values stay in the array, so the hooks
+        // do not see them go.
+        let hooks = (
+            self.on_remove.take(),
+            self.observer.take(),
+            self.release.take(),
+        );
+        let mut result = Ok(());
+        for (first, last, v) in straddling {
+            let marks = self
+                .remove_with_marks(first)
+                .map_or(MarkSet::EMPTY, |(_, marks)| marks);
+            for (first, last) in outside(first, last) {
+                result = result.and(self.store_spans(first, last, v));
+                let indices = self
+                    .extract(first, last)
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>();
+                for index in indices {
+                    let mut cursor = self.cursor_mut(index);
+                    marks.indices().for_each(|mark| cursor.mark(mark));
+                }
+            }
+        }
+        (self.on_remove, self.observer, self.release) = hooks;
+        // Only the entries wholly within the range leave the array.
+        while let Some((index, _)) = self.extract(start, end).next() {
+            self.remove(index);
+        }
+        let result = result.and(self.store_spans(start, end, value));
+        self.trim_preload(preloaded);
+        result
+    }
+
+    /// Lay `value` over the empty range from `start` to `end`.
+    fn store_spans(&mut self, start: u64, end: u64, value: &'a T) -> Result<(), XaError> {
+        for (first, shift, sibs) in spans(start, end) {
+            let mut xas = State::new(first);
+            xas.shift = shift;
+            xas.sibs = sibs;
+            xas.store(self, RawEntry::value(value));
+            xas.take_error()?;
+        }
+        Ok(())
+    }
+
+    /// Get the first and last index of the entry covering `index`, along
+    /// with its value.
+    pub(crate) fn entry_span(&self, index: u64) -> Option<(u64, u64, &'a T)> {
+        let mut entry = self.head;
+        let Some(mut node) = entry.as_node() else {
+            return entry.as_value().filter(|_| index == 0).map(|v| (0, 0, v));
+        };
+        loop {
+            if !node.covers(index) {
+                return None;
+            }
+            let mut offset = node.get_offset(index);
+            entry = *node.entry(offset);
+            if let Some(sibling) = entry.as_sibling() {
+                offset = sibling;
+                entry = *node.entry(offset);
+            }
+            match entry.as_node_or_value() {
+                Some(NodeOrValue::Node(child)) => node = child,
+                Some(NodeOrValue::Value(v)) => {
+                    let sibs = (offset + 1..CHUNK_SIZE as u8)
+                        .take_while(|ofs| node.entry(*ofs).as_sibling() == Some(offset))
+                        .count() as u64;
+                    let first = node.slot_index(offset);
+                    return Some((
+                        first,
+                        first + (sibs << node.shift | ((1 << node.shift) - 1)),
+                        v,
+                    ));
+                }
+                None => return None,
+            }
+        }
+    }
+
+    /// Store `value` at the lowest absent index, returning that index, like
+    /// the kernel's `xa_alloc`. See [`RawXArray::alloc_in`].
+    #[inline]
+    pub fn alloc(&mut self, value: &'a T) -> Result<u64, AllocError> {
+        self.alloc_in(
+            value,
+            XaLimit {
+                min: 0,
+                max: u64::MAX,
+            },
+        )
+    }
+
+    /// Store `value` at the lowest absent index within `limit`, returning
+    /// that index.
+    ///
+    /// Free indices are found through the population count of each node,
+    /// skipping full subtrees without visiting their slots. A stored
+    /// [`None`] keeps its index allocated.
+    ///
+    /// Fails with [`XaError::Busy`] if every index within `limit`, up to
+    /// [`RawXArray::limit`], is present, or with [`XaError::NoMem`] if a
+    /// node cannot be allocated.
+    pub fn alloc_in(&mut self, value: &'a T, limit: XaLimit) -> Result<u64, AllocError> {
+        let index = self
+            .next_free(limit.min)
+            .filter(|index| *index <= limit.max)
+            .ok_or(XaError::Busy)?;
+        self.store(index, value)?;
+        Ok(index)
+    }
+
+    /// Most nodes a single store may allocate: a new head, the node where
+    /// its path splits off an existing one, and the node it lands in.
+    #[cfg(not(feature = "linux"))]
+    pub const MAX_STORE_NODES: usize = 3;
+
+    /// Most nodes a single store may allocate: every level above the old
+    /// head when the tree grows, and then one per level down to the leaf.
+    #[cfg(feature = "linux")]
+    pub const MAX_STORE_NODES: usize = 2 * (u64::BITS as usize).div_ceil(CHUNK_SHIFT) - 1;
+
+    /// Set `n` node allocations aside, so the stores that follow allocate
+    /// nothing until they are used up, like the kernel's `xas_nomem`.
+    ///
+    /// This lets the allocation happen before taking a lock that the store
+    /// is then made under; [`RawXArray::MAX_STORE_NODES`] covers any single
+    /// store. Allocations beyond `n` already set aside are freed, so
+    /// `preload(0)` gives them all back.
+    ///
+    /// Fails with [`XaError::NoMem`] if the allocator fails, keeping the
+    /// allocations made so far.
+    pub fn preload(&mut self, n: usize) -> Result<(), XaError> {
+        self.trim_preload(n);
+        self.preload
+            .try_reserve(n - self.preload.len())
+            .map_err(|_| XaError::NoMem)?;
+        while self.preload.len() < n {
+            let ptr = self
+                .node_alloc
+                .alloc_node(node_layout::<MARKS>())
+                .ok_or(XaError::NoMem)?;
+            self.preload.push(ptr.cast());
+        }
+        Ok(())
+    }
+
+    /// Number of node allocations set aside by [`RawXArray::preload`].
+    #[inline]
+    pub fn preloaded(&self) -> usize {
+        self.preload.len()
+    }
+
+    fn trim_preload(&mut self, n: usize) {
+        while self.preload.len() > n {
+            let ptr = self.preload.pop().unwrap();
+            // SAFETY: preloaded allocations come from the allocator and hold
+            // no node.
+            unsafe {
+                self.node_alloc
+                    .free_node(ptr.cast(), node_layout::<MARKS>())
+            };
+        }
+    }
+
+    /// Build the node paths down to every index of `indices` without storing
+    /// any value, so inserting at those indices later allocates nothing.
+    ///
+    /// Indices are sorted first, so neighbouring indices share their
+    /// descents. The nodes stay in place while empty until a removal along
+    /// their path or [`RawXArray::compact`] releases them.
+    ///
+    /// Fails with [`XaError::OutOfBounds`] without building anything if an
+    /// index lies beyond the limit of the array, or with [`XaError::NoMem`]
+    /// if a node cannot be allocated, keeping the paths built so far.
+    pub fn prefault<I>(&mut self, indices: I) -> Result<(), XaError>
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let mut indices = indices.into_iter().collect::<Vec<_>>();
+        if indices.iter().any(|&index| index > self.limit) {
+            return Err(XaError::OutOfBounds);
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        for index in indices {
+            let mut xas = State::new(index);
+            xas.create(self, false);
+            xas.take_error()?;
+        }
+        Ok(())
+    }
+
+    /// Insert a value computed from `f` if the given `index` is not present,
+    /// then returns a tuple of boolean that indicates whether new
+    /// entry is created and reference that stored in the XArray each.
+    #[inline]
+    pub fn get_or_with<'b, F>(&'b mut self, index: u64, f: F) -> (bool, &'a T)
+    where
+        F: FnOnce() -> &'a T,
+        'a: 'b,
+    {
+        self.cursor_mut(index).current_or_insert(f)
+    }
+
+    /// Remove value at the index, returning the value at the index.
+    #[inline]
+    pub fn remove(&mut self, index: u64) -> Option<&'a T> {
+        self.cursor_mut(index).remove()
+    }
+
+    /// Remove value at the index, returning it along with the marks it
+    /// carried.
+    #[inline]
+    pub fn remove_with_marks(&mut self, index: u64) -> Option<(&'a T, MarkSet)> {
+        self.cursor_mut(index).remove_with_marks()
+    }
+
+    /// Get the entry at the index, where `Some(None)` is an index holding a
+    /// stored [`None`] and [`None`] is an absent index.
+    #[inline]
+    pub fn get_option(&self, index: u64) -> Option<Option<&'a T>> {
+        self.cursor(index).current_option()
+    }
+
+    /// Insert `value` into the index if it is absent, keeping a [`None`]
+    /// as a present entry without a value.
+    ///
+    /// If the index is already present, its entry is returned and nothing
+    /// is stored.
+    #[inline]
+    pub fn insert_option(&mut self, index: u64, value: Option<&'a T>) -> Option<Option<&'a T>> {
+        self.cursor_mut(index).insert_option(value)
+    }
+
+    /// Remove the entry at the index, whether it holds a value or a stored
+    /// [`None`], returning it.
+    #[inline]
+    pub fn remove_option(&mut self, index: u64) -> Option<Option<&'a T>> {
+        self.cursor_mut(index).remove_option()
+    }
+
+    /// Get the lowest index holding a present entry.
+    ///
+    /// Returns [`None`] if the array is empty.
+    pub fn min_index(&self) -> Option<u64> {
+        let mut entry = self.head;
+        let mut index = 0;
+        while let Some(node) = entry.as_node() {
+            let offset = (0..CHUNK_SIZE as u8).find(|ofs| node.entry(*ofs).is_populated())?;
+            index = node.slot_index(offset);
+            entry = *node.entry(offset);
+        }
+        entry.has_value().then_some(index)
+    }
+
+    /// Get the highest index holding a present entry.
+    ///
+    /// Returns [`None`] if the array is empty.
+    pub fn max_index(&self) -> Option<u64> {
+        let mut entry = self.head;
+        let mut index = 0;
+        while let Some(node) = entry.as_node() {
+            let offset = (0..CHUNK_SIZE as u8)
+                .rev()
+                .find(|ofs| node.entry(*ofs).is_populated())?;
+            index = node.slot_index(offset);
+            entry = *node.entry(offset);
+            if !entry.is_node() {
+                index += (1 << node.shift) - 1;
+            }
+        }
+        entry.has_value().then_some(index)
+    }
+
+    /// Get the nearest index strictly after `index` holding a present entry.
+    ///
+    /// Returns [`None`] if there is no such entry.
+    pub fn next_present(&self, index: u64) -> Option<u64> {
+        let index = index.checked_add(1)?;
+        match self.head.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.find_at_or_after(index),
+            _ => None,
+        }
+    }
+
+    /// Get the nearest index strictly before `index` holding a present entry.
+    ///
+    /// Returns [`None`] if there is no such entry.
+    pub fn prev_present(&self, index: u64) -> Option<u64> {
+        let index = index.checked_sub(1)?;
+        match self.head.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.find_at_or_before(index),
+            _ => self.head.has_value().then_some(0),
+        }
+    }
+
+    /// Get the value at the lowest index holding one, along with that index.
+    #[inline]
+    pub fn first(&self) -> Option<(u64, &'a T)> {
+        self.range_next(0)
+    }
+
+    /// Get the value at the highest index holding one, along with that
+    /// index.
+    #[inline]
+    pub fn last(&self) -> Option<(u64, &'a T)> {
+        self.range_prev(u64::MAX)
+    }
+
+    /// Get the value at the nearest index at or after `index` holding one,
+    /// along with that index, like a ceiling lookup in a sorted map.
+    ///
+    /// The search follows the node path from `index`, skipping empty
+    /// subtrees, rather than scanning the indices one by one. Reserved
+    /// indices are stepped over.
+    pub fn range_next(&self, mut index: u64) -> Option<(u64, &'a T)> {
+        loop {
+            let found = match self.head.as_node_or_value() {
+                Some(NodeOrValue::Node(node)) => node.find_at_or_after(index)?,
+                _ if index == 0 && self.head.has_value() => 0,
+                _ => return None,
+            };
+            match self.get(found) {
+                Some(value) => return Some((found, value)),
+                None => index = found.checked_add(1)?,
+            }
+        }
+    }
+
+    /// Get the value at the nearest index at or before `index` holding one,
+    /// along with that index, like a floor lookup in a sorted map. See
+    /// [`RawXArray::range_next`].
+    pub fn range_prev(&self, mut index: u64) -> Option<(u64, &'a T)> {
+        loop {
+            let found = match self.head.as_node_or_value() {
+                Some(NodeOrValue::Node(node)) => node.find_at_or_before(index)?,
+                _ if self.head.has_value() => 0,
+                _ => return None,
+            };
+            match self.get(found) {
+                Some(value) => return Some((found, value)),
+                None => index = found.checked_sub(1)?,
+            }
+        }
+    }
+
+    /// Find the lowest absent index at or after `index`.
+    ///
+    /// Full subtrees are skipped using their population count. Returns
+    /// [`None`] if every index from `index` up to [`RawXArray::limit`] is
+    /// present.
+    pub fn next_free(&self, index: u64) -> Option<u64> {
+        let free = match self.head.as_node() {
+            Some(node) if node.covers(index) => node
+                .find_free_at_or_after(index)
+                .or_else(|| node.last_index().checked_add(1))?,
+            Some(_) => index,
+            None if self.head.has_value() && index == 0 => 1,
+            None => index,
+        };
+        (free <= self.limit).then_some(free)
+    }
+
+    /// Find the lowest index, a multiple of `align`, starting `len`
+    /// consecutive absent indices, for carving contiguous regions out of
+    /// the index space.
+    ///
+    /// Only the runs from [`RawXArray::gaps`] are examined. Returns
+    /// [`None`] if no such run lies within [`RawXArray::limit`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` or `align` is zero.
+    pub fn find_free_range(&self, len: u64, align: u64) -> Option<u64> {
+        assert!(len > 0 && align > 0, "xarray: empty range or alignment");
+        self.gaps(0, u64::MAX).find_map(|(first, last)| {
+            let first = first.checked_next_multiple_of(align)?;
+            first
+                .checked_add(len - 1)
+                .filter(|end| *end <= last)
+                .map(|_| first)
+        })
+    }
+
+    /// Iterate over the runs of absent indices within `start..=end`, as
+    /// inclusive `(first, last)` pairs in ascending order.
+    ///
+    /// Each run is found with [`RawXArray::next_free`] and ended with
+    /// [`RawXArray::next_present`], so full and empty subtrees are skipped
+    /// without being visited. Indices past [`RawXArray::limit`] are never
+    /// reported.
+    ///
+    /// ```
+    /// use xarray::RawXArray;
+    ///
+    /// let mut array = RawXArray::new();
+    /// array.insert(2, &2).unwrap();
+    /// array.insert(3, &3).unwrap();
+    /// array.insert(7, &7).unwrap();
+    /// let gaps = array.gaps(0, 9).collect::<Vec<_>>();
+    /// assert_eq!(gaps, [(0, 1), (4, 6), (8, 9)]);
+    /// ```
+    pub fn gaps(&self, start: u64, end: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let mut next = Some(start);
+        core::iter::from_fn(move || {
+            let index = next.filter(|index| *index <= end)?;
+            let first = self.next_free(index).filter(|first| *first <= end)?;
+            let last = self
+                .next_present(first)
+                .map_or(u64::MAX, |present| present - 1)
+                .min(end)
+                .min(self.limit);
+            next = last.checked_add(1);
+            Some((first, last))
+        })
+    }
+
+    /// Count the present entries whose index lies within `range`.
+    ///
+    /// Subtrees that are entirely covered by the range are accounted from
+    /// their node's population count without being visited. With the
+    /// `linux` feature, nodes keep no such count, and those subtrees are
+    /// walked as well.
+    pub fn count_range(&self, range: RangeInclusive<u64>) -> u64 {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end {
+            return 0;
+        }
+        match self.head.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.count_range(start, end),
+            _ => (start == 0) as u64 * self.head.total(),
+        }
+    }
+
+    /// Count the entries whose index lies within `range` that have `mark`
+    /// set, as many as filtering [`RawXArray::extract`] with the mark yields.
+    ///
+    /// Only the subtrees whose mark bitmaps have `mark` set are visited.
+    pub fn count_marked(&self, range: RangeInclusive<u64>, mark: impl MarkIndex) -> u64 {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end || !self.is_marked(mark) {
+            return 0;
+        }
+        match self.head.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.count_marked(start, end, mark),
+            _ => (start == 0) as u64,
+        }
+    }
+
+    /// Tell whether no entry is present within `range`, agreeing with
+    /// [`RawXArray::count_range`] returning zero.
+    ///
+    /// The walk stops at the first present index, skipping empty subtrees,
+    /// rather than counting the whole range.
+    pub fn is_range_empty(&self, range: RangeInclusive<u64>) -> bool {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end {
+            return true;
+        }
+        match self.head.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.find_at_or_after(start).is_none_or(|i| i > end),
+            _ => start > 0 || !self.head.has_value(),
+        }
+    }
+
+    /// Set mark `to` on up to `nr` entries in `range` that carry mark `from`,
+    /// clearing `from` on them as well if `clear` is set.
+    ///
+    /// Returns the number of entries processed, along with the index to
+    /// resume from if the walk stopped at `nr` before the end of `range`.
+    pub fn tag_if_tagged(
+        &mut self,
+        range: RangeInclusive<u64>,
+        from: impl MarkIndex,
+        to: impl MarkIndex,
+        clear: bool,
+        nr: usize,
+    ) -> (usize, Option<u64>) {
+        let (start, end) = range.into_inner();
+        let mut tagged = 0;
+        let mut iter = self.extract_mut(start, end).filter_mark(from);
+        while let Some((index, _)) = iter.next() {
+            if tagged == nr {
+                return (tagged, Some(index));
+            }
+            let cursor = iter.as_cursor_mut();
+            cursor.mark(to);
+            if clear {
+                cursor.unmark(from);
+            }
+            tagged += 1;
+        }
+        (tagged, None)
+    }
+
+    /// Set `mark` on every entry within `range`, as marking each entry
+    /// [`RawXArray::extract_mut`] yields through its cursor would.
+    ///
+    /// Every node overlapping the range is visited once, setting the bits of
+    /// its slots and then the one of its branch, rather than walking up from
+    /// each entry.
+    pub fn set_mark_range(&mut self, range: RangeInclusive<u64>, mark: impl MarkIndex) {
+        self.mark_range(range, mark, true);
+    }
+
+    /// Clear `mark` on every entry within `range`. See
+    /// [`RawXArray::set_mark_range`].
+    ///
+    /// Only the subtrees whose mark bitmaps have `mark` set are visited.
+    pub fn clear_mark_range(&mut self, range: RangeInclusive<u64>, mark: impl MarkIndex) {
+        self.mark_range(range, mark, false);
+    }
+
+    /// Clear `mark` on every entry of the array.
+    ///
+    /// Only the nodes whose bitmaps have `mark` set are visited, each
+    /// bitmap being zeroed at once.
+    pub fn clear_mark_all(&mut self, mark: impl MarkIndex) {
+        if !self.is_marked(mark) {
+            return;
+        }
+        let observer = &mut self.observer;
+        let mut changed = |index| {
+            if let Some(observer) = observer.as_mut() {
+                observer.on_mark(index, mark.index(), false);
+            }
+        };
+        match self.head.as_node() {
+            Some(node) => node.clear_mark_all(mark, &mut changed),
+            None => changed(0),
+        }
+        self.marks &= !(1 << mark.index());
+    }
+
+    fn mark_range(&mut self, range: RangeInclusive<u64>, mark: impl MarkIndex, set: bool) {
+        let (start, end) = range.into_inner();
+        let was_marked = self.is_marked(mark);
+        if start > end {
+            return;
+        }
+        let observer = &mut self.observer;
+        let mut changed = |index| {
+            if let Some(observer) = observer.as_mut() {
+                observer.on_mark(index, mark.index(), set);
+            }
+        };
+        let marked = match self.head.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.mark_range(start, end, mark, set, &mut changed),
+            Some(NodeOrValue::Value(_)) if start == 0 => {
+                if was_marked != set {
+                    changed(0);
+                }
+                set
+            }
+            _ => return,
+        };
+        if marked {
+            self.marks |= 1 << mark.index();
+        } else {
+            self.marks &= !(1 << mark.index());
+        }
+    }
+
+    /// Remove every entry carrying `mark`, returning the number of removed
+    /// entries.
+    ///
+    /// Only the subtrees whose mark bitmaps have `mark` set are visited.
+    pub fn erase_marked(&mut self, mark: impl MarkIndex) -> usize {
+        self.erase_marked_with(mark, |_, _| ())
+    }
+
+    pub(crate) fn erase_marked_with<F>(&mut self, mark: impl MarkIndex, mut erased: F) -> usize
+    where
+        F: FnMut(u64, &'a T),
+    {
+        let mut count = 0;
+        let mut iter = self.extract_mut(0, u64::MAX).filter_mark(mark);
+        while let Some((index, _)) = iter.next() {
+            let value = iter.as_cursor_mut().remove().unwrap();
+            erased(index, value);
+            count += 1;
+        }
+        count
+    }
+
+    /// Remove every entry for which `keep` returns `false`, returning the
+    /// number of removed entries.
+    ///
+    /// The array is walked once, in index order, and nodes left empty are
+    /// freed along the way.
+    pub fn retain<F>(&mut self, keep: F) -> usize
+    where
+        F: FnMut(u64, &'a T) -> bool,
+    {
+        self.retain_with(keep, |_, _| ())
+    }
+
+    pub(crate) fn retain_with<F, R>(&mut self, mut keep: F, mut removed: R) -> usize
+    where
+        F: FnMut(u64, &'a T) -> bool,
+        R: FnMut(u64, &'a T),
+    {
+        let mut count = 0;
+        let mut iter = self.extract_mut(0, u64::MAX);
+        while let Some((index, value)) = iter.next() {
+            if !keep(index, value) {
+                iter.as_cursor_mut().remove();
+                removed(index, value);
+                count += 1;
+            }
         }
+        count
     }
 
-    /// Determine if an array has any present entries.
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.head.is_null()
+    /// Register a shrinker that picks the entries evicted by
+    /// [`RawXArray::shrink`], replacing any previously registered one.
+    pub fn set_shrinker<S>(&mut self, shrinker: S)
+    where
+        S: Shrinker<T> + 'a,
+    {
+        self.shrinker = Some(Box::new(shrinker));
     }
 
-    /// Inquire whether any entry in this array has a mark set.
-    #[inline]
-    pub fn is_marked(&self, mark: XaMark) -> bool {
-        self.marks & (1 << mark as usize) != 0
+    /// Unregister the shrinker, returning it.
+    pub fn take_shrinker(&mut self) -> Option<Box<dyn Shrinker<T> + 'a>> {
+        self.shrinker.take()
     }
 
-    /// Get value at the index.
+    /// Scan up to `nr` entries under memory pressure, evicting the ones the
+    /// registered shrinker picks. Returns the number of evicted entries.
     ///
-    /// If the xarray contains the value at the index, return [`Some`].
-    /// Otherwise, return [`None`].
+    /// Successive calls resume scanning where the previous one stopped,
+    /// wrapping around at the end of the array.
+    pub fn shrink(&mut self, nr: usize) -> usize {
+        self.shrink_with(nr, |_, _| ())
+    }
+
+    pub(crate) fn shrink_with<F>(&mut self, nr: usize, mut evicted: F) -> usize
+    where
+        F: FnMut(u64, &'a T),
+    {
+        let Some(mut shrinker) = self.shrinker.take() else {
+            return 0;
+        };
+        let ceil = |xa: &Self, index| {
+            xa.get(index)
+                .map(|_| index)
+                .or_else(|| xa.next_present(index))
+        };
+        let mut index = self.shrink_index;
+        let mut freed = 0;
+        for _ in 0..core::cmp::min(nr as u64, self.head.total()) {
+            let Some(i) = ceil(self, index).or_else(|| ceil(self, 0)) else {
+                break;
+            };
+            let value = self.get(i).unwrap();
+            if shrinker.scan(i, value) {
+                self.remove(i);
+                evicted(i, value);
+                freed += 1;
+            }
+            index = i.wrapping_add(1);
+        }
+        self.shrink_index = index;
+        self.shrinker = Some(shrinker);
+        freed
+    }
+
+    /// Get the policy releasing nodes on removal.
     #[inline]
-    pub fn get(&self, index: u64) -> Option<&'a T> {
-        self.cursor(index).current()
+    pub fn shrink_policy(&self) -> ShrinkPolicy {
+        self.shrink_policy
     }
 
-    /// Insert value into the index.
+    /// Set the policy releasing nodes on removal.
     ///
-    /// If the xarray does not contains the value at the index,
-    /// [`None`] is returned.
-    /// value is the reference of T, which outlives than self.
+    /// Workloads that keep reinserting into the range they remove from can
+    /// defer or disable node release, keeping the nodes around for the next
+    /// insertion instead of freeing and reallocating them.
     #[inline]
-    pub fn insert<'b>(&'b mut self, index: u64, value: &'a T) -> Option<&'a T>
+    pub fn set_shrink_policy(&mut self, policy: ShrinkPolicy) {
+        self.shrink_policy = policy;
+    }
+
+    /// Remove every entry, tearing down the whole tree in a single pass
+    /// instead of removing the entries one by one.
+    ///
+    /// Removed values are reported to the removal hook and the observer.
+    /// Allocations set aside by [`RawXArray::preload`] are kept.
+    pub fn clear(&mut self) {
+        let head = core::mem::replace(&mut self.head, RawEntry::EMPTY);
+        self.notify_replace(0, head, RawEntry::EMPTY);
+        self.free_head(head);
+        self.marks = 0;
+        self.high_index = None;
+        self.deferred = 0;
+        self.splits.clear();
+        self.split_orders = 0;
+        #[cfg(feature = "aux")]
+        {
+            self.head_aux = 0;
+        }
+    }
+
+    /// Free every empty node, collapse single-child nodes and lower the root
+    /// as far as the present entries allow.
+    pub fn compact(&mut self) {
+        self.deferred = 0;
+        let Some(head) = self.head.as_node() else {
+            return;
+        };
+        self.compact_node(head);
+        if head.count == 0 {
+            self.head = RawEntry::EMPTY;
+            self.free_node(head);
+        } else {
+            let mut xas = State::new(0);
+            xas.node = NodeOrState::Node(head);
+            xas.shrink(self);
+        }
+    }
+
+    fn compact_node(&mut self, node: &mut Node<T, MARKS>) {
+        for offset in 0..CHUNK_SIZE as u8 {
+            let Some(child) = node.entry(offset).as_node() else {
+                continue;
+            };
+            self.compact_node(child);
+            if child.count == 0 {
+                *node.entry(offset) = RawEntry::EMPTY;
+                node.count -= 1;
+                self.free_node(child);
+            } else if child.count == 1 && !cfg!(feature = "linux") {
+                let grandchild = (0..CHUNK_SIZE as u8).find_map(|ofs| child.entry(ofs).as_node());
+                if let Some(grandchild) = grandchild {
+                    grandchild.parent = Parent::node(node);
+                    grandchild.offset = offset;
+                    *node.entry(offset) = RawEntry::node(grandchild);
+                    self.free_node(child);
+                }
+            }
+        }
+    }
+
+    /// Register a callback invoked with the index and value of every entry
+    /// that is removed or overwritten, including the entries torn down along
+    /// with the array. Replaces any previously registered callback.
+    pub fn set_on_remove<F>(&mut self, f: F)
     where
-        'a: 'b,
+        F: FnMut(u64, &T) + 'a,
     {
-        self.cursor_mut(index).insert(value)
+        self.on_remove = Some(Box::new(f));
     }
 
-    /// Insert a value computed from `f` if the given `index` is not present,
-    /// then returns a tuple of boolean that indicates whether new
-    /// entry is created and reference that stored in the XArray each.
-    #[inline]
-    pub fn get_or_with<'b, F>(&'b mut self, index: u64, f: F) -> (bool, &'a T)
+    /// Unregister the removal callback, returning it.
+    pub fn take_on_remove(&mut self) -> Option<RemoveHook<'a, T>> {
+        self.on_remove.take()
+    }
+
+    /// Attach an observer notified of every insert, overwrite, removal and
+    /// mark change, replacing any previously attached one.
+    pub fn set_observer<O>(&mut self, observer: O)
     where
-        F: FnOnce() -> &'a T,
-        'a: 'b,
+        O: Observer<T> + 'a,
     {
-        self.cursor_mut(index).current_or_insert(f)
+        self.observer = Some(Box::new(observer));
     }
 
-    /// Remove value at the index, returning the value at the index.
-    #[inline]
-    pub fn remove(&mut self, index: u64) -> Option<&'a T> {
-        self.cursor_mut(index).remove()
+    /// Detach the observer, returning it.
+    pub fn take_observer(&mut self) -> Option<Box<dyn Observer<T> + 'a>> {
+        self.observer.take()
+    }
+
+    /// Attach an instrument invoked at internal events, replacing any
+    /// previously attached one.
+    #[cfg(feature = "instrument")]
+    pub fn set_instrument<I>(&mut self, instrument: I)
+    where
+        I: crate::Instrument + 'a,
+    {
+        self.instrument = Some(Box::new(instrument));
+    }
+
+    /// Detach the instrument, returning it.
+    #[cfg(feature = "instrument")]
+    pub fn take_instrument(&mut self) -> Option<Box<dyn crate::Instrument + 'a>> {
+        self.instrument.take()
+    }
+
+    /// Find how the pieces of the entry of `order` at `first`, broken up
+    /// into smaller ones, stand, looking only at the slots within it.
+    fn pieces(&self, first: u64, order: u8) -> Pieces<'a, T> {
+        let mut scan = Scan {
+            first,
+            last: first | Self::order_mask(order),
+            next: first,
+            alike: None,
+        };
+        let found = match self.head.as_node() {
+            Some(node) => scan.node(node),
+            // The head only holds index 0, and a split entry is larger.
+            None if self.head.is_value() && first == 0 => Err(Pieces::Apart),
+            None => Ok(()),
+        };
+        match (found, scan.alike) {
+            (Err(pieces), _) => pieces,
+            (Ok(()), None) => Pieces::Gone,
+            (Ok(()), Some(_)) if scan.next != scan.last.wrapping_add(1) => Pieces::Apart,
+            (Ok(()), Some((value, _, aux))) => Pieces::Alike(value, aux),
+        }
+    }
+
+    /// Merge the pieces of the entry split around `index` back into one
+    /// entry once they hold the same value, marks and user byte again, and
+    /// forget the split once they are all gone, working outwards through
+    /// entries split more than once.
+    ///
+    /// Returns `true` if the tree changed.
+    pub(crate) fn merge_splits(&mut self, index: u64) -> bool {
+        let mut merged = false;
+        let mut orders = self.split_orders;
+        while orders != 0 {
+            let order = orders.trailing_zeros() as u8;
+            orders &= orders - 1;
+            let first = index & !Self::order_mask(order);
+            if !self.splits.contains(&(first, order)) {
+                continue;
+            }
+            match self.pieces(first, order) {
+                Pieces::Apart => break,
+                Pieces::Gone => (),
+                Pieces::Alike(value, _aux) => {
+                    // Nothing is removed or dropped, so neither the hooks
+                    // nor the release see the pieces go.
+                    let hooks = (
+                        self.on_remove.take(),
+                        self.observer.take(),
+                        self.release.take(),
+                    );
+                    let mut xas = State::new(first);
+                    xas.set_order(order);
+                    xas.store(self, RawEntry::value(value));
+                    let stored = xas.take_error().is_ok();
+                    #[cfg(feature = "aux")]
+                    if stored {
+                        xas.set_aux(self, _aux);
+                    }
+                    (self.on_remove, self.observer, self.release) = hooks;
+                    if !stored {
+                        break;
+                    }
+                    merged = true;
+                }
+            }
+            self.splits.remove(&(first, order));
+        }
+        if self.splits.is_empty() {
+            self.split_orders = 0;
+        }
+        merged
+    }
+
+    /// Remember that the entry of `order` at `first` was broken up by
+    /// [`CursorMut::split`].
+    pub(crate) fn record_split(&mut self, first: u64, order: u8) {
+        self.splits.insert((first, order));
+        self.split_orders |= 1 << order;
+    }
+
+    /// Forget the entries split within `start..=end`.
+    pub(crate) fn forget_splits(&mut self, start: u64, end: u64) {
+        let inside = self
+            .splits
+            .range((start, 0)..=(end, u8::MAX))
+            .filter(|&&(first, order)| first | Self::order_mask(order) <= end)
+            .copied()
+            .collect::<Vec<_>>();
+        for split in inside {
+            self.splits.remove(&split);
+        }
+        if self.splits.is_empty() {
+            self.split_orders = 0;
+        }
+    }
+
+    /// Mask of the offsets within an entry of `order`.
+    pub(crate) fn order_mask(order: u8) -> u64 {
+        if order < u64::BITS as u8 {
+            (1 << order) - 1
+        } else {
+            u64::MAX
+        }
+    }
+
+    /// Report that the entry `old` at `index` is being replaced by `new`.
+    pub(crate) fn notify_replace(
+        &mut self,
+        index: u64,
+        old: RawEntry<T, MARKS>,
+        new: RawEntry<T, MARKS>,
+    ) {
+        let Self {
+            on_remove,
+            observer,
+            ..
+        } = self;
+        if on_remove.is_none() && observer.is_none() {
+            return;
+        }
+        if let (Some(old), Some(new)) = (old.as_value(), new.as_value()) {
+            if let Some(on_remove) = on_remove.as_mut() {
+                on_remove(index, old);
+            }
+            if let Some(observer) = observer.as_mut() {
+                observer.on_overwrite(index, old, new);
+            }
+            return;
+        }
+        let mut removed = |index, value: &T| {
+            if let Some(on_remove) = on_remove.as_mut() {
+                on_remove(index, value);
+            }
+            if let Some(observer) = observer.as_mut() {
+                observer.on_remove(index, value);
+            }
+        };
+        match old.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.for_each_value(&mut removed),
+            Some(NodeOrValue::Value(old)) => removed(index, old),
+            None => (),
+        }
+        self.notify_moved(index, new, true);
+    }
+
+    /// Report to the observer that the values under `entry` at `index` have
+    /// been linked in (`inserted == true`) or unlinked from the tree, without
+    /// being dropped.
+    pub(crate) fn notify_moved(&mut self, index: u64, entry: RawEntry<T, MARKS>, inserted: bool) {
+        let Some(observer) = self.observer.as_mut() else {
+            return;
+        };
+        let mut notify = |index, value: &T| {
+            if inserted {
+                observer.on_insert(index, value);
+            } else {
+                observer.on_remove(index, value);
+            }
+        };
+        match entry.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => node.for_each_value(&mut notify),
+            Some(NodeOrValue::Value(value)) => notify(index, value),
+            None => (),
+        }
     }
 
     /// Provides a cursor at the index.
     #[inline]
-    pub fn cursor<'b>(&'b self, index: u64) -> Cursor<'a, 'b, T> {
+    pub fn cursor<'b>(&'b self, index: u64) -> Cursor<'a, 'b, T, MARKS, A> {
         Cursor {
             xa: self,
             xas: State::new(index),
@@ -100,7 +1889,7 @@ where
 
     /// Provides a cursor with editing operations at the index.
     #[inline]
-    pub fn cursor_mut<'b>(&'b mut self, index: u64) -> CursorMut<'a, 'b, T> {
+    pub fn cursor_mut<'b>(&'b mut self, index: u64) -> CursorMut<'a, 'b, T, MARKS, A> {
         CursorMut {
             xa: self,
             xas: State::new(index),
@@ -108,43 +1897,143 @@ where
     }
 
     /// Extract range iterator starting from `start` to `end` (inclusive).
-    pub fn extract(&self, start: u64, end: u64) -> Range<T> {
+    pub fn extract(&self, start: u64, end: u64) -> Range<'a, '_, T, MARKS, A> {
         Range {
             cursor: self.cursor(start),
             end,
-            mark: None,
+            filter: MarkFilter::default(),
+            back: State::new(end),
         }
     }
 
     /// Extract range iterator starting from `start` to `end` (inclusive).
-    pub fn extract_mut<'b>(&'b mut self, start: u64, end: u64) -> RangeMut<'a, 'b, T> {
+    pub fn extract_mut<'b>(&'b mut self, start: u64, end: u64) -> RangeMut<'a, 'b, T, MARKS, A> {
         RangeMut {
             cursor: self.cursor_mut(start),
             end,
-            mark: None,
+            filter: MarkFilter::default(),
+        }
+    }
+
+    /// Resume the iteration paused with [`Range::pause`] or
+    /// [`RangeMut::pause`], with the same end and filters.
+    ///
+    /// The array may have been modified in between: the walk restarts from
+    /// the head at the index following the last entry yielded, so entries
+    /// stored behind it are not yielded and freed nodes are never touched.
+    pub fn resume(&self, token: ResumeToken) -> Range<'a, '_, T, MARKS, A> {
+        let mut range = self.extract(token.next.unwrap_or(token.end), token.end);
+        range.filter = token.filter;
+        if token.next.is_none() {
+            range.cursor.xas.node = NodeOrState::Bound;
+        }
+        range
+    }
+
+    /// Resume the iteration paused with [`Range::pause`] or
+    /// [`RangeMut::pause`] as a [`RangeMut`]. See [`RawXArray::resume`].
+    pub fn resume_mut(&mut self, token: ResumeToken) -> RangeMut<'a, '_, T, MARKS, A> {
+        let mut range = self.extract_mut(token.next.unwrap_or(token.end), token.end);
+        range.filter = token.filter;
+        if token.next.is_none() {
+            range.cursor.xas.node = NodeOrState::Bound;
         }
+        range
     }
 
     /// Get iterator of the Xarray
-    pub fn iter(&self) -> Range<T> {
+    pub fn iter(&self) -> Range<'a, '_, T, MARKS, A> {
         self.extract(0, u64::MAX)
     }
 
+    /// Collect every present entry into a vector ordered by index.
+    pub fn to_vec(&self) -> Vec<(u64, &'a T)> {
+        let mut vec = Vec::with_capacity(self.head.total() as usize);
+        vec.extend(self.iter());
+        vec
+    }
+
     /// Get mutable iterator of the Xarray
-    pub fn iter_mut<'b>(&'b mut self) -> RangeMut<'a, 'b, T> {
+    pub fn iter_mut<'b>(&'b mut self) -> RangeMut<'a, 'b, T, MARKS, A> {
         self.extract_mut(0, u64::MAX)
     }
 
-    pub(crate) fn free_nodes(&mut self, mut node: &mut Node<T>) {
+    /// Allocate a node of `shift` covering `index` onwards, yet to be linked
+    /// into the tree.
+    ///
+    /// Fails with [`XaError::NoMem`] if the node cannot be allocated.
+    fn new_node<'b>(&mut self, shift: u8, index: u64) -> Result<&'b mut Node<T, MARKS>, XaError> {
+        let mut xas = State::new(index);
+        let node = Node::new(shift, index, &mut NodeOrState::Empty).unwrap();
+        let node = xas
+            .leak(&mut self.preload, &self.node_alloc, node)
+            .ok_or(XaError::NoMem)?;
+        self.nodes += 1;
+        self.peak_nodes = core::cmp::max(self.peak_nodes, self.nodes);
+        #[cfg(feature = "stats")]
+        {
+            self.stats.allocs += 1;
+        }
+        #[cfg(feature = "instrument")]
+        if let Some(instrument) = self.instrument.as_ref() {
+            instrument.on_alloc(node.shift);
+        }
+        Ok(node)
+    }
+
+    /// Release a node that has been unlinked from the tree.
+    pub(crate) fn free_node(&mut self, node: &mut Node<T, MARKS>) {
+        self.nodes -= 1;
+        self.generation = self.generation.wrapping_add(1);
+        #[cfg(feature = "stats")]
+        {
+            self.stats.frees += 1;
+        }
+        #[cfg(feature = "instrument")]
+        if let Some(instrument) = self.instrument.as_ref() {
+            instrument.on_free(node.shift);
+        }
+        #[cfg(feature = "poison")]
+        self.quarantine.push(node, &self.node_alloc);
+        // SAFETY: the node is unlinked, and nodes come from the allocator.
+        #[cfg(not(feature = "poison"))]
+        unsafe {
+            self.node_alloc
+                .free_node(NonNull::from(node).cast(), node_layout::<MARKS>())
+        };
+    }
+
+    /// Release an entry unlinked from the head, along with the values it
+    /// holds.
+    fn free_head(&mut self, head: RawEntry<T, MARKS>) {
+        match head.as_node_or_value() {
+            Some(NodeOrValue::Node(node)) => self.free_nodes(node),
+            Some(NodeOrValue::Value(v)) => {
+                if let Some(release) = self.release {
+                    release(v);
+                }
+            }
+            None => (),
+        }
+    }
+
+    /// Release a subtree that has been unlinked from the tree, along with
+    /// the values it holds.
+    pub(crate) fn free_nodes(&mut self, mut node: &mut Node<T, MARKS>) {
         let mut offset = 0;
         let raw_top = RawEntry::node(node);
         loop {
-            match node.entry(offset).as_node() {
-                Some(n) if node.shift > 0 => {
+            match node.entry(offset).as_node_or_value() {
+                Some(NodeOrValue::Node(n)) if node.shift > 0 => {
                     node = n;
                     offset = 0;
                     continue;
                 }
+                Some(NodeOrValue::Value(v)) => {
+                    if let Some(release) = self.release {
+                        release(v);
+                    }
+                }
                 _ => (),
             }
 
@@ -157,8 +2046,7 @@ where
                 node.nr_value = 0;
 
                 let is_node_top = node.as_raw() == raw_top;
-                // drop.
-                unsafe { drop(Box::from_raw(node)) };
+                self.free_node(node);
                 if is_node_top {
                     return;
                 }
@@ -168,14 +2056,25 @@ where
     }
 }
 
-impl<'a, T> core::fmt::Debug for RawXArray<'a, T>
+impl<'a, T> From<&'a [Option<T>]> for RawXArray<'a, T>
+where
+    T: 'a,
+{
+    /// Create new XArray Object from a dense slice, storing each [`Some`]
+    /// element at the index of its position and skipping [`None`]s.
+    fn from(values: &'a [Option<T>]) -> Self {
+        Self::from_dense(values.iter().map(Option::as_ref))
+    }
+}
+
+impl<'a, T, const MARKS: usize, A: NodeAlloc> core::fmt::Debug for RawXArray<'a, T, MARKS, A>
 where
     T: core::fmt::Debug,
 {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        fn fmt_inner<T>(
+        fn fmt_inner<T, const MARKS: usize>(
             f: &mut core::fmt::Formatter<'_>,
-            node: &mut Node<T>,
+            node: &mut Node<T, MARKS>,
             d: usize,
         ) -> core::fmt::Result
         where
@@ -201,31 +2100,281 @@ where
             }
             Ok(())
         }
-        writeln!(f, "XArray {{")?;
-        if let Some(head) = self.head.as_node() {
-            fmt_inner(f, head, 1)?;
+        writeln!(f, "XArray {{")?;
+        if let Some(head) = self.head.as_node() {
+            fmt_inner(f, head, 1)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl<'a, T, const MARKS: usize, A: NodeAlloc> core::ops::Drop for RawXArray<'a, T, MARKS, A>
+where
+    T: 'a,
+{
+    fn drop(&mut self) {
+        if let Some(mut on_remove) = self.on_remove.take() {
+            match self.head.as_node_or_value() {
+                Some(NodeOrValue::Node(node)) => node.for_each_value(&mut on_remove),
+                Some(NodeOrValue::Value(v)) => on_remove(0, v),
+                None => (),
+            }
+        }
+        self.free_head(self.head);
+        self.trim_preload(0);
+        #[cfg(feature = "poison")]
+        self.quarantine.release(&self.node_alloc);
+    }
+}
+
+pub struct Cursor<'a, 'b, T, const MARKS: usize = 3, A: NodeAlloc = GlobalBox> {
+    xa: &'b RawXArray<'a, T, MARKS, A>,
+    xas: State<'b, T, MARKS>,
+}
+
+impl<'a, 'b, T, const MARKS: usize, A: NodeAlloc> Cursor<'a, 'b, T, MARKS, A> {
+    /// Returns a reference to the element that the cursor is currently pointing
+    /// to.
+    ///
+    /// If the underlying value is exist, return [`Some`].
+    /// Otherwise, return [`None`].
+    #[inline]
+    pub fn current(&mut self) -> Option<&'a T> {
+        // https://elixir.bootlin.com/linux/latest/source/lib/xarray.c#L1298
+        let Self { xa, xas } = self;
+        xas.load(xa).as_value()
+    }
+
+    /// Returns the entry that the cursor is currently pointing to, where
+    /// `Some(None)` is a stored [`None`] and [`None`] is an absent index.
+    #[inline]
+    pub fn current_option(&mut self) -> Option<Option<&'a T>> {
+        let Self { xa, xas } = self;
+        xas.load(xa).as_option()
+    }
+
+    /// Inquire whether the element that the cursor is currently pointing
+    /// to has `mark` set.
+    #[inline]
+    pub fn is_marked(&mut self, mark: impl MarkIndex) -> bool {
+        let Self { xa, xas } = self;
+        xas.load(xa).is_value() && xas.is_marked(xa, mark)
+    }
+
+    /// Move the cursor to `index`, as if it had just been created there
+    /// and read.
+    ///
+    /// The nodes walked down to the previous index are reused: the walk
+    /// starts from the lowest one covering `index` as well, so a nearby
+    /// index within the same leaf is reached without walking the tree.
+    #[inline]
+    pub fn seek(&mut self, index: u64) {
+        let Self { xa, xas } = self;
+        xas.seek(xa, index);
+    }
+
+    /// Get the set of marks the element that the cursor is currently
+    /// pointing to carries, empty if there is no value.
+    #[inline]
+    pub fn marks(&mut self) -> MarkSet {
+        let Self { xa, xas } = self;
+        match xas.load(xa).is_value() {
+            true => MarkSet(xas.marks(xa)),
+            false => MarkSet::EMPTY,
+        }
+    }
+
+    /// Get the user byte of the element that the cursor is currently
+    /// pointing to, or [`None`] if there is no value.
+    #[cfg(feature = "aux")]
+    #[inline]
+    pub fn aux(&mut self) -> Option<u8> {
+        let Self { xa, xas } = self;
+        xas.load(xa).is_value().then(|| xas.aux(xa))
+    }
+
+    /// Returns a key that the cursor is currently pointing to.
+    #[inline]
+    pub fn key(&mut self) -> u64 {
+        self.xas.index
+    }
+
+    /// Move the cursor to next allocated value.
+    #[inline]
+    pub fn next_allocated(&mut self) {
+        let Self { xas, xa } = self;
+        xas.get_next(xa, u64::MAX);
+    }
+
+    /// Move the cursor to the previous allocated value.
+    ///
+    /// If there is none, the cursor wraps around to [`u64::MAX`].
+    #[inline]
+    pub fn prev_allocated(&mut self) {
+        let Self { xas, xa } = self;
+        xas.get_prev(xa, 0);
+    }
+
+    fn walk(&mut self) {
+        let Self { xa, xas } = self;
+        if xas.node.is_restart() {
+            xas.load(xa);
+        }
+    }
+
+    /// Save the position of the cursor, to come back to it with
+    /// [`Cursor::restore`].
+    pub fn bookmark(&mut self) -> Bookmark<'b, T, MARKS> {
+        self.walk();
+        Bookmark::new(self.xa, &self.xas)
+    }
+
+    /// Move the cursor back to a position saved with [`Cursor::bookmark`].
+    pub fn restore(&mut self, bookmark: &Bookmark<'b, T, MARKS>) {
+        bookmark.restore(self.xa, &mut self.xas);
+    }
+
+    /// Returns the shift of the node holding the slot the cursor is on, or
+    /// [`None`] if the cursor is not inside a node.
+    pub fn node_shift(&mut self) -> Option<u8> {
+        self.walk();
+        self.xas.node.get().map(|node| node.shift)
+    }
+
+    /// Returns the offset of the slot the cursor is on within its node.
+    pub fn offset(&mut self) -> u8 {
+        self.walk();
+        self.xas.offset
+    }
+
+    /// Get the value at slot `offset` of the leaf node the cursor is in,
+    /// without walking the array again.
+    ///
+    /// # Safety
+    ///
+    /// The cursor must have been walked to a leaf node, such as by
+    /// [`Cursor::current`], and the slot at `offset` of that node must hold
+    /// a value. This is only checked with debug assertions enabled.
+    #[inline]
+    pub unsafe fn leaf_get_unchecked(&self, offset: u8) -> &'a T {
+        debug_assert!(offset < CHUNK_SIZE as u8, "xarray: offset out of bound");
+        let node = self.xas.node.get();
+        debug_assert!(
+            matches!(&node, Some(node) if node.shift == 0),
+            "xarray: cursor not in a leaf"
+        );
+        node.unwrap_unchecked().entry(offset).as_value_unchecked()
+    }
+
+    /// Returns `true` if the slot the cursor is on holds a child node.
+    pub fn is_internal(&mut self) -> bool {
+        self.walk();
+        let offset = self.xas.offset;
+        self.xas
+            .node
+            .get()
+            .is_some_and(|node| node.entry(offset).is_node())
+    }
+
+    /// Move the cursor up to the slot of the parent node holding the
+    /// current node. The index of the cursor is kept.
+    ///
+    /// Returns `false` if the current node is the root.
+    pub fn ascend(&mut self) -> bool {
+        self.walk();
+        self.xas.ascend()
+    }
+
+    /// Move the cursor down to the slot at `offset` of the child node held
+    /// by the current slot, pointing the cursor at the first index of that
+    /// slot.
+    ///
+    /// Returns `false` if the current slot does not hold a node.
+    pub fn descend(&mut self, offset: u8) -> bool {
+        self.walk();
+        self.xas.descend_to(offset)
+    }
+
+    /// Move the cursor to the next populated slot of the current node,
+    /// pointing the cursor at the first index of that slot.
+    ///
+    /// Returns `false` if there is no such slot.
+    pub fn next_slot(&mut self) -> bool {
+        self.walk();
+        self.xas.next_slot()
+    }
+}
+
+/// Position of a cursor saved by [`Cursor::bookmark`] or
+/// [`CursorMut::bookmark`].
+///
+/// Along with the index, a bookmark remembers the node the cursor was in.
+/// Restoring goes straight back to that node unless nodes were freed or
+/// moved since, in which case the cursor walks down from the head again.
+pub struct Bookmark<'b, T, const MARKS: usize = 3> {
+    xa: *const (),
+    generation: u64,
+    index: u64,
+    offset: u8,
+    node: *mut Node<T, MARKS>,
+    _marker: core::marker::PhantomData<&'b ()>,
+}
+
+impl<'b, T, const MARKS: usize> Bookmark<'b, T, MARKS> {
+    fn new<A: NodeAlloc>(xa: &RawXArray<'_, T, MARKS, A>, xas: &State<'b, T, MARKS>) -> Self {
+        Self {
+            xa: xa as *const RawXArray<T, MARKS, A> as *const (),
+            generation: xa.generation,
+            index: xas.index,
+            offset: xas.offset,
+            node: xas
+                .node
+                .get()
+                .map_or(core::ptr::null_mut(), |node| node as *mut Node<T, MARKS>),
+            _marker: core::marker::PhantomData,
+        }
+    }
+
+    fn restore<A: NodeAlloc>(
+        &self,
+        xa: &RawXArray<'_, T, MARKS, A>,
+        xas: &mut State<'b, T, MARKS>,
+    ) {
+        *xas = State::new(self.index);
+        if self.xa == xa as *const RawXArray<T, MARKS, A> as *const ()
+            && self.generation == xa.generation
+        {
+            // SAFETY: the array is borrowed for 'b and has freed or moved no
+            // node since the bookmark was taken, so the node is still alive
+            // and still holds the index.
+            if let Some(node) = unsafe { self.node.as_mut() } {
+                xas.offset = self.offset;
+                xas.node = NodeOrState::Node(node);
+            }
         }
-        writeln!(f, "}}")
+    }
+
+    /// The index the bookmark points to.
+    #[inline]
+    pub fn index(&self) -> u64 {
+        self.index
     }
 }
 
-impl<'a, T> core::ops::Drop for RawXArray<'a, T>
-where
-    T: 'a,
-{
-    fn drop(&mut self) {
-        if let Some(head) = self.head.as_node() {
-            self.free_nodes(head);
-        }
+impl<'b, T, const MARKS: usize> Clone for Bookmark<'b, T, MARKS> {
+    fn clone(&self) -> Self {
+        *self
     }
 }
 
-pub struct Cursor<'a, 'b, T> {
-    xa: &'b RawXArray<'a, T>,
-    xas: State<'b, T>,
+impl<'b, T, const MARKS: usize> Copy for Bookmark<'b, T, MARKS> {}
+
+pub struct CursorMut<'a, 'b, T, const MARKS: usize = 3, A: NodeAlloc = GlobalBox> {
+    pub(crate) xa: &'b mut RawXArray<'a, T, MARKS, A>,
+    pub(crate) xas: State<'b, T, MARKS>,
 }
 
-impl<'a, 'b, T> Cursor<'a, 'b, T> {
+impl<'a, 'b, T, const MARKS: usize, A: NodeAlloc> CursorMut<'a, 'b, T, MARKS, A> {
     /// Returns a reference to the element that the cursor is currently pointing
     /// to.
     ///
@@ -238,59 +2387,169 @@ impl<'a, 'b, T> Cursor<'a, 'b, T> {
         xas.load(xa).as_value()
     }
 
-    /// Returns a key that the cursor is currently pointing to.
+    /// Get a pointer to the value at the cursor, for the owning wrappers.
     #[inline]
-    pub fn key(&mut self) -> u64 {
-        self.xas.index
+    pub(crate) fn current_ptr(&mut self) -> Option<NonNull<T>> {
+        let Self { xa, xas } = self;
+        xas.load(xa).as_value_ptr()
     }
 
-    /// Move the cursor to next allocated value.
-    #[inline]
-    pub fn next_allocated(&mut self) {
-        let Self { xas, xa } = self;
-        xas.get_next(xa, u64::MAX);
+    /// Save the position of the cursor, to come back to it with
+    /// [`CursorMut::restore`].
+    pub fn bookmark(&mut self) -> Bookmark<'b, T, MARKS> {
+        let Self { xa, xas } = self;
+        if xas.node.is_restart() {
+            xas.load(xa);
+        }
+        Bookmark::new(xa, xas)
     }
-}
 
-pub struct CursorMut<'a, 'b, T> {
-    pub(crate) xa: &'b mut RawXArray<'a, T>,
-    pub(crate) xas: State<'b, T>,
-}
+    /// Move the cursor back to a position saved with
+    /// [`CursorMut::bookmark`], even if the array was modified through the
+    /// cursor in the meantime.
+    pub fn restore(&mut self, bookmark: &Bookmark<'b, T, MARKS>) {
+        bookmark.restore(self.xa, &mut self.xas);
+    }
 
-impl<'a, 'b, T> CursorMut<'a, 'b, T> {
-    /// Returns a reference to the element that the cursor is currently pointing
-    /// to.
+    /// Inquire whether the element that the cursor is currently pointing
+    /// to has `mark` set.
+    #[inline]
+    pub fn is_marked(&mut self, mark: impl MarkIndex) -> bool {
+        let Self { xa, xas } = self;
+        xas.load(xa).is_value() && xas.is_marked(xa, mark)
+    }
+
+    /// Move the cursor to `index`, as if it had just been created there
+    /// and read.
     ///
-    /// If the underlying value is exist, return [`Some`].
-    /// Otherwise, return [`None`].
+    /// The nodes walked down to the previous index are reused: the walk
+    /// starts from the lowest one covering `index` as well, so a nearby
+    /// index within the same leaf is reached without walking the tree.
     #[inline]
-    pub fn current(&mut self) -> Option<&'b T> {
-        // https://elixir.bootlin.com/linux/latest/source/lib/xarray.c#L1298
+    pub fn seek(&mut self, index: u64) {
         let Self { xa, xas } = self;
-        xas.load(xa).as_value()
+        xas.seek(xa, index);
+    }
+
+    /// Get the set of marks the element that the cursor is currently
+    /// pointing to carries, empty if there is no value.
+    #[inline]
+    pub fn marks(&mut self) -> MarkSet {
+        let Self { xa, xas } = self;
+        match xas.load(xa).is_value() {
+            true => MarkSet(xas.marks(xa)),
+            false => MarkSet::EMPTY,
+        }
     }
 
     /// Set marks on the element that the cursor is currently pointing to.
     #[inline]
-    pub fn mark(&mut self, marks: XaMark) {
+    pub fn mark(&mut self, marks: impl MarkIndex) {
         let Self { xa, xas } = self;
         if xas.load(xa).is_value() {
-            xas.set_mark(xa, marks)
+            xas.set_mark(xa, marks);
+            self.merge_splits();
         }
     }
 
     /// Remove marks on the element that the cursor is currently pointing to.
     #[inline]
-    pub fn unmark(&mut self, marks: XaMark) {
+    pub fn unmark(&mut self, marks: impl MarkIndex) {
+        let Self { xa, xas } = self;
+        if xas.load(xa).is_value() {
+            xas.unset_mark(xa, marks);
+            self.merge_splits();
+        }
+    }
+
+    /// Remove `mark` from the element that the cursor is currently pointing
+    /// to, returning whether it was set.
+    #[inline]
+    pub fn test_and_clear_mark(&mut self, mark: impl MarkIndex) -> bool {
+        let Self { xa, xas } = self;
+        let marked = xas.load(xa).is_value() && xas.is_marked(xa, mark);
+        if marked {
+            xas.unset_mark(xa, mark);
+            self.merge_splits();
+        }
+        marked
+    }
+
+    /// Get the user tag bits of the value that the cursor is currently
+    /// pointing to, or [`None`] if there is no value.
+    #[inline]
+    pub fn tags(&mut self) -> Option<u8> {
+        let Self { xa, xas } = self;
+        let entry = xas.load(xa);
+        entry.is_value().then(|| entry.value_tags())
+    }
+
+    /// Set the user tag `bit` on the value that the cursor is currently
+    /// pointing to, leaving the value itself untouched.
+    ///
+    /// Tags are dropped when the value is replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bit` is not below [`RawXArray::VALUE_TAG_BITS`].
+    #[inline]
+    pub fn set_tag(&mut self, bit: u8) {
+        assert!(
+            u32::from(bit) < u32::from(RawXArray::<T>::VALUE_TAG_BITS),
+            "xarray: tag bit out of range"
+        );
+        if let Some(tags) = self.tags() {
+            let Self { xa, xas } = self;
+            xas.set_value_tags(xa, tags | 1 << bit);
+        }
+    }
+
+    /// Get the user byte of the element that the cursor is currently
+    /// pointing to, or [`None`] if there is no value.
+    #[cfg(feature = "aux")]
+    #[inline]
+    pub fn aux(&mut self) -> Option<u8> {
+        let Self { xa, xas } = self;
+        xas.load(xa).is_value().then(|| xas.aux(xa))
+    }
+
+    /// Set the user byte of the element that the cursor is currently
+    /// pointing to. The byte is cleared when the element is removed.
+    #[cfg(feature = "aux")]
+    #[inline]
+    pub fn set_aux(&mut self, aux: u8) {
         let Self { xa, xas } = self;
         if xas.load(xa).is_value() {
-            xas.unset_mark(xa, marks)
+            xas.set_aux(xa, aux);
+            self.merge_splits();
+        }
+    }
+
+    /// Clear the user tag `bit` on the value that the cursor is currently
+    /// pointing to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bit` is not below [`RawXArray::VALUE_TAG_BITS`].
+    #[inline]
+    pub fn clear_tag(&mut self, bit: u8) {
+        assert!(
+            u32::from(bit) < u32::from(RawXArray::<T>::VALUE_TAG_BITS),
+            "xarray: tag bit out of range"
+        );
+        if let Some(tags) = self.tags() {
+            let Self { xa, xas } = self;
+            xas.set_value_tags(xa, tags & !(1 << bit));
         }
     }
 
+    /// Move the cursor to the next index.
     #[inline]
     pub fn next(&mut self) {
-        let Self { ref mut xas, .. } = self;
+        let Self { xa, xas } = self;
+        if xas.node.is_restart() {
+            xas.load(xa);
+        }
         match xas.node.get() {
             Some(node) if node.shift == 0 && xas.offset != CHUNK_MASK as u8 => {
                 xas.index += 1;
@@ -311,8 +2570,10 @@ impl<'a, 'b, T> CursorMut<'a, 'b, T> {
         if let Some(curr) = xas.load(xa).as_value() {
             (false, curr)
         } else {
+            assert!(xas.index <= xa.limit, "xarray: index out of bounds");
             let value = f();
             xas.store(xa, RawEntry::value(value));
+            self.merge_splits();
             (true, value)
         }
     }
@@ -322,16 +2583,248 @@ impl<'a, 'b, T> CursorMut<'a, 'b, T> {
     /// If the xarray does not contains the value at the index,
     /// [`None`] is returned.
     /// value is the reference of T, which outlives than self.
-    #[inline]
-    pub fn insert(&mut self, value: &'a T) -> Option<&'a T> {
+    ///
+    /// Fails with [`XaError::OutOfBounds`] if the index lies beyond
+    /// [`RawXArray::limit`], with [`XaError::Busy`] if the index is
+    /// reserved, or with [`XaError::NoMem`] if a node cannot be allocated.
+    pub fn insert(&mut self, value: &'a T) -> Result<Option<&'a T>, XaError> {
         let Self { xa, xas } = self;
 
-        if let Some(v) = xas.load(xa).as_value() {
-            Some(v)
+        let entry = xas.load(xa);
+        if let Some(v) = entry.as_value() {
+            Ok(Some(v))
+        } else if entry.is_zero() {
+            Err(XaError::Busy)
+        } else if xas.index > xa.limit {
+            Err(XaError::OutOfBounds)
         } else {
             xas.store(xa, RawEntry::value(value));
-            None
+            xas.take_error()?;
+            self.merge_splits();
+            Ok(None)
+        }
+    }
+
+    /// Reserve the index at the cursor, storing a stored [`None`] there,
+    /// like the kernel's `xa_reserve`.
+    ///
+    /// The node path down to the index is built right away, so filling the
+    /// index later with [`CursorMut::store`] allocates nothing. Until then
+    /// the index reads back as [`None`] but counts as present, and inserts
+    /// leave it alone. Does nothing if the index is already present.
+    pub fn reserve(&mut self) -> Result<(), XaError> {
+        let Self { xa, xas } = self;
+
+        if xas.load(xa).has_value() {
+            Ok(())
+        } else if xas.index > xa.limit {
+            Err(XaError::OutOfBounds)
+        } else {
+            xas.store(xa, RawEntry::ZERO);
+            xas.take_error()
+        }
+    }
+
+    /// Release the index at the cursor if it is reserved, like the
+    /// kernel's `xa_release`. A value stored since is left alone.
+    pub fn release(&mut self) {
+        let Self { xa, xas } = self;
+
+        if xas.load(xa).is_zero() {
+            xas.store(xa, RawEntry::EMPTY);
+            self.merge_splits();
+        }
+    }
+
+    /// Store a new value into the xarray at the cursor, replacing the
+    /// entry there. Returns the value it replaces, if any.
+    ///
+    /// Marks set on the index are kept.
+    ///
+    /// Fails with [`XaError::OutOfBounds`] if the index lies beyond
+    /// [`RawXArray::limit`], or with [`XaError::NoMem`] if a node cannot be
+    /// allocated, in which case nothing is stored.
+    pub fn store(&mut self, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        let Self { xa, xas } = self;
+
+        if xas.index > xa.limit {
+            return Err(XaError::OutOfBounds);
+        }
+        let old = xas.store(xa, RawEntry::value(value));
+        xas.take_error()?;
+        self.merge_splits();
+        Ok(old.as_value())
+    }
+
+    /// Store a new value into the xarray at the cursor, replacing the
+    /// entry there along with its marks, which are kept. Returns the value
+    /// it replaces, if any.
+    ///
+    /// The same as [`CursorMut::store`], named after the policy it follows
+    /// for callers choosing between it and
+    /// [`CursorMut::replace_clear_marks`].
+    #[inline]
+    pub fn replace_keep_marks(&mut self, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        self.store(value)
+    }
+
+    /// Store a new value into the xarray at the cursor, replacing the
+    /// entry there and clearing every mark it carried, as if the index had
+    /// been emptied first. Returns the value it replaces, if any.
+    ///
+    /// Fails as [`CursorMut::store`] does, leaving the marks alone.
+    pub fn replace_clear_marks(&mut self, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        let old = self.store(value)?;
+        for mark in self.marks().indices() {
+            self.unmark(mark);
+        }
+        Ok(old)
+    }
+
+    /// Store `value` as a single entry covering the `2^order` indices
+    /// around the cursor, aligned to `2^order`, like the kernel's
+    /// multi-order entries. The cursor moves to the first of them.
+    ///
+    /// Every entry within the range is replaced, and all replaced values
+    /// are reported to the removal hook and the observer. The entry
+    /// previously at the first index is returned, unless it was one of
+    /// several smaller entries sharing a node. Marks carried by any
+    /// replaced entry are carried by the new one. To fail instead, or to
+    /// get every replaced entry back, see [`CursorMut::store_order_with`].
+    ///
+    /// Fails with [`XaError::OutOfBounds`] if the range reaches past the
+    /// limit of the array, or with [`XaError::NoMem`] if a node cannot be
+    /// allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is above 64.
+    pub fn store_order(&mut self, order: u8, value: &'a T) -> Result<Option<&'a T>, XaError> {
+        assert!(order <= u64::BITS as u8, "xarray: order out of range");
+        let Self { xa, xas } = self;
+        xas.set_order(order);
+        let first = xas.index;
+        let last = xas.index | ((xas.sibs as u64 + 1) << xas.shift).wrapping_sub(1);
+        let result = if last > xa.limit {
+            Err(XaError::OutOfBounds)
+        } else {
+            let old = xas.store(xa, RawEntry::value(value));
+            xas.take_error().map(|_| old.as_value())
+        };
+        xas.shift = 0;
+        xas.sibs = 0;
+        xas.node = NodeOrState::Restart;
+        if result.is_ok() {
+            self.xa.forget_splits(first, last);
+            self.merge_splits();
+        }
+        result
+    }
+
+    /// Store `value` as a single entry covering the `2^order` indices
+    /// around the cursor, aligned to `2^order`, handling the entries
+    /// already within the range as `policy` says. The cursor moves to the
+    /// first of them.
+    ///
+    /// With [`StorePolicy::Overwrite`], every entry displaced is returned
+    /// along with the first index it covered within the range, in index
+    /// order. The other policies return an empty list.
+    ///
+    /// Fails with [`XaError::Busy`] under [`StorePolicy::Fail`] if the range
+    /// holds an entry, with [`XaError::OutOfBounds`] if the range reaches
+    /// past the limit of the array, or with [`XaError::NoMem`] if a node
+    /// cannot be allocated. Nothing is displaced on failure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is above 64.
+    pub fn store_order_with(
+        &mut self,
+        order: u8,
+        value: &'a T,
+        policy: StorePolicy,
+    ) -> Result<Vec<(u64, &'a T)>, XaError> {
+        assert!(order <= u64::BITS as u8, "xarray: order out of range");
+        let mask = RawXArray::<T>::order_mask(order);
+        let (first, last) = (self.xas.index & !mask, self.xas.index | mask);
+        if last > self.xa.limit {
+            return Err(XaError::OutOfBounds);
+        }
+        let displaced = match policy {
+            StorePolicy::Force => Vec::new(),
+            StorePolicy::Fail | StorePolicy::Overwrite => {
+                self.xa.extract(first, last).collect::<Vec<_>>()
+            }
+        };
+        if policy == StorePolicy::Fail && !displaced.is_empty() {
+            return Err(XaError::Busy);
+        }
+        self.store_order(order, value)?;
+        Ok(displaced)
+    }
+
+    /// Split the multi-order entry covering the cursor into entries of
+    /// `2^order` indices each, aligned to `2^order`, like the kernel's
+    /// `xas_split`. Every piece holds the value, marks and user byte of
+    /// the original entry.
+    ///
+    /// Nothing is removed, so the removal hook and the observer are not
+    /// called. Does nothing if the entry is no larger than `2^order`.
+    ///
+    /// The array remembers the split: once a later update leaves every
+    /// piece holding the same value, marks and user byte again, they are
+    /// merged back into one entry of the original order, again without
+    /// calling the hooks. Once every piece is gone, the split is forgotten.
+    ///
+    /// Fails with [`XaError::NoMem`] if a node cannot be allocated, leaving
+    /// only the pieces stored so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entry would be split into more than 64 pieces; split
+    /// large entries one level at a time.
+    pub fn split(&mut self, order: u8) -> Result<(), XaError> {
+        let Self { xa, xas } = self;
+        let Some((first, last, value)) = xa.entry_span(xas.index) else {
+            return Ok(());
+        };
+        if order >= u64::BITS as u8 || (last - first) >> order == 0 {
+            return Ok(());
+        }
+        assert!(
+            (last - first) >> order < CHUNK_SIZE as u64,
+            "xarray: split into too many entries"
+        );
+        #[cfg(feature = "aux")]
+        let aux = xa.cursor_mut(first).aux();
+        let hooks = (xa.on_remove.take(), xa.observer.take());
+        let splits = (
+            core::mem::take(&mut xa.splits),
+            core::mem::take(&mut xa.split_orders),
+        );
+        let (_, marks) = xa.remove_with_marks(first).unwrap();
+        let mut piece = first;
+        let result = loop {
+            let end = core::cmp::min(piece | ((1 << order) - 1), last);
+            if let Err(err) = xa.store_spans(piece, end, value) {
+                break Err(err);
+            }
+            let mut cursor = xa.cursor_mut(piece);
+            marks.indices().for_each(|mark| cursor.mark(mark));
+            #[cfg(feature = "aux")]
+            cursor.set_aux(aux.unwrap_or(0));
+            if end == last {
+                break Ok(());
+            }
+            piece = end + 1;
+        };
+        (xa.on_remove, xa.observer) = hooks;
+        (xa.splits, xa.split_orders) = splits;
+        if result.is_ok() {
+            xa.record_split(first, (last - first).wrapping_add(1).trailing_zeros() as u8);
         }
+        *xas = State::new(xas.index);
+        result
     }
 
     /// Remove the current element from the xarray.
@@ -341,16 +2834,112 @@ impl<'a, 'b, T> CursorMut<'a, 'b, T> {
     /// value is the reference of T, which outlives than self.
     #[inline]
     pub fn remove(&mut self) -> Option<&'a T> {
+        self.remove_option().flatten()
+    }
+
+    /// Remove the current element from the xarray, returning it, and move
+    /// the cursor past it, as if it had just been created at the index
+    /// following the removed entry.
+    ///
+    /// The next [`CursorMut::next_allocated`] then lands on the first entry
+    /// after the removed one, however the removal reshaped the tree, so
+    /// entries can be drained while walking. If there is no value at the
+    /// index, nothing is removed and the cursor still moves along.
+    ///
+    /// ```
+    /// use xarray::RawXArray;
+    ///
+    /// let v = (0..100).collect::<Vec<u64>>();
+    /// let mut array = RawXArray::new();
+    /// for (i, n) in v.iter().enumerate() {
+    ///     array.insert(i as u64 * 100, n).unwrap();
+    /// }
+    /// let mut cursor = array.cursor_mut(0);
+    /// cursor.next_allocated();
+    /// while let Some(value) = cursor.current() {
+    ///     if value % 3 != 0 {
+    ///         cursor.remove_and_advance();
+    ///     }
+    ///     cursor.next_allocated();
+    /// }
+    /// assert!(array.iter().map(|(_, v)| *v).eq((0..100).step_by(3)));
+    /// ```
+    pub fn remove_and_advance(&mut self) -> Option<&'a T> {
         let Self { xa, xas } = self;
+        xas.load(xa);
+        let next = xas.pause();
+        let value = self.remove();
+        self.xas = State::new(next.unwrap_or(u64::MAX));
+        if next.is_none() {
+            self.xas.node = NodeOrState::Bound;
+        }
+        value
+    }
 
-        if let Some(v) = xas.load(xa).as_value() {
-            xas.store(xa, RawEntry::EMPTY);
+    /// Remove the element that the cursor is currently pointing to,
+    /// returning it along with the marks it carried.
+    pub fn remove_with_marks(&mut self) -> Option<(&'a T, MarkSet)> {
+        let marks = self.marks();
+        self.remove().map(|value| (value, marks))
+    }
+
+    /// Insert `value` at the nearest absent index at or after the cursor,
+    /// moving the cursor there and returning the index.
+    ///
+    /// Full subtrees are skipped using their population count. If every
+    /// index from the cursor up to [`RawXArray::limit`] is present, nothing
+    /// is stored and [`None`] is returned.
+    pub fn insert_at_next_free(&mut self, value: &'a T) -> Option<u64> {
+        let Self { xa, xas } = self;
+        let free = xa.next_free(xas.index)?;
+        *xas = State::new(free);
+        xas.store(xa, RawEntry::value(value));
+        self.merge_splits();
+        Some(free)
+    }
+
+    /// Insert `value` at the cursor if the index is absent, keeping a
+    /// [`None`] as a present entry without a value.
+    ///
+    /// If the index is already present, its entry is returned and nothing
+    /// is stored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index lies beyond [`RawXArray::limit`].
+    pub fn insert_option(&mut self, value: Option<&'a T>) -> Option<Option<&'a T>> {
+        let Self { xa, xas } = self;
+
+        if let Some(v) = xas.load(xa).as_option() {
             Some(v)
         } else {
+            assert!(xas.index <= xa.limit, "xarray: index out of bounds");
+            xas.store(xa, RawEntry::from_option(value));
+            self.merge_splits();
             None
         }
     }
 
+    /// Remove the entry at the cursor, whether it holds a value or a stored
+    /// [`None`], returning it.
+    pub fn remove_option(&mut self) -> Option<Option<&'a T>> {
+        let Self { xa, xas } = self;
+
+        let v = xas.load(xa).as_option()?;
+        xas.store(xa, RawEntry::EMPTY);
+        self.merge_splits();
+        Some(v)
+    }
+
+    /// Merge the entry split around the cursor back into one once its
+    /// pieces are alike again. See [`CursorMut::split`].
+    pub(crate) fn merge_splits(&mut self) {
+        let Self { xa, xas } = self;
+        if !xa.splits.is_empty() && xa.merge_splits(xas.index) {
+            *xas = State::new(xas.index);
+        }
+    }
+
     /// Returns a key that the cursor is currently pointing to.
     #[inline]
     pub fn key(&mut self) -> u64 {
@@ -363,94 +2952,344 @@ impl<'a, 'b, T> CursorMut<'a, 'b, T> {
         let Self { xas, xa } = self;
         xas.get_next(xa, u64::MAX);
     }
+
+    /// Move the cursor to the previous allocated value.
+    ///
+    /// If there is none, the cursor wraps around to [`u64::MAX`].
+    #[inline]
+    pub fn prev_allocated(&mut self) {
+        let Self { xas, xa } = self;
+        xas.get_prev(xa, 0);
+    }
 }
 
-pub struct Range<'a, 'b, T> {
-    cursor: Cursor<'a, 'b, T>,
+/// Position of a range iterator saved by [`Range::pause`] or
+/// [`RangeMut::pause`], to carry on with [`RawXArray::resume`] once the
+/// array is no longer borrowed, like the kernel's `xas_pause`.
+#[derive(Clone, Copy, Debug)]
+pub struct ResumeToken {
+    /// Index to resume from, [`None`] once the iteration is over.
+    next: Option<u64>,
     end: u64,
-    mark: Option<XaMark>,
+    filter: MarkFilter,
 }
 
-impl<'a, 'b, T> Range<'a, 'b, T> {
+impl ResumeToken {
+    /// The index the iteration resumes from, [`None`] if it is over.
     #[inline]
-    pub fn filter_mark(mut self, mark: XaMark) -> Self {
-        if self.mark.is_some() {
-            panic!("Multiple mark cannot be filtered at once");
-        }
-        self.mark = Some(mark);
+    pub fn next_index(&self) -> Option<u64> {
+        self.next.filter(|next| *next <= self.end)
+    }
+}
+
+pub struct Range<'a, 'b, T, const MARKS: usize = 3, A: NodeAlloc = GlobalBox> {
+    cursor: Cursor<'a, 'b, T, MARKS, A>,
+    end: u64,
+    filter: MarkFilter,
+    /// Position of the iteration from the back.
+    back: State<'b, T, MARKS>,
+}
+
+impl<'a, 'b, T, const MARKS: usize, A: NodeAlloc> Range<'a, 'b, T, MARKS, A> {
+    /// Only yield the entries that carry `mark`.
+    ///
+    /// Both directions skip the subtrees with no marked entry, so the last
+    /// marked entry before an index is found in a single descent:
+    ///
+    /// ```
+    /// use xarray::{RawXArray, XaMark};
+    ///
+    /// let v = (0..1000).collect::<Vec<u64>>();
+    /// let mut array = RawXArray::new();
+    /// for (i, n) in v.iter().enumerate() {
+    ///     array.insert(i as u64, n).unwrap();
+    /// }
+    /// array.cursor_mut(42).mark(XaMark::Mark1);
+    /// let last = array.extract(0, 500).filter_mark(XaMark::Mark1).next_back();
+    /// assert_eq!(last, Some((42, &42)));
+    /// ```
+    #[inline]
+    pub fn filter_mark(self, mark: impl MarkIndex) -> Self {
+        self.filter_all(MarkSet::EMPTY.with(mark))
+    }
+
+    /// Only yield the entries that do not carry `mark`.
+    #[inline]
+    pub fn filter_unmarked(mut self, mark: impl MarkIndex) -> Self {
+        self.filter.exclude::<MARKS>(MarkSet::EMPTY.with(mark));
+        self
+    }
+
+    /// Only yield the entries that carry every mark of `marks`.
+    ///
+    /// Filters add up, each narrowing the entries yielded, and subtrees
+    /// missing any of the marks are skipped.
+    #[inline]
+    pub fn filter_all(mut self, marks: MarkSet) -> Self {
+        self.filter.require::<MARKS>(marks);
+        self
+    }
+
+    /// Only yield the entries that carry at least one mark of `marks`,
+    /// skipping the subtrees carrying none of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator already filters on alternative marks.
+    #[inline]
+    pub fn filter_any(mut self, marks: MarkSet) -> Self {
+        self.filter.require_any::<MARKS>(marks);
         self
     }
 
     #[inline]
-    pub fn as_cursor(&self) -> &Cursor<'a, 'b, T> {
+    pub fn as_cursor(&self) -> &Cursor<'a, 'b, T, MARKS, A> {
         &self.cursor
     }
+
+    /// Stop the iteration, releasing the array, and return where to resume
+    /// it from with [`RawXArray::resume`]. The entries already yielded from
+    /// the back are not yielded again.
+    ///
+    /// ```
+    /// use xarray::RawXArray;
+    ///
+    /// let v = (0..100).collect::<Vec<u64>>();
+    /// let mut array = RawXArray::new();
+    /// for (i, n) in v.iter().enumerate() {
+    ///     array.insert(i as u64, n).unwrap();
+    /// }
+    /// let mut range = array.extract(10, 19);
+    /// assert_eq!(range.next(), Some((10, &10)));
+    /// let token = range.pause();
+    /// for i in 0..15 {
+    ///     array.remove(i);
+    /// }
+    /// assert!(array.resume(token).map(|(i, _)| i).eq(15..20));
+    /// ```
+    pub fn pause(self) -> ResumeToken {
+        ResumeToken {
+            next: self.cursor.xas.pause(),
+            end: self.end,
+            filter: self.filter,
+        }
+    }
+
+    /// Yield the user byte of every entry along with it.
+    #[cfg(feature = "aux")]
+    pub fn with_aux(mut self) -> impl Iterator<Item = (u64, &'a T, u8)> + 'b {
+        core::iter::from_fn(move || {
+            let (index, value) = self.next()?;
+            let Cursor { xa, xas } = &self.cursor;
+            Some((index, value, xas.aux(xa)))
+        })
+    }
 }
 
-impl<'a, 'b, T> core::iter::Iterator for Range<'a, 'b, T> {
-    type Item = (u64, &'b T);
+impl<'a, 'b, T, const MARKS: usize, A: NodeAlloc> core::iter::Iterator
+    for Range<'a, 'b, T, MARKS, A>
+{
+    type Item = (u64, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
         let Self {
             cursor: Cursor { xa, xas },
             end,
-            mark,
+            filter,
+            ..
         } = self;
 
         if xas.index > *end {
             return None;
         }
 
-        if let Some(mark) = *mark {
-            xas.get_next_marked(xa, mark, *end)
+        filter
+            .next(xa, xas, *end)
+            .map(|n| (xas.index, n.as_value().unwrap()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let Cursor { xa, xas } = &self.cursor;
+        (0, Some(remaining(xa, xas, self.end)))
+    }
+}
+
+impl<'a, 'b, T, const MARKS: usize, A: NodeAlloc> core::iter::DoubleEndedIterator
+    for Range<'a, 'b, T, MARKS, A>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let Self {
+            cursor: Cursor { xa, xas },
+            end,
+            filter,
+            back,
+        } = self;
+
+        // Stop short of the entries already yielded from the front.
+        let start = if xas.node.is_restart() {
+            xas.index
         } else {
-            xas.get_next(xa, *end)
+            xas.index.checked_add(1)?
+        };
+        let entry = filter.prev(xa, back, start)?;
+        match back.index.checked_sub(1) {
+            Some(last) => *end = last,
+            None => xas.node = NodeOrState::Bound,
         }
-        .map(|n| (xas.index, n.as_value().unwrap()))
+        Some((back.index, entry.as_value().unwrap()))
+    }
+}
+
+/// Upper bound on the number of entries a range iterator positioned at
+/// `xas` has yet to yield up to `end`.
+pub(crate) fn remaining<T, const MARKS: usize, A: NodeAlloc>(
+    xa: &RawXArray<T, MARKS, A>,
+    xas: &State<T, MARKS>,
+    end: u64,
+) -> usize {
+    if xas.index > end {
+        return 0;
     }
+    usize::try_from(xa.count_range(xas.index..=end)).unwrap_or(usize::MAX)
 }
 
-pub struct RangeMut<'a, 'b, T> {
-    cursor: CursorMut<'a, 'b, T>,
+pub struct RangeMut<'a, 'b, T, const MARKS: usize = 3, A: NodeAlloc = GlobalBox> {
+    cursor: CursorMut<'a, 'b, T, MARKS, A>,
     end: u64,
-    mark: Option<XaMark>,
+    filter: MarkFilter,
 }
 
-impl<'a, 'b, T> RangeMut<'a, 'b, T> {
+impl<'a, 'b, T, const MARKS: usize, A: NodeAlloc> RangeMut<'a, 'b, T, MARKS, A> {
     #[inline]
-    pub fn filter_mark(mut self, mark: XaMark) -> Self {
-        if self.mark.is_some() {
-            panic!("Multiple mark cannot be filtered at once");
-        }
-        self.mark = Some(mark);
+    pub fn filter_mark(self, mark: impl MarkIndex) -> Self {
+        self.filter_all(MarkSet::EMPTY.with(mark))
+    }
+
+    /// Only yield the entries that do not carry `mark`.
+    #[inline]
+    pub fn filter_unmarked(mut self, mark: impl MarkIndex) -> Self {
+        self.filter.exclude::<MARKS>(MarkSet::EMPTY.with(mark));
+        self
+    }
+
+    /// Only yield the entries that carry every mark of `marks`.
+    ///
+    /// Filters add up, each narrowing the entries yielded, and subtrees
+    /// missing any of the marks are skipped.
+    #[inline]
+    pub fn filter_all(mut self, marks: MarkSet) -> Self {
+        self.filter.require::<MARKS>(marks);
+        self
+    }
+
+    /// Only yield the entries that carry at least one mark of `marks`,
+    /// skipping the subtrees carrying none of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator already filters on alternative marks.
+    #[inline]
+    pub fn filter_any(mut self, marks: MarkSet) -> Self {
+        self.filter.require_any::<MARKS>(marks);
         self
     }
 
+    /// Yield the entries that carry `mark`, removing it from each as it is
+    /// yielded.
+    ///
+    /// The mark is cleared from the leaf the walk stands on, so finding the
+    /// next marked entry and clearing it take a single pass over each node.
+    ///
+    /// ```
+    /// use xarray::{RawXArray, XaMark};
+    ///
+    /// let v = (0..100).collect::<Vec<u64>>();
+    /// let mut array = RawXArray::new();
+    /// for (i, n) in v.iter().enumerate() {
+    ///     array.insert(i as u64, n).unwrap();
+    ///     if i % 10 == 0 {
+    ///         array.cursor_mut(i as u64).mark(XaMark::Mark0);
+    ///     }
+    /// }
+    /// let dirty = array.extract_mut(0, 49).take_marked(XaMark::Mark0);
+    /// assert!(dirty.map(|(i, _)| i).eq([0, 10, 20, 30, 40]));
+    /// assert_eq!(array.iter().filter_mark(XaMark::Mark0).count(), 5);
+    /// ```
+    pub fn take_marked(self, mark: impl MarkIndex) -> impl Iterator<Item = (u64, &'a T)> + 'b {
+        let mark = mark.index();
+        let mut range = self.filter_mark(mark);
+        core::iter::from_fn(move || {
+            let item = range.next()?;
+            range.cursor.test_and_clear_mark(mark);
+            Some(item)
+        })
+    }
+
     #[inline]
-    pub fn as_cursor_mut(&mut self) -> &mut CursorMut<'a, 'b, T> {
+    pub fn as_cursor_mut(&mut self) -> &mut CursorMut<'a, 'b, T, MARKS, A> {
         &mut self.cursor
     }
+
+    /// Stop the iteration, releasing the array, and return where to resume
+    /// it from. See [`Range::pause`].
+    pub fn pause(self) -> ResumeToken {
+        ResumeToken {
+            next: self.cursor.xas.pause(),
+            end: self.end,
+            filter: self.filter,
+        }
+    }
 }
 
-impl<'a, 'b, T> core::iter::Iterator for RangeMut<'a, 'b, T> {
-    type Item = (u64, &'b T);
+impl<'a, 'b, T, const MARKS: usize, A: NodeAlloc> core::iter::Iterator
+    for RangeMut<'a, 'b, T, MARKS, A>
+{
+    type Item = (u64, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
         let Self {
             cursor: CursorMut { xa, xas },
             end,
-            mark,
+            filter,
         } = self;
 
         if xas.index > *end {
             return None;
         }
 
-        if let Some(mark) = *mark {
-            xas.get_next_marked(xa, mark, *end)
-        } else {
-            xas.get_next(xa, *end)
-        }
-        .map(|n| (xas.index, n.as_value().unwrap()))
+        filter
+            .next(xa, xas, *end)
+            .map(|n| (xas.index, n.as_value().unwrap()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let CursorMut { xa, xas } = &self.cursor;
+        (0, Some(remaining(xa, xas, self.end)))
     }
 }
+
+/// Split the range from `start` to `end` into the fewest multi-order
+/// entries alignment allows, yielding the first index, shift and sibling
+/// count of each.
+fn spans(start: u64, end: u64) -> impl Iterator<Item = (u64, u8, u8)> {
+    let mut next = Some(start);
+    core::iter::from_fn(move || {
+        let first = next?;
+        // Pick the highest level `first` is aligned to with at least one
+        // whole slot left, then as many slots as fit in its node.
+        let left = end - first;
+        let mut shift = 0;
+        while shift + CHUNK_SHIFT < u64::BITS as usize
+            && first.trailing_zeros() as usize >= shift + CHUNK_SHIFT
+            && left >= (1 << (shift + CHUNK_SHIFT)) - 1
+        {
+            shift += CHUNK_SHIFT;
+        }
+        let offset = (first >> shift) & CHUNK_MASK as u64;
+        let extra = (left - ((1 << shift) - 1)) >> shift;
+        let sibs = core::cmp::min(extra, CHUNK_MASK as u64 - offset);
+        let last = first + (sibs << shift | ((1 << shift) - 1));
+        next = (last != end).then(|| last + 1);
+        Some((first, shift as u8, sibs as u8))
+    })
+}
@@ -0,0 +1,44 @@
+use crate::xarray_raw::Range;
+use crate::RawXArray;
+use core::iter::Peekable;
+use core::ops::RangeInclusive;
+
+/// Iterate over two arrays in lockstep within `range`, yielding
+/// `(index, a, b)` for every index present in either array.
+///
+/// Both trees are walked at once, each one skipping its own empty
+/// subtrees, so a region populated in only one array costs nothing in the
+/// other.
+pub fn zip_iter<'a, 'b, 'x, 'y, A, B>(
+    a: &'x RawXArray<'a, A>,
+    b: &'y RawXArray<'b, B>,
+    range: RangeInclusive<u64>,
+) -> Zip<'a, 'b, 'x, 'y, A, B> {
+    let (start, end) = range.into_inner();
+    Zip {
+        a: a.extract(start, end).peekable(),
+        b: b.extract(start, end).peekable(),
+    }
+}
+
+/// Iterator returned by [`zip_iter`].
+pub struct Zip<'a, 'b, 'x, 'y, A, B> {
+    a: Peekable<Range<'a, 'x, A>>,
+    b: Peekable<Range<'b, 'y, B>>,
+}
+
+impl<'a, 'b, 'x, 'y, A, B> Iterator for Zip<'a, 'b, 'x, 'y, A, B> {
+    type Item = (u64, Option<&'a A>, Option<&'b B>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = match (self.a.peek(), self.b.peek()) {
+            (Some((i, _)), Some((j, _))) => *i.min(j),
+            (Some((i, _)), None) => *i,
+            (None, Some((j, _))) => *j,
+            (None, None) => return None,
+        };
+        let a = self.a.next_if(|(i, _)| *i == index).map(|(_, v)| v);
+        let b = self.b.next_if(|(j, _)| *j == index).map(|(_, v)| v);
+        Some((index, a, b))
+    }
+}